@@ -0,0 +1,16 @@
+//! Input shared by the benchmarks.
+
+/// `len` text-like bytes with a skewed distribution, from a fixed xorshift
+/// seed.
+pub fn sample(len: usize) -> Vec<u8> {
+    let alphabet = b"eeeeeeettttaaaoooiinnsshhrrdlcumwfgypbvkjxqz      ,.\n";
+    let mut state = 0x2545_f491u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            alphabet[state as usize % alphabet.len()]
+        })
+        .collect()
+}
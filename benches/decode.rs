@@ -0,0 +1,56 @@
+//! Compares the table-driven decoder behind `decompress` with walking the
+//! tree one bit at a time. Run with `cargo bench`.
+
+mod common;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use compression::{HuffmanTree, compress, decompress};
+
+const LEN: usize = 4 << 20;
+const ROUNDS: u32 = 5;
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let mib_per_s = LEN as f64 / (1 << 20) as f64 / elapsed.as_secs_f64();
+    println!("{:<10} {:>8.2?} {:>8.1} MiB/s", name, elapsed, mib_per_s);
+}
+
+fn main() {
+    let data = common::sample(LEN);
+    let mut freq = [0u64; 256];
+    for &b in &data {
+        freq[b as usize] += 1;
+    }
+    let tree = HuffmanTree::from_frequencies(&freq).unwrap();
+    let encoded = tree.encode(&data).unwrap();
+    assert_eq!(encoded.decode().unwrap(), data);
+
+    let table = time(|| {
+        black_box(black_box(&encoded).decode_unverified().unwrap());
+    });
+    // The same payload decoded bit by bit through the tree.
+    let tree_walk = time(|| {
+        black_box(encoded.reinterpret_as(black_box(&tree)).unwrap());
+    });
+    let compressed = compress(&data);
+    let end_to_end = time(|| {
+        black_box(decompress(black_box(&compressed)).unwrap());
+    });
+
+    report("table", table);
+    report("tree walk", tree_walk);
+    report("decompress", end_to_end);
+    println!(
+        "speedup    {:.1}x",
+        tree_walk.as_secs_f64() / table.as_secs_f64()
+    );
+}
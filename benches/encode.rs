@@ -0,0 +1,107 @@
+//! Compares Huffman coding straight into packed bytes, as `HuffmanTree::encode`
+//! does, with collecting every bit in a `Vec<bool>` first and packing it
+//! afterwards, on 100 MB of text-like data. Reports the time and the peak
+//! heap use of each. Run with `cargo bench --bench encode`.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use compression::{HuffmanTree, histogram};
+
+const LEN: usize = 100_000_000;
+const ROUNDS: u32 = 3;
+
+/// The system allocator, keeping track of the most memory in use at once.
+struct Tracking;
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+// SAFETY: forwards to the system allocator and only adds bookkeeping.
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: forwarded from the caller.
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let in_use = IN_USE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(in_use, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: forwarded from the caller.
+        unsafe { System.dealloc(ptr, layout) };
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+/// The average time of `f` and the most heap it used beyond what was
+/// allocated before it ran.
+fn measure(mut f: impl FnMut()) -> (Duration, usize) {
+    let before = IN_USE.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    let elapsed = start.elapsed() / ROUNDS;
+    (elapsed, PEAK.load(Ordering::Relaxed) - before)
+}
+
+/// The payload built the old way: every bit as a `bool`, then packed.
+fn encode_via_bools(tree: &HuffmanTree, data: &[u8]) -> Vec<u8> {
+    let table = tree.to_lookup_array();
+    let mut bits: Vec<bool> = Vec::new();
+    for &b in data {
+        bits.extend(table[b as usize].0.as_ref().unwrap());
+    }
+    let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+    for (i, &bit) in bits.iter().enumerate() {
+        if bit {
+            bytes[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    bytes
+}
+
+fn report(name: &str, (elapsed, peak): (Duration, usize)) {
+    let mb_per_s = LEN as f64 / 1e6 / elapsed.as_secs_f64();
+    println!(
+        "{:<8} {:>9.2?} {:>8.1} MB/s {:>8.1} MB peak",
+        name,
+        elapsed,
+        mb_per_s,
+        peak as f64 / 1e6
+    );
+}
+
+fn main() {
+    let data = common::sample(LEN);
+    let tree = HuffmanTree::from_frequencies(&histogram(&data)).unwrap();
+    let encoded = tree.encode(&data).unwrap();
+    assert_eq!(encoded.data_bytes(), encode_via_bools(&tree, &data));
+    drop(encoded);
+
+    let bools = measure(|| {
+        black_box(encode_via_bools(black_box(&tree), black_box(&data)));
+    });
+    let packed = measure(|| {
+        black_box(black_box(&tree).encode(black_box(&data)).unwrap());
+    });
+
+    report("bools", bools);
+    report("packed", packed);
+    println!(
+        "speedup  {:.1}x, {:.1}x less memory",
+        bools.0.as_secs_f64() / packed.0.as_secs_f64(),
+        bools.1 as f64 / packed.1 as f64
+    );
+}
@@ -0,0 +1,67 @@
+//! Compares looking codes up in the array of `HuffmanTree::to_lookup_array`
+//! with looking them up in a `HashMap` keyed by byte, as `build_map` did,
+//! while collecting the codes of 1 MiB of text. Run with
+//! `cargo bench --bench lookup`.
+
+mod common;
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use compression::{HuffmanTree, histogram};
+
+const LEN: usize = 1 << 20;
+const ROUNDS: u32 = 20;
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let mib_per_s = LEN as f64 / (1 << 20) as f64 / elapsed.as_secs_f64();
+    println!("{:<8} {:>8.2?} {:>8.1} MiB/s", name, elapsed, mib_per_s);
+}
+
+/// The codes of `data` one after another, each looked up with `code`.
+fn collect<'a>(data: &[u8], code: impl Fn(u8) -> &'a [bool]) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(data.len() * 8);
+    for &b in data {
+        bits.extend_from_slice(code(b));
+    }
+    bits
+}
+
+fn main() {
+    let data = common::sample(LEN);
+    let tree = HuffmanTree::from_frequencies(&histogram(&data)).unwrap();
+    let table = tree.to_lookup_array();
+    let map: HashMap<u8, Vec<bool>> = (0..=255u8)
+        .filter_map(|b| Some((b, table[b as usize].0.clone()?)))
+        .collect();
+    let from_map = collect(&data, |b| &map[&b]);
+    assert_eq!(
+        from_map,
+        collect(&data, |b| table[b as usize].0.as_deref().unwrap())
+    );
+
+    let hashed = time(|| {
+        black_box(collect(black_box(&data), |b| &map[&b]));
+    });
+    let array = time(|| {
+        black_box(collect(black_box(&data), |b| {
+            table[b as usize].0.as_deref().unwrap()
+        }));
+    });
+
+    report("map", hashed);
+    report("array", array);
+    println!(
+        "speedup  {:.1}x",
+        hashed.as_secs_f64() / array.as_secs_f64()
+    );
+}
@@ -0,0 +1,65 @@
+/*
+ * C interface to the compression library. Build it with
+ *
+ *     cargo build --release -p compression-ffi
+ *
+ * and link against libwfh.so (or libwfh.a) in target/release.
+ *
+ * Every function that can fail returns WFH_OK or one of the negative
+ * WFH_ERR_* codes. Buffers returned through an output pointer belong to
+ * the caller and must be released with wfh_free. Input pointers may be
+ * NULL only when their length is 0.
+ */
+#ifndef WFH_H
+#define WFH_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define WFH_OK 0
+/* A required pointer was NULL. */
+#define WFH_ERR_NULL_POINTER (-1)
+/* The input is not a compressed stream. */
+#define WFH_ERR_INVALID_MAGIC (-2)
+/* The input ended before the stream did. */
+#define WFH_ERR_TRUNCATED (-3)
+/* The stream is malformed. */
+#define WFH_ERR_CORRUPT (-4)
+/* The decoded data does not match the stored checksum. */
+#define WFH_ERR_CHECKSUM (-5)
+/* The stream uses a format version or feature this build cannot read. */
+#define WFH_ERR_UNSUPPORTED (-6)
+
+/* Compresses input_len bytes at input into a new buffer stored in *out,
+ * with its length in *out_len. */
+int wfh_compress(const uint8_t *input, size_t input_len, uint8_t **out,
+                 size_t *out_len);
+
+/* Decompresses and verifies a stream into a new buffer stored in *out,
+ * with its length in *out_len. Nothing is stored on failure. */
+int wfh_decompress(const uint8_t *input, size_t input_len, uint8_t **out,
+                   size_t *out_len);
+
+/* Releases a buffer returned by wfh_compress or wfh_decompress, given with
+ * the length returned alongside it. Does nothing for NULL. */
+void wfh_free(uint8_t *data, size_t len);
+
+/* Largest stream wfh_compress can produce for input_len bytes. */
+size_t wfh_compress_bound(size_t input_len);
+
+/* Stores in *len the length a stream decodes to, read from its header. */
+int wfh_decompressed_len(const uint8_t *input, size_t input_len,
+                         uint64_t *len);
+
+/* A static description of a WFH_* code. */
+const char *wfh_error_message(int code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
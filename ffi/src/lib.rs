@@ -0,0 +1,4 @@
+//! Shared and static library exporting the C ABI of `compression::ffi`,
+//! declared in `include/wfh.h`.
+
+pub use compression::ffi::*;
@@ -0,0 +1,23 @@
+//! Feeds arbitrary bytes to the decoders that take untrusted input. Any
+//! panic, or an allocation beyond the limit, is a bug; errors are not.
+//!
+//! Run with `cargo fuzz run decode` from the repository root.
+
+#![no_main]
+
+use compression::{Encoded, decode_with_limit, info};
+use libfuzzer_sys::fuzz_target;
+
+/// Most output any input may decode to.
+const LIMIT: usize = 1 << 20;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(out) = decode_with_limit(data, LIMIT as u64) {
+        assert!(out.len() <= LIMIT);
+    }
+    let _ = info::inspect(data);
+    if let Ok(encoded) = Encoded::from_bytes(data) {
+        let mut out = vec![0; LIMIT];
+        let _ = encoded.decode_into(&mut out);
+    }
+});
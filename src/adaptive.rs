@@ -0,0 +1,254 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bits::{BitReader, BitWriter, bit_count};
+use crate::error::CompressionError;
+
+/// Nodes in a tree over all 256 bytes and the not-yet-transmitted leaf.
+const MAX_NODES: usize = 2 * 257 - 1;
+const ROOT: usize = MAX_NODES - 1;
+const NONE: usize = usize::MAX;
+
+#[derive(Clone, Copy)]
+struct Node {
+    weight: u64,
+    parent: usize,
+    /// Children of an internal node; `NONE` for a leaf.
+    left: usize,
+    right: usize,
+    symbol: u8,
+}
+
+/// An adaptive Huffman code (FGK). Encoder and decoder start from the same
+/// tree holding only the not-yet-transmitted (NYT) leaf and update it the
+/// same way after every symbol, so no tree is stored.
+///
+/// Nodes are indexed by their FGK number: weights never decrease as the
+/// number grows and siblings have adjacent numbers. A byte seen for the
+/// first time is sent as the NYT code followed by its 8 bits.
+struct AdaptiveTree {
+    nodes: Vec<Node>,
+    leaf_of: [usize; 256],
+    nyt: usize,
+}
+
+impl AdaptiveTree {
+    fn new() -> AdaptiveTree {
+        let empty = Node {
+            weight: 0,
+            parent: NONE,
+            left: NONE,
+            right: NONE,
+            symbol: 0,
+        };
+        AdaptiveTree {
+            nodes: vec![empty; MAX_NODES],
+            leaf_of: [NONE; 256],
+            nyt: ROOT,
+        }
+    }
+
+    /// Appends the code of `symbol` to `bits`.
+    fn encode(&self, symbol: u8, bits: &mut BitWriter) {
+        let leaf = self.leaf_of[symbol as usize];
+        if leaf == NONE {
+            self.push_path(self.nyt, bits);
+            bits.push_value(symbol as u64, 8);
+        } else {
+            self.push_path(leaf, bits);
+        }
+    }
+
+    fn push_path(&self, mut node: usize, bits: &mut BitWriter) {
+        let mut path = Vec::new();
+        while node != ROOT {
+            let parent = self.nodes[node].parent;
+            path.push(self.nodes[parent].right == node);
+            node = parent;
+        }
+        for &bit in path.iter().rev() {
+            bits.push_bit(bit);
+        }
+    }
+
+    /// Reads one symbol from `bits`, or `None` if they end first.
+    fn decode(&self, bits: &mut BitReader) -> Option<u8> {
+        let mut node = ROOT;
+        while self.nodes[node].left != NONE {
+            node = if bits.read_bit()? {
+                self.nodes[node].right
+            } else {
+                self.nodes[node].left
+            };
+        }
+        if node != self.nyt {
+            return Some(self.nodes[node].symbol);
+        }
+        bits.read_bits(8).map(|symbol| symbol as u8)
+    }
+
+    /// Counts one more occurrence of `symbol`.
+    fn update(&mut self, symbol: u8) {
+        let mut node = self.leaf_of[symbol as usize];
+        if node == NONE {
+            // The NYT leaf becomes an internal node over a new NYT leaf
+            // and a leaf for `symbol`.
+            let parent = self.nyt;
+            let (nyt, leaf) = (parent - 2, parent - 1);
+            self.nodes[parent].left = nyt;
+            self.nodes[parent].right = leaf;
+            self.nodes[nyt] = Node {
+                weight: 0,
+                parent,
+                left: NONE,
+                right: NONE,
+                symbol: 0,
+            };
+            self.nodes[leaf] = Node {
+                weight: 0,
+                parent,
+                left: NONE,
+                right: NONE,
+                symbol,
+            };
+            self.nyt = nyt;
+            self.leaf_of[symbol as usize] = leaf;
+            node = leaf;
+        }
+        loop {
+            let leader = self.block_leader(node);
+            if leader != node && leader != self.nodes[node].parent {
+                self.swap(node, leader);
+                node = leader;
+            }
+            self.nodes[node].weight += 1;
+            if node == ROOT {
+                break;
+            }
+            node = self.nodes[node].parent;
+        }
+    }
+
+    /// Highest-numbered node with the same weight as `node`.
+    fn block_leader(&self, node: usize) -> usize {
+        let weight = self.nodes[node].weight;
+        let mut leader = node;
+        while leader < ROOT && self.nodes[leader + 1].weight == weight {
+            leader += 1;
+        }
+        leader
+    }
+
+    /// Exchanges the subtrees numbered `a` and `b`, which keep their
+    /// positions in the tree.
+    fn swap(&mut self, a: usize, b: usize) {
+        let (parent_a, parent_b) = (self.nodes[a].parent, self.nodes[b].parent);
+        self.nodes.swap(a, b);
+        self.nodes[a].parent = parent_a;
+        self.nodes[b].parent = parent_b;
+        for node in [a, b] {
+            let Node { left, right, .. } = self.nodes[node];
+            if left == NONE {
+                self.leaf_of[self.nodes[node].symbol as usize] = node;
+            } else {
+                self.nodes[left].parent = node;
+                self.nodes[right].parent = node;
+            }
+        }
+    }
+}
+
+/// Encodes `data` in one pass with an adaptive Huffman code. The output is
+/// the codes packed into bytes, the last one padded with zero bits; the
+/// decoder needs the length of `data` to know where they end.
+pub fn adaptive_encode(data: &[u8]) -> Vec<u8> {
+    let mut tree = AdaptiveTree::new();
+    let mut bits = BitWriter::new();
+    for &b in data {
+        tree.encode(b, &mut bits);
+        tree.update(b);
+    }
+    bits.into_bytes()
+}
+
+/// Decodes `len` bytes from the output of `adaptive_encode`. Fails if the
+/// codes end early or are followed by more than padding.
+pub fn adaptive_decode(payload: &[u8], len: u64) -> Result<Vec<u8>, CompressionError> {
+    let (out, used) = adaptive_decode_prefix(payload, len)?;
+    let mut bits = BitReader::new(&payload[(used / 8) as usize..]);
+    bits.set_position(used % 8);
+    if bit_count(payload) > used.next_multiple_of(8) || bits.any(|bit| bit) {
+        return Err(CompressionError::CorruptData);
+    }
+    Ok(out)
+}
+
+/// Decodes `len` symbols from the start of `payload` and returns them with
+/// the number of bits they took up, ignoring whatever follows.
+pub(crate) fn adaptive_decode_prefix(
+    payload: &[u8],
+    len: u64,
+) -> Result<(Vec<u8>, u64), CompressionError> {
+    let mut tree = AdaptiveTree::new();
+    let mut bits = BitReader::new(payload);
+    let mut out = Vec::new();
+    for _ in 0..len {
+        let symbol = tree
+            .decode(&mut bits)
+            .ok_or(CompressionError::TruncatedInput)?;
+        tree.update(symbol);
+        out.push(symbol);
+    }
+    Ok((out, bits.position()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_text() {
+        let data = b"adaptive huffman coding learns the statistics as it goes";
+        let encoded = adaptive_encode(data);
+        assert!(encoded.len() < data.len());
+        assert_eq!(adaptive_decode(&encoded, data.len() as u64).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_all_bytes() {
+        let data: Vec<u8> = (0..=255).chain((0..=255).rev()).chain(0..100).collect();
+        let encoded = adaptive_encode(&data);
+        assert_eq!(adaptive_decode(&encoded, data.len() as u64).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_shifting_statistics() {
+        let data: Vec<u8> = (0..20_000u32)
+            .map(|i| ((i * i) % (7 + i / 1000)) as u8 + (i / 5000) as u8 * 40)
+            .collect();
+        let encoded = adaptive_encode(&data);
+        assert_eq!(adaptive_decode(&encoded, data.len() as u64).unwrap(), data);
+    }
+
+    #[test]
+    fn repeated_byte_costs_one_bit_each() {
+        // 8 bits for the first 'a', then one bit for each repeat.
+        let encoded = adaptive_encode(&[b'a'; 17]);
+        assert_eq!(encoded.len(), 3);
+        assert_eq!(adaptive_decode(&encoded, 17).unwrap(), [b'a'; 17]);
+    }
+
+    #[test]
+    fn wrong_length_is_rejected() {
+        let encoded = adaptive_encode(b"abcabc");
+        assert_eq!(
+            adaptive_decode(&encoded, 20),
+            Err(CompressionError::TruncatedInput)
+        );
+        let padded = [encoded.as_slice(), &[0]].concat();
+        assert_eq!(
+            adaptive_decode(&padded, 6),
+            Err(CompressionError::CorruptData)
+        );
+    }
+}
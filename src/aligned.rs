@@ -0,0 +1,146 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bits::{BitReader, BitWriter};
+use crate::error::CompressionError;
+use crate::{HuffmanTree, TreeSection, histogram, parse_tree_section, serialize_tree_section};
+
+/// Encodes `data` with every code padded with zero bits to a byte boundary,
+/// so each symbol starts on its own byte. The output is the serialized tree
+/// followed by the padded codes.
+pub fn encode_aligned(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let Some(tree) = HuffmanTree::from_frequencies(&histogram(data)) else {
+        let mut out = serialize_tree_section(data.get(..1).unwrap_or_default(), &[]);
+        out.resize(out.len() + data.len(), 0);
+        return Ok(out);
+    };
+
+    let table = tree.to_lookup_array();
+    let mut payload = BitWriter::new();
+    for &b in data {
+        payload.push_bits(table[b as usize].0.as_ref().unwrap());
+        payload.align_to_byte();
+    }
+    let mut out = tree.serialize();
+    out.extend(payload.into_bytes());
+    Ok(out)
+}
+
+pub fn decode_aligned(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let (section, payload) = split_header(data)?;
+    let Some(tree) = section.tree else {
+        return match section.leaves[..] {
+            [] if payload.is_empty() => Ok(Vec::new()),
+            [symbol] => Ok(vec![symbol; payload.len()]),
+            _ => Err(CompressionError::CorruptData),
+        };
+    };
+    let widths = symbol_widths(&tree);
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < payload.len() {
+        let symbol = decode_at(&tree, &payload[pos..])?;
+        out.push(symbol);
+        pos += widths[symbol as usize];
+    }
+    if pos != payload.len() {
+        return Err(CompressionError::TruncatedInput);
+    }
+    Ok(out)
+}
+
+/// Decodes the symbol at `index`. When every code fits in one byte the
+/// symbol is read straight from byte `index` of the payload; otherwise the
+/// preceding symbols' widths are walked.
+pub fn aligned_symbol_at(data: &[u8], index: usize) -> Result<u8, CompressionError> {
+    let (section, payload) = split_header(data)?;
+    let Some(tree) = section.tree else {
+        return match section.leaves[..] {
+            [symbol] if index < payload.len() => Ok(symbol),
+            _ => Err(CompressionError::TruncatedInput),
+        };
+    };
+    if tree.longest_code_length() <= 8 {
+        let start = payload
+            .get(index..)
+            .filter(|rest| !rest.is_empty())
+            .ok_or(CompressionError::TruncatedInput)?;
+        return decode_at(&tree, start);
+    }
+
+    let widths = symbol_widths(&tree);
+    let mut pos = 0;
+    for _ in 0..index {
+        let symbol = decode_at(&tree, payload.get(pos..).unwrap_or(&[]))?;
+        pos += widths[symbol as usize];
+    }
+    decode_at(&tree, payload.get(pos..).unwrap_or(&[]))
+}
+
+fn split_header(data: &[u8]) -> Result<(TreeSection, &[u8]), CompressionError> {
+    let section = parse_tree_section(data, true)?;
+    let payload = &data[section.len..];
+    Ok((section, payload))
+}
+
+/// Number of payload bytes each symbol occupies, indexed by byte value.
+fn symbol_widths(tree: &HuffmanTree) -> [usize; 256] {
+    let mut widths = [0; 256];
+    for (width, (_, len)) in widths.iter_mut().zip(tree.to_lookup_array().iter()) {
+        *width = (*len as usize).div_ceil(8);
+    }
+    widths
+}
+
+fn decode_at(tree: &HuffmanTree, bytes: &[u8]) -> Result<u8, CompressionError> {
+    tree.decode_symbol(&mut BitReader::new(bytes))
+        .ok_or(CompressionError::TruncatedInput)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_aligned() {
+        let data = b"abracadabra, abracadabra!";
+        assert_eq!(
+            decode_aligned(&encode_aligned(data).unwrap()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn round_trip_aligned_long_codes() {
+        let data: Vec<u8> = (0..40).collect();
+        assert_eq!(
+            decode_aligned(&encode_aligned(&data).unwrap()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn four_symbol_codes_take_one_byte_each() {
+        let data: Vec<u8> = b"abcd".iter().cycle().take(100).copied().collect();
+        let encoded = encode_aligned(&data).unwrap();
+        let (_, payload) = split_header(&encoded).unwrap();
+        assert_eq!(payload.len(), data.len());
+        assert_eq!(aligned_symbol_at(&encoded, 49).unwrap(), data[49]);
+    }
+
+    #[test]
+    fn random_access_with_wide_codes() {
+        let data: Vec<u8> = (0..20).chain(0..20).collect();
+        let encoded = encode_aligned(&data).unwrap();
+        assert_eq!(aligned_symbol_at(&encoded, 35).unwrap(), data[35]);
+    }
+
+    #[test]
+    fn round_trip_single_symbol() {
+        let data = b"zzzz";
+        assert_eq!(
+            decode_aligned(&encode_aligned(data).unwrap()).unwrap(),
+            data
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use crate::block::DEFAULT_BLOCK_SIZE;
+use crate::{
+    CHECKSUM_LEN, CONTAINER_LEN, HuffmanTree, STORED_OVERHEAD, compress, count_unique_bytes,
+    histogram,
+};
+
+const BLOCK_SIZES: [usize; 5] = [512, 1024, 4096, 16384, 65536];
+const SVG_BAR_WIDTH: usize = 2;
+const SVG_HEIGHT: usize = 100;
+
+/// Local Shannon entropy (bits per byte) of every window position.
+pub struct RepetitionMap {
+    pub window_size: usize,
+    pub local_entropy: Vec<f64>,
+}
+
+impl RepetitionMap {
+    /// Renders the map as an SVG bar chart, one bar per window position,
+    /// scaled so that 8 bits per byte fills the full height.
+    pub fn to_svg(&self) -> String {
+        let width = self.local_entropy.len() * SVG_BAR_WIDTH;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+            width, SVG_HEIGHT
+        );
+        for (i, &entropy) in self.local_entropy.iter().enumerate() {
+            let height = entropy / 8.0 * SVG_HEIGHT as f64;
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{:.2}\" width=\"{}\" height=\"{:.2}\"/>\n",
+                i * SVG_BAR_WIDTH,
+                SVG_HEIGHT as f64 - height,
+                SVG_BAR_WIDTH,
+                height
+            ));
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Slides a `window_size` window over `data` and records the entropy of
+/// each window. Data shorter than the window yields an empty map.
+pub fn compute_repetition_map(data: &[u8], window_size: usize) -> RepetitionMap {
+    let mut local_entropy = Vec::new();
+    if window_size == 0 || data.len() < window_size {
+        return RepetitionMap {
+            window_size,
+            local_entropy,
+        };
+    }
+
+    let mut counts = [0usize; 256];
+    for &b in &data[..window_size] {
+        counts[b as usize] += 1;
+    }
+    local_entropy.push(entropy(&counts, window_size));
+    for i in window_size..data.len() {
+        counts[data[i - window_size] as usize] -= 1;
+        counts[data[i] as usize] += 1;
+        local_entropy.push(entropy(&counts, window_size));
+    }
+    RepetitionMap {
+        window_size,
+        local_entropy,
+    }
+}
+
+/// Lengths of the runs of equal bytes in some data. A high average run
+/// length suggests run-length encoding will help before Huffman coding.
+#[derive(Debug, PartialEq)]
+pub struct ByteRunStats {
+    pub max_run_length: usize,
+    pub avg_run_length: f64,
+    pub run_count: usize,
+}
+
+pub fn compute_run_stats(data: &[u8]) -> ByteRunStats {
+    let mut max_run_length = 0;
+    let mut run_count = 0;
+    let mut run_length = 0;
+    for (i, &b) in data.iter().enumerate() {
+        if i > 0 && data[i - 1] == b {
+            run_length += 1;
+        } else {
+            run_count += 1;
+            run_length = 1;
+        }
+        max_run_length = max_run_length.max(run_length);
+    }
+    let avg_run_length = if run_count == 0 {
+        0.0
+    } else {
+        data.len() as f64 / run_count as f64
+    };
+    ByteRunStats {
+        max_run_length,
+        avg_run_length,
+        run_count,
+    }
+}
+
+/// Where the bytes of `compress(data)` go and how close the code comes to
+/// the entropy of the data, to tell whether a poor ratio comes from the
+/// model or from header overhead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Occurrences of every byte value, indexed by byte.
+    pub frequencies: [u64; 256],
+    /// Shannon entropy of the byte frequencies, in bits per byte.
+    pub entropy: f64,
+    /// Bits per byte spent on the payload: the average code length, or 8
+    /// if the data is stored.
+    pub average_code_length: f64,
+    pub original_len: usize,
+    pub compressed_len: usize,
+    /// Payload bytes of `compressed_len`.
+    pub payload_len: usize,
+    /// Bytes of `compressed_len` that are not payload: container, trees,
+    /// frame headers and checksum.
+    pub header_len: usize,
+}
+
+impl Stats {
+    /// Compressed size divided by the original size, 0 for empty data.
+    pub fn ratio(&self) -> f64 {
+        if self.original_len == 0 {
+            return 0.0;
+        }
+        self.compressed_len as f64 / self.original_len as f64
+    }
+}
+
+/// Compresses `data` with [`compress`] and reports on the result.
+pub fn analyze(data: &[u8]) -> Stats {
+    let frequencies = histogram(data);
+    let compressed_len = compress(data).len();
+    let (payload_bits, payload_len) = if compressed_len == predict_coded_size(data) {
+        block_sizes(data).fold((0, 0), |(bits, len), (_, block_bits)| {
+            (bits + block_bits, len + block_bits.div_ceil(8) as usize)
+        })
+    } else {
+        (data.len() as u64 * 8, data.len())
+    };
+    let average_code_length = if data.is_empty() {
+        0.0
+    } else {
+        payload_bits as f64 / data.len() as f64
+    };
+    Stats {
+        frequencies,
+        entropy: entropy(&frequencies.map(|f| f as usize), data.len()),
+        average_code_length,
+        original_len: data.len(),
+        compressed_len,
+        payload_len,
+        header_len: compressed_len - payload_len,
+    }
+}
+
+/// Size in bytes of `compress(data)`, computed from the code lengths without
+/// producing any output.
+pub fn predict_compressed_size(data: &[u8]) -> usize {
+    predict_coded_size(data).min(data.len() + STORED_OVERHEAD)
+}
+
+/// Size of `data` Huffman coded, even if storing it would be smaller.
+fn predict_coded_size(data: &[u8]) -> usize {
+    let blocks = block_sizes(data).map(|(header, bits)| header + bits.div_ceil(8) as usize);
+    if data.len() > DEFAULT_BLOCK_SIZE {
+        let frames: usize = blocks.map(|block| 8 + 1 + CHECKSUM_LEN + block).sum();
+        CONTAINER_LEN + frames + CHECKSUM_LEN
+    } else {
+        CONTAINER_LEN + 1 + blocks.sum::<usize>() + CHECKSUM_LEN
+    }
+}
+
+/// Size of the tree section and number of payload bits of every block
+/// `compress(data)` codes on its own.
+fn block_sizes(data: &[u8]) -> impl Iterator<Item = (usize, u64)> + '_ {
+    let blocks: Box<dyn Iterator<Item = &[u8]>> = if data.len() > DEFAULT_BLOCK_SIZE {
+        Box::new(data.chunks(DEFAULT_BLOCK_SIZE))
+    } else {
+        Box::new(std::iter::once(data))
+    };
+    blocks.map(predict_block_size)
+}
+
+/// Size of the tree section and number of payload bits for `data`.
+fn predict_block_size(data: &[u8]) -> (usize, u64) {
+    match HuffmanTree::from_frequencies(&histogram(data)) {
+        Some(tree) => {
+            let payload_bits = tree
+                .encoded_bit_count(data)
+                .expect("tree built from the data covers it");
+            // Leaf count, leaves, longest code length and the counts of
+            // the shorter lengths.
+            let header =
+                2 + count_unique_bytes(data) as usize + tree.longest_code_length() as usize;
+            (header, payload_bits)
+        }
+        None => (2 + count_unique_bytes(data) as usize, 0),
+    }
+}
+
+/// Picks the block size, out of 512 B to 64 KiB, that minimizes the
+/// predicted total size when every block gets its own tree. Data whose
+/// statistics change quickly favours small blocks; uniform data favours
+/// large blocks that amortize the tree headers.
+pub fn find_optimal_block_size(data: &[u8]) -> usize {
+    BLOCK_SIZES
+        .iter()
+        .copied()
+        .min_by_key(|&size| {
+            data.chunks(size)
+                .map(predict_compressed_size)
+                .sum::<usize>()
+        })
+        .unwrap()
+}
+
+/// Number of positions where a byte differs from the one before it. Divided
+/// by the data length, values close to 1 suggest random (likely
+/// incompressible) data and values close to 0 long runs.
+pub fn count_transitions(data: &[u8]) -> u64 {
+    data.windows(2).filter(|pair| pair[0] != pair[1]).count() as u64
+}
+
+fn entropy(counts: &[usize; 256], total: usize) -> f64 {
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_bytes(n: usize) -> Vec<u8> {
+        let mut state = 0x9e37_79b9u32;
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn repeated_bytes_have_zero_entropy() {
+        let map = compute_repetition_map(&[7; 64], 16);
+        assert_eq!(map.local_entropy.len(), 49);
+        assert!(map.local_entropy.iter().all(|&e| e == 0.0));
+    }
+
+    #[test]
+    fn distinct_bytes_have_maximal_entropy() {
+        let data: Vec<u8> = (0..=255).collect();
+        let map = compute_repetition_map(&data, 256);
+        assert!((map.local_entropy[0] - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn svg_has_one_bar_per_window() {
+        let map = compute_repetition_map(b"abababab", 4);
+        assert_eq!(map.to_svg().matches("<rect").count(), 5);
+    }
+
+    #[test]
+    fn random_data_has_many_transitions() {
+        let data = random_bytes(4096);
+        assert!(count_transitions(&data) as f64 / data.len() as f64 > 0.4);
+    }
+
+    #[test]
+    fn repeated_data_has_few_transitions() {
+        let mut data = vec![0u8; 2048];
+        data.extend(vec![1u8; 2048]);
+        assert_eq!(count_transitions(&data), 1);
+        assert!((count_transitions(&data) as f64 / data.len() as f64) < 0.1);
+    }
+
+    #[test]
+    fn short_inputs_have_no_transitions() {
+        assert_eq!(count_transitions(b""), 0);
+        assert_eq!(count_transitions(b"x"), 0);
+    }
+
+    #[test]
+    fn run_stats_of_repeated_data() {
+        let stats = compute_run_stats(&[9; 100]);
+        assert_eq!(
+            stats,
+            ByteRunStats {
+                max_run_length: 100,
+                avg_run_length: 100.0,
+                run_count: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn run_stats_of_alternating_data() {
+        let stats = compute_run_stats(b"abababab");
+        assert_eq!(stats.max_run_length, 1);
+        assert_eq!(stats.run_count, 8);
+        assert_eq!(stats.avg_run_length, 1.0);
+    }
+
+    #[test]
+    fn run_stats_of_random_data() {
+        let stats = compute_run_stats(&random_bytes(4096));
+        assert!(stats.avg_run_length < 1.1);
+        assert!(stats.max_run_length < 4);
+    }
+
+    #[test]
+    fn run_stats_of_empty_data() {
+        let stats = compute_run_stats(b"");
+        assert_eq!(stats.run_count, 0);
+        assert_eq!(stats.avg_run_length, 0.0);
+    }
+
+    #[test]
+    fn prediction_matches_encoded_size() {
+        for data in [
+            &b""[..],
+            b"aaaa",
+            b"abab",
+            b"the quick brown fox jumps over the lazy dog",
+        ] {
+            assert_eq!(predict_compressed_size(data), crate::compress(data).len());
+        }
+    }
+
+    #[test]
+    fn prediction_matches_block_encoded_size() {
+        let data: Vec<u8> = random_bytes(DEFAULT_BLOCK_SIZE + 1000)
+            .iter()
+            .map(|b| b % 32)
+            .collect();
+        assert_eq!(predict_compressed_size(&data), crate::compress(&data).len());
+    }
+
+    #[test]
+    fn prediction_matches_stored_size() {
+        let data = random_bytes(5000);
+        assert_eq!(predict_compressed_size(&data), crate::compress(&data).len());
+    }
+
+    #[test]
+    fn stats_split_output_into_header_and_payload() {
+        let data = b"abracadabra, abracadabra".repeat(40);
+        let stats = analyze(&data);
+        assert_eq!(stats.compressed_len, crate::compress(&data).len());
+        assert_eq!(stats.frequencies[b'a' as usize], 400);
+        assert_eq!(stats.header_len + stats.payload_len, stats.compressed_len);
+        assert!(stats.entropy <= stats.average_code_length);
+        assert!(stats.average_code_length < stats.entropy + 1.0);
+        assert!(stats.ratio() < 0.5);
+    }
+
+    #[test]
+    fn stats_of_stored_data() {
+        let stats = analyze(&random_bytes(5000));
+        assert_eq!(stats.average_code_length, 8.0);
+        assert_eq!(stats.payload_len, 5000);
+        assert_eq!(stats.header_len, STORED_OVERHEAD);
+        assert!(stats.entropy > 7.9);
+    }
+
+    #[test]
+    fn mixed_sections_prefer_smaller_blocks() {
+        let text: Vec<u8> = b"plain ascii text section "
+            .iter()
+            .cycle()
+            .take(4096)
+            .copied()
+            .collect();
+        let binary: Vec<u8> = (0..4096u32).map(|i| 0x80 + (i % 9) as u8).collect();
+        let mixed: Vec<u8> = [text.as_slice(), &binary].concat().repeat(8);
+        let uniform: Vec<u8> = text.repeat(16);
+        assert!(find_optimal_block_size(&mixed) <= 4096);
+        assert!(find_optimal_block_size(&mixed) < find_optimal_block_size(&uniform));
+    }
+}
@@ -0,0 +1,781 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::atomic::write_atomic;
+use crate::error::CompressionError;
+use crate::extension::{Extension, FILE_METADATA};
+use crate::{compress, decompress};
+
+const ARCHIVE_MAGIC: [u8; 4] = *b"WFHA";
+const ARCHIVE_VERSION: u8 = 1;
+/// Offset of the entry count, after the magic and the version.
+const COUNT_OFFSET: u64 = 5;
+/// Path length, mode, modification time, size and compressed length.
+const ENTRY_FIELDS_LEN: usize = 2 + 4 + 8 + 8 + 8;
+
+/// A file in an archive: its path relative to the archive root, with `/`
+/// between components, its Unix permission bits, its modification time in
+/// seconds since the Unix epoch and its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: String,
+    pub mode: u32,
+    pub mtime: u64,
+    pub data: Vec<u8>,
+}
+
+/// The metadata of an archive entry, as listed without decompressing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub path: String,
+    pub mode: u32,
+    pub mtime: u64,
+    pub size: u64,
+    pub compressed_len: u64,
+}
+
+/// Packs `entries` into one archive, each compressed on its own.
+///
+/// The archive is the magic `WFHA`, a version byte and the entry count as
+/// a little-endian `u32`, followed for every entry by:
+///
+/// | bytes | field |
+/// |-------|-------|
+/// | 2 | path length `n`, little-endian |
+/// | n | path, UTF-8 |
+/// | 4 | mode, little-endian |
+/// | 8 | modification time, little-endian |
+/// | 8 | original size, little-endian |
+/// | 8 | compressed length `c`, little-endian |
+/// | c | output of [`compress`] |
+///
+/// Only the counted entries belong to the archive. Bytes after them are
+/// left over from an interrupted [`append_archive`] and are ignored.
+///
+/// Fails with [`CorruptHeader`](CompressionError::CorruptHeader) on a path
+/// that is not safe to extract (see [`decode_archive`]) or too long.
+pub fn encode_archive(entries: &[Entry]) -> Result<Vec<u8>, CompressionError> {
+    let mut out = ARCHIVE_MAGIC.to_vec();
+    out.push(ARCHIVE_VERSION);
+    out.extend((entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        encode_entry(entry, &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Adds `entries` to the end of the archive in `file` without rewriting
+/// the entries already in it. The new entries are written and synced
+/// first and the entry count in the header is updated last, so an
+/// interruption leaves the archive as it was. An entry with the path of an
+/// earlier one replaces it on extraction.
+pub fn append_archive(file: &mut File, entries: &[Entry]) -> Result<(), CompressionError> {
+    let mut encoded = Vec::new();
+    for entry in entries {
+        encode_entry(entry, &mut encoded)?;
+    }
+    let (count, end) = scan(&mut *file)?;
+    let count = u32::try_from(entries.len())
+        .ok()
+        .and_then(|added| count.checked_add(added))
+        .ok_or(CompressionError::CorruptHeader)?;
+    // Drops whatever an interrupted append left after the last entry.
+    file.set_len(end)?;
+    file.seek(SeekFrom::Start(end))?;
+    file.write_all(&encoded)?;
+    file.sync_data()?;
+    file.seek(SeekFrom::Start(COUNT_OFFSET))?;
+    file.write_all(&count.to_le_bytes())?;
+    file.sync_data()?;
+    Ok(())
+}
+
+fn encode_entry(entry: &Entry, out: &mut Vec<u8>) -> Result<(), CompressionError> {
+    check_path(&entry.path)?;
+    let path_len = u16::try_from(entry.path.len()).map_err(|_| CompressionError::CorruptHeader)?;
+    let compressed = compress(&entry.data);
+    out.extend(path_len.to_le_bytes());
+    out.extend(entry.path.as_bytes());
+    out.extend(entry.mode.to_le_bytes());
+    out.extend(entry.mtime.to_le_bytes());
+    out.extend((entry.data.len() as u64).to_le_bytes());
+    out.extend((compressed.len() as u64).to_le_bytes());
+    out.extend(compressed);
+    Ok(())
+}
+
+/// Reads the entry count of the archive in `file` and skips over its
+/// entries without reading their data. Returns the count and where the
+/// last entry ends.
+fn scan(file: &mut File) -> Result<(u32, u64), CompressionError> {
+    file.seek(SeekFrom::Start(0))?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut header = [0u8; COUNT_OFFSET as usize + 4];
+    read_fields(&mut reader, &mut header)?;
+    let count = parse_header(&mut &header[..])?;
+    let mut pos = header.len() as u64;
+    for _ in 0..count {
+        let mut fields = [0u8; ENTRY_FIELDS_LEN];
+        read_fields(&mut reader, &mut fields[..2])?;
+        let path_len = u16::from_le_bytes([fields[0], fields[1]]) as u64;
+        reader.seek_relative(path_len as i64)?;
+        read_fields(&mut reader, &mut fields[2..])?;
+        let compressed_len = u64::from_le_bytes(fields[ENTRY_FIELDS_LEN - 8..].try_into().unwrap());
+        pos = [path_len, ENTRY_FIELDS_LEN as u64, compressed_len]
+            .into_iter()
+            .try_fold(pos, u64::checked_add)
+            .filter(|&end| end <= len)
+            .ok_or(CompressionError::TruncatedInput)?;
+        reader.seek(SeekFrom::Start(pos))?;
+    }
+    Ok((count, pos))
+}
+
+/// Fills `buf` from `reader`, failing with
+/// [`TruncatedInput`](CompressionError::TruncatedInput) at the end.
+fn read_fields(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), CompressionError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => CompressionError::TruncatedInput,
+        _ => e.into(),
+    })
+}
+
+/// Returns whether `data` starts with the archive magic.
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(&ARCHIVE_MAGIC)
+}
+
+/// Lists the entries of an archive without decompressing them.
+pub fn list_archive(data: &[u8]) -> Result<Vec<EntryInfo>, CompressionError> {
+    Ok(parse(data)?.into_iter().map(|(info, _)| info).collect())
+}
+
+/// Unpacks every entry of an archive. Paths that are empty, absolute or
+/// contain `..` components are rejected, so extracting under a directory
+/// cannot write outside it.
+pub fn decode_archive(data: &[u8]) -> Result<Vec<Entry>, CompressionError> {
+    parse(data)?
+        .into_iter()
+        .map(|(info, compressed)| {
+            let data = decompress(compressed)?;
+            if data.len() as u64 != info.size {
+                return Err(CompressionError::LengthMismatch {
+                    expected: info.size,
+                    actual: data.len() as u64,
+                });
+            }
+            Ok(Entry {
+                path: info.path,
+                mode: info.mode,
+                mtime: info.mtime,
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Which of the files below a directory [`read_entries_with`] takes.
+///
+/// A pattern without a `/` is matched against the name of a file or
+/// directory, one with a `/` against its path below the directory given
+/// to `read_entries_with`. `*` matches any run of characters but `/`, `?`
+/// any one character but `/` and `**` any number of whole directories.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    follow_symlinks: bool,
+}
+
+impl WalkOptions {
+    pub fn new() -> WalkOptions {
+        WalkOptions::default()
+    }
+
+    /// Takes only files matching `pattern` or another include pattern.
+    /// Directories are walked either way.
+    pub fn with_include(mut self, pattern: &str) -> WalkOptions {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// Leaves out files matching `pattern`, and directories matching it
+    /// with everything below them. Wins over the include patterns.
+    pub fn with_exclude(mut self, pattern: &str) -> WalkOptions {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Whether symbolic links found in a directory are followed. They are
+    /// skipped by default; links given as paths themselves are always
+    /// followed. A link back to a directory being walked is skipped either
+    /// way.
+    pub fn with_follow_symlinks(mut self, follow: bool) -> WalkOptions {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Whether a file or directory at `below`, its `/`-separated path under
+    /// the given directory, is taken.
+    fn takes(&self, below: &str, is_dir: bool) -> bool {
+        let matches = |pattern: &String| {
+            let target = match pattern.contains('/') {
+                true => below,
+                false => below.rsplit('/').next().unwrap_or(below),
+            };
+            glob_match(pattern.as_bytes(), target.as_bytes())
+        };
+        if self.exclude.iter().any(matches) {
+            return false;
+        }
+        is_dir || self.include.is_empty() || self.include.iter().any(matches)
+    }
+}
+
+/// Reads the files at `paths` into entries. A directory adds every file
+/// below it, skipping symbolic links. Entry paths are relative to the
+/// parent of the given path, so `some/dir` yields entries under `dir/`.
+pub fn read_entries(paths: &[&Path]) -> Result<Vec<Entry>, CompressionError> {
+    read_entries_with(paths, &WalkOptions::new())
+}
+
+/// Like [`read_entries`] but takes from directories only what `walk`
+/// allows. The given paths themselves are always taken.
+pub fn read_entries_with(
+    paths: &[&Path],
+    walk: &WalkOptions,
+) -> Result<Vec<Entry>, CompressionError> {
+    let mut entries = Vec::new();
+    for path in paths {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut walker = Walker {
+            walk,
+            base,
+            root: path,
+            ancestors: Vec::new(),
+            entries: &mut entries,
+        };
+        walker.add(path)?;
+    }
+    Ok(entries)
+}
+
+/// The state of one walk of [`read_entries_with`] from `root`.
+struct Walker<'a> {
+    walk: &'a WalkOptions,
+    base: &'a Path,
+    root: &'a Path,
+    /// The directories being walked, resolved, to notice link cycles.
+    ancestors: Vec<PathBuf>,
+    entries: &'a mut Vec<Entry>,
+}
+
+impl Walker<'_> {
+    fn add(&mut self, path: &Path) -> Result<(), CompressionError> {
+        let given = path == self.root;
+        let link = std::fs::symlink_metadata(path)?.file_type().is_symlink();
+        if link && !given && !self.walk.follow_symlinks {
+            return Ok(());
+        }
+        let metadata = std::fs::metadata(path)?;
+        if !given {
+            let below = slash_path(path.strip_prefix(self.root).unwrap_or(path))?;
+            if !self.walk.takes(&below, metadata.is_dir()) {
+                return Ok(());
+            }
+        }
+        if metadata.is_dir() {
+            let resolved = std::fs::canonicalize(path)?;
+            if self.ancestors.contains(&resolved) {
+                return Ok(());
+            }
+            let mut children: Vec<PathBuf> = std::fs::read_dir(path)?
+                .map(|child| child.map(|child| child.path()))
+                .collect::<Result<_, _>>()?;
+            children.sort();
+            self.ancestors.push(resolved);
+            for child in children {
+                self.add(&child)?;
+            }
+            self.ancestors.pop();
+            return Ok(());
+        }
+        let relative = path.strip_prefix(self.base).unwrap_or(path);
+        self.entries
+            .push(read_entry(path, slash_path(relative)?, &metadata)?);
+        Ok(())
+    }
+}
+
+/// `path` with `/` between its components.
+fn slash_path(path: &Path) -> Result<String, CompressionError> {
+    let components: Option<Vec<&str>> = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect();
+    Ok(components
+        .ok_or(CompressionError::Io(std::io::ErrorKind::InvalidData))?
+        .join("/"))
+}
+
+/// Whether `text` matches the glob `pattern` as described on
+/// [`WalkOptions`].
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            let Some(rest) = rest.strip_prefix(b"/") else {
+                return rest.is_empty() || glob_match(&pattern[1..], text);
+            };
+            // `**/` stands for no directories or any number of them.
+            (0..=text.len())
+                .filter(|&i| i == 0 || text[i - 1] == b'/')
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'*', rest @ ..] => {
+            let component = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=component).any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => matches!(text, [c, tail @ ..] if *c != b'/' && glob_match(rest, tail)),
+        [c, rest @ ..] => matches!(text, [t, tail @ ..] if t == c && glob_match(rest, tail)),
+    }
+}
+
+/// The file at `path` as an entry named `path_name`.
+fn read_entry(
+    path: &Path,
+    path_name: String,
+    metadata: &std::fs::Metadata,
+) -> Result<Entry, CompressionError> {
+    Ok(Entry {
+        path: path_name,
+        mode: file_mode(metadata),
+        mtime: modified_secs(metadata)?,
+        data: std::fs::read(path)?,
+    })
+}
+
+/// Modification time in seconds since the Unix epoch, 0 for earlier times.
+fn modified_secs(metadata: &std::fs::Metadata) -> Result<u64, CompressionError> {
+    Ok(metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs()))
+}
+
+/// Writes `entries` below `dir`, creating directories as needed, replacing
+/// files already there and restoring the mode and modification time of
+/// every file. Each file is written with [`write_atomic`], so none is left
+/// half-written.
+pub fn write_entries(entries: &[Entry], dir: &Path) -> Result<(), CompressionError> {
+    write_entries_with(entries, dir, true, true)
+}
+
+/// Like [`write_entries`] but leaves the mode and modification time to the
+/// system unless `preserve` is set, and unless `overwrite` is set fails
+/// with an [`AlreadyExists`](std::io::ErrorKind::AlreadyExists) error
+/// before writing anything if any of the files exists.
+pub fn write_entries_with(
+    entries: &[Entry],
+    dir: &Path,
+    preserve: bool,
+    overwrite: bool,
+) -> Result<(), CompressionError> {
+    let mut dests = Vec::with_capacity(entries.len());
+    for entry in entries {
+        check_path(&entry.path)?;
+        let dest: PathBuf = dir.join(entry.path.split('/').collect::<PathBuf>());
+        if !overwrite && dest.symlink_metadata().is_ok() {
+            return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+        }
+        dests.push(dest);
+    }
+    for (entry, dest) in entries.iter().zip(dests) {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(&dest, &entry.data, true)?;
+        if preserve {
+            restore_metadata(&dest, entry.mode, entry.mtime);
+        }
+    }
+    Ok(())
+}
+
+/// A [`FILE_METADATA`] extension recording the mode and modification time
+/// of the file at `path`, so a file compressed and decompressed on its own
+/// keeps them like an archive entry does.
+pub fn metadata_extension(path: &Path) -> Result<Extension, CompressionError> {
+    let metadata = std::fs::metadata(path)?;
+    let mut value = file_mode(&metadata).to_le_bytes().to_vec();
+    value.extend(modified_secs(&metadata)?.to_le_bytes());
+    Ok(Extension::new(FILE_METADATA, value))
+}
+
+/// Gives the file at `path` the mode and modification time recorded by the
+/// first [`FILE_METADATA`] extension in `extensions`, as far as the file
+/// system allows. Does nothing if there is none or its value is not 12
+/// bytes long.
+pub fn restore_from_extensions(path: &Path, extensions: &[Extension]) {
+    let Some(extension) = extensions.iter().find(|e| e.kind == FILE_METADATA) else {
+        return;
+    };
+    let Ok(value) = <[u8; 12]>::try_from(&extension.value[..]) else {
+        return;
+    };
+    let mode = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+    let mut mtime = [0; 8];
+    mtime.copy_from_slice(&value[4..]);
+    restore_metadata(path, mode, u64::from_le_bytes(mtime));
+}
+
+/// Sets the modification time and then the mode of `path`. This is best
+/// effort: a file system that refuses either, such as one that does not
+/// know Unix permissions, leaves the file as written.
+fn restore_metadata(path: &Path, mode: u32, mtime: u64) {
+    let modified = UNIX_EPOCH + Duration::from_secs(mtime);
+    let _ = File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(modified));
+    let _ = set_file_mode(path, mode);
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: u32) -> Result<(), CompressionError> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(std::fs::set_permissions(
+        path,
+        std::fs::Permissions::from_mode(mode & 0o7777),
+    )?)
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(path: &Path, mode: u32) -> Result<(), CompressionError> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    Ok(std::fs::set_permissions(path, permissions)?)
+}
+
+/// Splits an archive into the metadata and compressed bytes of its
+/// entries, ignoring any bytes after the last one.
+fn parse(mut rest: &[u8]) -> Result<Vec<(EntryInfo, &[u8])>, CompressionError> {
+    let count = parse_header(&mut rest)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let path_len = u16::from_le_bytes(take(&mut rest)?) as usize;
+        let path = take_slice(&mut rest, path_len)?;
+        let path = String::from_utf8(path.to_vec()).map_err(|_| CompressionError::CorruptHeader)?;
+        check_path(&path)?;
+        let mode = u32::from_le_bytes(take(&mut rest)?);
+        let mtime = u64::from_le_bytes(take(&mut rest)?);
+        let size = u64::from_le_bytes(take(&mut rest)?);
+        let compressed_len = u64::from_le_bytes(take(&mut rest)?);
+        let len = usize::try_from(compressed_len).map_err(|_| CompressionError::CorruptHeader)?;
+        let compressed = take_slice(&mut rest, len)?;
+        entries.push((
+            EntryInfo {
+                path,
+                mode,
+                mtime,
+                size,
+                compressed_len,
+            },
+            compressed,
+        ));
+    }
+    Ok(entries)
+}
+
+/// Checks the magic and version at the start of `rest` and returns the
+/// entry count, leaving `rest` at the first entry.
+fn parse_header(rest: &mut &[u8]) -> Result<u32, CompressionError> {
+    if !rest.starts_with(&ARCHIVE_MAGIC[..rest.len().min(ARCHIVE_MAGIC.len())]) {
+        return Err(CompressionError::InvalidMagic);
+    }
+    *rest = rest.get(ARCHIVE_MAGIC.len()..).unwrap_or_default();
+    let [version] = take(rest)?;
+    if version > ARCHIVE_VERSION {
+        return Err(CompressionError::UnsupportedVersion {
+            found: version,
+            supported: ARCHIVE_VERSION,
+        });
+    }
+    Ok(u32::from_le_bytes(take(rest)?))
+}
+
+fn check_path(path: &str) -> Result<(), CompressionError> {
+    let unsafe_component = path
+        .split('/')
+        .any(|component| component.is_empty() || component == "..");
+    if unsafe_component || path.contains('\\') {
+        return Err(CompressionError::CorruptHeader);
+    }
+    Ok(())
+}
+
+fn take<const N: usize>(rest: &mut &[u8]) -> Result<[u8; N], CompressionError> {
+    Ok(take_slice(rest, N)?.try_into().unwrap())
+}
+
+fn take_slice<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8], CompressionError> {
+    if rest.len() < len {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::scratch;
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry {
+                path: "docs/readme.txt".to_string(),
+                mode: 0o644,
+                mtime: 1_700_000_000,
+                data: b"read me, read me, read me".to_vec(),
+            },
+            Entry {
+                path: "bin/run".to_string(),
+                mode: 0o755,
+                mtime: 1_700_000_100,
+                data: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trip_entries() {
+        let archive = encode_archive(&entries()).unwrap();
+        assert!(is_archive(&archive));
+        assert_eq!(decode_archive(&archive).unwrap(), entries());
+    }
+
+    #[test]
+    fn list_reports_metadata() {
+        let archive = encode_archive(&entries()).unwrap();
+        let listed = list_archive(&archive).unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].path, "docs/readme.txt");
+        assert_eq!(listed[0].size, 25);
+        assert_eq!(listed[1].mode, 0o755);
+    }
+
+    #[test]
+    fn unsafe_paths_are_rejected() {
+        for path in ["/etc/passwd", "../up", "a/../../b", "", "a\\b"] {
+            let entry = Entry {
+                path: path.to_string(),
+                mode: 0,
+                mtime: 0,
+                data: Vec::new(),
+            };
+            assert_eq!(
+                encode_archive(&[entry]),
+                Err(CompressionError::CorruptHeader)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_archive_is_rejected() {
+        let archive = encode_archive(&entries()).unwrap();
+        assert_eq!(
+            list_archive(&archive[..archive.len() - 1]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn appended_entries_follow_the_old_ones() {
+        let dir = scratch("archive-append");
+        let path = dir.join("backup.hfa");
+        let entries = entries();
+        let (first, second) = entries.split_at(1);
+        std::fs::write(&path, encode_archive(first).unwrap()).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let mut file = File::options().read(true).write(true).open(&path).unwrap();
+        append_archive(&mut file, second).unwrap();
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(after[9..before.len()], before[9..]);
+        assert_eq!(after, encode_archive(&entries).unwrap());
+
+        // A torn append past the last counted entry is ignored, then dropped.
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"half an entry").unwrap();
+        assert_eq!(
+            decode_archive(&std::fs::read(&path).unwrap()).unwrap(),
+            entries
+        );
+        append_archive(&mut file, &[]).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), after);
+
+        std::fs::write(&path, &after[..after.len() - 1]).unwrap();
+        let mut file = File::options().read(true).write(true).open(&path).unwrap();
+        assert_eq!(
+            append_archive(&mut file, second),
+            Err(CompressionError::TruncatedInput)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn directory_round_trips_through_the_file_system() {
+        let root = scratch("archive-tree");
+        let source = root.join("src/tree");
+        std::fs::create_dir_all(source.join("nested")).unwrap();
+        std::fs::write(source.join("a.txt"), b"alpha alpha alpha").unwrap();
+        std::fs::write(source.join("nested/b.bin"), [1, 2, 3]).unwrap();
+
+        let entries = read_entries(&[source.as_path()]).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["tree/a.txt", "tree/nested/b.bin"]);
+
+        let archive = encode_archive(&entries).unwrap();
+        let dest = root.join("out");
+        write_entries(&decode_archive(&archive).unwrap(), &dest).unwrap();
+        assert_eq!(
+            std::fs::read(dest.join("tree/nested/b.bin")).unwrap(),
+            [1, 2, 3]
+        );
+        let restored = read_entries(&[dest.join("tree").as_path()]).unwrap();
+        assert_eq!(restored, entries);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn globs_match_names_and_paths() {
+        let matches = |pattern: &str, text: &str| glob_match(pattern.as_bytes(), text.as_bytes());
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", "ab.txt"));
+        assert!(matches("**/*.o", "a.o"));
+        assert!(matches("**/*.o", "build/deep/a.o"));
+        assert!(matches("target/**", "target/debug/app"));
+        assert!(!matches("target/**", "src/target"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+    }
+
+    #[test]
+    fn walk_applies_patterns_and_skips_links() {
+        let root = scratch("archive-walk");
+        let source = root.join("proj");
+        std::fs::create_dir_all(source.join("src")).unwrap();
+        std::fs::create_dir_all(source.join("target/debug")).unwrap();
+        std::fs::write(source.join("src/main.rs"), b"fn main() {}").unwrap();
+        std::fs::write(source.join("src/notes.txt"), b"notes").unwrap();
+        std::fs::write(source.join("target/debug/app.rs"), b"built").unwrap();
+        std::fs::write(source.join("Cargo.toml"), b"[package]").unwrap();
+        let paths = |walk: &WalkOptions| -> Vec<String> {
+            read_entries_with(&[source.as_path()], walk)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect()
+        };
+        let walk = WalkOptions::new()
+            .with_include("*.rs")
+            .with_include("Cargo.toml")
+            .with_exclude("target");
+        assert_eq!(paths(&walk), ["proj/Cargo.toml", "proj/src/main.rs"]);
+        let walk = WalkOptions::new().with_exclude("src/*.txt");
+        assert_eq!(
+            paths(&walk),
+            [
+                "proj/Cargo.toml",
+                "proj/src/main.rs",
+                "proj/target/debug/app.rs"
+            ]
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(source.join("src"), source.join("link")).unwrap();
+            std::os::unix::fs::symlink(&source, source.join("src/loop")).unwrap();
+            let walk = WalkOptions::new().with_include("*.rs");
+            assert_eq!(
+                paths(&walk),
+                ["proj/src/main.rs", "proj/target/debug/app.rs"]
+            );
+            let walk = walk.with_follow_symlinks(true);
+            assert_eq!(
+                paths(&walk),
+                [
+                    "proj/link/main.rs",
+                    "proj/src/main.rs",
+                    "proj/target/debug/app.rs"
+                ]
+            );
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn metadata_is_restored_unless_left_out() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = scratch("archive-metadata");
+        let entries = entries();
+        write_entries(&entries, &root).unwrap();
+        let run = std::fs::metadata(root.join("bin/run")).unwrap();
+        assert_eq!(run.permissions().mode() & 0o777, 0o755);
+        assert_eq!(modified_secs(&run).unwrap(), 1_700_000_100);
+
+        let plain = root.join("plain");
+        write_entries_with(&entries, &plain, false, false).unwrap();
+        assert_eq!(
+            write_entries_with(&entries, &plain, false, false),
+            Err(CompressionError::Io(std::io::ErrorKind::AlreadyExists))
+        );
+        let run = std::fs::metadata(plain.join("bin/run")).unwrap();
+        assert_ne!(modified_secs(&run).unwrap(), 1_700_000_100);
+
+        let stream = crate::extension::add_extensions(
+            &compress(b"#!/bin/sh\n"),
+            &[metadata_extension(&root.join("bin/run")).unwrap()],
+        )
+        .unwrap();
+        let copy = root.join("copy");
+        std::fs::write(&copy, decompress(&stream).unwrap()).unwrap();
+        restore_from_extensions(&copy, &crate::extension::extensions(&stream).unwrap());
+        let copied = std::fs::metadata(&copy).unwrap();
+        assert_eq!(copied.permissions().mode() & 0o777, 0o755);
+        assert_eq!(modified_secs(&copied).unwrap(), 1_700_000_100);
+
+        let bare = root.join("bare");
+        std::fs::write(&bare, b"").unwrap();
+        restore_from_extensions(&bare, &[Extension::new(FILE_METADATA, [0; 11])]);
+        assert_ne!(
+            modified_secs(&std::fs::metadata(&bare).unwrap()).unwrap(),
+            0
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,262 @@
+use alloc::vec::Vec;
+
+use crate::bits::{BitReader, BitWriter};
+use crate::error::CompressionError;
+
+const HALF: u64 = 1 << 31;
+const QUARTER: u64 = 1 << 30;
+const TOP: u64 = (1 << 32) - 1;
+/// The model is halved once its total reaches this, which keeps
+/// `range * total` within a `u64` and lets old statistics fade.
+const MAX_TOTAL: u32 = 1 << 16;
+
+/// Adaptive arithmetic coder fed one symbol at a time. Every byte value
+/// starts with a count of one and the count of each coded symbol is bumped
+/// afterwards, so the decoder can follow the same model without a header.
+pub struct StreamingArithmeticEncoder {
+    model: [u32; 256],
+    low: u64,
+    high: u64,
+    /// Opposite bits owed after the next emitted bit, from intervals that
+    /// straddled the midpoint.
+    pending: u32,
+    out: BitWriter,
+}
+
+impl Default for StreamingArithmeticEncoder {
+    fn default() -> StreamingArithmeticEncoder {
+        StreamingArithmeticEncoder {
+            model: [1; 256],
+            low: 0,
+            high: TOP,
+            pending: 0,
+            out: BitWriter::new(),
+        }
+    }
+}
+
+impl StreamingArithmeticEncoder {
+    pub fn new() -> StreamingArithmeticEncoder {
+        StreamingArithmeticEncoder::default()
+    }
+
+    /// Narrows the interval to `symbol`, emits the bits that are settled
+    /// and then updates the model.
+    pub fn update(&mut self, symbol: u8) {
+        let (cum_low, cum_high, total) = interval(&self.model, symbol);
+        let range = self.high - self.low + 1;
+        self.high = self.low + range * cum_high / total - 1;
+        self.low += range * cum_low / total;
+
+        loop {
+            if self.high < HALF {
+                self.emit(false);
+            } else if self.low >= HALF {
+                self.emit(true);
+                self.low -= HALF;
+                self.high -= HALF;
+            } else if self.low >= QUARTER && self.high < 3 * QUARTER {
+                self.pending += 1;
+                self.low -= QUARTER;
+                self.high -= QUARTER;
+            } else {
+                break;
+            }
+            self.low *= 2;
+            self.high = self.high * 2 + 1;
+        }
+        adapt(&mut self.model, symbol);
+    }
+
+    /// Emits enough bits to pin a value inside the final interval and
+    /// returns the output, padded with zero bits to a whole byte.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.pending += 1;
+        self.emit(self.low >= QUARTER);
+        self.out.into_bytes()
+    }
+
+    fn emit(&mut self, bit: bool) {
+        self.out.push_bit(bit);
+        for _ in 0..self.pending {
+            self.out.push_bit(!bit);
+        }
+        self.pending = 0;
+    }
+}
+
+/// Decodes `len` symbols written by `StreamingArithmeticEncoder`. The
+/// stream carries no length, so it has to be known up front.
+///
+/// Fails with [`TruncatedInput`](CompressionError::TruncatedInput) once
+/// decoding needs more than a value's worth of bits past the end of `data`,
+/// so a wrong `len` cannot decode without end, and with
+/// [`CorruptData`](CompressionError::CorruptData) if the value leaves the
+/// interval.
+pub fn decode_arithmetic(data: &[u8], len: usize) -> Result<Vec<u8>, CompressionError> {
+    let mut bits = Bits {
+        input: BitReader::new(data),
+        past_end: 0,
+    };
+    let mut model = [1u32; 256];
+    let mut low = 0u64;
+    let mut high = TOP;
+    let mut value = 0u64;
+    for _ in 0..32 {
+        value = value * 2 + bits.next();
+    }
+
+    // Capped so a wrong length cannot reserve more than the input could
+    // plausibly hold.
+    let mut out = Vec::with_capacity(len.min(data.len().saturating_mul(8)));
+    for _ in 0..len {
+        // The encoder's final bits pin the value with at most 32 bits to
+        // spare, which the decoder reads ahead.
+        if bits.past_end > 32 {
+            return Err(CompressionError::TruncatedInput);
+        }
+        let total = model.iter().map(|&c| c as u64).sum::<u64>();
+        let range = high - low + 1;
+        let offset = value
+            .checked_sub(low)
+            .filter(|_| value <= high)
+            .ok_or(CompressionError::CorruptData)?;
+        let scaled = ((offset + 1) * total - 1) / range;
+        let mut cum = 0u64;
+        let symbol = model
+            .iter()
+            .position(|&c| {
+                cum += c as u64;
+                scaled < cum
+            })
+            .ok_or(CompressionError::CorruptData)? as u8;
+        let (cum_low, cum_high, total) = interval(&model, symbol);
+        high = low + range * cum_high / total - 1;
+        low += range * cum_low / total;
+
+        loop {
+            if high < HALF {
+                // Lower half: nothing to subtract before shifting.
+            } else if low >= HALF {
+                value -= HALF;
+                low -= HALF;
+                high -= HALF;
+            } else if low >= QUARTER && high < 3 * QUARTER {
+                value -= QUARTER;
+                low -= QUARTER;
+                high -= QUARTER;
+            } else {
+                break;
+            }
+            low *= 2;
+            high = high * 2 + 1;
+            value = value * 2 + bits.next();
+        }
+        adapt(&mut model, symbol);
+        out.push(symbol);
+    }
+    Ok(out)
+}
+
+/// The input bits, then zeros past its end, counted in `past_end`.
+struct Bits<'a> {
+    input: BitReader<'a>,
+    past_end: u64,
+}
+
+impl Bits<'_> {
+    fn next(&mut self) -> u64 {
+        self.input.next().unwrap_or_else(|| {
+            self.past_end += 1;
+            false
+        }) as u64
+    }
+}
+
+/// Cumulative counts below and up to `symbol`, and the model total.
+fn interval(model: &[u32; 256], symbol: u8) -> (u64, u64, u64) {
+    let cum_low: u64 = model[..symbol as usize].iter().map(|&c| c as u64).sum();
+    let cum_high = cum_low + model[symbol as usize] as u64;
+    let total = cum_high
+        + model[symbol as usize + 1..]
+            .iter()
+            .map(|&c| c as u64)
+            .sum::<u64>();
+    (cum_low, cum_high, total)
+}
+
+fn adapt(model: &mut [u32; 256], symbol: u8) {
+    model[symbol as usize] += 1;
+    if model.iter().sum::<u32>() >= MAX_TOTAL {
+        for count in model.iter_mut() {
+            *count = (*count).div_ceil(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_all(data: &[u8]) -> Vec<u8> {
+        let mut encoder = StreamingArithmeticEncoder::new();
+        for &b in data {
+            encoder.update(b);
+        }
+        encoder.finish()
+    }
+
+    #[test]
+    fn round_trip_text() {
+        let data = b"abracadabra, abracadabra! the quick brown fox jumps over the lazy dog";
+        assert_eq!(
+            decode_arithmetic(&encode_all(data), data.len()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn round_trip_all_byte_values() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        assert_eq!(
+            decode_arithmetic(&encode_all(&data), data.len()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn skewed_data_adapts_below_one_bit_per_symbol() {
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| if i % 50 == 0 { b'b' } else { b'a' })
+            .collect();
+        let encoded = encode_all(&data);
+        assert!(encoded.len() * 8 < data.len() / 2);
+        assert_eq!(decode_arithmetic(&encoded, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(decode_arithmetic(&encode_all(b""), 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn wrong_lengths_and_truncation_fail() {
+        let data = b"an arithmetic stream that is cut short or overstated".repeat(20);
+        let encoded = encode_all(&data);
+        assert_eq!(
+            decode_arithmetic(&encoded, usize::MAX),
+            Err(CompressionError::TruncatedInput)
+        );
+        assert_eq!(
+            decode_arithmetic(&encoded[..encoded.len() / 2], data.len()),
+            Err(CompressionError::TruncatedInput)
+        );
+        // Flipped bits decode to something else or fail, but never panic.
+        for i in (0..encoded.len()).step_by(7) {
+            let mut corrupt = encoded.clone();
+            corrupt[i] ^= 0x55;
+            let decoded = decode_arithmetic(&corrupt, data.len());
+            assert!(i > 0 || decoded != Ok(data.clone()));
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::error::CompressionError;
+
+/// Tells temporary files of one process apart.
+static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+
+/// Writes `data` to `path` so that the file at `path` is either left as it
+/// was or holds all of `data`, never part of it. The data goes to a
+/// temporary file in the same directory, which is renamed over `path` once
+/// it is complete and removed if anything fails first.
+///
+/// Fails with an [`Io`](CompressionError::Io) error of kind
+/// [`AlreadyExists`](std::io::ErrorKind::AlreadyExists) if `path` exists,
+/// unless `overwrite` is set. The temporary file is then hard-linked to
+/// `path` instead of renamed, which fails without touching a file created
+/// at `path` while the data was being written.
+///
+/// On a file system without hard links, such as FAT or some network and
+/// FUSE mounts, an empty file is created at `path` first, failing if one
+/// exists, and the temporary file is renamed over it. A file created at
+/// `path` while the data was being written is still kept, but a reader may
+/// see the empty file until the rename, and a crash in between leaves it.
+pub fn write_atomic(path: &Path, data: &[u8], overwrite: bool) -> Result<(), CompressionError> {
+    if !overwrite && path.symlink_metadata().is_ok() {
+        return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+    }
+    let temp = TempFile::create(path)?;
+    (&temp.file).write_all(data)?;
+    temp.file.sync_all()?;
+    temp.persist(path, overwrite)
+}
+
+/// Removes the temporary file of a [`write_atomic`] in progress when the
+/// process is interrupted or terminated, before exiting. Without this a
+/// Ctrl-C leaves the temporary file behind, though never a partial file
+/// under the final name. Only has an effect on Unix.
+pub fn remove_partial_output_on_interrupt() {
+    #[cfg(unix)]
+    unix::install();
+}
+
+/// A file that is removed when dropped unless it is persisted.
+struct TempFile {
+    file: File,
+    path: PathBuf,
+    persisted: bool,
+    #[cfg(unix)]
+    _registered: unix::Registration,
+}
+
+impl TempFile {
+    /// Creates a hidden file next to `dest`.
+    fn create(dest: &Path) -> Result<TempFile, CompressionError> {
+        let name = dest
+            .file_name()
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        let path = dest.with_file_name(format!(
+            ".{}.{}-{}.tmp",
+            name.to_string_lossy(),
+            std::process::id(),
+            NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        Ok(TempFile {
+            file,
+            #[cfg(unix)]
+            _registered: unix::Registration::new(&path),
+            path,
+            persisted: false,
+        })
+    }
+
+    /// Renames the file to `dest`, replacing any file there, or with
+    /// `overwrite` unset links it to `dest` and removes it on drop. Where
+    /// the link is refused, as on file systems without hard links, it
+    /// [claims](TempFile::persist_new) `dest` instead.
+    fn persist(mut self, dest: &Path, overwrite: bool) -> Result<(), CompressionError> {
+        if !overwrite {
+            return match std::fs::hard_link(&self.path, dest) {
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::Unsupported | std::io::ErrorKind::PermissionDenied
+                    ) =>
+                {
+                    self.persist_new(dest)
+                }
+                result => Ok(result?),
+            };
+        }
+        std::fs::rename(&self.path, dest)?;
+        self.persisted = true;
+        Ok(())
+    }
+
+    /// Creates an empty file at `dest`, failing if one exists, and renames
+    /// the file over it. Unlike the link this is not atomic: the empty file
+    /// is visible until the rename, and is removed if the rename fails.
+    fn persist_new(mut self, dest: &Path) -> Result<(), CompressionError> {
+        File::options().write(true).create_new(true).open(dest)?;
+        if let Err(e) = std::fs::rename(&self.path, dest) {
+            let _ = std::fs::remove_file(dest);
+            return Err(e.into());
+        }
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::{CString, c_char, c_int};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn unlink(path: *const c_char) -> c_int;
+        fn _exit(status: c_int) -> !;
+    }
+
+    /// Path of the temporary file being written, or null. Only one is
+    /// tracked: the CLI writes one output at a time.
+    static PENDING: AtomicPtr<c_char> = AtomicPtr::new(ptr::null_mut());
+
+    extern "C" fn on_signal(signum: c_int) {
+        let path = PENDING.swap(ptr::null_mut(), Ordering::SeqCst);
+        // SAFETY: `unlink` and `_exit` are async-signal-safe, and `path` is
+        // null or the string of a live `Registration`.
+        unsafe {
+            if !path.is_null() {
+                unlink(path);
+            }
+            _exit(128 + signum);
+        }
+    }
+
+    pub(super) fn install() {
+        // SAFETY: `on_signal` only calls async-signal-safe functions.
+        unsafe {
+            signal(SIGINT, on_signal);
+            signal(SIGTERM, on_signal);
+        }
+    }
+
+    /// Makes a path the one `on_signal` removes, until dropped.
+    pub(super) struct Registration {
+        path: Option<CString>,
+    }
+
+    impl Registration {
+        pub(super) fn new(path: &Path) -> Registration {
+            let path = CString::new(path.as_os_str().as_bytes()).ok();
+            if let Some(path) = &path {
+                PENDING.store(path.as_ptr().cast_mut(), Ordering::SeqCst);
+            }
+            Registration { path }
+        }
+    }
+
+    impl Drop for Registration {
+        /// Stops tracking the path unless another one has replaced it.
+        fn drop(&mut self) {
+            if let Some(path) = &self.path {
+                let _ = PENDING.compare_exchange(
+                    path.as_ptr().cast_mut(),
+                    ptr::null_mut(),
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::scratch;
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn existing_files_are_kept_unless_overwritten() {
+        let dir = scratch("atomic-overwrite");
+        let path = dir.join("out.hfz");
+        write_atomic(&path, b"first", false).unwrap();
+        assert_eq!(
+            write_atomic(&path, b"second", false),
+            Err(CompressionError::Io(std::io::ErrorKind::AlreadyExists))
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        write_atomic(&path, b"second", true).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        assert_eq!(names(&dir), ["out.hfz"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_created_during_the_write_are_kept() {
+        let dir = scratch("atomic-race");
+        let path = dir.join("out.hfz");
+        let temp = TempFile::create(&path).unwrap();
+        (&temp.file).write_all(b"late").unwrap();
+        // Another writer gets there between the check and the rename.
+        std::fs::write(&path, b"first").unwrap();
+        assert_eq!(
+            temp.persist(&path, false),
+            Err(CompressionError::Io(std::io::ErrorKind::AlreadyExists))
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        assert_eq!(names(&dir), ["out.hfz"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn claiming_the_name_keeps_existing_files() {
+        let dir = scratch("atomic-claim");
+        let path = dir.join("out.hfz");
+        let temp = TempFile::create(&path).unwrap();
+        (&temp.file).write_all(b"data").unwrap();
+        temp.persist_new(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+
+        let temp = TempFile::create(&path).unwrap();
+        (&temp.file).write_all(b"late").unwrap();
+        assert_eq!(
+            temp.persist_new(&path),
+            Err(CompressionError::Io(std::io::ErrorKind::AlreadyExists))
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+        assert_eq!(names(&dir), ["out.hfz"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_leave_no_temporary_file() {
+        let dir = scratch("atomic-failed");
+        // A directory cannot be replaced by a file, so the rename fails
+        // after the data is written.
+        std::fs::create_dir(dir.join("taken")).unwrap();
+        assert!(write_atomic(&dir.join("taken"), b"data", true).is_err());
+        assert_eq!(names(&dir), ["taken"]);
+        assert!(write_atomic(&dir.join("missing/out"), b"data", true).is_err());
+        assert_eq!(names(&dir), ["taken"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant};
+
+use crate::options::{Algorithm, CompressionOptions, DEFAULT_LEVEL, MAX_LEVEL};
+use crate::{compress_with, decompress};
+
+/// How one set of options did on some data in [`benchmark`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub options: CompressionOptions,
+    pub original_len: usize,
+    pub compressed_len: usize,
+    pub compress_time: Duration,
+    pub decompress_time: Duration,
+}
+
+impl BenchResult {
+    /// Compressed size divided by the original size, 0 for empty data.
+    pub fn ratio(&self) -> f64 {
+        if self.original_len == 0 {
+            return 0.0;
+        }
+        self.compressed_len as f64 / self.original_len as f64
+    }
+
+    /// Original megabytes (10^6 bytes) compressed per second.
+    pub fn compress_speed(&self) -> f64 {
+        megabytes_per_second(self.original_len, self.compress_time)
+    }
+
+    /// Original megabytes (10^6 bytes) restored per second.
+    pub fn decompress_speed(&self) -> f64 {
+        megabytes_per_second(self.original_len, self.decompress_time)
+    }
+}
+
+fn megabytes_per_second(len: usize, time: Duration) -> f64 {
+    let secs = time.as_secs_f64();
+    if secs == 0.0 {
+        return 0.0;
+    }
+    len as f64 / 1e6 / secs
+}
+
+/// Compresses and decompresses `data` once with each of `options`, timing
+/// both, so settings can be picked by trying them on the data at hand.
+///
+/// Panics if a stream does not decode back to `data`, which would be a bug
+/// in the encoder.
+pub fn benchmark(data: &[u8], options: &[CompressionOptions]) -> Vec<BenchResult> {
+    options
+        .iter()
+        .map(|options| {
+            let start = Instant::now();
+            let compressed = compress_with(data, options);
+            let compress_time = start.elapsed();
+            let start = Instant::now();
+            let decoded = decompress(&compressed);
+            let decompress_time = start.elapsed();
+            assert!(
+                decoded.as_deref() == Ok(data),
+                "{:?} does not round-trip",
+                options
+            );
+            BenchResult {
+                options: *options,
+                original_len: data.len(),
+                compressed_len: compressed.len(),
+                compress_time,
+                decompress_time,
+            }
+        })
+        .collect()
+}
+
+/// The settings `compare` tries: every algorithm at the default level, and
+/// Huffman and LZ coding, which the level changes most, also at the
+/// lowest and the highest.
+pub fn sweep() -> Vec<CompressionOptions> {
+    let mut sweep = Vec::new();
+    for algorithm in Algorithm::ALL {
+        let levels: &[u8] = match algorithm {
+            Algorithm::Huffman | Algorithm::LzHuffman => &[1, DEFAULT_LEVEL, MAX_LEVEL],
+            _ => &[DEFAULT_LEVEL],
+        };
+        sweep.extend(levels.iter().map(|&level| CompressionOptions {
+            algorithm,
+            level,
+            ..Default::default()
+        }));
+    }
+    sweep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_setting_is_measured_in_order() {
+        let data = b"benchmark the settings on text like this. ".repeat(200);
+        let sweep = sweep();
+        let results = benchmark(&data, &sweep);
+        assert_eq!(results.len(), sweep.len());
+        for (result, options) in results.iter().zip(&sweep) {
+            assert_eq!(&result.options, options);
+            assert_eq!(result.compressed_len, compress_with(&data, options).len());
+            assert!(result.ratio() > 0.0);
+        }
+        let lz = |level| {
+            results
+                .iter()
+                .find(|r| r.options.algorithm == Algorithm::LzHuffman && r.options.level == level)
+                .unwrap()
+        };
+        assert!(lz(MAX_LEVEL).ratio() < 0.2);
+        assert!(lz(MAX_LEVEL).ratio() <= lz(1).ratio());
+        assert_eq!(benchmark(b"", &sweep[..1])[0].ratio(), 0.0);
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+
+/// Packs bits into bytes, most significant bit first. The last byte is
+/// padded with zero bits.
+///
+/// Bits gather in a 64-bit accumulator that goes to the bytes eight at a
+/// time, so pushing a code takes a few shifts instead of a step per bit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits not yet in `bytes`, from the most significant end.
+    acc: u64,
+    /// Number of bits in `acc`, less than 64.
+    pending: u32,
+    len: u64,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter::default()
+    }
+
+    /// A writer with room for `bytes` bytes of output.
+    pub fn with_capacity(bytes: usize) -> BitWriter {
+        BitWriter {
+            bytes: Vec::with_capacity(bytes),
+            ..BitWriter::default()
+        }
+    }
+
+    pub fn push_bit(&mut self, bit: bool) {
+        self.push_value(bit as u64, 1);
+    }
+
+    pub fn push_bits(&mut self, bits: &[bool]) {
+        for chunk in bits.chunks(64) {
+            let value = chunk.iter().fold(0, |acc, &bit| acc << 1 | bit as u64);
+            self.push_value(value, chunk.len() as u32);
+        }
+    }
+
+    /// Pushes the lowest `count` bits of `value`, most significant first.
+    /// Panics if `count` is more than 64.
+    pub fn push_value(&mut self, value: u64, count: u32) {
+        assert!(count <= 64, "at most 64 bits are pushed at once");
+        if count == 0 {
+            return;
+        }
+        let value = value & (u64::MAX >> (64 - count));
+        let free = 64 - self.pending;
+        if count < free {
+            self.acc |= value << (free - count);
+            self.pending += count;
+        } else {
+            // Fill the accumulator, flush it and keep what did not fit.
+            let spill = count - free;
+            self.acc |= value >> spill;
+            self.bytes.extend(self.acc.to_be_bytes());
+            self.acc = value.checked_shl(64 - spill).unwrap_or(0);
+            self.pending = spill;
+        }
+        self.len += count as u64;
+    }
+
+    /// Pads with zero bits up to the next byte boundary.
+    pub fn align_to_byte(&mut self) {
+        self.push_value(0, self.padding() as u32);
+    }
+
+    /// Number of bits pushed, not counting padding.
+    pub fn bit_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Number of zero bits padding the last byte.
+    pub fn padding(&self) -> u8 {
+        ((8 - self.len % 8) % 8) as u8
+    }
+
+    /// The bytes pushed so far, the last padded with zero bits, leaving the
+    /// writer as it is.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.clone().into_bytes()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = self.bytes;
+        let tail = self.pending.div_ceil(8) as usize;
+        bytes.extend(&self.acc.to_be_bytes()[..tail]);
+        bytes
+    }
+}
+
+/// Reads bits from bytes, most significant bit first, up to an end that
+/// may leave out the padding of the last byte. Bit positions are `u64`, as
+/// a payload of more than 512 MiB has more bits than a 32-bit `usize`
+/// counts.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: u64,
+    end: u64,
+}
+
+impl<'a> BitReader<'a> {
+    /// Reads every bit of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader::with_len(bytes, bit_count(bytes))
+    }
+
+    /// Reads the first `len` bits of `bytes`. Panics if `bytes` holds fewer.
+    pub fn with_len(bytes: &'a [u8], len: u64) -> BitReader<'a> {
+        assert!(len <= bit_count(bytes), "bit length exceeds the bytes");
+        BitReader {
+            bytes,
+            pos: 0,
+            end: len,
+        }
+    }
+
+    /// Reads `bytes` except the `padding` bits at the end of the last one.
+    /// Fails if the padding is not less than a byte, or with no bytes.
+    pub fn with_padding(bytes: &'a [u8], padding: u8) -> Result<BitReader<'a>, CompressionError> {
+        if padding >= 8 || (padding > 0 && bytes.is_empty()) {
+            return Err(CompressionError::InvalidPadding);
+        }
+        Ok(BitReader::with_len(
+            bytes,
+            bit_count(bytes) - padding as u64,
+        ))
+    }
+
+    pub fn read_bit(&mut self) -> Option<bool> {
+        if self.pos == self.end {
+            return None;
+        }
+        let bit = (self.bytes[(self.pos / 8) as usize] >> (7 - self.pos % 8)) & 1 == 1;
+        self.pos += 1;
+        Some(bit)
+    }
+
+    /// Reads `count` bits, at most 64, as a number, most significant first.
+    /// `None` if fewer are left, in which case nothing is consumed.
+    pub fn read_bits(&mut self, count: u32) -> Option<u64> {
+        if count as u64 > self.remaining() {
+            return None;
+        }
+        let mut value = 0u64;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Some(value)
+    }
+
+    /// Skips to the next byte boundary, or to the end if that comes first.
+    pub fn align_to_byte(&mut self) {
+        self.pos = self.pos.next_multiple_of(8).min(self.end);
+    }
+
+    /// Number of bits read so far.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Continues reading from bit `pos`. Panics if it is past the end.
+    pub fn set_position(&mut self, pos: u64) {
+        assert!(pos <= self.end, "position past the end of the bits");
+        self.pos = pos;
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.end - self.pos
+    }
+}
+
+/// Number of bits in `bytes`, padding included.
+pub fn bit_count(bytes: &[u8]) -> u64 {
+    bytes.len() as u64 * 8
+}
+
+impl Iterator for BitReader<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        self.read_bit()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.remaining()) {
+            Ok(remaining) => (remaining, Some(remaining)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip_across_bytes() {
+        let mut writer = BitWriter::new();
+        writer.push_value(0b101, 3);
+        writer.push_value(0x1ff, 9);
+        writer.push_bit(true);
+        assert_eq!(writer.bit_len(), 13);
+        assert_eq!(writer.padding(), 3);
+        assert_eq!(writer.bytes(), [0b1011_1111, 0b1111_1000]);
+
+        let bytes = writer.into_bytes();
+        let mut reader = BitReader::with_padding(&bytes, 3).unwrap();
+        assert_eq!(reader.read_bits(3), Some(0b101));
+        assert_eq!(reader.read_bits(9), Some(0x1ff));
+        assert_eq!(reader.read_bits(2), None);
+        assert_eq!(reader.read_bit(), Some(true));
+        assert_eq!(reader.read_bit(), None);
+    }
+
+    #[test]
+    fn codes_straddle_the_accumulator() {
+        let mut writer = BitWriter::new();
+        let mut expected = Vec::new();
+        for (i, count) in [5, 64, 1, 60, 63, 7, 0, 64, 3].into_iter().enumerate() {
+            let value = 0x9e37_79b9_7f4a_7c15u64.rotate_left(i as u32 * 11);
+            writer.push_value(value, count);
+            expected.extend((0..count).rev().map(|bit| (value >> bit) & 1 == 1));
+        }
+        assert_eq!(writer.bit_len(), expected.len() as u64);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes.len(), expected.len().div_ceil(8));
+        assert!(BitReader::new(&bytes).zip(&expected).all(|(a, &b)| a == b));
+    }
+
+    #[test]
+    fn align_to_byte_pads_and_skips() {
+        let mut writer = BitWriter::new();
+        writer.push_bit(true);
+        writer.align_to_byte();
+        writer.push_bits(&[true, true]);
+        assert_eq!(writer.clone().into_bytes(), [0x80, 0xc0]);
+        assert_eq!(writer.padding(), 6);
+
+        let bytes = writer.into_bytes();
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read_bit(), Some(true));
+        reader.align_to_byte();
+        assert_eq!(reader.position(), 8);
+        assert_eq!(reader.collect::<Vec<_>>()[..2], [true, true]);
+    }
+
+    #[test]
+    fn padding_must_fit_in_the_last_byte() {
+        assert_eq!(
+            BitReader::with_padding(&[0], 8).err(),
+            Some(CompressionError::InvalidPadding)
+        );
+        assert_eq!(
+            BitReader::with_padding(&[], 1).err(),
+            Some(CompressionError::InvalidPadding)
+        );
+        assert_eq!(BitReader::with_padding(&[], 0).unwrap().remaining(), 0);
+    }
+}
@@ -0,0 +1,116 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use crate::error::CompressionError;
+
+/// A sequence of bits, most significant bit first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitVec {
+    bits: Vec<bool>,
+}
+
+impl BitVec {
+    pub fn new() -> BitVec {
+        BitVec::default()
+    }
+
+    /// Reads each hex digit as four bits, most significant first, so `"a3"`
+    /// becomes `1010 0011`.
+    pub fn from_hex(s: &str) -> Result<BitVec, CompressionError> {
+        let mut bits = Vec::with_capacity(s.len() * 4);
+        for c in s.chars() {
+            let nibble = c
+                .to_digit(16)
+                .ok_or(CompressionError::InvalidHexDigit { digit: c })?;
+            bits.extend((0..4).rev().map(|i| (nibble >> i) & 1 == 1));
+        }
+        Ok(BitVec { bits })
+    }
+
+    /// Inverse of `from_hex`. A trailing partial nibble is padded with zero
+    /// bits.
+    pub fn to_hex(&self) -> String {
+        self.bits
+            .chunks(4)
+            .map(|chunk| {
+                let nibble = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (i, &bit)| acc | ((bit as u32) << (3 - i)));
+                char::from_digit(nibble, 16).unwrap()
+            })
+            .collect()
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        self.bits.push(bit);
+    }
+
+    /// Packs the bits into bytes, most significant bit first, padding the
+    /// last byte with zero bits.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bits
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |acc, (i, &bit)| acc | ((bit as u8) << (7 - i)))
+            })
+            .collect()
+    }
+}
+
+impl Deref for BitVec {
+    type Target = [bool];
+
+    fn deref(&self) -> &[bool] {
+        &self.bits
+    }
+}
+
+impl From<Vec<bool>> for BitVec {
+    fn from(bits: Vec<bool>) -> BitVec {
+        BitVec { bits }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_digits_are_msb_first() {
+        let bits = BitVec::from_hex("a3").unwrap();
+        assert_eq!(
+            &*bits,
+            &[true, false, true, false, false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for hex in ["", "0", "ff", "0123456789abcdef"] {
+            assert_eq!(BitVec::from_hex(hex).unwrap().to_hex(), hex);
+        }
+    }
+
+    #[test]
+    fn empty_string_gives_empty_bitvec() {
+        assert!(BitVec::from_hex("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn partial_nibble_is_zero_padded() {
+        assert_eq!(BitVec::from(vec![true, true]).to_hex(), "c");
+    }
+
+    #[test]
+    fn invalid_digit_is_rejected() {
+        assert_eq!(
+            BitVec::from_hex("0g"),
+            Err(CompressionError::InvalidHexDigit { digit: 'g' })
+        );
+    }
+}
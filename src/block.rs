@@ -0,0 +1,719 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use crate::checksum::{Crc32, crc32};
+use crate::error::CompressionError;
+use crate::source::{Source, read_tree_section};
+use crate::version::SupportedFeature;
+use crate::{CHECKSUM_LEN, Container, Encoded, container_prefix, encode_block_limited};
+
+/// Block size `compress` uses for inputs longer than one block.
+pub const DEFAULT_BLOCK_SIZE: usize = 256 * 1024;
+/// Largest block size, which keeps every block and payload length within a
+/// `u32` even with 255-bit codes.
+pub const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+/// Length of the block length, payload length and padding of a frame.
+const FRAME_HEADER_LEN: usize = 9;
+/// Length of a block index entry: the compressed and the uncompressed
+/// offset of a block.
+pub(crate) const INDEX_ENTRY_LEN: usize = 16;
+
+/// Compresses `data` in blocks of `block_size` bytes, each with a tree of
+/// its own, so the code follows statistics that change over the data.
+///
+/// The container fields carry the multi-block, checksum and block checksum
+/// flags and the length of all of `data`. They are followed by one frame
+/// per block and the CRC-32 of all of `data`. A frame is laid out as:
+///
+/// | bytes | field |
+/// |-------|-------|
+/// | 4 | block length, little-endian |
+/// | 4 | payload length `p` in bytes, little-endian |
+/// | 1 | number of padding bits at the end of the payload |
+/// | 4 | CRC-32 of the block, little-endian |
+/// | 2 + … | tree section, as in [`Encoded`] |
+/// | p | payload |
+///
+/// The block checksum lets a decoder name the block that is damaged and,
+/// with [`recover_blocks`], restore the others. Streams written before it
+/// existed lack the flag and the field.
+///
+/// Panics if `block_size` is 0 or exceeds [`MAX_BLOCK_SIZE`].
+pub fn compress_blocks(data: &[u8], block_size: usize) -> Vec<u8> {
+    check_block_size(block_size);
+    encode_blocks(data, block_size, 1, u8::MAX, &mut |_, _| {})
+}
+
+/// Compresses blocks on several scoped threads. The output is the same as
+/// that of [`compress_blocks`] with the same block size.
+pub struct ParallelEncoder {
+    threads: usize,
+    block_size: usize,
+    max_code_length: u8,
+}
+
+impl ParallelEncoder {
+    /// Panics if `threads` is 0.
+    pub fn new(threads: usize) -> ParallelEncoder {
+        assert!(threads > 0, "at least one thread is needed");
+        ParallelEncoder {
+            threads,
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_code_length: u8::MAX,
+        }
+    }
+
+    /// Keeps every code within `max_len` bits, as
+    /// [`HuffmanTree::from_frequencies_limited`](crate::HuffmanTree::from_frequencies_limited)
+    /// does. Panics if `max_len` is below
+    /// [`MIN_CODE_LENGTH_LIMIT`](crate::options::MIN_CODE_LENGTH_LIMIT).
+    pub fn with_max_code_length(mut self, max_len: u8) -> ParallelEncoder {
+        crate::options::check_code_length_limit(max_len);
+        self.max_code_length = max_len;
+        self
+    }
+
+    /// Panics if `block_size` is 0 or exceeds [`MAX_BLOCK_SIZE`].
+    pub fn with_block_size(mut self, block_size: usize) -> ParallelEncoder {
+        check_block_size(block_size);
+        self.block_size = block_size;
+        self
+    }
+
+    /// Gives each thread a contiguous run of blocks and joins the frames
+    /// in block order.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        self.encode_with_progress(data, |_, _| {})
+    }
+
+    /// Like `encode` but calls `on_progress` with the bytes of `data` coded
+    /// and the bytes of output so far each time the threads finish a
+    /// round of blocks.
+    pub fn encode_with_progress(
+        &self,
+        data: &[u8],
+        mut on_progress: impl FnMut(u64, u64),
+    ) -> Vec<u8> {
+        encode_blocks(
+            data,
+            self.block_size,
+            self.threads,
+            self.max_code_length,
+            &mut on_progress,
+        )
+    }
+}
+
+/// Blocks each thread codes between two progress reports.
+const BLOCKS_PER_ROUND: usize = 4;
+
+/// The stream of [`compress_blocks`] with codes of at most `max_len` bits,
+/// coded on `threads` threads in rounds of blocks, calling `progress` after
+/// each round.
+pub(crate) fn encode_blocks(
+    data: &[u8],
+    block_size: usize,
+    threads: usize,
+    max_len: u8,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Vec<u8> {
+    let mut out = multi_block_prefix(data.len() as u64);
+    let mut coded = 0;
+    for round in data.chunks(block_size.saturating_mul(threads * BLOCKS_PER_ROUND)) {
+        let mut blocks: Vec<&[u8]> = round.chunks(block_size).collect();
+        let frames = map_runs(&mut blocks, threads, |run| {
+            run.iter()
+                .map(|block| encode_frame_limited(block, max_len))
+                .collect::<Vec<_>>()
+        });
+        for frame in frames.into_iter().flatten() {
+            out.extend(frame);
+        }
+        coded += round.len() as u64;
+        progress(coded, out.len() as u64);
+    }
+    out.extend(crc32(data).to_le_bytes());
+    out
+}
+
+/// Applies `f` to contiguous runs of `items`, one run per thread on
+/// `threads` scoped threads, and returns the results in order. Without the
+/// `std` feature the runs are processed one after another.
+fn map_runs<T: Send, U: Send>(
+    items: &mut [T],
+    threads: usize,
+    f: impl Fn(&mut [T]) -> U + Sync,
+) -> Vec<U> {
+    let per_thread = items.len().div_ceil(threads).max(1);
+    #[cfg(feature = "std")]
+    {
+        let f = &f;
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = items
+                .chunks_mut(per_thread)
+                .map(|run| scope.spawn(move || f(run)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("block thread panicked"))
+                .collect()
+        })
+    }
+    #[cfg(not(feature = "std"))]
+    items.chunks_mut(per_thread).map(f).collect()
+}
+
+/// Compresses input that arrives in pieces of any size, such as the
+/// chunks of a network receive loop. Every block is coded once the input
+/// runs past it, so only the compressed frames and one block of input are
+/// held until [`finish`](IncrementalEncoder::finish).
+///
+/// Input that fits in one block is coded like [`compress`], longer input
+/// like [`compress_blocks`] with the same block size.
+#[cfg(feature = "std")]
+pub struct IncrementalEncoder {
+    block_size: usize,
+    pending: Vec<u8>,
+    frames: Vec<u8>,
+    len: u64,
+    crc: Crc32,
+}
+
+#[cfg(feature = "std")]
+impl Default for IncrementalEncoder {
+    fn default() -> IncrementalEncoder {
+        IncrementalEncoder::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl IncrementalEncoder {
+    pub fn new() -> IncrementalEncoder {
+        IncrementalEncoder {
+            block_size: DEFAULT_BLOCK_SIZE,
+            pending: Vec::new(),
+            frames: Vec::new(),
+            len: 0,
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Panics if `block_size` is 0 or exceeds [`MAX_BLOCK_SIZE`].
+    pub fn with_block_size(mut self, block_size: usize) -> IncrementalEncoder {
+        check_block_size(block_size);
+        self.block_size = block_size;
+        self
+    }
+
+    /// Returns the compressed stream of everything written.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.frames.is_empty() {
+            return crate::compress(&self.pending);
+        }
+        self.frames.extend(encode_frame(&self.pending));
+        let mut out = multi_block_prefix(self.len);
+        out.extend(self.frames);
+        out.extend(self.crc.finish().to_le_bytes());
+        out
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for IncrementalEncoder {
+    /// Takes all of `buf` and codes the blocks it completes. A block is
+    /// only coded once more input follows it, since the last block decides
+    /// whether the stream has blocks at all.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.crc.update(buf);
+        self.len += buf.len() as u64;
+        self.pending.extend_from_slice(buf);
+        if self.pending.len() > self.block_size {
+            let mut blocks = self.pending.chunks_exact(self.block_size);
+            let full = blocks.len() - usize::from(blocks.remainder().is_empty());
+            for block in blocks.by_ref().take(full) {
+                self.frames.extend(encode_frame(block));
+            }
+            self.pending.drain(..full * self.block_size);
+        }
+        Ok(buf.len())
+    }
+
+    /// Does nothing: no output exists before `finish`.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) fn check_block_size(block_size: usize) {
+    assert!(
+        (1..=MAX_BLOCK_SIZE).contains(&block_size),
+        "block size must be between 1 and {} bytes",
+        MAX_BLOCK_SIZE
+    );
+}
+
+pub(crate) fn multi_block_prefix(original_len: u64) -> Vec<u8> {
+    let flags = SupportedFeature::MultiBlock.bit()
+        | SupportedFeature::Checksum.bit()
+        | SupportedFeature::CanonicalTree.bit()
+        | SupportedFeature::BlockChecksum.bit();
+    container_prefix(original_len, flags)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn encode_frame(block: &[u8]) -> Vec<u8> {
+    encode_frame_limited(block, u8::MAX)
+}
+
+/// The frame of `block` with codes of at most `max_len` bits.
+fn encode_frame_limited(block: &[u8], max_len: u8) -> Vec<u8> {
+    let encoded = encode_block_limited(block, max_len);
+    let mut frame = Vec::with_capacity(
+        FRAME_HEADER_LEN + CHECKSUM_LEN + encoded.tree.len() + encoded.bytes.len(),
+    );
+    frame.extend((block.len() as u32).to_le_bytes());
+    frame.extend((encoded.bytes.len() as u32).to_le_bytes());
+    frame.push(encoded.padding);
+    frame.extend(crc32(block).to_le_bytes());
+    frame.extend(&encoded.tree);
+    frame.extend(&encoded.bytes);
+    frame
+}
+
+/// Decodes the frames and footer that follow the container fields of a
+/// multi-block stream, on `threads` threads.
+pub(crate) fn decode_blocks(
+    container: &Container,
+    mut rest: &[u8],
+    verify: bool,
+    threads: usize,
+) -> Result<Vec<u8>, CompressionError> {
+    if threads > 1 {
+        return decode_blocks_parallel(container, rest, verify, threads);
+    }
+    let mut out = Vec::new();
+    decode_frames(container, &mut rest, verify, |block, _| {
+        out.extend_from_slice(block);
+        Ok(())
+    })?;
+    Ok(out)
+}
+
+/// Reads all frames, then gives each thread a contiguous run of them to
+/// decode into its own part of the output.
+fn decode_blocks_parallel(
+    container: &Container,
+    mut rest: &[u8],
+    verify: bool,
+    threads: usize,
+) -> Result<Vec<u8>, CompressionError> {
+    let mut frames = Vec::new();
+    let mut promised = 0u64;
+    while promised < container.original_len {
+        let mut frame = read_frame(&mut rest, container)?;
+        if frame.original_len > container.original_len - promised {
+            return Err(CompressionError::CorruptHeader);
+        }
+        if !verify {
+            frame.checksum = None;
+        }
+        promised += frame.original_len;
+        frames.push(frame);
+    }
+    let mut out = vec![0u8; promised as usize];
+    let mut jobs = Vec::with_capacity(frames.len());
+    let mut unfilled = &mut out[..];
+    for (index, frame) in (0..).zip(&frames) {
+        let (block, tail) = unfilled.split_at_mut(frame.original_len as usize);
+        jobs.push((index, frame, block));
+        unfilled = tail;
+    }
+    map_runs(&mut jobs, threads, |run| {
+        run.iter_mut().try_for_each(|(index, frame, block)| {
+            frame
+                .decode_into(block)
+                .map(|_| ())
+                .map_err(block_error(*index))
+        })
+    })
+    .into_iter()
+    .collect::<Result<(), _>>()?;
+    let mut crc = Crc32::new();
+    crc.update(&out);
+    check_footer(container, &mut rest, verify, frames.len() as u64, crc)?;
+    Ok(out)
+}
+
+/// Reads frames from `source` until they add up to the original length and
+/// passes each decoded block and the length of its frame to `emit`, then
+/// skips the block index if there is one and checks the footer unless
+/// `verify` is off. Only one block is held in memory at a time. Returns the
+/// number of bytes decoded.
+pub(crate) fn decode_frames<S: Source>(
+    container: &Container,
+    source: &mut S,
+    verify: bool,
+    mut emit: impl FnMut(&[u8], u64) -> Result<(), CompressionError>,
+) -> Result<u64, CompressionError> {
+    let mut crc = Crc32::new();
+    let mut produced = 0u64;
+    let mut frames = 0u64;
+    while produced < container.original_len {
+        let frame = read_frame(source, container)?;
+        if frame.original_len > container.original_len - produced {
+            return Err(CompressionError::CorruptHeader);
+        }
+        let block = match verify {
+            true => frame.decode(),
+            false => frame.decode_unverified(),
+        }
+        .map_err(block_error(frames))?;
+        frames += 1;
+        crc.update(&block);
+        produced += block.len() as u64;
+        let checksum_len = frame.checksum.map_or(0, |_| CHECKSUM_LEN);
+        let frame_len = FRAME_HEADER_LEN + checksum_len + frame.tree.len() + frame.bytes.len();
+        emit(&block, frame_len as u64)?;
+    }
+    check_footer(container, source, verify, frames, crc)?;
+    Ok(produced)
+}
+
+/// Skips the block index of `frames` entries if there is one and checks
+/// the footer against `crc` unless `verify` is off. Fails on trailing data.
+fn check_footer<S: Source>(
+    container: &Container,
+    source: &mut S,
+    verify: bool,
+    frames: u64,
+    crc: Crc32,
+) -> Result<(), CompressionError> {
+    if container.has_block_index() {
+        let mut index = vec![0u8; frames as usize * INDEX_ENTRY_LEN];
+        source.fill(&mut index)?;
+        let mut count = [0u8; 4];
+        source.fill(&mut count)?;
+        if u32::from_le_bytes(count) as u64 != frames {
+            return Err(CompressionError::CorruptData);
+        }
+    }
+    if container.has_checksum() {
+        let mut footer = [0u8; CHECKSUM_LEN];
+        source.fill(&mut footer)?;
+        let expected = u32::from_le_bytes(footer);
+        let actual = crc.finish();
+        if verify && actual != expected {
+            return Err(CompressionError::ChecksumMismatch { expected, actual });
+        }
+    }
+    if !source.is_exhausted()? {
+        return Err(CompressionError::TrailingData);
+    }
+    Ok(())
+}
+
+/// Reads one frame of a stream with the fields in `container` into an
+/// `Encoded`, which carries the block checksum if the frame has one.
+pub(crate) fn read_frame<S: Source>(
+    source: &mut S,
+    container: &Container,
+) -> Result<Encoded, CompressionError> {
+    let canonical = container.has_canonical_tree();
+    let mut head = [0u8; FRAME_HEADER_LEN];
+    source.fill(&mut head)?;
+    let block_len = u32::from_le_bytes(head[0..4].try_into().unwrap());
+    let payload_len = u32::from_le_bytes(head[4..8].try_into().unwrap());
+    let padding = head[8];
+    if block_len == 0 || block_len as usize > MAX_BLOCK_SIZE {
+        return Err(CompressionError::CorruptHeader);
+    }
+    if padding >= 8 || (padding > 0 && payload_len == 0) {
+        return Err(CompressionError::InvalidPadding);
+    }
+    let checksum = match container.has_block_checksums() {
+        true => {
+            let mut crc = [0u8; CHECKSUM_LEN];
+            source.fill(&mut crc)?;
+            Some(u32::from_le_bytes(crc))
+        }
+        false => None,
+    };
+    let tree = read_tree_section(source, canonical)?;
+    let bytes = source.take_up_to(payload_len as usize)?;
+    if bytes.len() != payload_len as usize {
+        return Err(CompressionError::TruncatedInput);
+    }
+    Ok(Encoded {
+        original_len: block_len as u64,
+        checksum,
+        canonical,
+        tree,
+        bytes,
+        padding,
+    })
+}
+
+/// Turns a checksum mismatch of block `index` into one naming the block.
+pub(crate) fn block_error(index: u64) -> impl Fn(CompressionError) -> CompressionError {
+    move |e| match e {
+        CompressionError::ChecksumMismatch { expected, actual } => {
+            CompressionError::BlockChecksumMismatch {
+                block: index,
+                expected,
+                actual,
+            }
+        }
+        e => e,
+    }
+}
+
+/// What [`recover_blocks`] restored of a stream.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Recovered {
+    /// The decoded data, with zeros in place of every block that failed.
+    pub data: Vec<u8>,
+    /// The index of every block that failed and why.
+    pub failed: Vec<(u64, CompressionError)>,
+}
+
+/// Decodes a stream like [`decompress`](crate::decompress) but keeps going
+/// past blocks that fail to decode or to match their checksum, filling
+/// their part of the output with zeros so the other blocks stay at their
+/// offsets. The checksum of all of the data is only checked if no block
+/// failed.
+///
+/// Only multi-block streams without transform stages can be recovered in
+/// part; any other stream is decoded whole. Fails if the frames themselves
+/// are damaged so that the blocks after them cannot be found.
+pub fn recover_blocks(data: &[u8]) -> Result<Recovered, CompressionError> {
+    let (container, mut rest) = crate::parse_container(data)?;
+    if !container.has_blocks() || container.stage_flags() != 0 {
+        return crate::decompress(data).map(|data| Recovered {
+            data,
+            failed: Vec::new(),
+        });
+    }
+    let mut out = Vec::new();
+    let mut failed = Vec::new();
+    let mut frames = 0;
+    while (out.len() as u64) < container.original_len {
+        let frame = read_frame(&mut rest, &container)?;
+        if frame.original_len > container.original_len - out.len() as u64 {
+            return Err(CompressionError::CorruptHeader);
+        }
+        match frame.decode() {
+            Ok(block) => out.extend(block),
+            Err(e) => {
+                failed.push((frames, block_error(frames)(e)));
+                out.resize(out.len() + frame.original_len as usize, 0);
+            }
+        }
+        frames += 1;
+    }
+    let mut crc = Crc32::new();
+    crc.update(&out);
+    check_footer(&container, &mut rest, failed.is_empty(), frames, crc)?;
+    Ok(Recovered { data: out, failed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CONTAINER_LEN, compress, decompress, decompress_unverified};
+
+    fn shifting_data() -> Vec<u8> {
+        let text: Vec<u8> = b"lowercase text for the first half "
+            .iter()
+            .cycle()
+            .take(8192)
+            .copied()
+            .collect();
+        let digits: Vec<u8> = (0..8192u32).map(|i| b'0' + (i * 7 % 10) as u8).collect();
+        [text, digits].concat()
+    }
+
+    #[test]
+    fn round_trip_blocks() {
+        let data = shifting_data();
+        for block_size in [1, 100, 4096, 8192, 100_000] {
+            let encoded = compress_blocks(&data, block_size);
+            assert_eq!(decompress(&encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn round_trip_empty_blocks() {
+        assert_eq!(decompress(&compress_blocks(b"", 16)).unwrap(), b"");
+    }
+
+    #[test]
+    fn blocks_follow_changing_statistics() {
+        let data = shifting_data();
+        assert!(compress_blocks(&data, 8192).len() < compress(&data).len());
+    }
+
+    #[test]
+    fn large_input_is_split_into_blocks() {
+        let data: Vec<u8> = (0..DEFAULT_BLOCK_SIZE as u32 * 2 + 5)
+            .map(|i| (i % 61) as u8)
+            .collect();
+        let encoded = compress(&data);
+        assert_eq!(encoded, compress_blocks(&data, DEFAULT_BLOCK_SIZE));
+        assert_eq!(decompress(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn parallel_output_matches_serial() {
+        let data = shifting_data();
+        for threads in [1, 3, 8, 64] {
+            let encoder = ParallelEncoder::new(threads).with_block_size(1000);
+            assert_eq!(encoder.encode(&data), compress_blocks(&data, 1000));
+        }
+        assert_eq!(
+            ParallelEncoder::new(4).encode(b""),
+            compress_blocks(b"", DEFAULT_BLOCK_SIZE)
+        );
+    }
+
+    #[test]
+    fn incremental_output_matches_whole_input() {
+        let data = shifting_data();
+        for piece in [1, 7, 1000, 4096, data.len()] {
+            let mut encoder = IncrementalEncoder::new().with_block_size(1000);
+            for chunk in data.chunks(piece) {
+                encoder.write_all(chunk).unwrap();
+            }
+            assert_eq!(encoder.finish(), compress_blocks(&data, 1000));
+        }
+        for len in [0, 999, 1000] {
+            let mut encoder = IncrementalEncoder::new().with_block_size(1000);
+            encoder.write_all(&data[..len]).unwrap();
+            assert_eq!(encoder.finish(), compress(&data[..len]));
+        }
+    }
+
+    #[test]
+    fn parallel_decode_matches_serial() {
+        let data = shifting_data();
+        let encoded = compress_blocks(&data, 1000);
+        for threads in [1, 2, 7, 64] {
+            assert_eq!(crate::decompress_parallel(&encoded, threads).unwrap(), data);
+        }
+        let seekable = crate::seekable::compress_seekable(&data, 1000);
+        assert_eq!(crate::decompress_parallel(&seekable, 4).unwrap(), data);
+        assert_eq!(crate::decompress_parallel(b"WFHC", 4), decompress(b"WFHC"));
+        let mut corrupt = encoded.clone();
+        let footer = corrupt.len() - 1;
+        corrupt[footer] ^= 0xff;
+        assert!(matches!(
+            crate::decompress_parallel(&corrupt, 4),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+        let padded = [encoded.as_slice(), &[0]].concat();
+        assert_eq!(
+            crate::decompress_parallel(&padded, 4),
+            Err(CompressionError::TrailingData)
+        );
+    }
+
+    #[test]
+    fn streaming_decode_reads_frames() {
+        let data = shifting_data();
+        let mut out = Vec::new();
+        crate::stream::decode_streaming(&compress_blocks(&data, 1000)[..], &mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn corrupt_block_fails_the_checksum() {
+        let data = shifting_data();
+        let mut encoded = compress_blocks(&data, 4096);
+        let last_payload = encoded.len() - CHECKSUM_LEN - 1;
+        encoded[last_payload] ^= 0x80;
+        assert!(decompress(&encoded).is_err());
+        let mut encoded = compress_blocks(&data, 4096);
+        let footer = encoded.len() - 1;
+        encoded[footer] ^= 0xff;
+        assert!(matches!(
+            decompress(&encoded),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(decompress_unverified(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn damaged_blocks_are_named_and_skipped() {
+        let data = shifting_data();
+        let mut encoded = compress_blocks(&data, 4096);
+        // The block checksum of the second frame.
+        let crc_at = CONTAINER_LEN + encode_frame(&data[..4096]).len() + FRAME_HEADER_LEN;
+        encoded[crc_at] ^= 0xff;
+        assert!(matches!(
+            decompress(&encoded),
+            Err(CompressionError::BlockChecksumMismatch { block: 1, .. })
+        ));
+        assert!(matches!(
+            crate::decompress_parallel(&encoded, 3),
+            Err(CompressionError::BlockChecksumMismatch { block: 1, .. })
+        ));
+        let recovered = recover_blocks(&encoded).unwrap();
+        assert_eq!(recovered.failed.len(), 1);
+        assert!(matches!(
+            recovered.failed[0],
+            (1, CompressionError::BlockChecksumMismatch { block: 1, .. })
+        ));
+        assert_eq!(recovered.data[..4096], data[..4096]);
+        assert!(recovered.data[4096..8192].iter().all(|&b| b == 0));
+        assert_eq!(recovered.data[8192..], data[8192..]);
+
+        let intact = recover_blocks(&compress_blocks(&data, 4096)).unwrap();
+        assert_eq!(
+            intact,
+            Recovered {
+                data,
+                failed: Vec::new()
+            }
+        );
+    }
+
+    #[test]
+    fn frames_without_block_checksums_still_decode() {
+        let data = shifting_data();
+        let flags = SupportedFeature::MultiBlock.bit()
+            | SupportedFeature::Checksum.bit()
+            | SupportedFeature::CanonicalTree.bit();
+        let mut encoded = container_prefix(data.len() as u64, flags);
+        for block in data.chunks(4096) {
+            let mut frame = encode_frame(block);
+            frame.drain(FRAME_HEADER_LEN..FRAME_HEADER_LEN + CHECKSUM_LEN);
+            encoded.extend(frame);
+        }
+        encoded.extend(crc32(&data).to_le_bytes());
+        assert_eq!(decompress(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn truncated_and_trailing_frames_are_rejected() {
+        let encoded = compress_blocks(&shifting_data(), 4096);
+        assert_eq!(
+            decompress(&encoded[..encoded.len() - 10]),
+            Err(CompressionError::TruncatedInput)
+        );
+        let padded = [encoded.as_slice(), &[0]].concat();
+        assert_eq!(decompress(&padded), Err(CompressionError::TrailingData));
+    }
+
+    #[test]
+    fn whole_stream_decoders_reject_blocks() {
+        let encoded = compress_blocks(b"two blocks", 5);
+        let unsupported = || CompressionError::UnsupportedFeatures {
+            flags: SupportedFeature::MultiBlock.bit() | SupportedFeature::BlockChecksum.bit(),
+        };
+        assert_eq!(Encoded::from_bytes(&encoded).err(), Some(unsupported()));
+        let lazy: Vec<_> = crate::lazy::decode_lazy(&encoded).collect();
+        assert_eq!(lazy, vec![Err(unsupported())]);
+    }
+}
@@ -0,0 +1,241 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::transform::{rle_decode_limited, rle_encode};
+
+/// Bytes sorted together by one Burrows–Wheeler transform.
+pub(crate) const BLOCK_LEN: usize = 256 * 1024;
+/// Length and primary index in front of every block.
+pub(crate) const BLOCK_HEADER_LEN: usize = 8;
+
+/// Burrows–Wheeler transform, move-to-front and run-length encoding, the
+/// stages bzip2 runs before entropy coding. Sorting brings bytes with the
+/// same context together, move-to-front turns those clusters into runs of
+/// small numbers and [`rle_encode`] shortens the runs.
+///
+/// The data is split into blocks of up to 256 KiB. Each block is its length
+/// as a little-endian `u32`, the row of the sorted rotations holding the
+/// end of the block as a little-endian `u32`, and the move-to-front codes
+/// of the last column without the end marker. The blocks are then
+/// run-length encoded together.
+pub fn bwt_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + BLOCK_HEADER_LEN);
+    for block in data.chunks(BLOCK_LEN) {
+        let (last, primary) = transform(block);
+        out.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        out.extend_from_slice(&primary.to_le_bytes());
+        out.extend(move_to_front(&last));
+    }
+    rle_encode(&out)
+}
+
+pub fn bwt_decode(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    bwt_decode_limited(data, u64::MAX)
+}
+
+/// Like `bwt_decode` but fails once the output would exceed `limit` bytes.
+pub(crate) fn bwt_decode_limited(data: &[u8], limit: u64) -> Result<Vec<u8>, CompressionError> {
+    let blocks = limit.div_ceil(BLOCK_LEN as u64);
+    let framed_limit = limit.saturating_add(blocks.saturating_mul(BLOCK_HEADER_LEN as u64));
+    let framed = rle_decode_limited(data, framed_limit).map_err(|e| match e {
+        CompressionError::OutputLimitExceeded { .. } => {
+            CompressionError::OutputLimitExceeded { limit }
+        }
+        e => e,
+    })?;
+    let mut out = Vec::new();
+    let mut rest = &framed[..];
+    while !rest.is_empty() {
+        let (header, body) = rest
+            .split_at_checked(BLOCK_HEADER_LEN)
+            .ok_or(CompressionError::TruncatedInput)?;
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let primary = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        if len == 0 || len > BLOCK_LEN {
+            return Err(CompressionError::CorruptData);
+        }
+        if (out.len() + len) as u64 > limit {
+            return Err(CompressionError::OutputLimitExceeded { limit });
+        }
+        let (codes, next) = body
+            .split_at_checked(len)
+            .ok_or(CompressionError::TruncatedInput)?;
+        out.extend(inverse(&move_to_front_decode(codes), primary)?);
+        rest = next;
+    }
+    Ok(out)
+}
+
+/// The last column of the sorted rotations of `block` with an end marker
+/// that sorts before every byte, leaving out the marker, and the row the
+/// marker was in.
+fn transform(block: &[u8]) -> (Vec<u8>, u32) {
+    let sa = suffix_array(block);
+    // Row 0 is the suffix holding only the marker, preceded by the last byte.
+    let mut last = Vec::with_capacity(block.len());
+    last.extend(block.last());
+    let mut primary = 0;
+    for (row, &start) in sa.iter().enumerate() {
+        match start {
+            0 => primary = row as u32 + 1,
+            _ => last.push(block[start - 1]),
+        }
+    }
+    (last, primary)
+}
+
+/// Start positions of the suffixes of `data` in sorted order, where a
+/// suffix sorts before any longer suffix it is a prefix of. Sorts by the
+/// first 1, 2, 4, ... bytes until every suffix has its own rank.
+fn suffix_array(data: &[u8]) -> Vec<usize> {
+    let n = data.len();
+    let mut sa: Vec<usize> = (0..n).collect();
+    // Rank 0 is past the end, so shorter suffixes sort first.
+    let mut rank: Vec<usize> = data.iter().map(|&b| b as usize + 1).collect();
+    let mut next = vec![0; n];
+    let mut width = 1;
+    loop {
+        let key = |i: usize| (rank[i], rank.get(i + width).copied().unwrap_or(0));
+        sa.sort_unstable_by_key(|&i| key(i));
+        let mut distinct = 1;
+        for pair in sa.windows(2) {
+            if key(pair[0]) != key(pair[1]) {
+                distinct += 1;
+            }
+            next[pair[1]] = distinct;
+        }
+        if let Some(&first) = sa.first() {
+            next[first] = 1;
+        }
+        core::mem::swap(&mut rank, &mut next);
+        if distinct == n || width >= n {
+            return sa;
+        }
+        width *= 2;
+    }
+}
+
+/// Undoes `transform`: walks the last column backwards from the row of the
+/// marker suffix, which yields the block in reverse.
+fn inverse(last: &[u8], primary: usize) -> Result<Vec<u8>, CompressionError> {
+    let n = last.len();
+    if primary == 0 || primary > n {
+        return Err(CompressionError::CorruptData);
+    }
+    // First row of each byte in the first column, after the marker row.
+    let mut counts = [0usize; 256];
+    for &b in last {
+        counts[b as usize] += 1;
+    }
+    let mut starts = [0usize; 256];
+    let mut row = 1;
+    for (start, &count) in starts.iter_mut().zip(&counts) {
+        *start = row;
+        row += count;
+    }
+    // `lf[i]` is the row of the rotation that ends one byte earlier than
+    // row `i`, for every row but the marker's.
+    let mut lf = vec![0; n + 1];
+    let mut seen = [0usize; 256];
+    for (i, row) in (0..=n).filter(|&row| row != primary).enumerate() {
+        let b = last[i] as usize;
+        lf[row] = starts[b] + seen[b];
+        seen[b] += 1;
+    }
+
+    let column_at = |row: usize| last[if row < primary { row } else { row - 1 }];
+    let mut out = vec![0; n];
+    let mut row = 0;
+    for slot in out.iter_mut().rev() {
+        if row == primary {
+            return Err(CompressionError::CorruptData);
+        }
+        *slot = column_at(row);
+        row = lf[row];
+    }
+    if row != primary {
+        return Err(CompressionError::CorruptData);
+    }
+    Ok(out)
+}
+
+/// Replaces every byte with its position in a list of all byte values, then
+/// moves it to the front of the list.
+fn move_to_front(data: &[u8]) -> Vec<u8> {
+    let mut order: [u8; 256] = core::array::from_fn(|i| i as u8);
+    data.iter()
+        .map(|&b| {
+            let index = order.iter().position(|&x| x == b).unwrap();
+            order.copy_within(..index, 1);
+            order[0] = b;
+            index as u8
+        })
+        .collect()
+}
+
+fn move_to_front_decode(codes: &[u8]) -> Vec<u8> {
+    let mut order: [u8; 256] = core::array::from_fn(|i| i as u8);
+    codes
+        .iter()
+        .map(|&index| {
+            let b = order[index as usize];
+            order.copy_within(..index as usize, 1);
+            order[0] = b;
+            b
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transform_matches_the_sorted_rotations() {
+        // Rotations of "banana$": $banana, a$banan, ana$ban, anana$b,
+        // banana$, na$bana, nana$ba.
+        assert_eq!(transform(b"banana"), (b"annbaa".to_vec(), 4));
+        assert_eq!(inverse(b"annbaa", 4).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn round_trips_text_runs_and_empty_input() {
+        let text = b"the quick brown fox jumps over the lazy dog. ".repeat(40);
+        let mut mixed = vec![7u8; 1000];
+        mixed.extend((0..3000u32).map(|i| (i * i % 251) as u8));
+        for data in [&text[..], &mixed, b"", b"x", b"abababab"] {
+            let encoded = bwt_encode(data);
+            assert_eq!(bwt_decode(&encoded).unwrap(), data);
+        }
+        assert!(bwt_encode(&text).len() < text.len() / 4);
+    }
+
+    #[test]
+    fn large_inputs_span_blocks() {
+        let data: Vec<u8> = (0..BLOCK_LEN as u32 + 1000)
+            .map(|i| b"source code\n"[(i % 12) as usize] ^ (i / 4099) as u8)
+            .collect();
+        assert_eq!(bwt_decode(&bwt_encode(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn corrupt_blocks_are_rejected() {
+        let mut framed = 3u32.to_le_bytes().to_vec();
+        framed.extend(9u32.to_le_bytes());
+        framed.extend(move_to_front(b"abc"));
+        assert_eq!(
+            bwt_decode(&rle_encode(&framed)),
+            Err(CompressionError::CorruptData)
+        );
+        assert_eq!(
+            bwt_decode(&rle_encode(&framed[..6])),
+            Err(CompressionError::TruncatedInput)
+        );
+        let encoded = bwt_encode(b"limited output");
+        assert_eq!(
+            bwt_decode_limited(&encoded, 4),
+            Err(CompressionError::OutputLimitExceeded { limit: 4 })
+        );
+    }
+}
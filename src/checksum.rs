@@ -0,0 +1,86 @@
+/// CRC-32 with the IEEE polynomial, as used by zlib, gzip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+const TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// Incremental form of `crc32` for data that arrives in pieces.
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32 { state: !0 }
+    }
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32::default()
+    }
+
+    /// Continues the checksum whose [`finish`](Crc32::finish) returned
+    /// `crc`, as if the data before had been passed to this one.
+    pub fn resume(crc: u32) -> Crc32 {
+        Crc32 { state: !crc }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.state = TABLE[((self.state ^ b as u32) & 0xff) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data = b"the quick brown fox jumps over the lazy dog";
+        let mut crc = Crc32::new();
+        for piece in data.chunks(7) {
+            crc.update(piece);
+        }
+        assert_eq!(crc.finish(), crc32(data));
+        let (head, tail) = data.split_at(10);
+        let mut resumed = Crc32::resume(crc32(head));
+        resumed.update(tail);
+        assert_eq!(resumed.finish(), crc32(data));
+    }
+}
@@ -0,0 +1,261 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::checksum::crc32;
+use crate::error::CompressionError;
+use crate::options::{Algorithm, CompressionOptions};
+use crate::version::SupportedFeature;
+use crate::{compress_with, container_prefix, decompress, parse_container};
+
+/// Smallest id of a codec from outside the crate; the ids below are kept
+/// for the built-in algorithms.
+pub const FIRST_CUSTOM_CODEC: u8 = 128;
+
+/// A compression algorithm that a [`CodecRegistry`] dispatches to by id.
+///
+/// The built-in algorithms are [`Builtin`] codecs whose output is a whole
+/// stream, as [`compress_with`] writes it. The output of any other codec is
+/// wrapped by the registry in a container with the codec flag, followed by
+/// the id, the output and a CRC-32 of the data, so that [`decompress`]
+/// fails cleanly on it and the registry can find the codec again.
+pub trait Codec: Send + Sync {
+    fn id(&self) -> u8;
+    fn compress(
+        &self,
+        data: &[u8],
+        options: &CompressionOptions,
+    ) -> Result<Vec<u8>, CompressionError>;
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError>;
+}
+
+/// One of the algorithms of [`compress_with`] as a codec, with the id
+/// [`Algorithm::id`]. It ignores the algorithm of the options it is given.
+pub struct Builtin(pub Algorithm);
+
+impl Codec for Builtin {
+    fn id(&self) -> u8 {
+        self.0.id()
+    }
+
+    fn compress(
+        &self,
+        data: &[u8],
+        options: &CompressionOptions,
+    ) -> Result<Vec<u8>, CompressionError> {
+        let options = CompressionOptions {
+            algorithm: self.0,
+            ..*options
+        };
+        Ok(compress_with(data, &options))
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        decompress(data)
+    }
+}
+
+/// Codecs by id: the built-in algorithms and any added with
+/// [`with_codec`](CodecRegistry::with_codec).
+pub struct CodecRegistry {
+    codecs: BTreeMap<u8, Box<dyn Codec>>,
+}
+
+impl Default for CodecRegistry {
+    fn default() -> CodecRegistry {
+        CodecRegistry::new()
+    }
+}
+
+impl CodecRegistry {
+    /// A registry of the built-in algorithms.
+    pub fn new() -> CodecRegistry {
+        let codecs = Algorithm::ALL
+            .iter()
+            .map(|&algorithm| {
+                (
+                    algorithm.id(),
+                    Box::new(Builtin(algorithm)) as Box<dyn Codec>,
+                )
+            })
+            .collect();
+        CodecRegistry { codecs }
+    }
+
+    /// Adds `codec`. Panics if its id is below [`FIRST_CUSTOM_CODEC`] or
+    /// already taken.
+    pub fn with_codec(mut self, codec: impl Codec + 'static) -> CodecRegistry {
+        let id = codec.id();
+        assert!(
+            id >= FIRST_CUSTOM_CODEC,
+            "codec ids below {} are reserved",
+            FIRST_CUSTOM_CODEC
+        );
+        assert!(
+            !self.codecs.contains_key(&id),
+            "codec id {} is already registered",
+            id
+        );
+        self.codecs.insert(id, Box::new(codec));
+        self
+    }
+
+    pub fn get(&self, id: u8) -> Option<&dyn Codec> {
+        self.codecs.get(&id).map(|codec| &**codec)
+    }
+
+    /// Compresses `data` with the codec `id`.
+    pub fn compress(
+        &self,
+        id: u8,
+        data: &[u8],
+        options: &CompressionOptions,
+    ) -> Result<Vec<u8>, CompressionError> {
+        let codec = self.get(id).ok_or(CompressionError::UnknownCodec { id })?;
+        if id < FIRST_CUSTOM_CODEC {
+            return codec.compress(data, options);
+        }
+        let mut flags = SupportedFeature::Codec.bit();
+        if options.checksum {
+            flags |= SupportedFeature::Checksum.bit();
+        }
+        let mut out = container_prefix(data.len() as u64, flags);
+        out.push(id);
+        out.extend(codec.compress(data, options)?);
+        if options.checksum {
+            out.extend(crc32(data).to_le_bytes());
+        }
+        Ok(out)
+    }
+
+    /// Decompresses a stream of any registered codec, found from the
+    /// header.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let (container, rest) = parse_container(data)?;
+        if !container.has_codec() {
+            let id = container.algorithm().id();
+            return self
+                .get(id)
+                .ok_or(CompressionError::UnknownCodec { id })?
+                .decompress(data);
+        }
+        let (&id, rest) = rest.split_first().ok_or(CompressionError::TruncatedInput)?;
+        let codec = self
+            .get(id)
+            .filter(|_| id >= FIRST_CUSTOM_CODEC)
+            .ok_or(CompressionError::UnknownCodec { id })?;
+        let (payload, checksum) = container.split_footer(rest)?;
+        let out = codec.decompress(payload)?;
+        if out.len() as u64 != container.original_len {
+            return Err(CompressionError::LengthMismatch {
+                expected: container.original_len,
+                actual: out.len() as u64,
+            });
+        }
+        if let Some(expected) = checksum {
+            let actual = crc32(&out);
+            if actual != expected {
+                return Err(CompressionError::ChecksumMismatch { expected, actual });
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stores every byte XORed with a key, as a stand-in for a codec of
+    /// another crate.
+    struct Xor(u8);
+
+    impl Codec for Xor {
+        fn id(&self) -> u8 {
+            200
+        }
+
+        fn compress(
+            &self,
+            data: &[u8],
+            _: &CompressionOptions,
+        ) -> Result<Vec<u8>, CompressionError> {
+            Ok(data.iter().map(|b| b ^ self.0).collect())
+        }
+
+        fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+            Ok(data.iter().map(|b| b ^ self.0).collect())
+        }
+    }
+
+    #[test]
+    fn builtins_match_compress_with() {
+        let data = b"codecs by id, codecs by id".repeat(20);
+        let registry = CodecRegistry::new();
+        for algorithm in Algorithm::ALL {
+            let options = CompressionOptions {
+                algorithm,
+                ..Default::default()
+            };
+            let encoded = registry
+                .compress(algorithm.id(), &data, &Default::default())
+                .unwrap();
+            assert_eq!(encoded, compress_with(&data, &options));
+            assert_eq!(registry.decompress(&encoded).unwrap(), data);
+        }
+        assert_eq!(
+            registry.compress(FIRST_CUSTOM_CODEC, &data, &Default::default()),
+            Err(CompressionError::UnknownCodec {
+                id: FIRST_CUSTOM_CODEC
+            })
+        );
+    }
+
+    #[test]
+    fn custom_codecs_are_found_from_the_header() {
+        let registry = CodecRegistry::new().with_codec(Xor(0x5a));
+        let encoded = registry
+            .compress(200, b"plugged in", &Default::default())
+            .unwrap();
+        assert_eq!(registry.decompress(&encoded).unwrap(), b"plugged in");
+        assert_eq!(
+            decompress(&encoded),
+            Err(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Codec.bit()
+            })
+        );
+        assert_eq!(
+            CodecRegistry::new().decompress(&encoded),
+            Err(CompressionError::UnknownCodec { id: 200 })
+        );
+
+        let mut damaged = encoded.clone();
+        damaged[20] ^= 1;
+        assert!(matches!(
+            registry.decompress(&damaged),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "reserved")]
+    fn builtin_ids_are_reserved() {
+        struct Low;
+        impl Codec for Low {
+            fn id(&self) -> u8 {
+                3
+            }
+            fn compress(
+                &self,
+                _: &[u8],
+                _: &CompressionOptions,
+            ) -> Result<Vec<u8>, CompressionError> {
+                Ok(Vec::new())
+            }
+            fn decompress(&self, _: &[u8]) -> Result<Vec<u8>, CompressionError> {
+                Ok(Vec::new())
+            }
+        }
+        let _ = CodecRegistry::new().with_codec(Low);
+    }
+}
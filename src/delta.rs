@@ -0,0 +1,110 @@
+use alloc::vec::Vec;
+
+use crate::bits::{BitReader, BitWriter, bit_count};
+use crate::error::CompressionError;
+
+const COUNT_LEN: usize = 8;
+
+/// Encodes a non-decreasing list as the gaps between neighbours (the first
+/// gap is from 0), each written as an Elias-gamma code of `gap + 1`. The
+/// output is the element count as a little-endian `u64` followed by the
+/// packed codes.
+pub fn encode_delta_u32(data: &[u32]) -> Result<Vec<u8>, CompressionError> {
+    let mut bits = BitWriter::new();
+    let mut prev = 0;
+    for (index, &value) in data.iter().enumerate() {
+        if value < prev {
+            return Err(CompressionError::UnsortedInput { index });
+        }
+        let n = (value - prev) as u64 + 1;
+        let width = 64 - n.leading_zeros();
+        bits.push_value(0, width - 1);
+        bits.push_value(n, width);
+        prev = value;
+    }
+
+    let mut out = (data.len() as u64).to_le_bytes().to_vec();
+    out.extend(bits.into_bytes());
+    Ok(out)
+}
+
+pub fn decode_delta_u32(data: &[u8]) -> Result<Vec<u32>, CompressionError> {
+    if data.len() < COUNT_LEN {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let (count, payload) = data.split_at(COUNT_LEN);
+    let count = u64::from_le_bytes(count.try_into().unwrap());
+    // Every code is at least one bit long.
+    if count > bit_count(payload) {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let count = count as usize;
+
+    let mut bits = BitReader::new(payload);
+    let mut out = Vec::with_capacity(count);
+    let mut prev = 0u32;
+    for _ in 0..count {
+        let mut zeros = 0;
+        while !bits.read_bit().ok_or(CompressionError::TruncatedInput)? {
+            zeros += 1;
+            if zeros > 32 {
+                return Err(CompressionError::CorruptData);
+            }
+        }
+        let n = (1 << zeros)
+            | bits
+                .read_bits(zeros)
+                .ok_or(CompressionError::TruncatedInput)?;
+        let value = prev as u64 + n - 1;
+        prev = u32::try_from(value).map_err(|_| CompressionError::CorruptData)?;
+        out.push(prev);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_sequential_ids() {
+        let data: Vec<u32> = (1..=10_000).collect();
+        let encoded = encode_delta_u32(&data).unwrap();
+        assert!(encoded.len() < data.len());
+        assert_eq!(decode_delta_u32(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_repeats_and_large_gaps() {
+        let data = [0, 0, 7, 7, 1_000_000, u32::MAX];
+        assert_eq!(
+            decode_delta_u32(&encode_delta_u32(&data).unwrap()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        assert_eq!(
+            decode_delta_u32(&encode_delta_u32(&[]).unwrap()).unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn unsorted_input_is_rejected() {
+        assert_eq!(
+            encode_delta_u32(&[1, 5, 3]),
+            Err(CompressionError::UnsortedInput { index: 2 })
+        );
+    }
+
+    #[test]
+    fn truncated_payload_is_rejected() {
+        let encoded = encode_delta_u32(&[1, 100, 10_000]).unwrap();
+        assert_eq!(
+            decode_delta_u32(&encoded[..encoded.len() - 1]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::bits::{BitWriter, bit_count};
+use crate::checksum::crc32;
+use crate::error::CompressionError;
+use crate::table::DecodeTable;
+use crate::{HuffmanTree, histogram, parse_tree_section};
+
+const MIN_NGRAM: usize = 1;
+const MAX_NGRAM: usize = 8;
+const DICTIONARY_MAGIC: [u8; 4] = *b"WFHD";
+/// Length of the dictionary id and padding byte before each message.
+const MESSAGE_HEADER_LEN: usize = 5;
+
+/// Builds a `dict_size`-byte dictionary from the substrings (1 to 8 bytes
+/// long) that occur most often across `samples`.
+///
+/// Substrings are ranked by the number of bytes they cover in the corpus
+/// (`count * len`), and a candidate is skipped if it already appears in the
+/// dictionary built so far.
+pub fn dictionary_train(samples: &[&[u8]], dict_size: usize) -> Vec<u8> {
+    let mut counts: BTreeMap<&[u8], usize> = BTreeMap::new();
+    for sample in samples {
+        for len in MIN_NGRAM..=MAX_NGRAM {
+            for ngram in sample.windows(len) {
+                *counts.entry(ngram).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut ranked: Vec<(&[u8], usize)> = counts.into_iter().collect();
+    ranked.sort_by(|(a, a_count), (b, b_count)| {
+        (b_count * b.len())
+            .cmp(&(a_count * a.len()))
+            .then_with(|| a.cmp(b))
+    });
+
+    let mut dict = Vec::new();
+    for (ngram, _) in ranked {
+        if dict.len() >= dict_size {
+            break;
+        }
+        if dict.windows(ngram.len()).any(|w| w == ngram) {
+            continue;
+        }
+        dict.extend_from_slice(ngram);
+    }
+    dict.truncate(dict_size);
+    dict
+}
+
+/// A Huffman code trained on sample messages. Messages encoded with it
+/// carry the id of the dictionary instead of a tree, so the decoder needs
+/// the same dictionary.
+///
+/// Every byte has a code, also those missing from the samples.
+pub struct Dictionary {
+    /// Canonical tree section, as in a stream header.
+    section: Vec<u8>,
+    codes: Box<[(Option<Vec<bool>>, u8); 256]>,
+    table: DecodeTable,
+}
+
+impl Dictionary {
+    fn from_tree(tree: HuffmanTree) -> Dictionary {
+        Dictionary {
+            section: tree.serialize(),
+            codes: tree.to_lookup_array(),
+            table: DecodeTable::new(&tree),
+        }
+    }
+
+    /// CRC-32 of the tree, written at the start of every message.
+    pub fn id(&self) -> u32 {
+        crc32(&self.section)
+    }
+
+    /// The magic `WFHD` followed by the tree section.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = DICTIONARY_MAGIC.to_vec();
+        out.extend(&self.section);
+        out
+    }
+
+    /// Inverse of `to_bytes`. Fails unless the tree codes all 256 bytes.
+    pub fn from_bytes(data: &[u8]) -> Result<Dictionary, CompressionError> {
+        let rest = data
+            .strip_prefix(&DICTIONARY_MAGIC)
+            .ok_or(CompressionError::InvalidMagic)?;
+        let section = parse_tree_section(rest, true)?;
+        if section.len != rest.len() {
+            return Err(CompressionError::CorruptHeader);
+        }
+        match section.tree {
+            Some(tree) if section.leaves.len() == 256 => Ok(Dictionary::from_tree(tree)),
+            _ => Err(CompressionError::CorruptTree),
+        }
+    }
+}
+
+/// Builds a [`Dictionary`] from the byte frequencies of `samples`. Every
+/// byte counts once more than it occurs, so bytes the samples lack still
+/// get a (long) code.
+pub fn train(samples: &[&[u8]]) -> Dictionary {
+    let mut freq = [1u64; 256];
+    for sample in samples {
+        for (total, count) in freq.iter_mut().zip(histogram(sample)) {
+            *total += count;
+        }
+    }
+    let tree = HuffmanTree::from_frequencies(&freq).expect("every byte has a frequency");
+    let canonical = HuffmanTree::from_code_lengths(&tree.code_lengths())
+        .expect("a tree's own code lengths form a complete code");
+    Dictionary::from_tree(canonical)
+}
+
+/// Encodes `data` with the code of `dict`. The output is the dictionary id
+/// as a little-endian `u32`, the number of padding bits at the end and the
+/// codes, with no tree, length or checksum.
+pub fn encode_with_dict(data: &[u8], dict: &Dictionary) -> Vec<u8> {
+    let mut bits = BitWriter::new();
+    for &b in data {
+        bits.push_bits(dict.codes[b as usize].0.as_ref().unwrap());
+    }
+    let mut out = dict.id().to_le_bytes().to_vec();
+    out.push(bits.padding());
+    out.extend(bits.into_bytes());
+    out
+}
+
+/// Inverse of `encode_with_dict`. Fails if `data` was encoded with another
+/// dictionary or its codes do not end on a symbol boundary.
+pub fn decode_with_dict(data: &[u8], dict: &Dictionary) -> Result<Vec<u8>, CompressionError> {
+    if data.len() < MESSAGE_HEADER_LEN {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let found = u32::from_le_bytes(data[..4].try_into().unwrap());
+    if found != dict.id() {
+        return Err(CompressionError::DictionaryMismatch {
+            expected: dict.id(),
+            found,
+        });
+    }
+    let padding = data[4];
+    let payload = &data[MESSAGE_HEADER_LEN..];
+    if padding >= 8 || (padding > 0 && payload.is_empty()) {
+        return Err(CompressionError::InvalidPadding);
+    }
+    dict.table
+        .decode(payload, bit_count(payload) - padding as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dictionary_has_requested_size() {
+        let samples: [&[u8]; 2] = [b"{\"id\":1,\"name\":\"a\"}", b"{\"id\":2,\"name\":\"b\"}"];
+        assert_eq!(dictionary_train(&samples, 16).len(), 16);
+    }
+
+    #[test]
+    fn dictionary_contains_common_substring() {
+        let samples: [&[u8]; 3] = [b"GET /index.html", b"GET /about.html", b"GET /contact.html"];
+        let dict = dictionary_train(&samples, 32);
+        assert!(dict.windows(5).any(|w| w == b".html"));
+    }
+
+    #[test]
+    fn empty_samples_give_empty_dictionary() {
+        assert!(dictionary_train(&[], 16).is_empty());
+    }
+
+    const MESSAGES: [&[u8]; 3] = [
+        br#"{"id":1,"status":"ok"}"#,
+        br#"{"id":2,"status":"ok"}"#,
+        br#"{"id":3,"status":"error"}"#,
+    ];
+
+    #[test]
+    fn messages_round_trip_without_a_tree() {
+        // Real training sets hold many messages, which outweigh the one
+        // extra count of every byte.
+        let dict = train(&MESSAGES.repeat(100));
+        for message in MESSAGES.iter().chain([&&b""[..], &&b"\x00\xff unseen"[..]]) {
+            let encoded = encode_with_dict(message, &dict);
+            assert_eq!(decode_with_dict(&encoded, &dict).unwrap(), *message);
+        }
+        let encoded = encode_with_dict(MESSAGES[0], &dict);
+        assert!(encoded.len() < MESSAGES[0].len());
+        assert!(encoded.len() < crate::compress(MESSAGES[0]).len());
+    }
+
+    #[test]
+    fn dictionary_round_trips_through_bytes() {
+        let dict = train(&MESSAGES);
+        let loaded = Dictionary::from_bytes(&dict.to_bytes()).unwrap();
+        assert_eq!(loaded.id(), dict.id());
+        let encoded = encode_with_dict(MESSAGES[2], &dict);
+        assert_eq!(decode_with_dict(&encoded, &loaded).unwrap(), MESSAGES[2]);
+        assert_eq!(
+            Dictionary::from_bytes(b"WFHC").err(),
+            Some(CompressionError::InvalidMagic)
+        );
+    }
+
+    #[test]
+    fn other_dictionary_is_rejected() {
+        let dict = train(&MESSAGES);
+        let other = train(&[b"completely different samples"]);
+        let encoded = encode_with_dict(MESSAGES[1], &dict);
+        assert_eq!(
+            decode_with_dict(&encoded, &other),
+            Err(CompressionError::DictionaryMismatch {
+                expected: other.id(),
+                found: dict.id(),
+            })
+        );
+    }
+}
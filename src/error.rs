@@ -0,0 +1,172 @@
+use core::fmt;
+
+/// What went wrong in an [`Io`](CompressionError::Io) error. Nothing does
+/// I/O without the `std` feature, so the variant cannot occur then.
+#[cfg(feature = "std")]
+type IoErrorKind = std::io::ErrorKind;
+#[cfg(not(feature = "std"))]
+type IoErrorKind = core::convert::Infallible;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompressionError {
+    TruncatedInput,
+    InvalidMagic,
+    CorruptHeader,
+    CorruptData,
+    CorruptTree,
+    DecodingFailed,
+    InvalidPadding,
+    LengthMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    SymbolNotInTree {
+        symbol: u8,
+    },
+    Io(IoErrorKind),
+    MissingPart {
+        index: u16,
+    },
+    SchemaTooLong {
+        len: usize,
+    },
+    UnsupportedVersion {
+        found: u8,
+        supported: u8,
+    },
+    UnsupportedFeatures {
+        flags: u32,
+    },
+    InvalidHexDigit {
+        digit: char,
+    },
+    UnsortedInput {
+        index: usize,
+    },
+    OutputLimitExceeded {
+        limit: u64,
+    },
+    MissingBlockIndex,
+    DictionaryMismatch {
+        expected: u32,
+        found: u32,
+    },
+    VerificationFailed {
+        offset: u64,
+    },
+    BlockChecksumMismatch {
+        block: u64,
+        expected: u32,
+        actual: u32,
+    },
+    UnknownCodec {
+        id: u8,
+    },
+    TrailingData,
+    UnsupportedExtension {
+        kind: u8,
+    },
+    TooFewSymbols {
+        kept: usize,
+    },
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionError::TruncatedInput => write!(f, "input ended unexpectedly"),
+            CompressionError::InvalidMagic => write!(f, "input is not a compressed stream"),
+            CompressionError::CorruptHeader => write!(f, "header is malformed"),
+            CompressionError::CorruptData => write!(f, "compressed data is malformed"),
+            CompressionError::CorruptTree => write!(f, "code tree is malformed"),
+            CompressionError::DecodingFailed => {
+                write!(f, "bit stream does not end on a symbol boundary")
+            }
+            CompressionError::InvalidPadding => {
+                write!(f, "padding does not fit in the last payload byte")
+            }
+            CompressionError::LengthMismatch { expected, actual } => write!(
+                f,
+                "header promises {} bytes but the payload holds {}",
+                expected, actual
+            ),
+            CompressionError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum {:#010x} does not match the stored {:#010x}",
+                actual, expected
+            ),
+            CompressionError::SymbolNotInTree { symbol } => {
+                write!(f, "byte {:#04x} is not in the code tree", symbol)
+            }
+            CompressionError::Io(kind) => write!(f, "I/O error: {}", kind),
+            CompressionError::MissingPart { index } => write!(f, "part {} is missing", index),
+            CompressionError::SchemaTooLong { len } => {
+                write!(f, "schema is {} bytes, at most 255 are allowed", len)
+            }
+            CompressionError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "format version {} is newer than supported version {}",
+                found, supported
+            ),
+            CompressionError::UnsupportedFeatures { flags } => {
+                write!(f, "unsupported format features {:#x}", flags)
+            }
+            CompressionError::InvalidHexDigit { digit } => {
+                write!(f, "'{}' is not a hex digit", digit)
+            }
+            CompressionError::UnsortedInput { index } => {
+                write!(f, "input is not sorted at index {}", index)
+            }
+            CompressionError::OutputLimitExceeded { limit } => {
+                write!(f, "output would exceed the limit of {} bytes", limit)
+            }
+            CompressionError::MissingBlockIndex => {
+                write!(f, "stream has no block index for random access")
+            }
+            CompressionError::DictionaryMismatch { expected, found } => write!(
+                f,
+                "message was encoded with dictionary {:#010x}, not {:#010x}",
+                found, expected
+            ),
+            CompressionError::VerificationFailed { offset } => write!(
+                f,
+                "decoded output differs from the original at byte {}",
+                offset
+            ),
+            CompressionError::BlockChecksumMismatch {
+                block,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "block {} checksum {:#010x} does not match the stored {:#010x}",
+                block, actual, expected
+            ),
+            CompressionError::UnknownCodec { id } => {
+                write!(f, "no codec with id {} is registered", id)
+            }
+            CompressionError::TrailingData => {
+                write!(f, "unexpected bytes after the end of the stream")
+            }
+            CompressionError::UnsupportedExtension { kind } => {
+                write!(f, "unsupported must-understand extension {:#04x}", kind)
+            }
+            CompressionError::TooFewSymbols { kept } => {
+                write!(f, "{} symbols are too few for a code tree", kept)
+            }
+        }
+    }
+}
+
+impl core::error::Error for CompressionError {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for CompressionError {
+    fn from(e: std::io::Error) -> CompressionError {
+        CompressionError::Io(e.kind())
+    }
+}
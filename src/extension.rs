@@ -0,0 +1,166 @@
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::format::Cursor;
+use crate::version::SupportedFeature;
+use crate::{CONTAINER_LEN, add_flags, parse_container};
+
+/// Bit of an extension kind that marks the extension as must-understand:
+/// a decoder that does not know the kind fails with
+/// [`UnsupportedExtension`](CompressionError::UnsupportedExtension)
+/// instead of skipping it.
+pub const MUST_UNDERSTAND: u8 = 0x80;
+
+/// Optional kind holding the Unix permission bits of the file a stream was
+/// compressed from as a little-endian `u32`, then its modification time in
+/// seconds since the Unix epoch as a little-endian `u64`.
+pub const FILE_METADATA: u8 = 0x01;
+
+/// Must-understand kinds this build decodes. None are defined yet, so every
+/// must-understand extension is rejected.
+const UNDERSTOOD: [u8; 0] = [];
+
+/// An entry of the extension area that follows the container fields of a
+/// stream with the [`Extensions`](SupportedFeature::Extensions) flag.
+///
+/// The area is a list of entries, each a kind byte, the length of the
+/// value as a little-endian `u16` and the value, ended by a kind of 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension {
+    pub kind: u8,
+    pub value: Vec<u8>,
+}
+
+impl Extension {
+    /// Panics if `kind` is 0, which ends the area, or if `value` is longer
+    /// than `u16::MAX` bytes.
+    pub fn new(kind: u8, value: impl Into<Vec<u8>>) -> Extension {
+        let value = value.into();
+        assert!(kind != 0, "extension kind 0 is reserved");
+        assert!(
+            value.len() <= u16::MAX as usize,
+            "extension values are at most {} bytes",
+            u16::MAX
+        );
+        Extension { kind, value }
+    }
+
+    pub fn must_understand(&self) -> bool {
+        self.kind & MUST_UNDERSTAND != 0
+    }
+}
+
+/// Reads an extension area. Fails on a must-understand kind this build does
+/// not know; unknown optional kinds are returned for the caller to ignore.
+pub(crate) fn parse_extensions(cursor: &mut Cursor) -> Result<Vec<Extension>, CompressionError> {
+    let mut extensions = Vec::new();
+    loop {
+        let kind = cursor.u8()?;
+        if kind == 0 {
+            return Ok(extensions);
+        }
+        let len = cursor.u16_le()? as usize;
+        let value = cursor.bytes(len)?.to_vec();
+        let extension = Extension { kind, value };
+        if extension.must_understand() && !UNDERSTOOD.contains(&kind) {
+            return Err(CompressionError::UnsupportedExtension { kind });
+        }
+        extensions.push(extension);
+    }
+}
+
+/// The extensions in the header of the stream at the start of `data`, empty
+/// if it has none.
+pub fn extensions(data: &[u8]) -> Result<Vec<Extension>, CompressionError> {
+    Ok(parse_container(data)?.0.extensions)
+}
+
+/// Adds `extensions` to the header of the stream at the start of `data`,
+/// after any it has already. Streams joined after it are left as they are.
+///
+/// Fails with [`UnsupportedFeatures`](CompressionError::UnsupportedFeatures)
+/// on a stream with a block index, whose frame offsets would no longer
+/// match.
+pub fn add_extensions(data: &[u8], extensions: &[Extension]) -> Result<Vec<u8>, CompressionError> {
+    let (container, rest) = parse_container(data)?;
+    if container.has_block_index() {
+        let flags = SupportedFeature::BlockIndex.bit();
+        return Err(CompressionError::UnsupportedFeatures { flags });
+    }
+    let mut out = data[..CONTAINER_LEN].to_vec();
+    add_flags(&mut out, SupportedFeature::Extensions.bit());
+    for extension in container.extensions.iter().chain(extensions) {
+        out.push(extension.kind);
+        out.extend((extension.value.len() as u16).to_le_bytes());
+        out.extend(&extension.value);
+    }
+    out.push(0);
+    out.extend(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::compress_blocks;
+    use crate::seekable::compress_seekable;
+    use crate::{compress, decompress, info};
+
+    #[test]
+    fn optional_extensions_are_skipped() {
+        let data = b"extensible, extensible".repeat(10);
+        let encoded = compress(&data);
+        let tagged = add_extensions(&encoded, &[Extension::new(0x21, "name.txt")]).unwrap();
+        let tagged = add_extensions(&tagged, &[Extension::new(0x7f, [])]).unwrap();
+        assert_eq!(tagged.len(), encoded.len() + 3 + 8 + 3 + 1);
+        assert_eq!(decompress(&tagged).unwrap(), data);
+        assert_eq!(
+            extensions(&tagged).unwrap(),
+            [Extension::new(0x21, "name.txt"), Extension::new(0x7f, [])]
+        );
+        assert_eq!(extensions(&encoded).unwrap(), []);
+
+        let joined = [&tagged[..], &encoded].concat();
+        assert_eq!(decompress(&joined).unwrap(), [&data[..], &data].concat());
+        let info = info::inspect(&tagged).unwrap();
+        assert_eq!(info.extensions.len(), 2);
+        assert_eq!(info.original_len, data.len() as u64);
+    }
+
+    #[test]
+    fn multi_block_streams_take_extensions() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7 % 61) as u8).collect();
+        let tag = [Extension::new(1, "x")];
+        let tagged = add_extensions(&compress_blocks(&data, 4096), &tag).unwrap();
+        assert_eq!(decompress(&tagged).unwrap(), data);
+        // Offsets in a block index would point before the frames.
+        assert_eq!(
+            add_extensions(&compress_seekable(&data, 4096), &tag),
+            Err(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::BlockIndex.bit()
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_must_understand_extensions_are_rejected() {
+        let encoded = compress(b"must understand");
+        let tagged = add_extensions(&encoded, &[Extension::new(0x81, [1, 2])]).unwrap();
+        assert_eq!(
+            decompress(&tagged),
+            Err(CompressionError::UnsupportedExtension { kind: 0x81 })
+        );
+        // An area cut off in the middle of an entry is truncated.
+        let end = CONTAINER_LEN + 2;
+        assert_eq!(
+            decompress(&tagged[..end]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "reserved")]
+    fn kind_zero_is_reserved() {
+        let _ = Extension::new(0, []);
+    }
+}
@@ -0,0 +1,241 @@
+//! C ABI over [`compress`] and [`decompress`], declared in
+//! `ffi/include/wfh.h`. The `compression-ffi` crate in `ffi/` builds it as
+//! a shared and a static library named `wfh`.
+//!
+//! Every function returns [`WFH_OK`] or one of the negative `WFH_ERR_*`
+//! codes. Buffers returned through an output pointer belong to the caller
+//! and must be released with [`wfh_free`].
+
+use std::ffi::{c_char, c_int};
+
+use crate::{CompressionError, STORED_OVERHEAD, compress, decompress, parse_container};
+
+pub const WFH_OK: c_int = 0;
+/// A required pointer was null.
+pub const WFH_ERR_NULL_POINTER: c_int = -1;
+/// The input is not a compressed stream.
+pub const WFH_ERR_INVALID_MAGIC: c_int = -2;
+/// The input ended before the stream did.
+pub const WFH_ERR_TRUNCATED: c_int = -3;
+/// The stream is malformed.
+pub const WFH_ERR_CORRUPT: c_int = -4;
+/// The decoded data does not match the stored checksum.
+pub const WFH_ERR_CHECKSUM: c_int = -5;
+/// The stream uses a format version or feature this build cannot read.
+pub const WFH_ERR_UNSUPPORTED: c_int = -6;
+
+/// The `WFH_ERR_*` code for `e`.
+fn error_code(e: &CompressionError) -> c_int {
+    match e {
+        CompressionError::InvalidMagic => WFH_ERR_INVALID_MAGIC,
+        CompressionError::TruncatedInput => WFH_ERR_TRUNCATED,
+        CompressionError::ChecksumMismatch { .. }
+        | CompressionError::BlockChecksumMismatch { .. } => WFH_ERR_CHECKSUM,
+        CompressionError::UnsupportedVersion { .. }
+        | CompressionError::UnsupportedFeatures { .. }
+        | CompressionError::UnsupportedExtension { .. }
+        | CompressionError::UnknownCodec { .. } => WFH_ERR_UNSUPPORTED,
+        _ => WFH_ERR_CORRUPT,
+    }
+}
+
+/// The `len` bytes at `data`, or an empty slice for a null `data` with a
+/// `len` of 0.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes unless `len` is 0.
+unsafe fn input_slice<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (data.is_null(), len) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        // SAFETY: the caller guarantees `data` is valid for `len` bytes.
+        (false, _) => Some(unsafe { std::slice::from_raw_parts(data, len) }),
+    }
+}
+
+/// Hands `bytes` to the caller through `out` and `out_len`.
+///
+/// # Safety
+///
+/// `out` and `out_len` must be valid for writes.
+unsafe fn hand_over(bytes: Vec<u8>, out: *mut *mut u8, out_len: *mut usize) {
+    let bytes = Box::into_raw(bytes.into_boxed_slice());
+    // SAFETY: the caller guarantees both pointers are valid for writes.
+    unsafe {
+        *out_len = bytes.len();
+        *out = bytes.cast();
+    }
+}
+
+/// Compresses `input_len` bytes at `input` and stores a buffer holding the
+/// stream in `*out` and its length in `*out_len`.
+///
+/// # Safety
+///
+/// `input` must be valid for reads of `input_len` bytes, or null if
+/// `input_len` is 0. `out` and `out_len` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wfh_compress(
+    input: *const u8,
+    input_len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    // SAFETY: forwarded from the caller.
+    let Some(data) = (unsafe { input_slice(input, input_len) }) else {
+        return WFH_ERR_NULL_POINTER;
+    };
+    if out.is_null() || out_len.is_null() {
+        return WFH_ERR_NULL_POINTER;
+    }
+    // SAFETY: both pointers were checked for null and are otherwise valid
+    // by the caller's guarantee.
+    unsafe { hand_over(compress(data), out, out_len) };
+    WFH_OK
+}
+
+/// Decompresses and verifies the `input_len`-byte stream at `input` and
+/// stores a buffer holding the data in `*out` and its length in
+/// `*out_len`. Nothing is stored on failure.
+///
+/// # Safety
+///
+/// As for [`wfh_compress`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wfh_decompress(
+    input: *const u8,
+    input_len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    // SAFETY: forwarded from the caller.
+    let Some(data) = (unsafe { input_slice(input, input_len) }) else {
+        return WFH_ERR_NULL_POINTER;
+    };
+    if out.is_null() || out_len.is_null() {
+        return WFH_ERR_NULL_POINTER;
+    }
+    match decompress(data) {
+        // SAFETY: as in `wfh_compress`.
+        Ok(decoded) => unsafe { hand_over(decoded, out, out_len) },
+        Err(e) => return error_code(&e),
+    }
+    WFH_OK
+}
+
+/// Releases a buffer returned by [`wfh_compress`] or [`wfh_decompress`].
+/// Does nothing for a null `data`.
+///
+/// # Safety
+///
+/// `data` and `len` must be a buffer and its length as returned by this
+/// library, not released before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wfh_free(data: *mut u8, len: usize) {
+    if data.is_null() {
+        return;
+    }
+    // SAFETY: the caller passes back a boxed slice from `hand_over`.
+    drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)) });
+}
+
+/// Largest stream [`wfh_compress`] can produce for `input_len` bytes.
+#[unsafe(no_mangle)]
+pub extern "C" fn wfh_compress_bound(input_len: usize) -> usize {
+    input_len.saturating_add(STORED_OVERHEAD)
+}
+
+/// Stores in `*len` the length the stream at `input` decodes to, read from
+/// its header without decoding it.
+///
+/// # Safety
+///
+/// `input` must be valid for reads of `input_len` bytes, or null if
+/// `input_len` is 0. `len` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wfh_decompressed_len(
+    input: *const u8,
+    input_len: usize,
+    len: *mut u64,
+) -> c_int {
+    // SAFETY: forwarded from the caller.
+    let Some(data) = (unsafe { input_slice(input, input_len) }) else {
+        return WFH_ERR_NULL_POINTER;
+    };
+    if len.is_null() {
+        return WFH_ERR_NULL_POINTER;
+    }
+    match parse_container(data) {
+        Ok((container, _)) => {
+            // SAFETY: checked for null, otherwise valid by the caller's
+            // guarantee.
+            unsafe { *len = container.original_len };
+            WFH_OK
+        }
+        Err(e) => error_code(&e),
+    }
+}
+
+/// A static, NUL-terminated description of a `WFH_*` code.
+#[unsafe(no_mangle)]
+pub extern "C" fn wfh_error_message(code: c_int) -> *const c_char {
+    let message: &'static std::ffi::CStr = match code {
+        WFH_OK => c"success",
+        WFH_ERR_NULL_POINTER => c"a required pointer was null",
+        WFH_ERR_INVALID_MAGIC => c"input is not a compressed stream",
+        WFH_ERR_TRUNCATED => c"input ended unexpectedly",
+        WFH_ERR_CORRUPT => c"compressed data is malformed",
+        WFH_ERR_CHECKSUM => c"checksum does not match the decoded data",
+        WFH_ERR_UNSUPPORTED => c"stream uses an unsupported format version or feature",
+        _ => c"unknown error code",
+    };
+    message.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    /// Runs `f` with output pointers and returns its code and the buffer.
+    fn call(
+        f: unsafe extern "C" fn(*const u8, usize, *mut *mut u8, *mut usize) -> c_int,
+        data: &[u8],
+    ) -> (c_int, Vec<u8>) {
+        let mut out = ptr::null_mut();
+        let mut out_len = 0;
+        let code = unsafe { f(data.as_ptr(), data.len(), &mut out, &mut out_len) };
+        if code != WFH_OK {
+            return (code, Vec::new());
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(out, out_len) }.to_vec();
+        unsafe { wfh_free(out, out_len) };
+        (code, bytes)
+    }
+
+    #[test]
+    fn round_trip_through_the_c_abi() {
+        let data = b"called from C through the foreign function interface";
+        let (code, compressed) = call(wfh_compress, data);
+        assert_eq!(code, WFH_OK);
+        assert_eq!(compressed, compress(data));
+        assert!(compressed.len() <= wfh_compress_bound(data.len()));
+
+        let mut len = 0;
+        let code = unsafe { wfh_decompressed_len(compressed.as_ptr(), compressed.len(), &mut len) };
+        assert_eq!((code, len), (WFH_OK, data.len() as u64));
+        assert_eq!(call(wfh_decompress, &compressed), (WFH_OK, data.to_vec()));
+    }
+
+    #[test]
+    fn errors_come_back_as_codes() {
+        assert_eq!(call(wfh_decompress, b"nope").0, WFH_ERR_INVALID_MAGIC);
+        let compressed = compress(b"some text");
+        assert_eq!(call(wfh_decompress, &compressed[..10]).0, WFH_ERR_TRUNCATED);
+        let code = unsafe { wfh_compress(ptr::null(), 4, ptr::null_mut(), ptr::null_mut()) };
+        assert_eq!(code, WFH_ERR_NULL_POINTER);
+        assert_eq!(call(wfh_compress, b"").0, WFH_OK);
+        unsafe { wfh_free(ptr::null_mut(), 0) };
+    }
+}
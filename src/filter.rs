@@ -0,0 +1,137 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+
+/// Bytes a filter takes up after the container of a filtered stream.
+pub(crate) const FILTER_LEN: usize = 2;
+
+/// A reversible pre-filter run over the data before the algorithm, for
+/// arrays of fixed-width numbers.
+///
+/// The bytes are split into `stride` lanes, byte `i` going to lane
+/// `i % stride`, and the lanes are written one after another, so that for
+/// `f32` samples and a stride of 4 all exponent bytes end up together.
+/// With `delta` every byte is then replaced by its difference from the
+/// byte before it in the same lane, which turns slowly changing sensor
+/// readings or PCM audio into runs of small values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Filter {
+    pub stride: u8,
+    pub delta: bool,
+}
+
+impl Filter {
+    /// Differences between bytes `stride` apart. Panics if `stride` is 0.
+    pub fn delta(stride: u8) -> Filter {
+        check_stride(stride);
+        Filter {
+            stride,
+            delta: true,
+        }
+    }
+
+    /// Splits the data into `stride` lanes without taking differences.
+    /// Panics if `stride` is 0.
+    pub fn interleave(stride: u8) -> Filter {
+        check_stride(stride);
+        Filter {
+            stride,
+            delta: false,
+        }
+    }
+
+    /// The lanes of `data` one after another, as differences with `delta`.
+    pub fn apply(&self, data: &[u8]) -> Vec<u8> {
+        let stride = self.stride as usize;
+        let mut out = Vec::with_capacity(data.len());
+        for lane in 0..stride.min(data.len()) {
+            let mut prev = 0u8;
+            for &b in data[lane..].iter().step_by(stride) {
+                out.push(match self.delta {
+                    true => b.wrapping_sub(prev),
+                    false => b,
+                });
+                prev = b;
+            }
+        }
+        out
+    }
+
+    /// The data `apply` was given, back from its output.
+    pub fn undo(&self, data: &[u8]) -> Vec<u8> {
+        let stride = self.stride as usize;
+        let mut out = vec![0u8; data.len()];
+        let mut filtered = data.iter();
+        for lane in 0..stride.min(data.len()) {
+            let mut prev = 0u8;
+            for b in out[lane..].iter_mut().step_by(stride) {
+                let value = *filtered.next().unwrap();
+                *b = match self.delta {
+                    true => prev.wrapping_add(value),
+                    false => value,
+                };
+                prev = *b;
+            }
+        }
+        out
+    }
+
+    /// The stride byte, then 1 for `delta` or 0.
+    pub(crate) fn to_bytes(self) -> [u8; FILTER_LEN] {
+        [self.stride, self.delta as u8]
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Filter, CompressionError> {
+        match *bytes {
+            [0, _] | [_, 2..=u8::MAX] => Err(CompressionError::CorruptHeader),
+            [stride, delta] => Ok(Filter {
+                stride,
+                delta: delta == 1,
+            }),
+            _ => Err(CompressionError::TruncatedInput),
+        }
+    }
+}
+
+pub(crate) fn check_stride(stride: u8) {
+    assert!(stride > 0, "filter stride must be at least 1");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lanes_are_split_and_differenced() {
+        let data = [1, 10, 2, 20, 4, 40, 7];
+        assert_eq!(Filter::interleave(2).apply(&data), [1, 2, 4, 7, 10, 20, 40]);
+        assert_eq!(Filter::delta(2).apply(&data), [1, 1, 2, 3, 10, 10, 20]);
+        assert_eq!(Filter::delta(1).apply(&[5, 3, 3]), [5, 254, 0]);
+        for filter in [Filter::delta(1), Filter::delta(4), Filter::interleave(3)] {
+            for len in [0, 1, 2, 7, 100] {
+                let data: Vec<u8> = (0..len).map(|i| (i * 37 % 251) as u8).collect();
+                assert_eq!(filter.undo(&filter.apply(&data)), data);
+            }
+        }
+        // More lanes than bytes leaves the bytes in place.
+        assert_eq!(Filter::interleave(8).apply(&[1, 2, 3]), [1, 2, 3]);
+    }
+
+    #[test]
+    fn bad_filter_bytes_are_rejected() {
+        assert_eq!(Filter::from_bytes(&[4, 1]), Ok(Filter::delta(4)));
+        assert_eq!(
+            Filter::from_bytes(&[0, 1]),
+            Err(CompressionError::CorruptHeader)
+        );
+        assert_eq!(
+            Filter::from_bytes(&[4, 2]),
+            Err(CompressionError::CorruptHeader)
+        );
+        assert_eq!(
+            Filter::from_bytes(&[4]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+}
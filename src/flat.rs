@@ -0,0 +1,187 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::{HuffmanTree, Node};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatNode {
+    Leaf(u8),
+    /// Indices of the children in `FlatTree::nodes`.
+    Internal {
+        left: usize,
+        right: usize,
+    },
+}
+
+/// A code tree stored in one vector in breadth-first order, with the root
+/// at index 0, so decoding follows indices instead of boxed pointers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatTree {
+    pub nodes: Vec<FlatNode>,
+}
+
+enum Pending<'a> {
+    Leaf(u8),
+    Tree(&'a HuffmanTree),
+}
+
+impl FlatTree {
+    pub fn from_huffman_tree(tree: &HuffmanTree) -> FlatTree {
+        let mut nodes = Vec::new();
+        let mut queue = VecDeque::from([Pending::Tree(tree)]);
+        while let Some(pending) = queue.pop_front() {
+            match pending {
+                Pending::Leaf(b) => nodes.push(FlatNode::Leaf(b)),
+                Pending::Tree(t) => {
+                    // The children land after every node still queued.
+                    let left = nodes.len() + 1 + queue.len();
+                    nodes.push(FlatNode::Internal {
+                        left,
+                        right: left + 1,
+                    });
+                    for child in [&t.left, &t.right] {
+                        queue.push_back(match child {
+                            Node::Leaf(b) => Pending::Leaf(*b),
+                            Node::Tree(t) => Pending::Tree(t),
+                        });
+                    }
+                }
+            }
+        }
+        FlatTree { nodes }
+    }
+
+    /// Panics if the nodes do not describe a tree `from_huffman_tree` could
+    /// have produced.
+    pub fn to_huffman_tree(&self) -> HuffmanTree {
+        self.subtree(0)
+    }
+
+    fn subtree(&self, index: usize) -> HuffmanTree {
+        let FlatNode::Internal { left, right } = self.nodes[index] else {
+            panic!("node {} is not an internal node", index);
+        };
+        HuffmanTree {
+            left: self.node(left),
+            right: self.node(right),
+        }
+    }
+
+    fn node(&self, index: usize) -> Node {
+        match self.nodes[index] {
+            FlatNode::Leaf(b) => Node::Leaf(b),
+            FlatNode::Internal { .. } => Node::Tree(Box::new(self.subtree(index))),
+        }
+    }
+
+    /// Index of the child of the internal node at `index` that `bit`
+    /// selects. Panics if that node is a leaf.
+    pub fn child(&self, index: usize, bit: bool) -> usize {
+        match self.nodes[index] {
+            FlatNode::Internal { left, right } => {
+                if bit {
+                    right
+                } else {
+                    left
+                }
+            }
+            FlatNode::Leaf(_) => panic!("node {} is a leaf", index),
+        }
+    }
+
+    /// Decodes a payload of `bits` with no padding.
+    pub fn decode(&self, bits: &[bool]) -> Result<Vec<u8>, CompressionError> {
+        let mut out = Vec::new();
+        let mut index = 0;
+        for &bit in bits {
+            let FlatNode::Internal { left, right } = self.nodes[index] else {
+                return Err(CompressionError::CorruptTree);
+            };
+            index = if bit { right } else { left };
+            if let FlatNode::Leaf(b) = self.nodes[index] {
+                out.push(b);
+                index = 0;
+            }
+        }
+        if index != 0 {
+            return Err(CompressionError::DecodingFailed);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Encoded, histogram};
+
+    /// a = 0, b = 10, c = 11
+    fn abc_tree() -> HuffmanTree {
+        let mut freq = [0u64; 256];
+        freq[b'a' as usize] = 2;
+        freq[b'b' as usize] = 1;
+        freq[b'c' as usize] = 1;
+        HuffmanTree::from_frequencies(&freq).unwrap()
+    }
+
+    fn payload_bits(encoded: &Encoded) -> Vec<bool> {
+        let total = encoded.bytes.len() * 8 - encoded.padding as usize;
+        (0..total)
+            .map(|i| (encoded.bytes[i / 8] >> (7 - i % 8)) & 1 == 1)
+            .collect()
+    }
+
+    #[test]
+    fn nodes_are_in_breadth_first_order() {
+        let flat = FlatTree::from_huffman_tree(&abc_tree());
+        assert_eq!(
+            flat.nodes,
+            vec![
+                FlatNode::Internal { left: 1, right: 2 },
+                FlatNode::Leaf(b'a'),
+                FlatNode::Internal { left: 3, right: 4 },
+                FlatNode::Leaf(b'b'),
+                FlatNode::Leaf(b'c'),
+            ]
+        );
+    }
+
+    #[test]
+    fn conversion_round_trips() {
+        let tree = HuffmanTree::from_frequencies(&histogram(b"abbcccddddeeeeefg")).unwrap();
+        let flat = FlatTree::from_huffman_tree(&tree);
+        assert_eq!(flat.to_huffman_tree().serialize(), tree.serialize());
+    }
+
+    #[test]
+    fn flat_decode_matches_tree_decode() {
+        let mut state = 0xdead_beefu32;
+        for len in [2, 10, 100, 1000] {
+            let data: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    b'a' + (state % 12) as u8
+                })
+                .collect();
+            let Some(tree) = HuffmanTree::from_frequencies(&histogram(&data)) else {
+                continue;
+            };
+            let encoded = tree.encode(&data).unwrap();
+            let flat = FlatTree::from_huffman_tree(&tree);
+            assert_eq!(
+                flat.decode(&payload_bits(&encoded)).unwrap(),
+                encoded.decode().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn decode_rejects_partial_code() {
+        let flat = FlatTree::from_huffman_tree(&abc_tree());
+        assert_eq!(flat.decode(&[true]), Err(CompressionError::DecodingFailed));
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::extension::parse_extensions;
+use crate::version::VersionNegotiator;
+use crate::{CONTAINER_LEN, Container, HuffmanTree, MAGIC};
+
+/// Reads the fields of a header from the front of a byte slice. Every read
+/// fails with [`TruncatedInput`](CompressionError::TruncatedInput) instead
+/// of panicking if the slice ends first, and leaves the cursor where it
+/// was.
+pub(crate) struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Cursor<'a> {
+        Cursor { data, position: 0 }
+    }
+
+    /// Number of bytes read so far.
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The bytes not read yet.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], CompressionError> {
+        let bytes = self
+            .rest()
+            .get(..len)
+            .ok_or(CompressionError::TruncatedInput)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], CompressionError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, CompressionError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub(crate) fn u16_le(&mut self) -> Result<u16, CompressionError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    pub(crate) fn u32_le(&mut self) -> Result<u32, CompressionError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    pub(crate) fn u64_le(&mut self) -> Result<u64, CompressionError> {
+        self.array().map(u64::from_le_bytes)
+    }
+}
+
+/// Checks the container fields at the start of `data`, and the extension
+/// area after them if the flags announce one, and returns them with the
+/// rest of `data`. Unknown optional flags are cleared.
+pub(crate) fn parse_container(data: &[u8]) -> Result<(Container, &[u8]), CompressionError> {
+    if !data.starts_with(&MAGIC[..data.len().min(MAGIC.len())]) {
+        return Err(CompressionError::InvalidMagic);
+    }
+    if data.len() < CONTAINER_LEN {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let mut cursor = Cursor::new(&data[MAGIC.len()..]);
+    let version = cursor.u8()?;
+    let flags = cursor.u32_le()?;
+    let negotiator = VersionNegotiator::current();
+    negotiator.check(version, flags)?;
+    let mut container = Container {
+        original_len: cursor.u64_le()?,
+        flags: flags & negotiator.known_flags(),
+        extensions: Vec::new(),
+        header_len: CONTAINER_LEN,
+    };
+    if container.has_extensions() {
+        container.extensions = parse_extensions(&mut cursor)?;
+        container.header_len = MAGIC.len() + cursor.position();
+    }
+    Ok((container, cursor.rest()))
+}
+
+/// The tree section of a stream header, as parsed by `parse_tree_section`.
+pub(crate) struct TreeSection {
+    /// Leaves left to right. Empty data has none and single-symbol data
+    /// one, in which case there is no tree.
+    pub(crate) leaves: Vec<u8>,
+    pub(crate) tree: Option<HuffmanTree>,
+    /// Number of bytes the section takes up.
+    pub(crate) len: usize,
+}
+
+/// Parses the tree section at the start of `data`, in the canonical layout
+/// or the older one with a depth per leaf; whatever follows it is ignored.
+pub(crate) fn parse_tree_section(
+    data: &[u8],
+    canonical: bool,
+) -> Result<TreeSection, CompressionError> {
+    let mut cursor = Cursor::new(data);
+    let n = cursor.u16_le()? as usize;
+    if n > 256 {
+        return Err(CompressionError::CorruptTree);
+    }
+    let leaves = cursor.bytes(n)?;
+    let mut seen = [false; 256];
+    for &b in leaves {
+        if core::mem::replace(&mut seen[b as usize], true) {
+            return Err(CompressionError::CorruptTree);
+        }
+    }
+    // The canonical layout stores the longest code length and the counts
+    // of the shorter ones, the older layout a depth per leaf.
+    let tree = match n {
+        0 | 1 => None,
+        _ if canonical => {
+            let start = cursor.position();
+            let longest = cursor.u8()? as usize;
+            // A code of length 0 cannot tell two leaves apart.
+            let shorter = longest
+                .checked_sub(1)
+                .ok_or(CompressionError::CorruptTree)?;
+            cursor.bytes(shorter)?;
+            let counts = &data[start..cursor.position()];
+            Some(HuffmanTree::from_canonical_counts(leaves, counts)?)
+        }
+        _ => Some(HuffmanTree::from_leaf_depths(leaves, cursor.bytes(n)?)?),
+    };
+    Ok(TreeSection {
+        leaves: leaves.to_vec(),
+        tree,
+        len: cursor.position(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_reads_little_endian_fields() {
+        let data = [1, 2, 0, 3, 0, 0, 0, 9];
+        let mut cursor = Cursor::new(&data);
+        assert_eq!(cursor.u8(), Ok(1));
+        assert_eq!(cursor.u16_le(), Ok(2));
+        assert_eq!(cursor.u32_le(), Ok(3));
+        assert_eq!(cursor.u64_le(), Err(CompressionError::TruncatedInput));
+        // A failed read consumes nothing.
+        assert_eq!(cursor.position(), 7);
+        assert_eq!(cursor.bytes(1), Ok(&[9][..]));
+        assert_eq!(cursor.rest(), &[]);
+    }
+
+    #[test]
+    fn malformed_tree_sections_are_typed() {
+        let cases: [(&[u8], CompressionError); 6] = [
+            (&[3], CompressionError::TruncatedInput),
+            (&[3, 0, b'a', b'b'], CompressionError::TruncatedInput),
+            (&[2, 0, b'a', b'b'], CompressionError::TruncatedInput),
+            (&[2, 1], CompressionError::CorruptTree),
+            (&[2, 0, b'a', b'a', 1], CompressionError::CorruptTree),
+            // A longest code length of 0 leaves no bits to tell `a` and
+            // `b` apart.
+            (&[2, 0, b'a', b'b', 0], CompressionError::CorruptTree),
+        ];
+        for (section, expected) in cases {
+            assert_eq!(
+                parse_tree_section(section, true).err(),
+                Some(expected),
+                "{:?}",
+                section
+            );
+        }
+        let section = parse_tree_section(&[2, 0, b'a', b'b', 1, 0xff], true).unwrap();
+        assert_eq!((section.leaves, section.len), (vec![b'a', b'b'], 5));
+    }
+}
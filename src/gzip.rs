@@ -0,0 +1,660 @@
+use alloc::collections::BinaryHeap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::checksum::crc32;
+use crate::error::CompressionError;
+use crate::lzss::{MIN_MATCH, MatchFinder};
+use crate::package_merge::package_merge;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const METHOD_DEFLATE: u8 = 8;
+const GZIP_HEADER_LEN: usize = 10;
+const GZIP_TRAILER_LEN: usize = 8;
+/// Operating system byte of the header: unknown.
+const OS_UNKNOWN: u8 = 255;
+const FLAG_HCRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const RESERVED_FLAGS: u8 = 0xe0;
+
+const END_OF_BLOCK: usize = 256;
+const LITERAL_CODES: usize = 286;
+const DISTANCE_CODES: usize = 30;
+const CODE_LENGTH_CODES: usize = 19;
+const MAX_CODE_LEN: u8 = 15;
+const MAX_CODE_LENGTH_LEN: u8 = 7;
+/// Number of tokens coded with one pair of trees.
+const BLOCK_TOKENS: usize = 1 << 16;
+/// Matches tried per position, as `lzss_encode` does.
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which a dynamic block lists the lengths of the code length
+/// codes.
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Returns whether `data` starts with the gzip magic.
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+/// Wraps the DEFLATE stream of `data` in a gzip member (RFC 1952) with no
+/// file name or modification time, which `gunzip` decompresses.
+pub fn gzip_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = GZIP_MAGIC.to_vec();
+    out.extend([METHOD_DEFLATE, 0, 0, 0, 0, 0, 0, OS_UNKNOWN]);
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Decodes a gzip file, which may hold several members one after another,
+/// as `gunzip` does. The optional header fields are skipped and the CRC-32
+/// and length of every member are checked.
+pub fn gzip_decode(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    if !is_gzip(data) {
+        return Err(CompressionError::InvalidMagic);
+    }
+    let mut out = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let body = skip_member_header(rest)?;
+        let (member, used) = inflate(body)?;
+        let trailer = body
+            .get(used..used + GZIP_TRAILER_LEN)
+            .ok_or(CompressionError::TruncatedInput)?;
+        let expected = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+        let actual = crc32(&member);
+        if actual != expected {
+            return Err(CompressionError::ChecksumMismatch { expected, actual });
+        }
+        let len = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+        if len != member.len() as u32 {
+            return Err(CompressionError::LengthMismatch {
+                expected: len as u64,
+                actual: member.len() as u64,
+            });
+        }
+        out.extend(member);
+        rest = &body[used + GZIP_TRAILER_LEN..];
+    }
+    Ok(out)
+}
+
+/// Checks the fixed header of a gzip member and returns what follows its
+/// optional fields.
+fn skip_member_header(data: &[u8]) -> Result<&[u8], CompressionError> {
+    if !is_gzip(data) {
+        return Err(CompressionError::CorruptData);
+    }
+    if data.len() < GZIP_HEADER_LEN {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let flags = data[3];
+    if data[2] != METHOD_DEFLATE || flags & RESERVED_FLAGS != 0 {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let mut pos = GZIP_HEADER_LEN;
+    if flags & FLAG_EXTRA != 0 {
+        let len = data
+            .get(pos..pos + 2)
+            .ok_or(CompressionError::TruncatedInput)?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|field| field.iter().position(|&b| b == 0))
+                .ok_or(CompressionError::TruncatedInput)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+    data.get(pos..).ok_or(CompressionError::TruncatedInput)
+}
+
+/// A literal byte or a copy of earlier output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, dist: u16 },
+}
+
+/// Encodes `data` as a raw DEFLATE stream (RFC 1951) of dynamic Huffman
+/// blocks. Matches come from the LZSS match finder, so they reach at most
+/// 4 KiB back and are at most 18 bytes long.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let tokens = tokenize(data);
+    let mut bits = LsbWriter::default();
+    let blocks: Vec<&[Token]> = match tokens.len() {
+        0 => vec![&[]],
+        _ => tokens.chunks(BLOCK_TOKENS).collect(),
+    };
+    for (i, block) in blocks.iter().enumerate() {
+        write_block(&mut bits, block, i + 1 == blocks.len());
+    }
+    bits.finish()
+}
+
+fn tokenize(data: &[u8]) -> Vec<Token> {
+    let mut finder = MatchFinder::new(data, MAX_CHAIN);
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, dist) = finder.longest_match(pos);
+        let next = if len >= MIN_MATCH {
+            tokens.push(Token::Match {
+                len: len as u16,
+                dist: dist as u16,
+            });
+            pos + len
+        } else {
+            tokens.push(Token::Literal(data[pos]));
+            pos + 1
+        };
+        for p in pos..next {
+            finder.insert(p);
+        }
+        pos = next;
+    }
+    tokens
+}
+
+/// Index of the last entry of `table` that is at most `value`, with the
+/// extra bits that select `value` from there.
+fn bucket(table: &[u16], value: u16) -> (usize, u16) {
+    let index = table.partition_point(|&base| base <= value) - 1;
+    (index, value - table[index])
+}
+
+fn write_block(bits: &mut LsbWriter, tokens: &[Token], last: bool) {
+    let mut literal_freq = [0u64; LITERAL_CODES];
+    let mut distance_freq = [0u64; DISTANCE_CODES];
+    literal_freq[END_OF_BLOCK] = 1;
+    for &token in tokens {
+        match token {
+            Token::Literal(b) => literal_freq[b as usize] += 1,
+            Token::Match { len, dist } => {
+                literal_freq[END_OF_BLOCK + 1 + bucket(&LENGTH_BASE, len).0] += 1;
+                distance_freq[bucket(&DISTANCE_BASE, dist).0] += 1;
+            }
+        }
+    }
+    let literal_lens = code_lengths(&literal_freq, MAX_CODE_LEN);
+    let distance_lens = code_lengths(&distance_freq, MAX_CODE_LEN);
+    let hlit = used_len(&literal_lens).max(END_OF_BLOCK + 1);
+    let hdist = used_len(&distance_lens).max(1);
+
+    let lens = [&literal_lens[..hlit], &distance_lens[..hdist]].concat();
+    let runs = run_length_encode(&lens);
+    let mut run_freq = [0u64; CODE_LENGTH_CODES];
+    for &(symbol, _) in &runs {
+        run_freq[symbol as usize] += 1;
+    }
+    let run_lens = code_lengths(&run_freq, MAX_CODE_LENGTH_LEN);
+    let hclen = CODE_LENGTH_ORDER
+        .iter()
+        .rposition(|&symbol| run_lens[symbol] != 0)
+        .map_or(0, |i| i + 1)
+        .max(4);
+
+    bits.push(last as u32, 1);
+    bits.push(2, 2);
+    bits.push((hlit - 257) as u32, 5);
+    bits.push((hdist - 1) as u32, 5);
+    bits.push((hclen - 4) as u32, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..hclen] {
+        bits.push(run_lens[symbol] as u32, 3);
+    }
+    let run_codes = canonical_codes(&run_lens);
+    for (symbol, extra) in runs {
+        let symbol = symbol as usize;
+        bits.push_code(run_codes[symbol], run_lens[symbol]);
+        match symbol {
+            16 => bits.push(extra as u32, 2),
+            17 => bits.push(extra as u32, 3),
+            18 => bits.push(extra as u32, 7),
+            _ => {}
+        }
+    }
+
+    let literal_codes = canonical_codes(&literal_lens);
+    let distance_codes = canonical_codes(&distance_lens);
+    for &token in tokens {
+        match token {
+            Token::Literal(b) => {
+                bits.push_code(literal_codes[b as usize], literal_lens[b as usize]);
+            }
+            Token::Match { len, dist } => {
+                let (index, extra) = bucket(&LENGTH_BASE, len);
+                let symbol = END_OF_BLOCK + 1 + index;
+                bits.push_code(literal_codes[symbol], literal_lens[symbol]);
+                bits.push(extra as u32, LENGTH_EXTRA[index] as u32);
+                let (index, extra) = bucket(&DISTANCE_BASE, dist);
+                bits.push_code(distance_codes[index], distance_lens[index]);
+                bits.push(extra as u32, DISTANCE_EXTRA[index] as u32);
+            }
+        }
+    }
+    bits.push_code(literal_codes[END_OF_BLOCK], literal_lens[END_OF_BLOCK]);
+}
+
+/// Number of entries up to and including the last nonzero one.
+fn used_len(lens: &[u8]) -> usize {
+    lens.iter().rposition(|&len| len != 0).map_or(0, |i| i + 1)
+}
+
+/// Code lengths as a dynamic block lists them: symbol 16 repeats the
+/// previous length 3 to 6 times, 17 and 18 give 3 to 10 and 11 to 138
+/// zeros. Each entry is a symbol and the value of its extra bits.
+fn run_length_encode(lens: &[u8]) -> Vec<(u8, u8)> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < lens.len() {
+        let len = lens[i];
+        let run = lens[i..].iter().take_while(|&&l| l == len).count();
+        if len == 0 && run >= 11 {
+            let run = run.min(138);
+            runs.push((18, (run - 11) as u8));
+            i += run;
+        } else if len == 0 && run >= 3 {
+            runs.push((17, (run - 3) as u8));
+            i += run;
+        } else if len != 0 && run >= 4 {
+            // The first length is sent as itself, the rest as repeats.
+            runs.push((len, 0));
+            let repeat = (run - 1).min(6);
+            runs.push((16, (repeat - 3) as u8));
+            i += 1 + repeat;
+        } else {
+            runs.push((len, 0));
+            i += 1;
+        }
+    }
+    runs
+}
+
+/// Huffman code lengths of at most `limit` bits for `freq`. Every code
+/// gets at least two symbols, since decoders reject a lone one-bit code.
+/// When the Huffman code is too deep, package-merge gives the best code
+/// that fits.
+fn code_lengths(freq: &[u64], limit: u8) -> Vec<u8> {
+    let mut freq = freq.to_vec();
+    for symbol in 0..freq.len() {
+        if freq.iter().filter(|&&f| f > 0).count() >= 2 {
+            break;
+        }
+        freq[symbol] = freq[symbol].max(1);
+    }
+    let lens = huffman_lengths(&freq);
+    if lens.iter().all(|&len| len <= limit) {
+        return lens;
+    }
+    package_merge(&freq, limit)
+}
+
+/// Depth of every symbol in a Huffman tree over `freq`, 0 for symbols that
+/// do not occur.
+pub(crate) fn huffman_lengths(freq: &[u64]) -> Vec<u8> {
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = freq
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| **f > 0)
+        .map(|(symbol, &f)| Reverse((f, symbol)))
+        .collect();
+    let mut parent = vec![usize::MAX; freq.len()];
+    while heap.len() > 1 {
+        let Reverse((left_weight, left)) = heap.pop().unwrap();
+        let Reverse((right_weight, right)) = heap.pop().unwrap();
+        let node = parent.len();
+        parent.push(usize::MAX);
+        parent[left] = node;
+        parent[right] = node;
+        heap.push(Reverse((left_weight + right_weight, node)));
+    }
+    (0..freq.len())
+        .map(|symbol| {
+            let mut depth = 0;
+            let mut node = symbol;
+            while parent[node] != usize::MAX {
+                node = parent[node];
+                depth += 1;
+            }
+            depth
+        })
+        .collect()
+}
+
+/// Canonical codes for `lens`, as RFC 1951 assigns them.
+fn canonical_codes(lens: &[u8]) -> Vec<u16> {
+    let mut count = [0u16; MAX_CODE_LEN as usize + 1];
+    for &len in lens {
+        count[len as usize] += 1;
+    }
+    count[0] = 0;
+    let mut next = [0u16; MAX_CODE_LEN as usize + 1];
+    for len in 1..=MAX_CODE_LEN as usize {
+        next[len] = (next[len - 1] + count[len - 1]) << 1;
+    }
+    lens.iter()
+        .map(|&len| {
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+/// Packs bits least significant first, as DEFLATE stores everything but
+/// Huffman codes.
+#[derive(Default)]
+struct LsbWriter {
+    out: Vec<u8>,
+    acc: u64,
+    filled: u32,
+}
+
+impl LsbWriter {
+    fn push(&mut self, value: u32, count: u32) {
+        self.acc |= (value as u64) << self.filled;
+        self.filled += count;
+        while self.filled >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.filled -= 8;
+        }
+    }
+
+    /// Pushes a Huffman code, which DEFLATE stores most significant bit
+    /// first.
+    fn push_code(&mut self, code: u16, len: u8) {
+        let reversed = code.reverse_bits() >> (16 - len as u32);
+        self.push(reversed as u32, len as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.filled > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// Decodes a raw DEFLATE stream and returns the output with the number of
+/// bytes of `data` the stream took up, rounded up to a whole byte.
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), CompressionError> {
+    let mut bits = LsbReader { data, pos: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => inflate_stored(&mut bits, &mut out)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_codes(&mut bits, &literals, &distances, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut bits)?;
+                inflate_codes(&mut bits, &literals, &distances, &mut out)?;
+            }
+            _ => return Err(CompressionError::CorruptData),
+        }
+        if last {
+            return Ok((out, bits.pos.div_ceil(8) as usize));
+        }
+    }
+}
+
+fn inflate_stored(bits: &mut LsbReader, out: &mut Vec<u8>) -> Result<(), CompressionError> {
+    bits.align_to_byte();
+    let len = bits.read(16)?;
+    let complement = bits.read(16)?;
+    if len != !complement & 0xffff {
+        return Err(CompressionError::CorruptData);
+    }
+    let start = (bits.pos / 8) as usize;
+    let block = bits
+        .data
+        .get(start..start + len as usize)
+        .ok_or(CompressionError::TruncatedInput)?;
+    out.extend_from_slice(block);
+    bits.pos += len as u64 * 8;
+    Ok(())
+}
+
+fn fixed_codes() -> (Decoder, Decoder) {
+    let mut lens = [8u8; 288];
+    lens[144..256].fill(9);
+    lens[256..280].fill(7);
+    let literals = Decoder::new(&lens).expect("the fixed code is complete");
+    let distances = Decoder::new(&[5; DISTANCE_CODES]).expect("the fixed code is complete");
+    (literals, distances)
+}
+
+fn read_dynamic_codes(bits: &mut LsbReader) -> Result<(Decoder, Decoder), CompressionError> {
+    let hlit = bits.read(5)? as usize + 257;
+    let hdist = bits.read(5)? as usize + 1;
+    let hclen = bits.read(4)? as usize + 4;
+    if hlit > LITERAL_CODES || hdist > DISTANCE_CODES {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let mut run_lens = [0u8; CODE_LENGTH_CODES];
+    for &symbol in &CODE_LENGTH_ORDER[..hclen] {
+        run_lens[symbol] = bits.read(3)? as u8;
+    }
+    let runs = Decoder::new(&run_lens)?;
+    let mut lens = Vec::with_capacity(hlit + hdist);
+    while lens.len() < hlit + hdist {
+        let (len, repeat) = match runs.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lens.last().ok_or(CompressionError::CorruptHeader)?;
+                (previous, 3 + bits.read(2)?)
+            }
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        if lens.len() + repeat as usize > hlit + hdist {
+            return Err(CompressionError::CorruptHeader);
+        }
+        lens.extend(core::iter::repeat_n(len, repeat as usize));
+    }
+    if lens[END_OF_BLOCK] == 0 {
+        return Err(CompressionError::CorruptHeader);
+    }
+    Ok((Decoder::new(&lens[..hlit])?, Decoder::new(&lens[hlit..])?))
+}
+
+fn inflate_codes(
+    bits: &mut LsbReader,
+    literals: &Decoder,
+    distances: &Decoder,
+    out: &mut Vec<u8>,
+) -> Result<(), CompressionError> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < END_OF_BLOCK {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+        let index = symbol - END_OF_BLOCK - 1;
+        if index >= LENGTH_BASE.len() {
+            return Err(CompressionError::CorruptData);
+        }
+        let len = LENGTH_BASE[index] as usize + bits.read(LENGTH_EXTRA[index] as u32)? as usize;
+        let index = distances.decode(bits)? as usize;
+        if index >= DISTANCE_BASE.len() {
+            return Err(CompressionError::CorruptData);
+        }
+        let dist =
+            DISTANCE_BASE[index] as usize + bits.read(DISTANCE_EXTRA[index] as u32)? as usize;
+        if dist > out.len() {
+            return Err(CompressionError::CorruptData);
+        }
+        for _ in 0..len {
+            out.push(out[out.len() - dist]);
+        }
+    }
+}
+
+/// Reads bits least significant first. Like [`BitReader`](crate::bits::BitReader)
+/// it counts bits in a `u64`.
+struct LsbReader<'a> {
+    data: &'a [u8],
+    pos: u64,
+}
+
+impl LsbReader<'_> {
+    fn read(&mut self, count: u32) -> Result<u32, CompressionError> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self
+                .data
+                .get((self.pos / 8) as usize)
+                .ok_or(CompressionError::TruncatedInput)?;
+            value |= ((*byte as u32 >> (self.pos % 8)) & 1) << i;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.pos = self.pos.next_multiple_of(8);
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length
+/// and the symbols in code order.
+struct Decoder {
+    counts: [u16; MAX_CODE_LEN as usize + 1],
+    symbols: Vec<u16>,
+}
+
+impl Decoder {
+    /// Fails if `lens` asks for more codes of some length than fit. Codes
+    /// may be left unused, which only fails once such a code is read.
+    fn new(lens: &[u8]) -> Result<Decoder, CompressionError> {
+        let mut counts = [0u16; MAX_CODE_LEN as usize + 1];
+        for &len in lens {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(CompressionError::CorruptTree);
+            }
+        }
+        let mut symbols = Vec::new();
+        for len in 1..=MAX_CODE_LEN {
+            for (symbol, _) in lens.iter().enumerate().filter(|(_, l)| **l == len) {
+                symbols.push(symbol as u16);
+            }
+        }
+        Ok(Decoder { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut LsbReader) -> Result<u16, CompressionError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.read(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(CompressionError::CorruptData)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `printf 'hello hello hello gzip\n' | gzip -n`.
+    const GNU_GZIP: [u8; 33] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x40, 0x22, 0xd3, 0xab, 0x32, 0x0b, 0xb8, 0x00, 0x9a, 0x8b, 0x73, 0xda, 0x17,
+        0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn round_trip_gzip() {
+        let text = b"DEFLATE interop: DEFLATE interop, gzip and gunzip".repeat(50);
+        let varied: Vec<u8> = (0..100_000u64).map(|i| (i * i % 251) as u8).collect();
+        for data in [&b""[..], b"a", &text, &varied] {
+            let encoded = gzip_encode(data);
+            assert!(is_gzip(&encoded));
+            assert_eq!(gzip_decode(&encoded).unwrap(), data);
+        }
+        assert!(gzip_encode(&text).len() < text.len() / 4);
+    }
+
+    #[test]
+    fn decodes_gzip_output() {
+        assert_eq!(gzip_decode(&GNU_GZIP).unwrap(), b"hello hello hello gzip\n");
+    }
+
+    #[test]
+    fn concatenated_members_decode_to_one_output() {
+        let joined = [gzip_encode(b"first, "), gzip_encode(b"second")].concat();
+        assert_eq!(gzip_decode(&joined).unwrap(), b"first, second");
+    }
+
+    #[test]
+    fn corrupt_trailer_is_rejected() {
+        let mut encoded = gzip_encode(b"checked by crc");
+        let crc_at = encoded.len() - GZIP_TRAILER_LEN;
+        encoded[crc_at] ^= 1;
+        assert!(matches!(
+            gzip_decode(&encoded),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            gzip_decode(&encoded[..encoded.len() - 3]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn deep_codes_are_limited() {
+        // Fibonacci frequencies give a code as deep as there are symbols.
+        let mut freq = vec![1u64, 1];
+        while freq.len() < 30 {
+            freq.push(freq[freq.len() - 1] + freq[freq.len() - 2]);
+        }
+        let lens = code_lengths(&freq, MAX_CODE_LEN);
+        assert!(lens.iter().all(|&len| (1..=MAX_CODE_LEN).contains(&len)));
+        assert!(Decoder::new(&lens).is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bits::{BitReader, BitWriter};
+use crate::error::CompressionError;
+use crate::{compress, decompress};
+
+const LENGTH_LEN: usize = 8;
+
+/// Encodes `data` in two levels: the popcount of every byte is Huffman
+/// coded, then each byte's rank among the bytes sharing its popcount is
+/// written with a fixed width for that class (0 bits for 0x00 and 0xFF).
+///
+/// Layout: the length of the coded popcounts as a little-endian `u64`, the
+/// coded popcounts, then the packed ranks.
+pub fn hamming_weight_encode(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let classes = PopcountClasses::new();
+    let popcounts: Vec<u8> = data.iter().map(|b| b.count_ones() as u8).collect();
+    let coded = compress(&popcounts);
+
+    let mut residuals = BitWriter::new();
+    for &b in data {
+        let width = classes.width(b.count_ones() as u8);
+        let rank = classes.rank[b as usize];
+        residuals.push_value(rank as u64, width);
+    }
+
+    let mut out = (coded.len() as u64).to_le_bytes().to_vec();
+    out.extend(coded);
+    out.extend(residuals.into_bytes());
+    Ok(out)
+}
+
+pub fn hamming_weight_decode(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    if data.len() < LENGTH_LEN {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let (len, rest) = data.split_at(LENGTH_LEN);
+    let len = u64::from_le_bytes(len.try_into().unwrap());
+    if (rest.len() as u64) < len {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let (coded, residuals) = rest.split_at(len as usize);
+    let popcounts = decompress(coded)?;
+
+    let classes = PopcountClasses::new();
+    let mut bits = BitReader::new(residuals);
+    let mut out = Vec::with_capacity(popcounts.len());
+    for popcount in popcounts {
+        let members = classes
+            .members
+            .get(popcount as usize)
+            .ok_or(CompressionError::CorruptData)?;
+        let rank = bits
+            .read_bits(classes.width(popcount))
+            .ok_or(CompressionError::TruncatedInput)?;
+        out.push(
+            *members
+                .get(rank as usize)
+                .ok_or(CompressionError::CorruptData)?,
+        );
+    }
+    Ok(out)
+}
+
+struct PopcountClasses {
+    /// The bytes of each popcount, in ascending order.
+    members: Vec<Vec<u8>>,
+    /// Index of every byte within its class.
+    rank: [u8; 256],
+}
+
+impl PopcountClasses {
+    fn new() -> PopcountClasses {
+        let mut members = vec![Vec::new(); 9];
+        let mut rank = [0u8; 256];
+        for b in 0..=255u8 {
+            let class = &mut members[b.count_ones() as usize];
+            rank[b as usize] = class.len() as u8;
+            class.push(b);
+        }
+        PopcountClasses { members, rank }
+    }
+
+    /// Bits needed to store a rank within the class of `popcount`.
+    fn width(&self, popcount: u8) -> u32 {
+        let size = self.members[popcount as usize].len();
+        usize::BITS - (size - 1).leading_zeros()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mostly_zero_bytes(n: usize) -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                if state.is_multiple_of(5) {
+                    (state >> 8) as u8
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_all_byte_values() {
+        let data: Vec<u8> = (0..=255).collect();
+        let encoded = hamming_weight_encode(&data).unwrap();
+        assert_eq!(hamming_weight_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let encoded = hamming_weight_encode(b"").unwrap();
+        assert_eq!(hamming_weight_decode(&encoded).unwrap(), b"");
+    }
+
+    #[test]
+    fn beats_flat_huffman_on_mostly_zero_data() {
+        let data = mostly_zero_bytes(10_000);
+        let encoded = hamming_weight_encode(&data).unwrap();
+        assert_eq!(hamming_weight_decode(&encoded).unwrap(), data);
+        assert!(encoded.len() < compress(&data).len());
+    }
+
+    #[test]
+    fn truncated_residuals_are_rejected() {
+        let encoded = hamming_weight_encode(b"\x01\x03\x07").unwrap();
+        assert_eq!(
+            hamming_weight_decode(&encoded[..encoded.len() - 1]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+}
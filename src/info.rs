@@ -0,0 +1,265 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::extension::Extension;
+use crate::filter::Filter;
+use crate::{
+    Algorithm, Encoded, HuffmanTree, block, histogram, parse_container, parse_tree_section,
+};
+
+/// What the header of a compressed stream says, as read by [`inspect`].
+#[derive(Debug, PartialEq)]
+pub struct StreamInfo {
+    pub version: u8,
+    pub algorithm: Algorithm,
+    /// Whether the data is stored without Huffman coding.
+    pub stored: bool,
+    /// The length recorded in the container. With an LZSS or RLE stage it
+    /// is the length of the stage output, not of the original data.
+    pub original_len: u64,
+    pub compressed_len: u64,
+    /// The Huffman-coded blocks, one for a single stream and none for a
+    /// stored or adaptive stream.
+    pub blocks: Vec<BlockInfo>,
+    pub checksum: Option<u32>,
+    /// The pre-filter of a filtered stream. The other fields then describe
+    /// the stream it wraps, except `compressed_len`, which is the whole.
+    pub filter: Option<Filter>,
+    /// The entries of the extension area, including optional ones of kinds
+    /// this build does not know.
+    pub extensions: Vec<Extension>,
+}
+
+/// The sizes of one Huffman-coded block.
+#[derive(Debug, PartialEq)]
+pub struct BlockInfo {
+    pub original_len: u64,
+    /// Bytes taken up by the tree section.
+    pub tree_len: usize,
+    pub payload_len: u64,
+    /// Padding bits at the end of the payload.
+    pub padding: u8,
+}
+
+/// Reads the container, tree sections and frame headers of `data` without
+/// decoding any payload.
+pub fn inspect(data: &[u8]) -> Result<StreamInfo, CompressionError> {
+    let (container, rest) = parse_container(data)?;
+    container.check_builtin()?;
+    if container.has_filter() {
+        let (filter, inner) = container.split_filter(rest)?;
+        return Ok(StreamInfo {
+            compressed_len: data.len() as u64,
+            filter: Some(filter),
+            extensions: container.extensions,
+            ..inspect(inner)?
+        });
+    }
+    let stored = container.has_stored();
+    let algorithm = container.algorithm();
+
+    let (blocks, checksum) =
+        if stored || container.has_adaptive() || container.has_range() || container.has_words() {
+            (Vec::new(), container.split_footer(rest)?.1)
+        } else if container.has_blocks() {
+            let (mut frames, checksum) = container.split_footer(rest)?;
+            let mut blocks = Vec::new();
+            let mut counted = 0;
+            while counted < container.original_len {
+                let frame = block::read_frame(&mut frames, &container)?;
+                counted += frame.original_len;
+                blocks.push(block_info(&frame));
+            }
+            if container.has_block_index() {
+                let index_len = blocks.len() * block::INDEX_ENTRY_LEN + 4;
+                frames = frames
+                    .get(index_len..)
+                    .ok_or(CompressionError::TruncatedInput)?;
+            }
+            if !frames.is_empty() {
+                return Err(CompressionError::TrailingData);
+            }
+            (blocks, checksum)
+        } else {
+            let encoded = Encoded::from_container(&container, rest)?;
+            (vec![block_info(&encoded)], encoded.checksum)
+        };
+    if container.has_checksum() && checksum.is_none() {
+        return Err(CompressionError::TruncatedInput);
+    }
+    Ok(StreamInfo {
+        version: data[4],
+        algorithm,
+        stored,
+        original_len: container.original_len,
+        compressed_len: data.len() as u64,
+        blocks,
+        checksum,
+        filter: None,
+        extensions: container.extensions,
+    })
+}
+
+/// The code of one Huffman-coded block, as read by [`block_trees`].
+pub struct BlockTree {
+    /// `None` when the block holds at most one distinct byte, which needs
+    /// no tree.
+    pub tree: Option<HuffmanTree>,
+    /// The bytes with a code, in the order the header lists them.
+    pub leaves: Vec<u8>,
+    /// How often every byte occurs in the block, after any LZSS, RLE or BWT
+    /// stage, as the tree was built for.
+    pub frequencies: [u64; 256],
+}
+
+/// The tree of every Huffman-coded block of `data`, with the frequencies
+/// it was built from, counted by decoding the block. Empty for stored,
+/// adaptive, range-coded and word streams, which have no byte tree.
+pub fn block_trees(data: &[u8]) -> Result<Vec<BlockTree>, CompressionError> {
+    let (container, rest) = parse_container(data)?;
+    container.check_builtin()?;
+    if container.has_filter() {
+        return block_trees(container.split_filter(rest)?.1);
+    }
+    if container.has_stored()
+        || container.has_adaptive()
+        || container.has_range()
+        || container.has_words()
+    {
+        return Ok(Vec::new());
+    }
+    let blocks = if container.has_blocks() {
+        let (mut frames, _) = container.split_footer(rest)?;
+        let mut blocks = Vec::new();
+        let mut counted = 0;
+        while counted < container.original_len {
+            let frame = block::read_frame(&mut frames, &container)?;
+            counted += frame.original_len;
+            blocks.push(frame);
+        }
+        blocks
+    } else {
+        vec![Encoded::from_container(&container, rest)?]
+    };
+    blocks
+        .iter()
+        .map(|encoded| {
+            let section = parse_tree_section(&encoded.tree, encoded.canonical)?;
+            Ok(BlockTree {
+                tree: section.tree,
+                leaves: section.leaves,
+                frequencies: histogram(&encoded.decode_unverified()?),
+            })
+        })
+        .collect()
+}
+
+fn block_info(encoded: &Encoded) -> BlockInfo {
+    BlockInfo {
+        original_len: encoded.original_len,
+        tree_len: encoded.tree.len(),
+        payload_len: encoded.bytes.len() as u64,
+        padding: encoded.padding,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::compress_blocks;
+    use crate::{CHECKSUM_LEN, CONTAINER_LEN, CompressionOptions, compress, compress_with};
+
+    #[test]
+    fn single_stream_info() {
+        let data = &b"inspect the header of this stream".repeat(5);
+        let encoded = compress(data);
+        let info = inspect(&encoded).unwrap();
+        assert_eq!(info.algorithm, Algorithm::Huffman);
+        assert_eq!(info.original_len, data.len() as u64);
+        assert_eq!(info.compressed_len, encoded.len() as u64);
+        assert_eq!(info.checksum, Some(crate::crc32(data)));
+        let [block] = &info.blocks[..] else {
+            panic!("expected one block");
+        };
+        assert_eq!(
+            CONTAINER_LEN + 1 + block.tree_len + block.payload_len as usize + CHECKSUM_LEN,
+            encoded.len()
+        );
+    }
+
+    #[test]
+    fn block_and_stage_info() {
+        let data = b"split into several blocks".repeat(4);
+        let info = inspect(&compress_blocks(&data, 30)).unwrap();
+        assert_eq!(info.blocks.len(), 4);
+        assert_eq!(info.blocks.iter().map(|b| b.original_len).sum::<u64>(), 100);
+        let indexed = inspect(&crate::seekable::compress_seekable(&data, 30)).unwrap();
+        assert_eq!(indexed.blocks, info.blocks);
+
+        let options = CompressionOptions {
+            algorithm: Algorithm::Rle,
+            ..Default::default()
+        };
+        let info = inspect(&compress_with(b"aaaabbbb", &options)).unwrap();
+        assert_eq!(info.algorithm, Algorithm::Rle);
+        assert!(info.stored && info.blocks.is_empty());
+
+        let options = CompressionOptions {
+            filter: Some(Filter::delta(2)),
+            ..options
+        };
+        let encoded = compress_with(b"aaaabbbb", &options);
+        let info = inspect(&encoded).unwrap();
+        assert_eq!(info.filter, Some(Filter::delta(2)));
+        assert_eq!(info.algorithm, Algorithm::Rle);
+        assert_eq!(info.compressed_len, encoded.len() as u64);
+    }
+
+    #[test]
+    fn trees_come_with_their_frequencies() {
+        let data = b"aaaabbc".repeat(10);
+        let trees = block_trees(&compress_blocks(&data, 35)).unwrap();
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[0].frequencies[b'a' as usize], 20);
+        assert_eq!(trees[0].leaves, b"abc");
+        let table = trees[0]
+            .tree
+            .as_ref()
+            .unwrap()
+            .code_table(&trees[0].frequencies);
+        assert_eq!(
+            table,
+            "symbol  len   frequency  code\n\
+             'a'     1            20  0\n\
+             'b'     2            10  10\n\
+             'c'     2             5  11\n"
+        );
+        let dot = trees[1].tree.as_ref().unwrap().to_dot();
+        assert!(dot.starts_with("digraph huffman {\n"));
+        assert!(dot.contains("label=\"'c'\""));
+        assert_eq!(dot.matches(" -> ").count(), 4);
+
+        let single = block_trees(&crate::compress(&[b'z'; 200])).unwrap();
+        assert!(single[0].tree.is_none());
+        assert_eq!(single[0].leaves, b"z");
+        let options = CompressionOptions {
+            algorithm: Algorithm::Range,
+            ..Default::default()
+        };
+        assert!(
+            block_trees(&compress_with(&data, &options))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn truncated_frames_are_reported() {
+        let encoded = compress_blocks(&[7u8; 100], 30);
+        assert_eq!(
+            inspect(&encoded[..encoded.len() - 8]).err(),
+            Some(CompressionError::TruncatedInput)
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use alloc::vec::Vec;
+
+use crate::bits::{BitReader, bit_count};
+use crate::checksum::Crc32;
+use crate::error::CompressionError;
+use crate::{HuffmanTree, MAX_LONE_SYMBOL_LEN, parse_container, parse_tree_section};
+
+/// Decodes `data` one symbol at a time. The header is parsed up front; if
+/// it is malformed the iterator yields that error once and then ends, as
+/// it does after a payload that stops in the middle of a code. A checksum
+/// mismatch is only known after the last byte and is yielded after it.
+/// Multi-block streams and streams with a stage are not supported.
+pub fn decode_lazy<'a>(data: &'a [u8]) -> impl Iterator<Item = Result<u8, CompressionError>> + 'a {
+    match LazyDecoder::new(data) {
+        Ok(decoder) => decoder,
+        Err(e) => LazyDecoder {
+            tree: None,
+            leaves: Vec::new(),
+            payload: &[],
+            pos: 0,
+            total_bits: 0,
+            stored: false,
+            checksum: None,
+            error: Some(e),
+        },
+    }
+}
+
+struct LazyDecoder<'a> {
+    tree: Option<HuffmanTree>,
+    leaves: Vec<u8>,
+    payload: &'a [u8],
+    pos: u64,
+    total_bits: u64,
+    /// Whether `payload` holds the bytes as is, one per position.
+    stored: bool,
+    /// The stored checksum and the running checksum of the yielded bytes.
+    checksum: Option<(u32, Crc32)>,
+    error: Option<CompressionError>,
+}
+
+impl<'a> LazyDecoder<'a> {
+    fn new(data: &'a [u8]) -> Result<LazyDecoder<'a>, CompressionError> {
+        let (container, rest) = parse_container(data)?;
+        container.check_whole_stream()?;
+        let (rest, checksum) = container.split_footer(rest)?;
+        let checksum = checksum.map(|expected| (expected, Crc32::new()));
+        if container.has_stored() {
+            if rest.len() as u64 != container.original_len {
+                return Err(CompressionError::LengthMismatch {
+                    expected: container.original_len,
+                    actual: rest.len() as u64,
+                });
+            }
+            return Ok(LazyDecoder {
+                tree: None,
+                leaves: Vec::new(),
+                payload: rest,
+                pos: 0,
+                total_bits: rest.len() as u64,
+                stored: true,
+                checksum,
+                error: None,
+            });
+        }
+        let (&padding, rest) = rest.split_first().ok_or(CompressionError::TruncatedInput)?;
+        let section = parse_tree_section(rest, container.has_canonical_tree())?;
+        let payload = &rest[section.len..];
+        let total_bits = bit_count(payload)
+            .checked_sub(padding as u64)
+            .filter(|_| padding < 8)
+            .ok_or(CompressionError::InvalidPadding)?;
+        if section.tree.is_none() && total_bits > 0 {
+            return Err(CompressionError::CorruptData);
+        }
+        // A lone symbol has an empty code: every position up to the
+        // original length is one copy of it.
+        let total_bits = match section.leaves[..] {
+            [_] if container.original_len > MAX_LONE_SYMBOL_LEN => {
+                return Err(CompressionError::CorruptData);
+            }
+            [_] => container.original_len,
+            _ => total_bits,
+        };
+        Ok(LazyDecoder {
+            tree: section.tree,
+            leaves: section.leaves,
+            payload,
+            pos: 0,
+            total_bits,
+            stored: false,
+            checksum,
+            error: None,
+        })
+    }
+
+    /// Adds `b` to the running checksum before it is yielded.
+    fn yielded(&mut self, b: u8) -> u8 {
+        if let Some((_, crc)) = &mut self.checksum {
+            crc.update(&[b]);
+        }
+        b
+    }
+}
+
+impl Iterator for LazyDecoder<'_> {
+    type Item = Result<u8, CompressionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            self.pos = self.total_bits;
+            return Some(Err(e));
+        }
+        if self.pos >= self.total_bits {
+            let (expected, crc) = self.checksum.take()?;
+            let actual = crc.finish();
+            return (actual != expected)
+                .then_some(Err(CompressionError::ChecksumMismatch { expected, actual }));
+        }
+        if self.stored {
+            self.pos += 1;
+            return Some(Ok(self.yielded(self.payload[self.pos as usize - 1])));
+        }
+        let Some(tree) = &self.tree else {
+            self.pos += 1;
+            return Some(Ok(self.yielded(self.leaves[0])));
+        };
+
+        let mut bits = BitReader::with_len(self.payload, self.total_bits);
+        bits.set_position(self.pos);
+        let symbol = tree.decode_symbol(&mut bits);
+        self.pos = bits.position();
+        match symbol {
+            Some(b) => Some(Ok(self.yielded(b))),
+            None => {
+                self.checksum = None;
+                Some(Err(CompressionError::DecodingFailed))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compress, container_prefix, decompress};
+
+    #[test]
+    fn lazy_decode_matches_eager_decode() {
+        let data = b"lazy decoding yields the same bytes";
+        let encoded = compress(data);
+        let lazy: Result<Vec<u8>, _> = decode_lazy(&encoded).collect();
+        assert_eq!(lazy.unwrap(), decompress(&encoded).unwrap());
+    }
+
+    #[test]
+    fn skip_and_take_read_the_middle() {
+        let data: Vec<u8> = (0..2000u32).map(|i| (i % 7) as u8 + b'a').collect();
+        let encoded = compress(&data);
+        let middle: Vec<u8> = decode_lazy(&encoded)
+            .skip(1000)
+            .take(100)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(middle, &data[1000..1100]);
+    }
+
+    #[test]
+    fn truncated_header_yields_one_error() {
+        let data = [container_prefix(5, 0), vec![0, 5, 0, b'a']].concat();
+        let results: Vec<_> = decode_lazy(&data).collect();
+        assert_eq!(results, vec![Err(CompressionError::TruncatedInput)]);
+    }
+
+    #[test]
+    fn stream_ending_mid_code_stops_after_error() {
+        // Tree a = 0, b = 10, c = 11 with a payload of "1" and seven padding bits.
+        let data = [
+            container_prefix(1, 0),
+            vec![7, 3, 0, b'a', b'b', b'c', 1, 2, 2, 0b1000_0000],
+        ]
+        .concat();
+        let results: Vec<_> = decode_lazy(&data).take_while(|r| r.is_ok()).collect();
+        assert!(results.is_empty());
+        assert_eq!(decode_lazy(&data).count(), 1);
+    }
+
+    #[test]
+    fn lazy_decode_single_symbol() {
+        let encoded = compress(b"qqq");
+        let lazy: Result<Vec<u8>, _> = decode_lazy(&encoded).collect();
+        assert_eq!(lazy.unwrap(), b"qqq");
+
+        let mut bomb = crate::encode_block(b"qqq").to_bytes();
+        bomb[9..17].copy_from_slice(&(MAX_LONE_SYMBOL_LEN + 1).to_le_bytes());
+        let results: Vec<_> = decode_lazy(&bomb).collect();
+        assert_eq!(results, [Err(CompressionError::CorruptData)]);
+    }
+
+    #[test]
+    fn lazy_decode_stored() {
+        let data: Vec<u8> = (0..=255).rev().collect();
+        let lazy: Result<Vec<u8>, _> = decode_lazy(&compress(&data)).collect();
+        assert_eq!(lazy.unwrap(), data);
+    }
+}
@@ -0,0 +1,3416 @@
+//! Huffman coding compressor.
+//!
+//! [`compress`] and [`decompress`] convert between raw bytes and the
+//! serialized stream described on [`Encoded`]. The submodules hold
+//! alternative encodings and analysis helpers built on the same trees.
+//!
+//! Without the default `std` feature the crate is `no_std` and needs only
+//! `alloc`. It then leaves out the modules that work on files, readers or
+//! threads, and everything decodes on one thread.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod adaptive;
+pub mod aligned;
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod archive;
+pub mod arithmetic;
+#[cfg(feature = "std")]
+pub mod atomic;
+#[cfg(feature = "std")]
+pub mod benchmark;
+pub mod bits;
+pub mod bitvec;
+pub mod block;
+pub mod bwt;
+pub mod checksum;
+pub mod codec;
+pub mod delta;
+pub mod dictionary;
+pub mod error;
+pub mod extension;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filter;
+pub mod flat;
+mod format;
+pub mod gzip;
+pub mod hamming;
+pub mod info;
+pub mod lazy;
+pub mod lzss;
+#[cfg(feature = "std")]
+pub mod mmap;
+pub mod options;
+pub mod pack;
+mod package_merge;
+pub mod range;
+pub mod records;
+#[cfg(feature = "std")]
+pub mod resume;
+#[cfg(feature = "std")]
+pub mod seekable;
+pub mod self_describing;
+pub mod shared;
+mod source;
+pub mod split;
+#[cfg(feature = "std")]
+pub mod stream;
+pub mod symbol;
+pub mod table;
+#[cfg(all(test, feature = "std"))]
+mod test_util;
+pub mod transform;
+pub mod version;
+pub mod words;
+
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use bits::{BitReader, BitWriter, bit_count};
+use checksum::{Crc32, crc32};
+pub use error::CompressionError;
+use filter::{FILTER_LEN, Filter};
+use format::{Cursor, TreeSection, parse_container, parse_tree_section};
+pub use options::{Algorithm, CompressionOptions, Encoder, EncoderBuilder};
+use version::{FORMAT_VERSION, SupportedFeature};
+use words::WordOrder;
+
+const MAGIC: [u8; 4] = *b"WFHC";
+/// Length of the magic, version, flags and original length.
+const CONTAINER_LEN: usize = 17;
+const CHECKSUM_LEN: usize = 4;
+/// Number of bytes a stored stream adds to the data: the container fields
+/// and the checksum.
+pub const STORED_OVERHEAD: usize = CONTAINER_LEN + CHECKSUM_LEN;
+/// Most bytes a stream of a lone symbol may claim. Its code is empty, so
+/// nothing but the header backs the length; decoding fails with
+/// [`CorruptData`](CompressionError::CorruptData) above this, and longer
+/// runs are encoded with a second, unused leaf so the payload bounds them.
+pub const MAX_LONE_SYMBOL_LEN: u64 = 1 << 32;
+
+/// A compressed stream. Its byte layout is:
+///
+/// | bytes | field |
+/// |-------|-------|
+/// | 4 | magic, `WFHC` |
+/// | 1 | format version |
+/// | 4 | feature flags, little-endian |
+/// | 8 | original length, little-endian |
+/// | 1 | number of padding bits at the end of the payload |
+/// | 2 | number of leaves `n`, little-endian |
+/// | n | leaves, left to right |
+/// | 1 | longest code length `m`, only when `n >= 2` |
+/// | m - 1 | number of codes of each length from 1 to `m - 1`, only when `n >= 2` |
+/// | rest | payload |
+/// | 4 | CRC-32 of the original data, little-endian, only with the checksum flag |
+///
+/// The codes are canonical: shorter codes come first and codes of equal
+/// length are in byte order, so the leaves are sorted by code length and
+/// then by byte and the number of codes of each length places them in the
+/// tree. The count for the longest length is whatever remains of `n`.
+/// Streams written before canonical codes, without the canonical-tree
+/// flag, store the depth of every leaf instead of the counts.
+///
+/// With the LZSS, RLE or BWT flag the Huffman-coded bytes are the output of
+/// [`lzss::lzss_encode`], [`transform::rle_encode`] or [`bwt::bwt_encode`], and the original
+/// length and checksum are those of that output. Only [`decompress`] undoes
+/// those stages. With the stored flag the container is followed directly
+/// by the bytes and the checksum, without padding byte or tree, and with
+/// the adaptive or range flag by the output of [`adaptive::adaptive_encode`]
+/// or [`range::range_encode`] and the checksum. The order-1 flag, only set
+/// together with the range flag, replaces [`range::range_encode`] by
+/// [`range::order1_encode`].
+pub struct Encoded {
+    original_len: u64,
+    checksum: Option<u32>,
+    /// Whether `tree` has the canonical layout.
+    canonical: bool,
+    tree: Vec<u8>,
+    bytes: Vec<u8>,
+    padding: u8,
+}
+
+impl Encoded {
+    /// Takes the payload `writer` of `data`, which is checksummed.
+    fn from_bits(writer: BitWriter, tree: Vec<u8>, data: &[u8]) -> Encoded {
+        Encoded {
+            original_len: data.len() as u64,
+            checksum: Some(crc32(data)),
+            canonical: true,
+            tree,
+            padding: writer.padding(),
+            bytes: writer.into_bytes(),
+        }
+    }
+
+    /// Everything before the payload: the container fields, the padding
+    /// byte and the tree.
+    pub fn header_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.checksum.is_some() {
+            flags |= SupportedFeature::Checksum.bit();
+        }
+        if self.canonical {
+            flags |= SupportedFeature::CanonicalTree.bit();
+        }
+        let mut out = container_prefix(self.original_len, flags);
+        out.push(self.padding);
+        out.extend(&self.tree);
+        out
+    }
+
+    pub fn data_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Everything after the payload: the checksum, if there is one.
+    fn footer_bytes(&self) -> Vec<u8> {
+        self.checksum
+            .map_or(Vec::new(), |crc| crc.to_le_bytes().to_vec())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header_bytes();
+        out.extend(&self.bytes);
+        out.extend(self.footer_bytes());
+        out
+    }
+
+    /// Fails on a wrong magic, a newer format version or unknown feature
+    /// flags as well as on a malformed tree or padding. A multi-block
+    /// stream has no single tree, a stored stream no tree at all and a
+    /// stream with a stage does not decode to the original data, so they
+    /// are rejected as unsupported; use [`decompress`] for those.
+    pub fn from_bytes(data: &[u8]) -> Result<Encoded, CompressionError> {
+        let (container, rest) = parse_container(data)?;
+        container.check_whole_stream()?;
+        if container.has_stored() {
+            return Err(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Stored.bit(),
+            });
+        }
+        Encoded::from_container(&container, rest)
+    }
+
+    /// Parses the single-stream fields that follow `container`.
+    fn from_container(container: &Container, rest: &[u8]) -> Result<Encoded, CompressionError> {
+        let (rest, checksum) = container.split_footer(rest)?;
+        let mut cursor = Cursor::new(rest);
+        let padding = cursor.u8()?;
+        let canonical = container.has_canonical_tree();
+        let section = parse_tree_section(cursor.rest(), canonical)?;
+        let tree = cursor.bytes(section.len)?.to_vec();
+        let bytes = cursor.rest().to_vec();
+        if padding >= 8 || (padding > 0 && bytes.is_empty()) {
+            return Err(CompressionError::InvalidPadding);
+        }
+        // Every code is at least one bit long, so the payload cannot hold
+        // more symbols than bits.
+        let total_bits = bit_count(&bytes) - padding as u64;
+        if section.tree.is_some() && container.original_len > total_bits {
+            return Err(CompressionError::CorruptHeader);
+        }
+        Ok(Encoded {
+            original_len: container.original_len,
+            checksum,
+            canonical,
+            tree,
+            bytes,
+            padding,
+        })
+    }
+
+    /// Fails if the payload ends in the middle of a code, does not hold as
+    /// many bytes as the header says or does not match the checksum.
+    pub fn decode(&self) -> Result<Vec<u8>, CompressionError> {
+        let out = self.decode_unverified()?;
+        if let Some(expected) = self.checksum {
+            let actual = crc32(&out);
+            if actual != expected {
+                return Err(CompressionError::ChecksumMismatch { expected, actual });
+            }
+        }
+        Ok(out)
+    }
+
+    /// Like `decode` but skips the checksum, which is faster.
+    pub fn decode_unverified(&self) -> Result<Vec<u8>, CompressionError> {
+        let out = self.decode_payload()?;
+        if out.len() as u64 != self.original_len {
+            return Err(CompressionError::LengthMismatch {
+                expected: self.original_len,
+                actual: out.len() as u64,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Like `decode` but writes into `out` instead of allocating, so one
+    /// buffer can be reused across streams. `out` must hold at least
+    /// [`original_len`](Encoded::original_len) bytes. Returns the number of
+    /// bytes written.
+    pub fn decode_into(&self, out: &mut [u8]) -> Result<usize, CompressionError> {
+        let limit = out.len() as u64;
+        if self.original_len > limit {
+            return Err(CompressionError::OutputLimitExceeded { limit });
+        }
+        let mut len = 0;
+        self.decode_each(|symbol| {
+            *out.get_mut(len)
+                .ok_or(CompressionError::OutputLimitExceeded { limit })? = symbol;
+            len += 1;
+            Ok(())
+        })?;
+        if len as u64 != self.original_len {
+            return Err(CompressionError::LengthMismatch {
+                expected: self.original_len,
+                actual: len as u64,
+            });
+        }
+        if let Some(expected) = self.checksum {
+            let actual = crc32(&out[..len]);
+            if actual != expected {
+                return Err(CompressionError::ChecksumMismatch { expected, actual });
+            }
+        }
+        Ok(len)
+    }
+
+    /// Like `decode` but passes the output to `consume` in chunks of
+    /// `chunk_size` bytes, the last one possibly shorter, so that no more
+    /// than one chunk of it is ever held in memory. The length and checksum
+    /// can only be checked once every chunk has been passed on, so a
+    /// consumer must not act on the output before this returns `Ok`.
+    /// Panics if `chunk_size` is 0.
+    pub fn decode_chunks(
+        &self,
+        chunk_size: usize,
+        mut consume: impl FnMut(&[u8]),
+    ) -> Result<(), CompressionError> {
+        assert!(chunk_size > 0, "chunk size must be at least 1");
+        let mut chunk = Vec::with_capacity(self.original_len.min(chunk_size as u64) as usize);
+        let mut crc = Crc32::new();
+        let mut len = 0u64;
+        let mut flush = |chunk: &mut Vec<u8>| {
+            crc.update(chunk);
+            len += chunk.len() as u64;
+            consume(chunk);
+            chunk.clear();
+        };
+        self.decode_each(|symbol| {
+            chunk.push(symbol);
+            if chunk.len() == chunk_size {
+                flush(&mut chunk);
+            }
+            Ok(())
+        })?;
+        if !chunk.is_empty() {
+            flush(&mut chunk);
+        }
+        if len != self.original_len {
+            return Err(CompressionError::LengthMismatch {
+                expected: self.original_len,
+                actual: len,
+            });
+        }
+        if let Some(expected) = self.checksum {
+            let actual = crc.finish();
+            if actual != expected {
+                return Err(CompressionError::ChecksumMismatch { expected, actual });
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of bytes the stream decodes to, as the header says.
+    pub fn original_len(&self) -> u64 {
+        self.original_len
+    }
+
+    fn decode_payload(&self) -> Result<Vec<u8>, CompressionError> {
+        // Every code is at least one bit long, so the payload caps the
+        // length even if the header is wrong.
+        let capacity = self.original_len.min(bit_count(&self.bytes));
+        let mut out = Vec::with_capacity(capacity as usize);
+        self.decode_each(|symbol| {
+            out.push(symbol);
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    /// Passes every decoded symbol to `emit`, stopping at its first error.
+    fn decode_each(
+        &self,
+        mut emit: impl FnMut(u8) -> Result<(), CompressionError>,
+    ) -> Result<(), CompressionError> {
+        let total_bits = bit_count(&self.bytes)
+            .checked_sub(self.padding as u64)
+            .ok_or(CompressionError::InvalidPadding)?;
+        let section = parse_tree_section(&self.tree, self.canonical)?;
+        let Some(tree) = section.tree else {
+            // A lone symbol has an empty code, so the original length is
+            // the symbol count.
+            return match section.leaves[..] {
+                [symbol] if total_bits == 0 && self.original_len <= MAX_LONE_SYMBOL_LEN => {
+                    (0..self.original_len).try_for_each(|_| emit(symbol))
+                }
+                [] if total_bits == 0 => Ok(()),
+                _ => Err(CompressionError::CorruptData),
+            };
+        };
+        table::DecodeTable::new(&tree).decode_each(&self.bytes, total_bits, emit)
+    }
+
+    /// Decodes the payload with `tree` instead of the stored tree, for
+    /// recovering streams whose tree header is damaged but known from
+    /// elsewhere.
+    pub fn reinterpret_as(&self, tree: &HuffmanTree) -> Result<Vec<u8>, CompressionError> {
+        let total_bits = bit_count(&self.bytes)
+            .checked_sub(self.padding as u64)
+            .ok_or(CompressionError::DecodingFailed)?;
+        let mut bits = BitReader::with_len(&self.bytes, total_bits);
+        let mut out = Vec::new();
+        while bits.remaining() > 0 {
+            let symbol = tree
+                .decode_symbol(&mut bits)
+                .ok_or(CompressionError::DecodingFailed)?;
+            out.push(symbol);
+        }
+        Ok(out)
+    }
+}
+
+enum Node {
+    Leaf(u8),
+    Tree(Box<HuffmanTree>),
+}
+
+/// The container fields of a stream.
+struct Container {
+    original_len: u64,
+    flags: u32,
+    extensions: Vec<extension::Extension>,
+    /// Bytes taken up by the container fields and the extension area.
+    header_len: usize,
+}
+
+impl Container {
+    fn has_checksum(&self) -> bool {
+        self.flags & SupportedFeature::Checksum.bit() != 0
+    }
+
+    fn has_canonical_tree(&self) -> bool {
+        self.flags & SupportedFeature::CanonicalTree.bit() != 0
+    }
+
+    fn has_blocks(&self) -> bool {
+        self.flags & SupportedFeature::MultiBlock.bit() != 0
+    }
+
+    fn has_stored(&self) -> bool {
+        self.flags & SupportedFeature::Stored.bit() != 0
+    }
+
+    fn has_block_index(&self) -> bool {
+        self.flags & SupportedFeature::BlockIndex.bit() != 0
+    }
+
+    fn has_block_checksums(&self) -> bool {
+        self.flags & SupportedFeature::BlockChecksum.bit() != 0
+    }
+
+    fn has_adaptive(&self) -> bool {
+        self.flags & SupportedFeature::Adaptive.bit() != 0
+    }
+
+    fn has_range(&self) -> bool {
+        self.flags & SupportedFeature::Range.bit() != 0
+    }
+
+    fn has_order1(&self) -> bool {
+        self.flags & SupportedFeature::Order1.bit() != 0
+    }
+
+    fn has_words(&self) -> bool {
+        self.flags & SupportedFeature::Words.bit() != 0
+    }
+
+    fn has_codec(&self) -> bool {
+        self.flags & SupportedFeature::Codec.bit() != 0
+    }
+
+    /// Fails on a stream of a codec from outside the crate, which only a
+    /// [`codec::CodecRegistry`] that knows the codec decodes.
+    fn check_builtin(&self) -> Result<(), CompressionError> {
+        if self.has_codec() {
+            let flags = SupportedFeature::Codec.bit();
+            return Err(CompressionError::UnsupportedFeatures { flags });
+        }
+        Ok(())
+    }
+
+    fn has_filter(&self) -> bool {
+        self.flags & SupportedFeature::Filter.bit() != 0
+    }
+
+    fn has_extensions(&self) -> bool {
+        self.flags & SupportedFeature::Extensions.bit() != 0
+    }
+
+    /// The filter of a filtered stream and the stream it wraps, given the
+    /// rest of it after `container`. The wrapped stream is not filtered
+    /// again, which keeps decoding from recursing without bound.
+    fn split_filter<'a>(&self, rest: &'a [u8]) -> Result<(Filter, &'a [u8]), CompressionError> {
+        if self.flags & !SupportedFeature::Extensions.bit() != SupportedFeature::Filter.bit() {
+            return Err(CompressionError::CorruptHeader);
+        }
+        let filter = Filter::from_bytes(rest.get(..FILTER_LEN).unwrap_or(rest))?;
+        let inner = &rest[FILTER_LEN..];
+        if parse_container(inner)?.0.has_filter() {
+            return Err(CompressionError::CorruptHeader);
+        }
+        Ok((filter, inner))
+    }
+
+    /// The algorithm the flags name. A filtered stream names none and
+    /// counts as Huffman coding.
+    fn algorithm(&self) -> Algorithm {
+        let has = |feature: SupportedFeature| self.flags & feature.bit() != 0;
+        if self.has_adaptive() {
+            Algorithm::Adaptive
+        } else if self.has_words() {
+            Algorithm::Words
+        } else if self.has_order1() {
+            Algorithm::Order1
+        } else if self.has_range() {
+            Algorithm::Range
+        } else if has(SupportedFeature::Bwt) {
+            Algorithm::Bwt
+        } else if has(SupportedFeature::Lzss) {
+            Algorithm::LzHuffman
+        } else if has(SupportedFeature::Rle) && self.has_stored() {
+            Algorithm::Rle
+        } else if has(SupportedFeature::Rle) {
+            Algorithm::RleHuffman
+        } else {
+            Algorithm::Huffman
+        }
+    }
+
+    /// Flags of the stages run before Huffman coding, which only
+    /// [`decompress`] undoes.
+    fn stage_flags(&self) -> u32 {
+        self.flags
+            & (SupportedFeature::Lzss.bit()
+                | SupportedFeature::Rle.bit()
+                | SupportedFeature::Bwt.bit())
+    }
+
+    /// Fails with the flags, if any, that only [`decompress`] handles:
+    /// multiple blocks, adaptive and range codes and the stages.
+    fn check_whole_stream(&self) -> Result<(), CompressionError> {
+        let flags = self.stage_flags()
+            | self.flags
+                & (SupportedFeature::MultiBlock.bit()
+                    | SupportedFeature::BlockChecksum.bit()
+                    | SupportedFeature::Adaptive.bit()
+                    | SupportedFeature::Range.bit()
+                    | SupportedFeature::Words.bit()
+                    | SupportedFeature::Filter.bit()
+                    | SupportedFeature::Codec.bit());
+        if flags != 0 {
+            return Err(CompressionError::UnsupportedFeatures { flags });
+        }
+        Ok(())
+    }
+
+    /// Splits the checksum, if the flags promise one, off the end of `rest`.
+    fn split_footer<'a>(
+        &self,
+        rest: &'a [u8],
+    ) -> Result<(&'a [u8], Option<u32>), CompressionError> {
+        if !self.has_checksum() {
+            return Ok((rest, None));
+        }
+        let split = rest
+            .len()
+            .checked_sub(CHECKSUM_LEN)
+            .ok_or(CompressionError::TruncatedInput)?;
+        let (rest, footer) = rest.split_at(split);
+        Ok((rest, Some(u32::from_le_bytes(footer.try_into().unwrap()))))
+    }
+}
+
+/// Magic, format version, `flags` and `original_len`.
+fn container_prefix(original_len: u64, flags: u32) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(FORMAT_VERSION);
+    out.extend(flags.to_le_bytes());
+    out.extend(original_len.to_le_bytes());
+    out
+}
+
+/// Serializes a canonical tree section from the leaves, sorted by depth
+/// and then by byte, and their depths. `depths` is empty for fewer than two
+/// leaves.
+fn serialize_tree_section(leaves: &[u8], depths: &[u8]) -> Vec<u8> {
+    let mut out = (leaves.len() as u16).to_le_bytes().to_vec();
+    out.extend(leaves);
+    if let Some(&longest) = depths.last() {
+        out.push(longest);
+        for len in 1..longest {
+            out.push(depths.iter().filter(|&&d| d == len).count() as u8);
+        }
+    }
+    out
+}
+
+/// `b` quoted if it is a printable ASCII character, in hex otherwise.
+fn symbol_label(b: u8) -> String {
+    match b {
+        b' '..=b'~' => format!("'{}'", b as char),
+        _ => format!("{:#04x}", b),
+    }
+}
+
+/// Which of the distinct bytes of some data a tree can encode.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub total_symbols: usize,
+    pub covered_symbols: usize,
+    pub missing: Vec<u8>,
+}
+
+impl CoverageReport {
+    /// Share of the distinct bytes that have a code, from 0 to 1. Data with
+    /// no bytes is fully covered.
+    pub fn fraction(&self) -> f64 {
+        if self.total_symbols == 0 {
+            1.0
+        } else {
+            self.covered_symbols as f64 / self.total_symbols as f64
+        }
+    }
+}
+
+impl core::fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let percent = self.fraction() * 100.0;
+        write!(
+            f,
+            "Coverage: {}/{} symbols ({:.1}%).",
+            self.covered_symbols, self.total_symbols, percent
+        )?;
+        if !self.missing.is_empty() {
+            let missing: Vec<String> = self.missing.iter().map(|b| format!("{:#04X}", b)).collect();
+            write!(f, " Missing: [{}]", missing.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of matching a bit prefix against the codes of a tree.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PrefixResult {
+    /// The prefix is exactly the code of this symbol.
+    Complete(u8),
+    /// The prefix is the start of at least one longer code.
+    NeedMoreBits,
+    /// No code starts with the prefix.
+    Invalid,
+}
+
+/// A binary code tree. A 0 bit selects `left` and a 1 bit `right`.
+pub struct HuffmanTree {
+    left: Node,
+    right: Node,
+}
+
+impl HuffmanTree {
+    /// Builds a canonical Huffman tree. The code lengths come from
+    /// repeatedly merging the two lightest nodes, ties going to the node
+    /// created first (leaves in byte order, then merged nodes), and the
+    /// codes are then assigned as in `from_code_lengths`. Returns `None` if
+    /// fewer than two bytes have a nonzero frequency.
+    pub fn from_frequencies(freq: &[u64; 256]) -> Option<HuffmanTree> {
+        let merged = HuffmanTree::merge_lightest(freq)?;
+        Some(
+            HuffmanTree::from_code_lengths(&merged.code_lengths())
+                .expect("a tree's own code lengths form a complete code"),
+        )
+    }
+
+    /// Like `from_frequencies` but with no code longer than `max_len` bits.
+    /// The Huffman code lengths are kept if they fit; otherwise the lengths
+    /// come from the package-merge algorithm, which finds the shortest
+    /// coding within the limit. A fixed limit lets decoders use tables of
+    /// a fixed width. Panics if `max_len` is below
+    /// [`MIN_CODE_LENGTH_LIMIT`](options::MIN_CODE_LENGTH_LIMIT), too
+    /// short for all 256 byte values.
+    pub fn from_frequencies_limited(freq: &[u64; 256], max_len: u8) -> Option<HuffmanTree> {
+        options::check_code_length_limit(max_len);
+        let tree = HuffmanTree::from_frequencies(freq)?;
+        if tree.longest_code_length() <= max_len {
+            return Some(tree);
+        }
+        let lengths = package_merge::package_merge(freq, max_len);
+        Some(
+            HuffmanTree::from_code_lengths(&lengths.try_into().unwrap())
+                .expect("package-merge lengths form a complete code"),
+        )
+    }
+
+    /// The tree built by merging the two lightest nodes until one is left,
+    /// the first one taken becoming the left child.
+    fn merge_lightest(freq: &[u64; 256]) -> Option<HuffmanTree> {
+        let mut nodes = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for (b, &f) in freq.iter().enumerate() {
+            if f > 0 {
+                heap.push(Reverse((f, nodes.len())));
+                nodes.push(Some(Node::Leaf(b as u8)));
+            }
+        }
+        if heap.len() < 2 {
+            return None;
+        }
+        loop {
+            let Reverse((left_weight, left)) = heap.pop().unwrap();
+            let Reverse((right_weight, right)) = heap.pop().unwrap();
+            let tree = HuffmanTree {
+                left: nodes[left].take().unwrap(),
+                right: nodes[right].take().unwrap(),
+            };
+            if heap.is_empty() {
+                return Some(tree);
+            }
+            heap.push(Reverse((left_weight + right_weight, nodes.len())));
+            nodes.push(Some(Node::Tree(Box::new(tree))));
+        }
+    }
+
+    fn child(&self, bit: bool) -> &Node {
+        if bit { &self.right } else { &self.left }
+    }
+
+    /// Serializes a canonical tree; `encode` canonicalizes others first.
+    fn serialize(&self) -> Vec<u8> {
+        debug_assert!(self.is_canonical());
+        let mut leaves = Vec::new();
+        let mut depths = Vec::new();
+        self.collect_leaves(1, &mut leaves, &mut depths);
+        serialize_tree_section(&leaves, &depths)
+    }
+
+    /// Returns whether the leaves, left to right, are sorted by depth and
+    /// then by byte, as `from_code_lengths` places them.
+    pub fn is_canonical(&self) -> bool {
+        let mut leaves = Vec::new();
+        let mut depths = Vec::new();
+        self.collect_leaves(1, &mut leaves, &mut depths);
+        let keys: Vec<(u8, u8)> = depths.into_iter().zip(leaves).collect();
+        keys.windows(2).all(|pair| pair[0] < pair[1])
+    }
+
+    /// Appends the leaves below this node, left to right, and their depths
+    /// given that this node's children are at `depth`.
+    fn collect_leaves(&self, depth: u8, leaves: &mut Vec<u8>, depths: &mut Vec<u8>) {
+        for node in [&self.left, &self.right] {
+            match node {
+                Node::Leaf(b) => {
+                    leaves.push(*b);
+                    depths.push(depth);
+                }
+                Node::Tree(t) => t.collect_leaves(depth + 1, leaves, depths),
+            }
+        }
+    }
+
+    /// Rebuilds a tree from its leaves, left to right, and their depths.
+    /// Fails unless they describe a tree in which every internal node has
+    /// two children.
+    fn from_leaf_depths(leaves: &[u8], depths: &[u8]) -> Result<HuffmanTree, CompressionError> {
+        let mut next = leaves
+            .iter()
+            .copied()
+            .zip(depths.iter().copied())
+            .peekable();
+        let tree = HuffmanTree {
+            left: node_from_depths(1, &mut next)?,
+            right: node_from_depths(1, &mut next)?,
+        };
+        if next.next().is_some() {
+            return Err(CompressionError::CorruptTree);
+        }
+        Ok(tree)
+    }
+
+    /// Rebuilds a canonical tree from its leaves, sorted by depth and then
+    /// by byte, and the `tail` of its tree section: the longest code length
+    /// followed by the number of codes of each shorter length.
+    fn from_canonical_counts(leaves: &[u8], tail: &[u8]) -> Result<HuffmanTree, CompressionError> {
+        let (&longest, counts) = tail.split_first().ok_or(CompressionError::CorruptTree)?;
+        let mut depths = Vec::with_capacity(leaves.len());
+        for (len, &count) in (1..).zip(counts) {
+            depths.extend(core::iter::repeat_n(len, count as usize));
+        }
+        if depths.len() >= leaves.len() {
+            return Err(CompressionError::CorruptTree);
+        }
+        depths.resize(leaves.len(), longest);
+        let sorted = depths
+            .iter()
+            .zip(leaves)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .all(|pair| pair[0] < pair[1]);
+        if !sorted {
+            return Err(CompressionError::CorruptTree);
+        }
+        HuffmanTree::from_leaf_depths(leaves, &depths)
+    }
+
+    /// Returns whether every byte in `symbols` is a leaf of the tree.
+    pub fn cover(&self, symbols: &[u8]) -> bool {
+        let leaves = self.leaf_set();
+        symbols.iter().all(|&b| leaves[b as usize])
+    }
+
+    pub fn coverage_report(&self, data: &[u8]) -> CoverageReport {
+        let mut missing = self.missing_symbols(data);
+        missing.sort_unstable();
+        let total_symbols = count_unique_bytes(data) as usize;
+        CoverageReport {
+            total_symbols,
+            covered_symbols: total_symbols - missing.len(),
+            missing,
+        }
+    }
+
+    /// The distinct bytes of `symbols` that are not leaves of the tree, in
+    /// order of first appearance.
+    pub fn missing_symbols(&self, symbols: &[u8]) -> Vec<u8> {
+        let mut leaves = self.leaf_set();
+        let mut missing = Vec::new();
+        for &b in symbols {
+            if !leaves[b as usize] {
+                missing.push(b);
+                leaves[b as usize] = true;
+            }
+        }
+        missing
+    }
+
+    fn leaf_set(&self) -> [bool; 256] {
+        let mut leaves = Vec::new();
+        self.collect_leaves(1, &mut leaves, &mut Vec::new());
+        let mut set = [false; 256];
+        for b in leaves {
+            set[b as usize] = true;
+        }
+        set
+    }
+
+    /// Code and code length for every byte value, indexed by byte.
+    pub fn to_lookup_array(&self) -> Box<[(Option<Vec<bool>>, u8); 256]> {
+        let mut table: Box<[(Option<Vec<bool>>, u8); 256]> =
+            Box::new(core::array::from_fn(|_| (None, 0)));
+        self.fill_lookup(&mut Vec::new(), &mut table);
+        table
+    }
+
+    fn fill_lookup(&self, code: &mut Vec<bool>, table: &mut [(Option<Vec<bool>>, u8); 256]) {
+        for bit in [false, true] {
+            code.push(bit);
+            match self.child(bit) {
+                Node::Leaf(b) => table[*b as usize] = (Some(code.clone()), code.len() as u8),
+                Node::Tree(t) => t.fill_lookup(code, table),
+            }
+            code.pop();
+        }
+    }
+
+    /// One line per symbol with a code: the symbol, its code, the code
+    /// length and its count in `freq`, shortest codes first, under a
+    /// header line.
+    pub fn code_table(&self, freq: &[u64; 256]) -> String {
+        let table = self.to_lookup_array();
+        let mut rows: Vec<(u8, u8, &[bool])> = (0..=255u8)
+            .filter_map(|b| {
+                let (code, len) = &table[b as usize];
+                code.as_deref().map(|code| (*len, b, code))
+            })
+            .collect();
+        rows.sort_unstable();
+        let mut out = format!("{:<8}{:<5}{:>10}  code\n", "symbol", "len", "frequency");
+        for (len, b, code) in rows {
+            let code: String = code
+                .iter()
+                .map(|&bit| if bit { '1' } else { '0' })
+                .collect();
+            out += &format!(
+                "{:<8}{:<5}{:>10}  {}\n",
+                symbol_label(b),
+                len,
+                freq[b as usize],
+                code
+            );
+        }
+        out
+    }
+
+    /// The tree in the Graphviz DOT language, for `dot -Tsvg`. Edges are
+    /// labelled with their bit and leaves with their symbol.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph huffman {\n    node [shape=circle, label=\"\"];\n");
+        self.write_dot(&mut 0, &mut out);
+        out.push_str("}\n");
+        out
+    }
+
+    /// Writes the nodes and edges below this node, numbered from `*next`,
+    /// and returns the number of this node.
+    fn write_dot(&self, next: &mut usize, out: &mut String) -> usize {
+        let id = *next;
+        *next += 1;
+        out.push_str(&format!("    n{};\n", id));
+        for bit in [false, true] {
+            let child = match self.child(bit) {
+                Node::Leaf(b) => {
+                    let leaf = *next;
+                    *next += 1;
+                    let label = symbol_label(*b).replace('\\', "\\\\").replace('"', "\\\"");
+                    out.push_str(&format!(
+                        "    n{} [shape=box, label=\"{}\"];\n",
+                        leaf, label
+                    ));
+                    leaf
+                }
+                Node::Tree(t) => t.write_dot(next, out),
+            };
+            out.push_str(&format!(
+                "    n{} -> n{} [label=\"{}\"];\n",
+                id, child, bit as u8
+            ));
+        }
+        id
+    }
+
+    /// Length of the shortest code, i.e. the depth of the shallowest leaf.
+    pub fn shortest_code_length(&self) -> u8 {
+        self.code_lengths()
+            .into_iter()
+            .filter(|&len| len > 0)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Length of the longest code, i.e. the depth of the tree.
+    pub fn longest_code_length(&self) -> u8 {
+        self.code_lengths().into_iter().max().unwrap_or(0)
+    }
+
+    /// Number of payload bits `data` encodes to with this tree.
+    fn encoded_bit_count(&self, data: &[u8]) -> Result<u64, CompressionError> {
+        let lengths = self.code_lengths();
+        data.iter()
+            .try_fold(0u64, |bits, &b| match lengths[b as usize] {
+                0 => Err(CompressionError::SymbolNotInTree { symbol: b }),
+                len => Ok(bits + len as u64),
+            })
+    }
+
+    /// Number of leading bits the codes of `a` and `b` share, or `None` if
+    /// either is not in the tree. For distinct symbols this is always less
+    /// than both code lengths.
+    pub fn common_prefix_length(&self, a: u8, b: u8) -> Option<usize> {
+        let table = self.to_lookup_array();
+        let code_a = table[a as usize].0.as_ref()?;
+        let code_b = table[b as usize].0.as_ref()?;
+        Some(
+            code_a
+                .iter()
+                .zip(code_b)
+                .take_while(|(x, y)| x == y)
+                .count(),
+        )
+    }
+
+    /// Returns whether no code is a prefix of another. The Kraft sum is
+    /// checked first as a cheap rejection before comparing the codes.
+    pub fn is_prefix_free(&self) -> bool {
+        let lengths: Vec<u8> = self
+            .code_lengths()
+            .into_iter()
+            .filter(|&len| len > 0)
+            .collect();
+        if compute_kraft_inequality(&lengths) > 1.0 + f64::EPSILON {
+            return false;
+        }
+        let mut codes: Vec<Vec<bool>> = self
+            .to_lookup_array()
+            .iter()
+            .filter_map(|(code, _)| code.clone())
+            .collect();
+        codes.sort();
+        codes.windows(2).all(|pair| !pair[1].starts_with(&pair[0]))
+    }
+
+    /// Returns whether the tree has only two leaves, each with a one-bit
+    /// code. Single-symbol inputs never reach a tree; they are special-cased
+    /// in the serialized form instead.
+    pub fn is_trivial(&self) -> bool {
+        matches!((&self.left, &self.right), (Node::Leaf(_), Node::Leaf(_)))
+    }
+
+    /// Fails on the first byte of `data` that is not a leaf. A tree that is
+    /// not canonical is swapped for the canonical tree with the same code
+    /// lengths, which codes `data` just as compactly.
+    pub fn encode(&self, data: &[u8]) -> Result<Encoded, CompressionError> {
+        if !self.is_canonical() {
+            return HuffmanTree::from_code_lengths(&self.code_lengths())
+                .expect("a tree's own code lengths form a complete code")
+                .encode(data);
+        }
+        Ok(Encoded::from_bits(
+            self.encode_bits(data)?,
+            self.serialize(),
+            data,
+        ))
+    }
+
+    /// Like `encode` for a tree trained on other data: first passes the
+    /// [`coverage_report`](HuffmanTree::coverage_report) of `data` to
+    /// `on_low_coverage` if less than `min_coverage` of its distinct bytes,
+    /// as a fraction from 0 to 1, have a code. A byte without a code still
+    /// fails the encoding.
+    pub fn encode_with_seed(
+        &self,
+        data: &[u8],
+        min_coverage: f64,
+        mut on_low_coverage: impl FnMut(&CoverageReport),
+    ) -> Result<Encoded, CompressionError> {
+        let report = self.coverage_report(data);
+        if report.fraction() < min_coverage {
+            on_low_coverage(&report);
+        }
+        self.encode(data)
+    }
+
+    /// The concatenated codes of `data`, packed as they are looked up.
+    fn encode_bits(&self, data: &[u8]) -> Result<BitWriter, CompressionError> {
+        if self.is_trivial() {
+            return self.encode_two_leaves(data);
+        }
+        let table = self.to_lookup_array();
+        // Every code of at most 64 bits as a number. Only a tree from a
+        // crafted header has longer ones, which are pushed bit by bit.
+        let mut codes = [(0u64, 0u32); 256];
+        for (packed, (code, _)) in codes.iter_mut().zip(table.iter()) {
+            if let Some(code) = code.as_ref().filter(|code| code.len() <= 64) {
+                let value = code.iter().fold(0, |acc, &bit| acc << 1 | bit as u64);
+                *packed = (value, code.len() as u32);
+            }
+        }
+        // Sized up front, as growing the buffer would briefly hold it twice.
+        let len: u64 = data.iter().map(|&b| table[b as usize].1 as u64).sum();
+        let mut bits = BitWriter::with_capacity(len.div_ceil(8) as usize);
+        for &b in data {
+            match (codes[b as usize], &table[b as usize].0) {
+                ((code, len), _) if len > 0 => bits.push_value(code, len),
+                (_, Some(code)) => bits.push_bits(code),
+                (_, None) => return Err(CompressionError::SymbolNotInTree { symbol: b }),
+            }
+        }
+        Ok(bits)
+    }
+
+    /// The codes of `data` under a trivial tree: one bit per byte, gathered
+    /// 64 at a time without looking anything up.
+    fn encode_two_leaves(&self, data: &[u8]) -> Result<BitWriter, CompressionError> {
+        let (Node::Leaf(left), Node::Leaf(right)) = (&self.left, &self.right) else {
+            unreachable!("a trivial tree has two leaves")
+        };
+        let code = |b: u8| -> Result<u64, CompressionError> {
+            match b {
+                b if b == *left => Ok(0),
+                b if b == *right => Ok(1),
+                symbol => Err(CompressionError::SymbolNotInTree { symbol }),
+            }
+        };
+        let mut bits = BitWriter::with_capacity(data.len().div_ceil(8));
+        let mut chunks = data.chunks_exact(64);
+        for chunk in &mut chunks {
+            let word = chunk
+                .iter()
+                .try_fold(0, |word, &b| code(b).map(|bit| word << 1 | bit))?;
+            bits.push_value(word, 64);
+        }
+        for &b in chunks.remainder() {
+            bits.push_value(code(b)?, 1);
+        }
+        Ok(bits)
+    }
+
+    pub fn code_for_prefix(&self, prefix: &[bool]) -> PrefixResult {
+        let mut current = self;
+        for (i, &bit) in prefix.iter().enumerate() {
+            match current.child(bit) {
+                Node::Tree(t) => current = t,
+                Node::Leaf(b) if i == prefix.len() - 1 => return PrefixResult::Complete(*b),
+                Node::Leaf(_) => return PrefixResult::Invalid,
+            }
+        }
+        PrefixResult::NeedMoreBits
+    }
+
+    /// Code length of every byte value, 0 for bytes not in the tree.
+    fn code_lengths(&self) -> [u8; 256] {
+        let mut lengths = [0u8; 256];
+        for (length, (_, len)) in lengths.iter_mut().zip(self.to_lookup_array().iter()) {
+            *length = *len;
+        }
+        lengths
+    }
+
+    /// Rebuilds a tree from per-byte code lengths, placing shorter codes to
+    /// the left and symbols sharing a length in byte order.
+    fn from_code_lengths(lengths: &[u8; 256]) -> Result<HuffmanTree, CompressionError> {
+        let mut symbols: Vec<u8> = (0..=255u8).filter(|&b| lengths[b as usize] > 0).collect();
+        symbols.sort_by_key(|&b| (lengths[b as usize], b));
+        let depths: Vec<u8> = symbols.iter().map(|&b| lengths[b as usize]).collect();
+        HuffmanTree::from_leaf_depths(&symbols, &depths)
+    }
+
+    /// Serializes the code lengths of all 256 byte values followed by
+    /// `freq` as 256 big-endian `u32`s.
+    pub fn serialize_with_frequencies(&self, freq: &[u32; 256]) -> Vec<u8> {
+        let mut out = self.code_lengths().to_vec();
+        for f in freq {
+            out.extend(f.to_be_bytes());
+        }
+        out
+    }
+
+    pub fn from_serialized_with_frequencies(
+        data: &[u8],
+    ) -> Result<(HuffmanTree, [u32; 256]), CompressionError> {
+        if data.len() < 256 * 5 {
+            return Err(CompressionError::TruncatedInput);
+        }
+        let lengths: &[u8; 256] = data[..256].try_into().unwrap();
+        let tree = HuffmanTree::from_code_lengths(lengths)?;
+        let mut freq = [0u32; 256];
+        for (f, bytes) in freq.iter_mut().zip(data[256..256 * 5].chunks(4)) {
+            *f = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        Ok((tree, freq))
+    }
+
+    /// Follows `bits` from the root and returns the symbol it reaches, or
+    /// `None` if the bits run out first.
+    fn decode_symbol(&self, bits: &mut impl Iterator<Item = bool>) -> Option<u8> {
+        let mut current = self;
+        loop {
+            match current.child(bits.next()?) {
+                Node::Leaf(b) => return Some(*b),
+                Node::Tree(t) => current = t,
+            }
+        }
+    }
+
+    /// Builds a tree from the symbols with a frequency of at least
+    /// `min_freq` and returns it with the excluded symbols (those with a
+    /// nonzero frequency below the threshold), in byte order.
+    ///
+    /// The first excluded symbol stays in the tree as the escape leaf,
+    /// weighted by all of them: [`encode_escaped`](HuffmanTree::encode_escaped)
+    /// codes every byte without a leaf of its own as the escape followed by
+    /// its eight bits.
+    ///
+    /// Fails with [`TooFewSymbols`](CompressionError::TooFewSymbols) if
+    /// the tree would have fewer than two leaves.
+    pub fn from_frequencies_filtered(
+        freq: &[u32; 256],
+        min_freq: u32,
+    ) -> Result<(HuffmanTree, Vec<u8>), CompressionError> {
+        let mut kept = [0u64; 256];
+        let mut excluded = Vec::new();
+        for b in 0..=255u8 {
+            match freq[b as usize] {
+                0 => {}
+                f if f >= min_freq => kept[b as usize] = f as u64,
+                _ => excluded.push(b),
+            }
+        }
+        if let Some(&escape) = excluded.first() {
+            kept[escape as usize] = excluded.iter().map(|&b| freq[b as usize] as u64).sum();
+        }
+        let tree = HuffmanTree::from_frequencies(&kept).ok_or(CompressionError::TooFewSymbols {
+            kept: kept.iter().filter(|&&f| f > 0).count(),
+        })?;
+        Ok((tree, excluded))
+    }
+
+    /// Codes `data` with the tree, writing a byte that is not a leaf, or is
+    /// `escape` itself, as the code of the `escape` leaf followed by the
+    /// byte's eight bits. The output is the number of padding bits at the
+    /// end and the codes, with no tree or length.
+    ///
+    /// Fails with [`SymbolNotInTree`](CompressionError::SymbolNotInTree) if
+    /// `escape` is not a leaf.
+    pub fn encode_escaped(&self, data: &[u8], escape: u8) -> Result<Vec<u8>, CompressionError> {
+        let table = self.to_lookup_array();
+        let escape_code = table[escape as usize]
+            .0
+            .as_ref()
+            .ok_or(CompressionError::SymbolNotInTree { symbol: escape })?;
+        let mut bits = BitWriter::new();
+        for &b in data {
+            match &table[b as usize].0 {
+                Some(code) if b != escape => bits.push_bits(code),
+                _ => {
+                    bits.push_bits(escape_code);
+                    bits.push_value(b as u64, 8);
+                }
+            }
+        }
+        let padding = bits.padding();
+        let mut out = bits.into_bytes();
+        out.insert(0, padding);
+        Ok(out)
+    }
+
+    /// Inverse of [`encode_escaped`](HuffmanTree::encode_escaped) with the
+    /// same tree and `escape`.
+    pub fn decode_escaped(&self, data: &[u8], escape: u8) -> Result<Vec<u8>, CompressionError> {
+        let (&padding, payload) = data.split_first().ok_or(CompressionError::TruncatedInput)?;
+        let mut bits = BitReader::with_padding(payload, padding)?;
+        let mut out = Vec::new();
+        while bits.remaining() > 0 {
+            let symbol = self
+                .decode_symbol(&mut bits)
+                .ok_or(CompressionError::DecodingFailed)?;
+            if symbol == escape {
+                let raw = bits.read_bits(8).ok_or(CompressionError::TruncatedInput)?;
+                out.push(raw as u8);
+            } else {
+                out.push(symbol);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Builds the node at `depth` from the upcoming leaves and their depths.
+fn node_from_depths(
+    depth: u8,
+    next: &mut core::iter::Peekable<impl Iterator<Item = (u8, u8)>>,
+) -> Result<Node, CompressionError> {
+    let &(symbol, leaf_depth) = next.peek().ok_or(CompressionError::CorruptTree)?;
+    match leaf_depth.cmp(&depth) {
+        core::cmp::Ordering::Equal => {
+            next.next();
+            Ok(Node::Leaf(symbol))
+        }
+        core::cmp::Ordering::Greater => Ok(Node::Tree(Box::new(HuffmanTree {
+            left: node_from_depths(depth + 1, next)?,
+            right: node_from_depths(depth + 1, next)?,
+        }))),
+        core::cmp::Ordering::Less => Err(CompressionError::CorruptTree),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TreeChoice {
+    A,
+    B,
+    Tie,
+}
+
+/// Payload sizes of some data under two candidate trees.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TreeComparison {
+    pub bits_with_a: u64,
+    pub bits_with_b: u64,
+    pub preferred: TreeChoice,
+    /// Bits saved by using `b` instead of `a`; negative when `a` is smaller.
+    pub bit_savings: i64,
+}
+
+/// Compares the encoded size of `data` under `a` and `b` without encoding
+/// it. Fails if either tree lacks a byte of `data`.
+pub fn compare_trees(
+    a: &HuffmanTree,
+    b: &HuffmanTree,
+    data: &[u8],
+) -> Result<TreeComparison, CompressionError> {
+    let bits_with_a = a.encoded_bit_count(data)?;
+    let bits_with_b = b.encoded_bit_count(data)?;
+    let preferred = match bits_with_a.cmp(&bits_with_b) {
+        core::cmp::Ordering::Less => TreeChoice::A,
+        core::cmp::Ordering::Greater => TreeChoice::B,
+        core::cmp::Ordering::Equal => TreeChoice::Tie,
+    };
+    Ok(TreeComparison {
+        bits_with_a,
+        bits_with_b,
+        preferred,
+        bit_savings: bits_with_a as i64 - bits_with_b as i64,
+    })
+}
+
+/// Compresses `data` into a stream that [`decompress`] restores. Data that
+/// Huffman coding would expand is stored as is, so the output is at most
+/// [`STORED_OVERHEAD`] bytes longer than `data`.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    compress_split(data, block::DEFAULT_BLOCK_SIZE, u8::MAX, &mut |_, _| {})
+}
+
+/// Like [`compress`] but splits inputs over `block_size` bytes into blocks
+/// of that size and keeps codes within `max_len` bits, calling `progress`
+/// as the blocks are coded.
+fn compress_split(
+    data: &[u8],
+    block_size: usize,
+    max_len: u8,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Vec<u8> {
+    let out = if data.len() > block_size {
+        block::encode_blocks(data, block_size, 1, max_len, progress)
+    } else {
+        encode_block_limited(data, max_len).to_bytes()
+    };
+    if out.len() > data.len() + STORED_OVERHEAD {
+        return store(data);
+    }
+    out
+}
+
+/// Like [`compress`] but with the settings in `options`. Panics if a field
+/// of `options` is out of the range its [`EncoderBuilder`] method accepts.
+pub fn compress_with(data: &[u8], options: &CompressionOptions) -> Vec<u8> {
+    compress_with_progress(data, options, &mut |_, _| {})
+}
+
+/// Like [`compress_with`] but calls `progress` with the bytes of `data`
+/// coded and the bytes of output so far: after every round of blocks when
+/// Huffman coding without a stage in front, and once at the end.
+pub(crate) fn compress_with_progress(
+    data: &[u8],
+    options: &CompressionOptions,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Vec<u8> {
+    if let Some(filter) = options.filter {
+        let unfiltered = CompressionOptions {
+            filter: None,
+            ..*options
+        };
+        let mut out = container_prefix(data.len() as u64, SupportedFeature::Filter.bit());
+        out.extend(filter.to_bytes());
+        out.extend(compress_with_progress(
+            &filter.apply(data),
+            &unfiltered,
+            progress,
+        ));
+        progress(data.len() as u64, out.len() as u64);
+        return out;
+    }
+    let level = options::level_settings(options.level);
+    let mut out = match options.algorithm {
+        Algorithm::Huffman => compress_huffman(data, options, level.block_size, progress),
+        Algorithm::Adaptive => compress_adaptive(data),
+        Algorithm::Range => compress_range(data, false),
+        Algorithm::Order1 => compress_range(data, true),
+        Algorithm::Words => compress_words(data, options.word_order),
+        Algorithm::LzHuffman => {
+            let tokens = lzss::encode_with(data, level.window, level.max_chain);
+            let mut out = compress_huffman(&tokens, options, level.block_size, &mut |_, _| {});
+            add_flags(&mut out, SupportedFeature::Lzss.bit());
+            out
+        }
+        Algorithm::Rle => {
+            let mut out = store(&transform::rle_encode(data));
+            add_flags(&mut out, SupportedFeature::Rle.bit());
+            out
+        }
+        Algorithm::RleHuffman => {
+            let rle = transform::rle_encode(data);
+            let mut out = compress_huffman(&rle, options, level.block_size, &mut |_, _| {});
+            add_flags(&mut out, SupportedFeature::Rle.bit());
+            out
+        }
+        Algorithm::Bwt => {
+            let bwt = bwt::bwt_encode(data);
+            let mut out = compress_huffman(&bwt, options, level.block_size, &mut |_, _| {});
+            add_flags(&mut out, SupportedFeature::Bwt.bit());
+            out
+        }
+    };
+    if !options.checksum {
+        remove_checksum(&mut out);
+    }
+    progress(data.len() as u64, out.len() as u64);
+    out
+}
+
+/// The Huffman layer of [`compress_with`]: [`compress`] with blocks of
+/// `level_block_size` bytes, or blocks of the chosen size on the chosen
+/// number of threads.
+fn compress_huffman(
+    data: &[u8],
+    options: &CompressionOptions,
+    level_block_size: usize,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Vec<u8> {
+    match (options.block_size, options.threads) {
+        (None, 1) => compress_split(data, level_block_size, options.max_code_length, progress),
+        (block_size, threads) => block::ParallelEncoder::new(threads)
+            .with_block_size(block_size.unwrap_or(level_block_size))
+            .with_max_code_length(options.max_code_length)
+            .encode_with_progress(data, progress),
+    }
+}
+
+/// A stream holding `data` coded with [`adaptive::adaptive_encode`]: the
+/// container with the adaptive flag, the codes and the checksum. Falls back
+/// to storing `data` like [`compress`].
+fn compress_adaptive(data: &[u8]) -> Vec<u8> {
+    let payload = adaptive::adaptive_encode(data);
+    if payload.len() > data.len() {
+        return store(data);
+    }
+    let flags = SupportedFeature::Adaptive.bit() | SupportedFeature::Checksum.bit();
+    let mut out = container_prefix(data.len() as u64, flags);
+    out.extend(payload);
+    out.extend(crc32(data).to_le_bytes());
+    out
+}
+
+/// The decoded bytes of an adaptive stream, given the rest of it after
+/// `container`.
+fn decode_adaptive(
+    container: &Container,
+    rest: &[u8],
+    verify: bool,
+) -> Result<Vec<u8>, CompressionError> {
+    if container.has_blocks() || container.has_stored() {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let (payload, checksum) = container.split_footer(rest)?;
+    let out = adaptive::adaptive_decode(payload, container.original_len)?;
+    if let Some(expected) = checksum.filter(|_| verify) {
+        let actual = crc32(&out);
+        if actual != expected {
+            return Err(CompressionError::ChecksumMismatch { expected, actual });
+        }
+    }
+    Ok(out)
+}
+
+/// A stream holding `data` as a range-coded container with the range flag,
+/// the model, the coded bytes and the checksum, or with the order-1 flag
+/// and no stored model if `order1` is set. Falls back to [`store`] if that
+/// would be longer.
+fn compress_range(data: &[u8], order1: bool) -> Vec<u8> {
+    let (payload, mut flags) = if order1 {
+        (range::order1_encode(data), SupportedFeature::Order1.bit())
+    } else {
+        (range::range_encode(data), 0)
+    };
+    if payload.len() > data.len() {
+        return store(data);
+    }
+    flags |= SupportedFeature::Range.bit() | SupportedFeature::Checksum.bit();
+    let mut out = container_prefix(data.len() as u64, flags);
+    out.extend(payload);
+    out.extend(crc32(data).to_le_bytes());
+    out
+}
+
+/// The decoded bytes of a range-coded stream, given the rest of it after
+/// `container`.
+fn decode_range(
+    container: &Container,
+    rest: &[u8],
+    verify: bool,
+) -> Result<Vec<u8>, CompressionError> {
+    if container.has_blocks() || container.has_stored() || container.has_adaptive() {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let (payload, checksum) = container.split_footer(rest)?;
+    let out = if container.has_order1() {
+        range::order1_decode(payload, container.original_len)?
+    } else {
+        range::range_decode(payload, container.original_len)?
+    };
+    if let Some(expected) = checksum.filter(|_| verify) {
+        let actual = crc32(&out);
+        if actual != expected {
+            return Err(CompressionError::ChecksumMismatch { expected, actual });
+        }
+    }
+    Ok(out)
+}
+
+/// A stream holding `data` as the container with the words flag, the
+/// payload of [`words::encode_payload`] and the checksum. Falls back to
+/// [`store`] if that would be longer.
+fn compress_words(data: &[u8], order: WordOrder) -> Vec<u8> {
+    let payload = words::encode_payload(data, order);
+    if payload.len() > data.len() {
+        return store(data);
+    }
+    let flags = SupportedFeature::Words.bit() | SupportedFeature::Checksum.bit();
+    let mut out = container_prefix(data.len() as u64, flags);
+    out.extend(payload);
+    out.extend(crc32(data).to_le_bytes());
+    out
+}
+
+/// The decoded bytes of a stream of 16-bit words, given the rest of it
+/// after `container`.
+fn decode_words(
+    container: &Container,
+    rest: &[u8],
+    verify: bool,
+) -> Result<Vec<u8>, CompressionError> {
+    if container.has_blocks() || container.has_stored() || container.has_range() {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let (payload, checksum) = container.split_footer(rest)?;
+    let (out, used) = words::decode_payload_prefix(payload, container.original_len)?;
+    if used != payload.len() {
+        return Err(CompressionError::CorruptData);
+    }
+    if let Some(expected) = checksum.filter(|_| verify) {
+        let actual = crc32(&out);
+        if actual != expected {
+            return Err(CompressionError::ChecksumMismatch { expected, actual });
+        }
+    }
+    Ok(out)
+}
+
+/// A stream holding `data` as is: the container with the stored flag, the
+/// bytes and their checksum.
+fn store(data: &[u8]) -> Vec<u8> {
+    let flags = SupportedFeature::Stored.bit() | SupportedFeature::Checksum.bit();
+    let mut out = container_prefix(data.len() as u64, flags);
+    out.extend(data);
+    out.extend(crc32(data).to_le_bytes());
+    out
+}
+
+/// The bytes of a stored stream, given the rest of it after `container`.
+fn decode_stored(
+    container: &Container,
+    rest: &[u8],
+    verify: bool,
+) -> Result<Vec<u8>, CompressionError> {
+    if container.has_blocks() {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let (data, checksum) = container.split_footer(rest)?;
+    if data.len() as u64 != container.original_len {
+        return Err(CompressionError::LengthMismatch {
+            expected: container.original_len,
+            actual: data.len() as u64,
+        });
+    }
+    if let Some(expected) = checksum.filter(|_| verify) {
+        let actual = crc32(data);
+        if actual != expected {
+            return Err(CompressionError::ChecksumMismatch { expected, actual });
+        }
+    }
+    Ok(data.to_vec())
+}
+
+/// Sets `flags` in the container of the stream `out`.
+fn add_flags(out: &mut [u8], flags: u32) {
+    let current = u32::from_le_bytes(out[5..9].try_into().unwrap());
+    out[5..9].copy_from_slice(&(current | flags).to_le_bytes());
+}
+
+/// Clears the checksum flag of a stream and drops the checksum at its end.
+fn remove_checksum(out: &mut Vec<u8>) {
+    let flags = u32::from_le_bytes(out[5..9].try_into().unwrap());
+    out[5..9].copy_from_slice(&(flags & !SupportedFeature::Checksum.bit()).to_le_bytes());
+    out.truncate(out.len() - CHECKSUM_LEN);
+}
+
+/// Encodes `data` with a tree of its own.
+#[cfg(test)]
+fn encode_block(data: &[u8]) -> Encoded {
+    encode_block_limited(data, u8::MAX)
+}
+
+/// Encodes `data` with a tree of its own whose codes have at most
+/// `max_len` bits.
+fn encode_block_limited(data: &[u8], max_len: u8) -> Encoded {
+    // Degenerate inputs skip counting frequencies and building a tree.
+    match count_unique_bytes(data) {
+        // Empty data has an empty tree. A lone symbol gets an empty code,
+        // so there is no payload and the original length is the symbol
+        // count.
+        0 | 1 if data.len() as u64 <= MAX_LONE_SYMBOL_LEN => {
+            let leaves = data.get(..1).unwrap_or_default();
+            return Encoded::from_bits(BitWriter::new(), serialize_tree_section(leaves, &[]), data);
+        }
+        // Both symbols get one-bit codes whatever their frequencies.
+        2 => {
+            let first = data[0];
+            let second = *data.iter().find(|&&b| b != first).unwrap();
+            let tree = HuffmanTree {
+                left: Node::Leaf(first.min(second)),
+                right: Node::Leaf(first.max(second)),
+            };
+            return tree.encode(data).expect("both leaves come from the data");
+        }
+        _ => {}
+    }
+    let freq = bound_lone_symbol(&histogram(data));
+    HuffmanTree::from_frequencies_limited(&freq, max_len)
+        .expect("at least two symbols are left")
+        .encode(data)
+        .expect("tree built from the data covers it")
+}
+
+/// `freq`, with a count of one for a second byte if it is a lone symbol
+/// repeated more than [`MAX_LONE_SYMBOL_LEN`] times, so that the stream
+/// gets a tree and a payload of one bit per byte.
+pub(crate) fn bound_lone_symbol(freq: &[u64; 256]) -> [u64; 256] {
+    let mut freq = *freq;
+    let mut present = (0..=255u8).filter(|&b| freq[b as usize] > 0);
+    if let (Some(b), None) = (present.next(), present.next())
+        && freq[b as usize] > MAX_LONE_SYMBOL_LEN
+    {
+        freq[b.wrapping_add(1) as usize] = 1;
+    }
+    freq
+}
+
+/// Builds the tree for `data` and returns only the header `compress` would
+/// write (container fields, padding byte and tree), for sending apart from
+/// the payload.
+pub fn encode_header_only(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let (tree, bits) = match HuffmanTree::from_frequencies(&bound_lone_symbol(&histogram(data))) {
+        Some(tree) => {
+            let bits = tree.encoded_bit_count(data)?;
+            (tree.serialize(), bits)
+        }
+        None => (
+            serialize_tree_section(data.get(..1).unwrap_or_default(), &[]),
+            0,
+        ),
+    };
+    let flags = SupportedFeature::Checksum.bit() | SupportedFeature::CanonicalTree.bit();
+    let mut header = container_prefix(data.len() as u64, flags);
+    header.push(((8 - bits % 8) % 8) as u8);
+    header.extend(tree);
+    Ok(header)
+}
+
+/// Encodes `data` with the tree in `header` and returns only the payload,
+/// followed by the checksum if `header` asks for one. `header` followed by
+/// the returned bytes decodes like the output of `compress`.
+pub fn encode_data_only(data: &[u8], header: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let (container, rest) = parse_container(header)?;
+    if container.original_len != data.len() as u64 {
+        return Err(CompressionError::LengthMismatch {
+            expected: container.original_len,
+            actual: data.len() as u64,
+        });
+    }
+    let [padding, section @ ..] = rest else {
+        return Err(CompressionError::TruncatedInput);
+    };
+    let parsed = parse_tree_section(section, container.has_canonical_tree())?;
+    if parsed.len != section.len() {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let bits = match parsed.tree {
+        Some(tree) => tree.encode_bits(data)?,
+        None => {
+            if let Some(&b) = data.iter().find(|b| !parsed.leaves.contains(b)) {
+                return Err(CompressionError::SymbolNotInTree { symbol: b });
+            }
+            BitWriter::new()
+        }
+    };
+    let mut encoded = Encoded::from_bits(bits, section.to_vec(), data);
+    if encoded.padding != *padding {
+        return Err(CompressionError::CorruptHeader);
+    }
+    if !container.has_checksum() {
+        encoded.checksum = None;
+    }
+    Ok([encoded.data_bytes(), &encoded.footer_bytes()].concat())
+}
+
+/// Restores the data passed to [`compress`], [`compress_with`] or
+/// [`compress_blocks`](block::compress_blocks). Several streams written one
+/// after another decode to their data joined in order.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    decode_container(data, true, u64::MAX, 1)
+}
+
+/// Like [`decompress`] but calls `on_progress` with the bytes of `data`
+/// read and the bytes decoded so far, after every block of a multi-block
+/// stream without transform stages and once at the end.
+pub fn decompress_with_progress(
+    data: &[u8],
+    mut on_progress: impl FnMut(u64, u64),
+) -> Result<Vec<u8>, CompressionError> {
+    let (container, mut rest) = parse_container(data)?;
+    if container.has_blocks() && container.stage_flags() == 0 {
+        let mut read = container.header_len as u64;
+        let mut out = Vec::new();
+        let blocks = block::decode_frames(&container, &mut rest, true, |block, frame_len| {
+            read += frame_len;
+            out.extend_from_slice(block);
+            on_progress(read, out.len() as u64);
+            Ok(())
+        });
+        // Streams joined after this one are left to `decompress`.
+        if blocks.is_ok() {
+            on_progress(data.len() as u64, out.len() as u64);
+            return Ok(out);
+        }
+    }
+    let out = decompress(data)?;
+    on_progress(data.len() as u64, out.len() as u64);
+    Ok(out)
+}
+
+/// Like [`decompress`] but does not verify the checksum.
+pub fn decompress_unverified(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    decode_container(data, false, u64::MAX, 1)
+}
+
+/// Like [`decompress`] but fails with
+/// [`OutputLimitExceeded`](CompressionError::OutputLimitExceeded) instead of
+/// producing more than `max_output_bytes`, for untrusted input. A header
+/// promising too much is rejected before anything is decoded.
+pub fn decode_with_limit(data: &[u8], max_output_bytes: u64) -> Result<Vec<u8>, CompressionError> {
+    decode_container(data, true, max_output_bytes, 1)
+}
+
+/// Like [`decompress`] but decodes the blocks of a multi-block stream on
+/// `threads` scoped threads. Other streams decode on one thread.
+///
+/// Panics if `threads` is 0.
+pub fn decompress_parallel(data: &[u8], threads: usize) -> Result<Vec<u8>, CompressionError> {
+    assert!(threads > 0, "at least one thread is needed");
+    decode_container(data, true, u64::MAX, threads)
+}
+
+/// Checks that `compressed`, the output of [`compress`], [`compress_with`]
+/// or [`gzip_encode`](gzip::gzip_encode), decodes to exactly the bytes
+/// read from `original`. Fails with
+/// [`VerificationFailed`](CompressionError::VerificationFailed) at the
+/// first byte that differs, or where one of them ends before the other.
+/// `original` is compared in chunks without being read into memory.
+#[cfg(feature = "std")]
+pub fn verify(
+    mut original: impl std::io::Read,
+    mut compressed: impl std::io::Read,
+) -> Result<(), CompressionError> {
+    let mut data = Vec::new();
+    compressed.read_to_end(&mut data)?;
+    let decoded = if gzip::is_gzip(&data) {
+        gzip::gzip_decode(&data)?
+    } else {
+        decompress(&data)?
+    };
+    let mut chunk = [0u8; 1 << 16];
+    let mut offset = 0;
+    loop {
+        let read = match original.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let expected = decoded.get(offset..).unwrap_or_default();
+        if let Some(i) = (0..read).find(|&i| expected.get(i) != Some(&chunk[i])) {
+            return Err(CompressionError::VerificationFailed {
+                offset: (offset + i) as u64,
+            });
+        }
+        offset += read;
+    }
+    if offset != decoded.len() {
+        return Err(CompressionError::VerificationFailed {
+            offset: offset as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Decodes `data` as one stream or, failing that, as several streams one
+/// after another, as `cat a.hfz b.hfz` leaves them, and joins their output.
+/// Each stream is measured from its own header and decoded on its own, and
+/// the first error is returned.
+fn decode_container(
+    data: &[u8],
+    verify: bool,
+    limit: u64,
+    threads: usize,
+) -> Result<Vec<u8>, CompressionError> {
+    // A single stream, by far the common case, is decoded only once.
+    let err = match decode_member(data, verify, limit, threads) {
+        Ok(out) => return Ok(out),
+        // The first stream alone is too long, and measuring it would mean
+        // decoding it without the limit.
+        Err(e @ CompressionError::OutputLimitExceeded { .. }) => return Err(e),
+        Err(e) => e,
+    };
+    match member_len(data) {
+        Ok(len) if data[len..].starts_with(&MAGIC) => {}
+        _ => return Err(err),
+    }
+    let mut rest = data;
+    let mut out = Vec::new();
+    while !rest.is_empty() {
+        // Anything but another stream after a stream is trailing data.
+        if !rest.starts_with(&MAGIC) {
+            return Err(CompressionError::TrailingData);
+        }
+        let (member, next) = rest.split_at(member_len(rest)?);
+        let decoded = decode_member(member, verify, limit - out.len() as u64, threads).map_err(
+            |e| match e {
+                CompressionError::OutputLimitExceeded { .. } => {
+                    CompressionError::OutputLimitExceeded { limit }
+                }
+                e => e,
+            },
+        )?;
+        out.extend(decoded);
+        rest = next;
+    }
+    Ok(out)
+}
+
+/// Length of the stream at the start of `data`, which may be followed by
+/// more. Streams of a single Huffman-coded, adaptive or range-coded block
+/// do not record their payload length, so those are decoded to find it.
+fn member_len(data: &[u8]) -> Result<usize, CompressionError> {
+    let (container, rest) = parse_container(data)?;
+    container.check_builtin()?;
+    if container.has_filter() {
+        let (_, inner) = container.split_filter(rest)?;
+        return Ok(container.header_len + FILTER_LEN + member_len(inner)?);
+    }
+    let footer_len = if container.has_checksum() {
+        CHECKSUM_LEN
+    } else {
+        0
+    };
+    let body_len = if container.has_stored() {
+        usize::try_from(container.original_len).map_err(|_| CompressionError::CorruptHeader)?
+    } else if container.has_adaptive() {
+        let bits = adaptive::adaptive_decode_prefix(rest, container.original_len)?.1;
+        bits.div_ceil(8) as usize
+    } else if container.has_order1() {
+        range::order1_decode_prefix(rest, container.original_len)?.1
+    } else if container.has_words() {
+        words::decode_payload_prefix(rest, container.original_len)?.1
+    } else if container.has_range() {
+        range::range_decode_prefix(rest, container.original_len)?.1
+    } else if container.has_blocks() {
+        let mut frames = rest;
+        let mut counted = 0u64;
+        let mut count = 0;
+        while counted < container.original_len {
+            counted += block::read_frame(&mut frames, &container)?.original_len;
+            count += 1;
+        }
+        let index_len = match container.has_block_index() {
+            true => count * block::INDEX_ENTRY_LEN + 4,
+            false => 0,
+        };
+        rest.len() - frames.len() + index_len
+    } else {
+        let [_padding, tail @ ..] = rest else {
+            return Err(CompressionError::TruncatedInput);
+        };
+        let section = parse_tree_section(tail, container.has_canonical_tree())?;
+        let payload = &tail[section.len..];
+        let bits = match section.tree {
+            Some(tree) => table::DecodeTable::new(&tree).decode_up_to(
+                payload,
+                bit_count(payload),
+                container.original_len,
+                |_| Ok(()),
+            )?,
+            None => 0,
+        };
+        1 + section.len + bits.div_ceil(8) as usize
+    };
+    body_len
+        .checked_add(container.header_len + footer_len)
+        .filter(|&len| len <= data.len())
+        .ok_or(CompressionError::TruncatedInput)
+}
+
+/// Decodes `data` as exactly one stream: the Huffman layer, in blocks or
+/// as a single stream, or the stored bytes, and then undoes the stage named
+/// in the flags. Fails if the output would exceed `limit` bytes. Blocks are
+/// decoded on `threads` threads.
+fn decode_member(
+    data: &[u8],
+    verify: bool,
+    limit: u64,
+    threads: usize,
+) -> Result<Vec<u8>, CompressionError> {
+    let (container, rest) = parse_container(data)?;
+    container.check_builtin()?;
+    if container.has_filter() {
+        if container.original_len > limit {
+            return Err(CompressionError::OutputLimitExceeded { limit });
+        }
+        let (filter, inner) = container.split_filter(rest)?;
+        let filtered = decode_member(inner, verify, limit, threads)?;
+        if filtered.len() as u64 != container.original_len {
+            return Err(CompressionError::CorruptData);
+        }
+        return Ok(filter.undo(&filtered));
+    }
+    // The output of a stage is at most twice as long as its input, plus
+    // the flag byte of a last LZSS group or the block headers of the BWT.
+    let layer_limit = match container.stage_flags() {
+        0 => limit,
+        flags if flags == SupportedFeature::Bwt.bit() => {
+            let blocks = limit.div_ceil(bwt::BLOCK_LEN as u64);
+            limit
+                .saturating_add(blocks.saturating_mul(bwt::BLOCK_HEADER_LEN as u64))
+                .saturating_mul(2)
+        }
+        _ => limit.saturating_mul(2).saturating_add(1),
+    };
+    if container.original_len > layer_limit {
+        return Err(CompressionError::OutputLimitExceeded { limit });
+    }
+    let decoded = if container.has_adaptive() {
+        decode_adaptive(&container, rest, verify)?
+    } else if container.has_range() {
+        decode_range(&container, rest, verify)?
+    } else if container.has_words() {
+        decode_words(&container, rest, verify)?
+    } else if container.has_order1() {
+        return Err(CompressionError::CorruptHeader);
+    } else if container.has_stored() {
+        decode_stored(&container, rest, verify)?
+    } else if container.has_blocks() {
+        block::decode_blocks(&container, rest, verify, threads)?
+    } else {
+        let encoded = Encoded::from_container(&container, rest)?;
+        if verify {
+            encoded.decode()?
+        } else {
+            encoded.decode_unverified()?
+        }
+    };
+    match container.stage_flags() {
+        0 => Ok(decoded),
+        flags if flags == SupportedFeature::Lzss.bit() => lzss::decode_limited(&decoded, limit),
+        flags if flags == SupportedFeature::Rle.bit() => {
+            transform::rle_decode_limited(&decoded, limit)
+        }
+        flags if flags == SupportedFeature::Bwt.bit() => bwt::bwt_decode_limited(&decoded, limit),
+        _ => Err(CompressionError::CorruptHeader),
+    }
+}
+
+/// Kraft sum `sum(2^-l)` over the code lengths `lengths`. A complete prefix
+/// code sums to exactly 1, an incomplete one to less and no prefix code can
+/// exceed 1.
+pub fn compute_kraft_inequality(lengths: &[u8]) -> f64 {
+    // Halving is exact and, unlike `powi`, needs no `std`.
+    lengths
+        .iter()
+        .map(|&len| (0..len).fold(1.0, |weight: f64, _| weight / 2.0))
+        .sum()
+}
+
+/// Number of distinct byte values in `data`, from 0 to 256.
+pub fn count_unique_bytes(data: &[u8]) -> u16 {
+    let mut seen = [false; 256];
+    for &b in data {
+        seen[b as usize] = true;
+    }
+    seen.iter().filter(|&&s| s).count() as u16
+}
+
+/// Number of occurrences of every byte value, indexed by byte.
+///
+/// Large inputs are counted into four separate tables, one per byte of
+/// every group of four, and summed at the end, so that runs of the same
+/// byte do not wait on each other's increments.
+pub fn histogram(bytes: &[u8]) -> [u64; 256] {
+    let mut freq = [0u64; 256];
+    if bytes.len() < HISTOGRAM_LANES_MIN {
+        for &b in bytes {
+            freq[b as usize] += 1;
+        }
+        return freq;
+    }
+    let mut lanes = [[0u64; 256]; 4];
+    let mut groups = bytes.chunks_exact(4);
+    for group in &mut groups {
+        for (lane, &b) in lanes.iter_mut().zip(group) {
+            lane[b as usize] += 1;
+        }
+    }
+    for &b in groups.remainder() {
+        lanes[0][b as usize] += 1;
+    }
+    for (i, total) in freq.iter_mut().enumerate() {
+        *total = lanes.iter().map(|lane| lane[i]).sum();
+    }
+    freq
+}
+
+/// Input length from which `histogram` counts in four tables.
+const HISTOGRAM_LANES_MIN: usize = 4096;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A right-leaning chain: the first symbol gets code 0, the next 10 and
+    /// so on, with the last two sharing the longest length.
+    fn chain(symbols: &[u8]) -> HuffmanTree {
+        let mut lengths = [0u8; 256];
+        for (i, &b) in symbols.iter().enumerate() {
+            lengths[b as usize] = (i + 1).min(symbols.len() - 1) as u8;
+        }
+        HuffmanTree::from_code_lengths(&lengths).unwrap()
+    }
+
+    #[test]
+    fn round_trip_repetitive_data() {
+        let data = b"aaaaaaaaaaaaaaaaaaaab";
+        assert_eq!(decompress(&compress(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_varied_data() {
+        let data = b"abcdefghijabcdefghij";
+        assert_eq!(decompress(&compress(data)).unwrap(), data);
+    }
+
+    /// `rest` behind valid container fields.
+    fn framed(original_len: u64, rest: &[u8]) -> Vec<u8> {
+        [container_prefix(original_len, 0).as_slice(), rest].concat()
+    }
+
+    #[test]
+    fn decompress_rejects_malformed_headers() {
+        assert_eq!(
+            decompress(&framed(0, &[0])),
+            Err(CompressionError::TruncatedInput)
+        );
+        assert_eq!(
+            decompress(&framed(3, &[0, 3, 0, b'a'])),
+            Err(CompressionError::TruncatedInput)
+        );
+        assert_eq!(
+            decompress(&framed(0, &[9, 0, 0, 0])),
+            Err(CompressionError::InvalidPadding)
+        );
+        // Depths 1, 1, 2 describe no tree.
+        assert_eq!(
+            decompress(&framed(3, &[0, 3, 0, b'a', b'b', b'c', 1, 1, 2])),
+            Err(CompressionError::CorruptTree)
+        );
+    }
+
+    #[test]
+    fn decompress_rejects_stream_ending_mid_code() {
+        // Tree a = 0, b = 10, c = 11 with a payload of "1" and seven padding bits.
+        assert_eq!(
+            decompress(&framed(
+                1,
+                &[7, 3, 0, b'a', b'b', b'c', 1, 2, 2, 0b1000_0000]
+            )),
+            Err(CompressionError::DecodingFailed)
+        );
+    }
+
+    #[test]
+    fn decompress_rejects_foreign_data() {
+        assert_eq!(
+            decompress(b"plain text"),
+            Err(CompressionError::InvalidMagic)
+        );
+        assert_eq!(decompress(b"WF"), Err(CompressionError::TruncatedInput));
+        assert_eq!(decompress(b""), Err(CompressionError::TruncatedInput));
+    }
+
+    #[test]
+    fn decompress_checks_version_and_flags() {
+        let mut newer = compress(b"versioned");
+        newer[4] = FORMAT_VERSION + 1;
+        assert_eq!(
+            decompress(&newer),
+            Err(CompressionError::UnsupportedVersion {
+                found: FORMAT_VERSION + 1,
+                supported: FORMAT_VERSION,
+            })
+        );
+        let mut flagged = compress(b"versioned");
+        flagged[7] = 0x80;
+        assert_eq!(
+            decompress(&flagged),
+            Err(CompressionError::UnsupportedFeatures { flags: 1 << 23 })
+        );
+        // Unknown optional flags are skipped.
+        let mut optional = compress(b"versioned");
+        optional[8] = 0x80;
+        assert_eq!(decompress(&optional).unwrap(), b"versioned");
+    }
+
+    #[test]
+    fn decompress_checks_original_length() {
+        let mut encoded = compress(b"abcabc");
+        encoded[9] = 7;
+        assert_eq!(
+            decompress(&encoded),
+            Err(CompressionError::LengthMismatch {
+                expected: 7,
+                actual: 6
+            })
+        );
+    }
+
+    #[test]
+    fn encode_rejects_bytes_missing_from_tree() {
+        for tree in [chain(b"ab"), chain(b"abc")] {
+            assert_eq!(
+                tree.encode(b"abz").err(),
+                Some(CompressionError::SymbolNotInTree { symbol: b'z' })
+            );
+        }
+    }
+
+    #[test]
+    fn repetitive_data_is_smaller_overall() {
+        let data = [&[b'a'; 60][..], b"b"].concat();
+        assert!(compress(&data).len() < data.len());
+    }
+
+    #[test]
+    fn non_repetitive_data_has_smaller_data_part() {
+        let data = b"abcdefghijabcdefghij";
+        let encoded = encode_block(data);
+        assert!(encoded.bytes.len() < data.len());
+    }
+
+    #[test]
+    fn code_lengths_of_two_symbol_tree() {
+        let tree = chain(b"ab");
+        assert_eq!(tree.shortest_code_length(), 1);
+        assert_eq!(tree.longest_code_length(), 1);
+    }
+
+    #[test]
+    fn code_lengths_of_three_symbol_tree() {
+        let tree = chain(b"abc");
+        assert_eq!(tree.shortest_code_length(), 1);
+        assert_eq!(tree.longest_code_length(), 2);
+    }
+
+    #[test]
+    fn code_lengths_of_skewed_tree() {
+        let tree = chain(b"abcdefgh");
+        assert_eq!(tree.shortest_code_length(), 1);
+        assert_eq!(tree.longest_code_length(), 7);
+    }
+
+    #[test]
+    fn count_unique_bytes_covers_full_range() {
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(count_unique_bytes(b""), 0);
+        assert_eq!(count_unique_bytes(b"aaaa"), 1);
+        assert_eq!(count_unique_bytes(b"abab"), 2);
+        assert_eq!(count_unique_bytes(&all), 256);
+    }
+
+    #[test]
+    fn round_trip_empty_data() {
+        assert_eq!(decompress(&compress(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn round_trip_single_symbol_data() {
+        let data = b"aaaaaaaaaaa";
+        assert_eq!(decompress(&compress(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn degenerate_inputs_take_fast_paths() {
+        let empty = encode_block(b"");
+        assert_eq!((empty.tree.as_slice(), empty.bytes.len()), (&[0, 0][..], 0));
+        let lone = encode_block(b"zzzz");
+        assert_eq!(
+            (lone.tree.as_slice(), lone.bytes.len()),
+            (&[1, 0, b'z'][..], 0)
+        );
+
+        // However skewed, two symbols take one bit each, the lower byte 0.
+        let data: Vec<u8> = (0..100u32)
+            .map(|i| if i % 25 == 0 { b'a' } else { b'b' })
+            .collect();
+        let two = encode_block(&data);
+        assert_eq!(two.bytes.len(), 13);
+        assert_eq!(two.bytes[0], 0b0111_1111);
+        assert_eq!(two.decode().unwrap(), data);
+        assert_eq!(two.tree, chain(b"ab").serialize());
+    }
+
+    #[test]
+    fn single_symbol_data_has_no_payload() {
+        let short = compress(b"aaaa");
+        let long = compress(&[b'a'; 100_000]);
+        assert_eq!(short.len(), long.len());
+        let encoded = Encoded::from_bytes(&long).unwrap();
+        assert!(encoded.data_bytes().is_empty());
+        assert_eq!(encoded.decode().unwrap(), vec![b'a'; 100_000]);
+    }
+
+    #[test]
+    fn decode_into_reuses_a_buffer() {
+        let mut buf = vec![0u8; 64];
+        for data in [&b"decode into a buffer"[..], b"zzzz", b""] {
+            let encoded = encode_block(data);
+            assert_eq!(encoded.original_len(), data.len() as u64);
+            let len = encoded.decode_into(&mut buf).unwrap();
+            assert_eq!(&buf[..len], data);
+        }
+        let encoded = encode_block(b"too long for the buffer");
+        assert_eq!(
+            encoded.decode_into(&mut buf[..4]),
+            Err(CompressionError::OutputLimitExceeded { limit: 4 })
+        );
+        let mut corrupt = encoded;
+        corrupt.checksum = Some(0);
+        assert!(matches!(
+            corrupt.decode_into(&mut buf),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn decode_chunks_holds_one_chunk_at_a_time() {
+        let data = b"decoded a few bytes at a time".repeat(10);
+        for chunk_size in [1, 7, 290, 1000] {
+            let mut out = Vec::new();
+            let mut sizes = Vec::new();
+            encode_block(&data)
+                .decode_chunks(chunk_size, |chunk| {
+                    out.extend_from_slice(chunk);
+                    sizes.push(chunk.len());
+                })
+                .unwrap();
+            assert_eq!(out, data);
+            let (last, full) = sizes.split_last().unwrap();
+            assert!(full.iter().all(|&len| len == chunk_size));
+            assert!((1..=chunk_size).contains(last));
+        }
+        // A lone symbol repeated far beyond one chunk.
+        let mut calls = 0;
+        encode_block(&[b'x'; 100_000])
+            .decode_chunks(4096, |chunk| {
+                assert!(chunk.len() <= 4096 && chunk.iter().all(|&b| b == b'x'));
+                calls += 1;
+            })
+            .unwrap();
+        assert_eq!(calls, 25);
+
+        let mut corrupt = encode_block(&data);
+        corrupt.checksum = Some(0);
+        assert!(matches!(
+            corrupt.decode_chunks(64, |_| {}),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_finds_the_first_difference() {
+        let data = b"verify the round trip of this text";
+        let compressed = compress(data);
+        assert_eq!(verify(&data[..], &compressed[..]), Ok(()));
+        assert_eq!(verify(&data[..], &gzip::gzip_encode(data)[..]), Ok(()));
+        let mut changed = data.to_vec();
+        changed[7] = b'!';
+        assert_eq!(
+            verify(&changed[..], &compressed[..]),
+            Err(CompressionError::VerificationFailed { offset: 7 })
+        );
+        assert_eq!(
+            verify(&data[..10], &compressed[..]),
+            Err(CompressionError::VerificationFailed { offset: 10 })
+        );
+        let longer = [&data[..], b"!"].concat();
+        assert_eq!(
+            verify(&longer[..], &compressed[..]),
+            Err(CompressionError::VerificationFailed {
+                offset: data.len() as u64
+            })
+        );
+    }
+
+    #[test]
+    fn empty_data_has_no_tree_or_payload() {
+        let encoded = encode_block(b"").to_bytes();
+        assert_eq!(encoded.len(), CONTAINER_LEN + 1 + 2 + CHECKSUM_LEN);
+        assert!(decompress(&encoded).unwrap().is_empty());
+        assert_eq!(compress(b"").len(), STORED_OVERHEAD);
+    }
+
+    #[test]
+    fn single_symbol_with_payload_is_rejected() {
+        let mut encoded = Encoded::from_bytes(&compress(b"aaaa")).unwrap();
+        encoded.bytes = vec![0];
+        assert_eq!(encoded.decode(), Err(CompressionError::CorruptData));
+    }
+
+    #[test]
+    fn round_trip_two_symbol_data() {
+        let data = b"abbbbbbbbba";
+        assert_eq!(decompress(&compress(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_all_byte_values() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        assert_eq!(decompress(&compress(&data)).unwrap(), data);
+        let coded = encode_block(&data).to_bytes();
+        assert_eq!(decompress(&coded).unwrap(), data);
+    }
+
+    /// All 256 byte values, skewed so that Huffman coding pays off.
+    fn skewed_all_bytes() -> Vec<u8> {
+        let mut data: Vec<u8> = (0..=255).collect();
+        data.extend((0..4000u32).map(|i| b"etaoin shrdlu"[(i * i % 13) as usize]));
+        data
+    }
+
+    #[test]
+    fn tree_section_over_255_bytes_round_trips() {
+        let data = skewed_all_bytes();
+        let encoded = compress(&data);
+        let header = Encoded::from_bytes(&encoded).unwrap().header_bytes();
+        let section = &header[CONTAINER_LEN + 1..];
+        assert_eq!(u16::from_le_bytes([section[0], section[1]]), 256);
+        assert!(section.len() > 255);
+        assert_eq!(decompress(&encoded).unwrap(), data);
+
+        let lazy: Result<Vec<u8>, _> = lazy::decode_lazy(&encoded).collect();
+        assert_eq!(lazy.unwrap(), data);
+        let mut streamed = Vec::new();
+        stream::decode_streaming(&encoded[..], &mut streamed).unwrap();
+        assert_eq!(streamed, data);
+        let blocks = block::compress_blocks(&data.repeat(3), data.len());
+        assert_eq!(decompress(&blocks).unwrap(), data.repeat(3));
+    }
+
+    #[test]
+    fn canonical_section_is_compact() {
+        let data: Vec<u8> = (0..=255).collect();
+        let section = HuffmanTree::from_frequencies(&histogram(&data))
+            .unwrap()
+            .serialize();
+        // Leaf count, 256 leaves, the longest length 8 and seven zero counts.
+        assert_eq!(section.len(), 2 + 256 + 1 + 7);
+        assert_eq!(section[258..], [8, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn trees_from_frequencies_are_canonical() {
+        let tree = HuffmanTree::from_frequencies(&histogram(b"mississippi river")).unwrap();
+        assert!(tree.is_canonical());
+        let section = parse_tree_section(&tree.serialize(), true).unwrap();
+        assert_eq!(section.tree.unwrap().code_lengths(), tree.code_lengths());
+    }
+
+    #[test]
+    fn non_canonical_tree_encodes_canonically() {
+        let tree = HuffmanTree::from_leaf_depths(b"cba", &[1, 2, 2]).unwrap();
+        assert!(!tree.is_canonical());
+        let encoded = tree.encode(b"abcabc").unwrap();
+        assert_eq!(decompress(&encoded.to_bytes()).unwrap(), b"abcabc");
+    }
+
+    #[test]
+    fn canonical_section_rejects_unsorted_leaves() {
+        // b before a at the same length.
+        assert!(matches!(
+            parse_tree_section(&[2, 0, b'b', b'a', 1], true),
+            Err(CompressionError::CorruptTree)
+        ));
+    }
+
+    #[test]
+    fn older_depth_layout_still_decodes() {
+        // Without the canonical flag: a = 0, b = 10 and c = 11 stored as a
+        // depth per leaf, then "abca" as 0 10 11 0 and two padding bits.
+        let data = framed(4, &[2, 3, 0, b'a', b'b', b'c', 1, 2, 2, 0b0101_1000]);
+        assert_eq!(decompress(&data).unwrap(), b"abca");
+    }
+
+    #[test]
+    fn canonical_layout_fixture_decodes() {
+        // With the canonical flag: leaves a, b, c by depth and then byte,
+        // the longest length 2 and one code of length 1, then "abca".
+        let flags = SupportedFeature::CanonicalTree.bit();
+        let mut data = container_prefix(4, flags);
+        data.extend([2, 3, 0, b'a', b'b', b'c', 2, 1, 0b0101_1000]);
+        assert_eq!(decompress(&data).unwrap(), b"abca");
+    }
+
+    #[test]
+    fn tied_frequencies_give_the_same_header_in_any_order() {
+        let forward = b"abcdefgh".repeat(8);
+        let backward = b"hgfedcba".repeat(8);
+        let tree = |data: &[u8]| HuffmanTree::from_frequencies(&histogram(data)).unwrap();
+        let (a, b) = (tree(&forward), tree(&backward));
+        assert_eq!(a.code_lengths(), b.code_lengths());
+        assert_eq!(
+            a.encode(&forward).unwrap().header_bytes(),
+            b.encode(&forward).unwrap().header_bytes()
+        );
+        assert_eq!(
+            a.encode(&forward).unwrap().to_bytes(),
+            b.encode(&forward).unwrap().to_bytes()
+        );
+    }
+
+    #[test]
+    fn code_lengths_stay_within_the_limit() {
+        // Fibonacci counts give a Huffman chain 24 codes deep.
+        let (mut a, mut b) = (1, 1);
+        let mut data = Vec::new();
+        for symbol in 0..25u8 {
+            data.extend(core::iter::repeat_n(symbol, a));
+            (a, b) = (b, a + b);
+        }
+        let freq = histogram(&data);
+        assert!(
+            HuffmanTree::from_frequencies(&freq)
+                .unwrap()
+                .longest_code_length()
+                > 12
+        );
+        let tree = HuffmanTree::from_frequencies_limited(&freq, 12).unwrap();
+        assert_eq!(tree.longest_code_length(), 12);
+        assert_eq!(tree.encode(&data).unwrap().decode().unwrap(), data);
+
+        let encoder = Encoder::builder().with_max_code_length(12).build();
+        let limited = encoder.encode(&data);
+        assert_eq!(decompress(&limited).unwrap(), data);
+        assert_ne!(limited, compress(&data));
+        let loose = Encoder::builder().with_max_code_length(30).build();
+        assert_eq!(loose.encode(&data), compress(&data));
+    }
+
+    #[test]
+    fn histogram_lanes_match_a_plain_count() {
+        let data: Vec<u8> = (0..HISTOGRAM_LANES_MIN as u32 * 3 + 3)
+            .map(|i| (i * 7 % 13 + i / 1000) as u8)
+            .collect();
+        for len in [0, 5, HISTOGRAM_LANES_MIN - 1, data.len()] {
+            let mut expected = [0u64; 256];
+            for &b in &data[..len] {
+                expected[b as usize] += 1;
+            }
+            assert_eq!(histogram(&data[..len]), expected);
+        }
+    }
+
+    #[test]
+    fn flat_distribution_gets_fixed_length_codes() {
+        let tree = HuffmanTree::from_frequencies(&[10; 256]).unwrap();
+        assert_eq!(tree.code_lengths(), [8; 256]);
+    }
+
+    #[test]
+    fn huffman_code_lengths_follow_frequencies() {
+        let mut freq = [0u64; 256];
+        for (b, f) in [
+            (b'a', 45),
+            (b'b', 13),
+            (b'c', 12),
+            (b'd', 16),
+            (b'e', 9),
+            (b'f', 5),
+        ] {
+            freq[b as usize] = f;
+        }
+        let lengths = HuffmanTree::from_frequencies(&freq).unwrap().code_lengths();
+        let expected = [
+            (b'a', 1),
+            (b'b', 3),
+            (b'c', 3),
+            (b'd', 3),
+            (b'e', 4),
+            (b'f', 4),
+        ];
+        for (b, len) in expected {
+            assert_eq!(lengths[b as usize], len);
+        }
+    }
+
+    #[test]
+    fn fewer_than_two_symbols_have_no_tree() {
+        let mut freq = [0u64; 256];
+        assert!(HuffmanTree::from_frequencies(&freq).is_none());
+        freq[7] = 3;
+        assert!(HuffmanTree::from_frequencies(&freq).is_none());
+    }
+
+    #[test]
+    fn tree_covers_its_training_alphabet() {
+        let tree = HuffmanTree::from_frequencies(&histogram(b"hello world")).unwrap();
+        assert!(tree.cover(b"hello"));
+        assert!(tree.cover(b""));
+        assert!(!tree.cover(b"help"));
+    }
+
+    #[test]
+    fn missing_symbols_are_reported_once() {
+        let tree = HuffmanTree::from_frequencies(&histogram(b"abc")).unwrap();
+        assert_eq!(tree.missing_symbols(b"abxcyx"), b"xy");
+    }
+
+    #[test]
+    fn codes_longer_than_64_bits_encode() {
+        // Byte `i` gets a code of `i + 1` bits, and the last two share the
+        // longest length, 70.
+        let mut lengths = [0u8; 256];
+        for (i, len) in lengths[..70].iter_mut().enumerate() {
+            *len = i as u8 + 1;
+        }
+        lengths[70] = 70;
+        let tree = HuffmanTree::from_code_lengths(&lengths).unwrap();
+        let data: Vec<u8> = (0..=70).rev().chain(0..=70).collect();
+        let encoded = tree.encode(&data).unwrap();
+        assert_eq!(encoded.decode().unwrap(), data);
+    }
+
+    #[test]
+    fn chain_codes_match_expected_bits() {
+        let table = chain(b"abcd").to_lookup_array();
+        // a = 0, b = 10, c = 110, d = 111
+        let expected = bitvec::BitVec::from_hex("5b8").unwrap();
+        let mut bits: Vec<bool> = Vec::new();
+        for b in b"abcd" {
+            bits.extend(table[*b as usize].0.as_ref().unwrap());
+        }
+        assert_eq!(bits, expected[..9]);
+    }
+
+    #[test]
+    fn round_trip_tree_with_frequencies() {
+        let data = b"mississippi river";
+        let mut freq = [0u32; 256];
+        for &b in data {
+            freq[b as usize] += 1;
+        }
+        let tree = HuffmanTree::from_frequencies(&histogram(data)).unwrap();
+        let serialized = tree.serialize_with_frequencies(&freq);
+        assert_eq!(serialized.len(), 256 * 5);
+
+        let (restored, restored_freq) =
+            HuffmanTree::from_serialized_with_frequencies(&serialized).unwrap();
+        assert_eq!(restored.code_lengths(), tree.code_lengths());
+        assert_eq!(restored_freq, freq);
+        assert_eq!(restored.encode(data).unwrap().decode().unwrap(), data);
+    }
+
+    #[test]
+    fn impossible_code_lengths_are_rejected() {
+        let mut data = vec![0u8; 256 * 5];
+        data[b'a' as usize] = 1;
+        data[b'b' as usize] = 1;
+        data[b'c' as usize] = 1;
+        assert_eq!(
+            HuffmanTree::from_serialized_with_frequencies(&data).err(),
+            Some(CompressionError::CorruptTree)
+        );
+    }
+
+    #[test]
+    fn kraft_sum_classifies_code_lengths() {
+        assert_eq!(compute_kraft_inequality(&[1, 2, 3, 3]), 1.0);
+        assert!(compute_kraft_inequality(&[2, 2, 2]) < 1.0);
+        assert!(compute_kraft_inequality(&[1, 1, 2]) > 1.0);
+    }
+
+    #[test]
+    fn built_trees_are_prefix_free() {
+        for leaves in [&b"ab"[..], b"abc", b"etaoinshrdlu"] {
+            let tree = chain(leaves);
+            assert!(tree.is_prefix_free());
+            let lengths: Vec<u8> = tree.code_lengths().into_iter().filter(|&l| l > 0).collect();
+            assert_eq!(compute_kraft_inequality(&lengths), 1.0);
+        }
+    }
+
+    #[test]
+    fn code_for_prefix_classifies_prefixes() {
+        // a = 0, b = 10, c = 11
+        let tree = chain(b"abc");
+        assert_eq!(tree.code_for_prefix(&[false]), PrefixResult::Complete(b'a'));
+        assert_eq!(
+            tree.code_for_prefix(&[true, true]),
+            PrefixResult::Complete(b'c')
+        );
+        assert_eq!(tree.code_for_prefix(&[]), PrefixResult::NeedMoreBits);
+        assert_eq!(tree.code_for_prefix(&[true]), PrefixResult::NeedMoreBits);
+        assert_eq!(tree.code_for_prefix(&[false, true]), PrefixResult::Invalid);
+    }
+
+    #[test]
+    fn lookup_array_marks_absent_bytes() {
+        let table = chain(b"abc").to_lookup_array();
+        assert_eq!(table[b'a' as usize], (Some(vec![false]), 1));
+        assert_eq!(table[b'c' as usize], (Some(vec![true, true]), 2));
+        assert_eq!(table[b'z' as usize], (None, 0));
+    }
+
+    #[test]
+    fn reinterpret_recovers_with_external_tree() {
+        let data = b"aaaabbbcc";
+        let tree = chain(b"abc");
+        let mut encoded = tree.encode(data).unwrap();
+        encoded.tree = vec![3, b'x', b'y', b'z'];
+        assert_eq!(encoded.reinterpret_as(&tree).unwrap(), data);
+    }
+
+    #[test]
+    fn reinterpret_rejects_stream_ending_mid_code() {
+        let encoded = chain(b"abc").encode(b"c").unwrap();
+        let other = chain(b"wxyz");
+        assert_eq!(
+            encoded.reinterpret_as(&other),
+            Err(CompressionError::DecodingFailed)
+        );
+    }
+
+    #[test]
+    fn coverage_report_lists_missing_bytes() {
+        let tree = chain(b"abc");
+        let report = tree.coverage_report(b"abc\x1b\x00cab");
+        assert_eq!(report.total_symbols, 5);
+        assert_eq!(report.covered_symbols, 3);
+        assert_eq!(report.missing, vec![0x00, 0x1b]);
+        assert_eq!(
+            report.to_string(),
+            "Coverage: 3/5 symbols (60.0%). Missing: [0x00, 0x1B]"
+        );
+    }
+
+    #[test]
+    fn coverage_report_of_covered_data() {
+        let report = chain(b"abc").coverage_report(b"cab");
+        assert!(report.missing.is_empty());
+        assert_eq!(report.to_string(), "Coverage: 3/3 symbols (100.0%).");
+    }
+
+    #[test]
+    fn compare_trees_prefers_tree_matching_frequencies() {
+        let data = b"aaaaaaaabbbbc";
+        let good = chain(b"abc");
+        let bad = chain(b"cba");
+        let comparison = compare_trees(&bad, &good, data).unwrap();
+        assert_eq!(comparison.bits_with_a, 8 * 2 + 4 * 2 + 1);
+        assert_eq!(comparison.bits_with_b, 8 + 4 * 2 + 2);
+        assert_eq!(comparison.preferred, TreeChoice::B);
+        assert_eq!(comparison.bit_savings, 7);
+    }
+
+    #[test]
+    fn seeded_encoding_warns_below_the_threshold() {
+        let seed = chain(b"abcd");
+        let mut warnings = Vec::new();
+        let encoded = seed
+            .encode_with_seed(b"abcabc", 1.0, |r| warnings.push(r.to_string()))
+            .unwrap();
+        assert_eq!(encoded.decode().unwrap(), b"abcabc");
+        assert!(warnings.is_empty());
+
+        let result = seed.encode_with_seed(b"abcxy", 0.75, |r| warnings.push(r.to_string()));
+        assert_eq!(
+            result.map(|_| ()),
+            Err(CompressionError::SymbolNotInTree { symbol: b'x' })
+        );
+        assert_eq!(
+            warnings,
+            ["Coverage: 3/5 symbols (60.0%). Missing: [0x78, 0x79]"]
+        );
+        // One missing byte in five stays above a lower threshold.
+        let _ = seed.encode_with_seed(b"abcdx", 0.75, |_| panic!("above the threshold"));
+    }
+
+    #[test]
+    fn compare_trees_reports_tie() {
+        let comparison = compare_trees(&chain(b"ab"), &chain(b"ba"), b"abab").unwrap();
+        assert_eq!(comparison.preferred, TreeChoice::Tie);
+        assert_eq!(comparison.bit_savings, 0);
+    }
+
+    #[test]
+    fn compare_trees_rejects_uncovered_data() {
+        let a = chain(b"ab");
+        let b = chain(b"abc");
+        assert_eq!(
+            compare_trees(&a, &b, b"abc"),
+            Err(CompressionError::SymbolNotInTree { symbol: b'c' })
+        );
+    }
+
+    #[test]
+    fn two_symbol_tree_is_trivial() {
+        assert!(chain(b"ab").is_trivial());
+        assert!(!chain(b"abc").is_trivial());
+    }
+
+    #[test]
+    fn trivial_tree_encodes_one_bit_per_symbol() {
+        let encoded = chain(b"ab").encode(b"abbabbba").unwrap();
+        assert_eq!(encoded.bytes, vec![0b0110_1110]);
+        assert_eq!(encoded.decode().unwrap(), b"abbabbba");
+
+        // Whole words of 64 bytes and a tail take the same codes.
+        let data: Vec<u8> = (0..150u32)
+            .map(|i| b"ab"[(i * 7 % 3 % 2) as usize])
+            .collect();
+        let encoded = chain(b"ab").encode(&data).unwrap();
+        assert_eq!(encoded.bytes.len(), 19);
+        assert_eq!(encoded.decode().unwrap(), data);
+        assert_eq!(
+            chain(b"ab").encode(b"abc").map(|_| ()),
+            Err(CompressionError::SymbolNotInTree { symbol: b'c' })
+        );
+    }
+
+    #[test]
+    fn header_and_data_make_up_the_stream() {
+        let encoded = encode_block(b"split me into header and data");
+        let header = encoded.header_bytes();
+        let data = encoded.data_bytes();
+        let footer = encoded.footer_bytes();
+        assert_eq!(footer.len(), CHECKSUM_LEN);
+        assert_eq!(
+            [header.as_slice(), data, &footer].concat(),
+            encoded.to_bytes()
+        );
+    }
+
+    #[test]
+    fn flipped_payload_bit_fails_the_checksum() {
+        // a = 0 and b = 1, so a flipped bit swaps one symbol for the other.
+        let mut encoded = compress(b"abababab");
+        let last_payload = encoded.len() - CHECKSUM_LEN - 1;
+        encoded[last_payload] ^= 1;
+        assert!(matches!(
+            decompress(&encoded),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(decompress_unverified(&encoded).unwrap(), b"abababaa");
+    }
+
+    #[test]
+    fn corrupt_footer_is_reported() {
+        let mut encoded = compress(b"checksummed");
+        let last = encoded.len() - 1;
+        encoded[last] ^= 0xff;
+        let expected = u32::from_le_bytes(encoded[last - 3..].try_into().unwrap());
+        assert_eq!(
+            decompress(&encoded),
+            Err(CompressionError::ChecksumMismatch {
+                expected,
+                actual: checksum::crc32(b"checksummed"),
+            })
+        );
+        assert_eq!(decompress_unverified(&encoded).unwrap(), b"checksummed");
+    }
+
+    #[test]
+    fn missing_footer_is_truncated() {
+        let encoded = compress(b"");
+        assert_eq!(
+            decompress(&encoded[..encoded.len() - 1]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn common_prefix_of_codes() {
+        // a = 0, b = 10, c = 110, d = 111
+        let tree = chain(b"abcd");
+        assert_eq!(tree.common_prefix_length(b'a', b'b'), Some(0));
+        assert_eq!(tree.common_prefix_length(b'c', b'd'), Some(2));
+        assert_eq!(tree.common_prefix_length(b'b', b'd'), Some(1));
+        assert_eq!(tree.common_prefix_length(b'a', b'z'), None);
+    }
+
+    #[test]
+    fn header_and_data_sent_separately_round_trip() {
+        for data in [
+            &b""[..],
+            b"zzz",
+            b"ab",
+            b"separate channels for header and data",
+        ] {
+            let header = encode_header_only(data).unwrap();
+            let payload = encode_data_only(data, &header).unwrap();
+            assert_eq!(decompress(&[header, payload].concat()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn data_only_rejects_bytes_missing_from_header() {
+        let header = encode_header_only(b"aabbc").unwrap();
+        assert_eq!(
+            encode_data_only(b"abxab", &header),
+            Err(CompressionError::SymbolNotInTree { symbol: b'x' })
+        );
+    }
+
+    #[test]
+    fn filtered_tree_excludes_rare_symbols() {
+        let mut freq = [0u32; 256];
+        freq[b'a' as usize] = 50;
+        freq[b'b' as usize] = 20;
+        freq[b'c' as usize] = 5;
+        freq[b'x' as usize] = 1;
+        freq[b'y' as usize] = 2;
+        let (tree, excluded) = HuffmanTree::from_frequencies_filtered(&freq, 5).unwrap();
+        assert_eq!(excluded, b"xy");
+        assert!(tree.cover(b"abc"));
+        // `x` is left as the escape leaf for both.
+        assert_eq!(tree.missing_symbols(b"abcxy"), b"y");
+        assert_eq!(tree.to_lookup_array()[b'a' as usize].1, 1);
+
+        // One symbol and the escape still make a tree, the escape alone not.
+        assert!(HuffmanTree::from_frequencies_filtered(&freq, 21).is_ok());
+        assert_eq!(
+            HuffmanTree::from_frequencies_filtered(&freq, 51).map(|_| ()),
+            Err(CompressionError::TooFewSymbols { kept: 1 })
+        );
+    }
+
+    #[test]
+    fn filtered_out_bytes_are_escaped() {
+        let data = b"abababcabba xyabc\x00abz".to_vec();
+        let mut freq = [0u32; 256];
+        for &b in &data {
+            freq[b as usize] += 1;
+        }
+        let (tree, excluded) = HuffmanTree::from_frequencies_filtered(&freq, 2).unwrap();
+        assert_eq!(excluded, b"\x00 xyz");
+        let escape = excluded[0];
+        let encoded = tree.encode_escaped(&data, escape).unwrap();
+        assert_eq!(tree.decode_escaped(&encoded, escape).unwrap(), data);
+        // Bytes never counted are escaped too.
+        let unseen = tree.encode_escaped(b"a\xffb", escape).unwrap();
+        assert_eq!(tree.decode_escaped(&unseen, escape).unwrap(), b"a\xffb");
+
+        // Each escaped byte costs the escape code and eight raw bits.
+        let escape_len = tree.to_lookup_array()[escape as usize].1 as usize;
+        let y = tree.encode_escaped(b"y", escape).unwrap();
+        assert_eq!(y[1..].len() * 8 - y[0] as usize, escape_len + 8);
+        assert_eq!(
+            chain(b"ab").encode_escaped(b"c", b'c'),
+            Err(CompressionError::SymbolNotInTree { symbol: b'c' })
+        );
+    }
+
+    #[test]
+    fn lz_huffman_beats_huffman_on_repeated_text() {
+        let data = b"the cat sat on the mat, the cat sat on the hat. ".repeat(40);
+        let options = CompressionOptions {
+            algorithm: Algorithm::LzHuffman,
+            ..Default::default()
+        };
+        let lz = compress_with(&data, &options);
+        assert!(lz.len() < compress(&data).len() / 4);
+        assert_eq!(decompress(&lz).unwrap(), data);
+        assert_eq!(decompress_unverified(&lz).unwrap(), data);
+        assert_eq!(
+            compress_with(&data, &CompressionOptions::default()),
+            compress(&data)
+        );
+    }
+
+    #[test]
+    fn lz_huffman_round_trips_in_blocks() {
+        let data: Vec<u8> = (0..block::DEFAULT_BLOCK_SIZE * 3)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect();
+        let options = CompressionOptions {
+            algorithm: Algorithm::LzHuffman,
+            ..Default::default()
+        };
+        assert_eq!(decompress(&compress_with(&data, &options)).unwrap(), data);
+    }
+
+    #[test]
+    fn delta_filter_shrinks_samples_and_round_trips() {
+        // A slow 16-bit ramp, as from a sensor or PCM audio.
+        let data: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| ((i * 3 + i / 7) as u16).to_le_bytes())
+            .collect();
+        let filtered = CompressionOptions {
+            filter: Some(Filter::delta(2)),
+            ..Default::default()
+        };
+        let encoded = compress_with(&data, &filtered);
+        assert!(encoded.len() < compress(&data).len() / 2);
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        assert_eq!(decompress_unverified(&encoded).unwrap(), data);
+        assert_eq!(
+            decode_with_limit(&encoded, data.len() as u64 - 1),
+            Err(CompressionError::OutputLimitExceeded {
+                limit: data.len() as u64 - 1
+            })
+        );
+        let twice = [encoded.clone(), encoded.clone()].concat();
+        assert_eq!(decompress(&twice).unwrap(), [&data[..], &data].concat());
+
+        let lz = CompressionOptions {
+            algorithm: Algorithm::LzHuffman,
+            filter: Some(Filter::interleave(4)),
+            ..Default::default()
+        };
+        assert_eq!(decompress(&compress_with(&data, &lz)).unwrap(), data);
+        assert_eq!(
+            Encoded::from_bytes(&encoded).err(),
+            Some(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Filter.bit()
+            })
+        );
+
+        // A filtered stream may not wrap another.
+        let mut nested = encoded[..CONTAINER_LEN + FILTER_LEN].to_vec();
+        nested.extend(&encoded);
+        assert_eq!(decompress(&nested), Err(CompressionError::CorruptHeader));
+    }
+
+    #[test]
+    fn single_stream_decoders_reject_lzss() {
+        let options = CompressionOptions {
+            algorithm: Algorithm::LzHuffman,
+            ..Default::default()
+        };
+        let encoded = compress_with(b"abcabcabcabc", &options);
+        let unsupported = CompressionError::UnsupportedFeatures {
+            flags: SupportedFeature::Lzss.bit(),
+        };
+        assert_eq!(Encoded::from_bytes(&encoded).err(), Some(unsupported));
+        assert_eq!(
+            stream::decode_streaming(&encoded[..], &mut Vec::new()),
+            Err(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Lzss.bit(),
+            })
+        );
+    }
+
+    #[test]
+    fn rle_modes_round_trip_sparse_data() {
+        let mut data = vec![0u8; 10_000];
+        for i in (0..data.len()).step_by(997) {
+            data[i] = 0xff;
+        }
+        let huffman = compress(&data).len();
+        for algorithm in [Algorithm::Rle, Algorithm::RleHuffman] {
+            let encoded = compress_with(
+                &data,
+                &CompressionOptions {
+                    algorithm,
+                    ..Default::default()
+                },
+            );
+            assert!(encoded.len() < huffman);
+            assert_eq!(decompress(&encoded).unwrap(), data);
+        }
+        let empty = compress_with(
+            b"",
+            &CompressionOptions {
+                algorithm: Algorithm::Rle,
+                ..Default::default()
+            },
+        );
+        assert_eq!(decompress(&empty).unwrap(), b"");
+    }
+
+    #[test]
+    fn levels_trade_speed_for_ratio() {
+        let data: Vec<u8> = (0..100_000u64)
+            .map(|i| b"level by level, "[(i * i % 7 + i) as usize % 16])
+            .collect();
+        let at = |algorithm, level| {
+            let encoded = Encoder::builder()
+                .with_algorithm(algorithm)
+                .with_level(level)
+                .build()
+                .encode(&data);
+            assert_eq!(decompress(&encoded).unwrap(), data);
+            encoded
+        };
+        assert_eq!(
+            at(Algorithm::Huffman, options::DEFAULT_LEVEL),
+            compress(&data)
+        );
+        let fast = parse_container(&at(Algorithm::Huffman, 1)).unwrap().0;
+        assert!(fast.has_blocks());
+        assert!(at(Algorithm::LzHuffman, 9).len() <= at(Algorithm::LzHuffman, 1).len());
+    }
+
+    #[test]
+    fn builder_settings_survive_decoding() {
+        let data = b"settings in the header, settings in the header".repeat(40);
+        let encoder = Encoder::builder()
+            .with_algorithm(Algorithm::LzHuffman)
+            .with_block_size(256)
+            .with_threads(3)
+            .with_level(9)
+            .with_checksum(false)
+            .build();
+        let encoded = encoder.encode(&data);
+        let (container, _) = parse_container(&encoded).unwrap();
+        assert!(container.has_blocks());
+        assert!(!container.has_checksum());
+        assert_eq!(container.stage_flags(), SupportedFeature::Lzss.bit());
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        let default = Encoder::builder().build();
+        assert_eq!(default.options(), &CompressionOptions::default());
+        assert_eq!(default.encode(&data), compress(&data));
+    }
+
+    #[test]
+    fn progress_is_reported_per_round_and_at_the_end() {
+        let data = b"progress through many small blocks. ".repeat(100);
+        let encoder = Encoder::builder()
+            .with_block_size(100)
+            .with_threads(2)
+            .build();
+        let mut reports = Vec::new();
+        let encoded =
+            encoder.encode_with_progress(&data, |read, written| reports.push((read, written)));
+        assert_eq!(encoded, encoder.encode(&data));
+        // Two threads code eight blocks a round.
+        assert_eq!(reports.len(), data.len().div_ceil(800) + 1);
+        assert!(reports.is_sorted());
+        assert_eq!(
+            reports.last(),
+            Some(&(data.len() as u64, encoded.len() as u64))
+        );
+
+        let mut reports = Vec::new();
+        let decoded =
+            decompress_with_progress(&encoded, |read, written| reports.push((read, written)))
+                .unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(reports.len(), data.len().div_ceil(100) + 1);
+        assert!(reports.is_sorted());
+        assert_eq!(
+            reports.last(),
+            Some(&(encoded.len() as u64, data.len() as u64))
+        );
+
+        let mut reports = Vec::new();
+        let small = compress(b"one report");
+        decompress_with_progress(&small, |read, written| reports.push((read, written))).unwrap();
+        assert_eq!(reports, [(small.len() as u64, 10)]);
+    }
+
+    #[test]
+    fn concatenated_streams_decode_in_order() {
+        let text = b"one stream after another, as cat leaves them. ".repeat(20);
+        let with = |algorithm, checksum| CompressionOptions {
+            algorithm,
+            checksum,
+            ..Default::default()
+        };
+        let members = [
+            (&text[..], compress(&text)),
+            (b"", compress(b"")),
+            (b"zzzz", compress(b"zzzz")),
+            (&text[..300], block::compress_blocks(&text[..300], 64)),
+            (&text[..100], seekable::compress_seekable(&text[..100], 30)),
+            (
+                b"aaaabbbbbbcc",
+                compress_with(b"aaaabbbbbbcc", &with(Algorithm::Rle, true)),
+            ),
+            (
+                &text[..],
+                compress_with(&text, &with(Algorithm::LzHuffman, false)),
+            ),
+            (
+                &text[..],
+                compress_with(&text, &with(Algorithm::Adaptive, true)),
+            ),
+            (
+                &text[..],
+                compress_with(&text, &with(Algorithm::Range, false)),
+            ),
+            (
+                &text[..],
+                compress_with(&text, &with(Algorithm::Order1, true)),
+            ),
+            (&text[..], compress_with(&text, &with(Algorithm::Bwt, true))),
+            (&text[..7], compress(&text[..7])),
+        ];
+        let expected: Vec<u8> = members.iter().flat_map(|m| m.0.iter().copied()).collect();
+        let joined: Vec<u8> = members.iter().flat_map(|m| m.1.iter().copied()).collect();
+        assert_eq!(decompress(&joined).unwrap(), expected);
+        assert_eq!(decompress_parallel(&joined, 3).unwrap(), expected);
+
+        let limit = expected.len() as u64 - 1;
+        assert_eq!(
+            decode_with_limit(&joined, limit),
+            Err(CompressionError::OutputLimitExceeded { limit })
+        );
+        let trailing = [&joined[..], b"tail"].concat();
+        assert_eq!(decompress(&trailing), Err(CompressionError::TrailingData));
+        let mut corrupt = joined.clone();
+        let second_payload = members[0].1.len() + members[1].1.len() + CONTAINER_LEN + 8;
+        corrupt[second_payload] ^= 0x10;
+        assert!(decompress(&corrupt).is_err());
+    }
+
+    #[test]
+    fn bwt_round_trips_and_is_recorded() {
+        let data =
+            b"2026-01-01 INFO request served in 12ms\n2026-01-01 WARN slow request\n".repeat(50);
+        let options = CompressionOptions {
+            algorithm: Algorithm::Bwt,
+            ..Default::default()
+        };
+        let encoded = compress_with(&data, &options);
+        let container = parse_container(&encoded).unwrap().0;
+        assert_eq!(container.stage_flags(), SupportedFeature::Bwt.bit());
+        assert!(encoded.len() < compress(&data).len());
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        assert_eq!(info::inspect(&encoded).unwrap().algorithm, Algorithm::Bwt);
+        assert_eq!(decompress(&compress_with(b"", &options)).unwrap(), b"");
+    }
+
+    #[test]
+    fn order1_streams_are_recorded_and_need_the_range_flag() {
+        let data = b"she sells sea shells by the sea shore, the shells she sells".repeat(30);
+        let options = CompressionOptions {
+            algorithm: Algorithm::Order1,
+            ..Default::default()
+        };
+        let encoded = compress_with(&data, &options);
+        let container = parse_container(&encoded).unwrap().0;
+        assert!(container.has_range() && container.has_order1());
+        assert!(encoded.len() * 3 < compress(&data).len() * 2);
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        assert_eq!(
+            info::inspect(&encoded).unwrap().algorithm,
+            Algorithm::Order1
+        );
+
+        let mut flagged = encoded.clone();
+        let flags = container.flags & !SupportedFeature::Range.bit();
+        flagged[5..9].copy_from_slice(&flags.to_le_bytes());
+        assert_eq!(decompress(&flagged), Err(CompressionError::CorruptHeader));
+    }
+
+    #[test]
+    fn word_streams_record_their_byte_order() {
+        // Sixteen code points whose high and low bytes alone say little.
+        let mut state = 0x2545_f491u32;
+        let mut data: Vec<u8> = (0..4000)
+            .flat_map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as u16 % 16 * 0x1001).to_be_bytes()
+            })
+            .collect();
+        data.push(b'!');
+        for order in [WordOrder::LittleEndian, WordOrder::BigEndian] {
+            let options = CompressionOptions {
+                algorithm: Algorithm::Words,
+                word_order: order,
+                ..Default::default()
+            };
+            let encoded = compress_with(&data, &options);
+            assert!(parse_container(&encoded).unwrap().0.has_words());
+            assert!(encoded.len() * 3 < compress(&data).len() * 2);
+            assert_eq!(decompress(&encoded).unwrap(), data);
+            assert_eq!(info::inspect(&encoded).unwrap().algorithm, Algorithm::Words);
+            let joined = [&encoded[..], &compress(b"tail")].concat();
+            assert_eq!(decompress(&joined).unwrap(), [&data[..], b"tail"].concat());
+            for short in [&b""[..], b"a", b"ab"] {
+                assert_eq!(decompress(&compress_with(short, &options)).unwrap(), short);
+            }
+        }
+        let options = CompressionOptions {
+            algorithm: Algorithm::Words,
+            word_order: WordOrder::BigEndian,
+            ..Default::default()
+        };
+        let mut encoded = compress_with(&data, &options);
+        assert_eq!(encoded[CONTAINER_LEN], 1);
+        encoded[CONTAINER_LEN] = 0;
+        assert!(matches!(
+            decompress(&encoded),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn range_coding_beats_huffman_on_skewed_data() {
+        let data: Vec<u8> = (0..20_000u32)
+            .map(|i| if i % 40 == 0 { b'!' } else { b'.' })
+            .collect();
+        let options = CompressionOptions {
+            algorithm: Algorithm::Range,
+            ..Default::default()
+        };
+        let encoded = compress_with(&data, &options);
+        assert!(parse_container(&encoded).unwrap().0.has_range());
+        assert!(encoded.len() * 4 < compress(&data).len());
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        assert_eq!(info::inspect(&encoded).unwrap().algorithm, Algorithm::Range);
+
+        let mut corrupt = encoded.clone();
+        let middle = corrupt.len() / 2;
+        corrupt[middle] ^= 0x40;
+        assert!(decompress(&corrupt).is_err());
+        assert_eq!(
+            Encoded::from_bytes(&encoded).err(),
+            Some(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Range.bit()
+            })
+        );
+    }
+
+    #[test]
+    fn checksum_can_be_left_out() {
+        for algorithm in [
+            Algorithm::Huffman,
+            Algorithm::Rle,
+            Algorithm::Adaptive,
+            Algorithm::Range,
+            Algorithm::Order1,
+        ] {
+            let options = CompressionOptions {
+                algorithm,
+                checksum: false,
+                ..Default::default()
+            };
+            let data = b"no checksum at the end".repeat(8);
+            let with = compress_with(
+                &data,
+                &CompressionOptions {
+                    algorithm,
+                    ..Default::default()
+                },
+            );
+            let without = compress_with(&data, &options);
+            assert_eq!(without.len() + CHECKSUM_LEN, with.len());
+            assert_eq!(decompress(&without).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn stored_rle_is_checksummed() {
+        let options = CompressionOptions {
+            algorithm: Algorithm::Rle,
+            ..Default::default()
+        };
+        let mut encoded = compress_with(b"aaaabbbbbbcc", &options);
+        assert_eq!(
+            Encoded::from_bytes(&encoded).err(),
+            Some(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Rle.bit(),
+            })
+        );
+        encoded[CONTAINER_LEN] ^= 1;
+        assert!(matches!(
+            decompress(&encoded),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(decompress_unverified(&encoded).unwrap(), b"aaaaabbbbbbcc");
+    }
+
+    #[test]
+    fn incompressible_data_is_stored() {
+        let data: Vec<u8> = (0..=255).collect();
+        let encoded = compress(&data);
+        assert_eq!(encoded.len(), data.len() + STORED_OVERHEAD);
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        let data = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaab";
+        assert!(compress(data).len() < data.len() + STORED_OVERHEAD);
+        let stored = store(b"stored");
+        assert_eq!(
+            Encoded::from_bytes(&stored).err(),
+            Some(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Stored.bit(),
+            })
+        );
+    }
+
+    #[test]
+    fn adaptive_round_trips_without_a_tree() {
+        let options = CompressionOptions {
+            algorithm: Algorithm::Adaptive,
+            ..Default::default()
+        };
+        let data = b"one pass, no tree: one pass, no tree, one pass".repeat(10);
+        let encoded = compress_with(&data, &options);
+        assert!(encoded.len() < data.len() * 2 / 3);
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        assert_eq!(
+            Encoded::from_bytes(&encoded).err(),
+            Some(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::Adaptive.bit(),
+            })
+        );
+        let random: Vec<u8> = (0..=255).collect();
+        let stored = compress_with(&random, &options);
+        assert_eq!(stored.len(), random.len() + STORED_OVERHEAD);
+        assert_eq!(decompress(&stored).unwrap(), random);
+    }
+
+    #[test]
+    fn lone_symbol_lengths_are_capped() {
+        // A lone symbol claiming 2^62 bytes in a 25-byte stream.
+        let mut bomb = encode_block(b"a").to_bytes();
+        bomb[9..17].copy_from_slice(&(1u64 << 62).to_le_bytes());
+        assert_eq!(decompress(&bomb), Err(CompressionError::CorruptData));
+
+        // Longer runs are given a second leaf, so they still decode.
+        let mut freq = [0; 256];
+        freq[b'a' as usize] = MAX_LONE_SYMBOL_LEN + 1;
+        let tree = HuffmanTree::from_frequencies(&bound_lone_symbol(&freq)).unwrap();
+        assert_eq!(tree.encode(b"aaaa").unwrap().data_bytes(), [0]);
+        freq[b'a' as usize] = MAX_LONE_SYMBOL_LEN;
+        assert_eq!(bound_lone_symbol(&freq), freq);
+    }
+
+    #[test]
+    fn limit_rejects_a_large_declared_length_up_front() {
+        // A lone symbol promising 2^40 bytes in a 24-byte stream.
+        let mut bomb = encode_block(b"a").to_bytes();
+        bomb[9..17].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert_eq!(
+            decode_with_limit(&bomb, 1 << 20),
+            Err(CompressionError::OutputLimitExceeded { limit: 1 << 20 })
+        );
+        let data = b"well within the limit".repeat(4);
+        assert_eq!(decode_with_limit(&compress(&data), 84).unwrap(), data);
+        assert!(decode_with_limit(&compress(&data), 83).is_err());
+
+        // Range-coded data has no payload bits to bound the length by, so
+        // the limit must hold before the stream is measured.
+        let options = CompressionOptions {
+            algorithm: Algorithm::Range,
+            ..Default::default()
+        };
+        let mut bomb = compress_with(&[7u8; 1000], &options);
+        bomb[9..17].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert_eq!(
+            decode_with_limit(&bomb, 1 << 20),
+            Err(CompressionError::OutputLimitExceeded { limit: 1 << 20 })
+        );
+    }
+
+    #[test]
+    fn mutated_streams_fail_cleanly() {
+        // Every single-byte change and every truncation of a stream of each
+        // kind must decode, or fail with an error, within the limit.
+        let data = b"mutations of every header field, mutations".repeat(3);
+        let mut streams: Vec<Vec<u8>> = Algorithm::ALL
+            .iter()
+            .map(|&algorithm| {
+                let options = CompressionOptions {
+                    algorithm,
+                    ..Default::default()
+                };
+                compress_with(&data, &options)
+            })
+            .collect();
+        streams.push(block::compress_blocks(&data, 40));
+        streams.push(compress_with(
+            &data,
+            &CompressionOptions {
+                filter: Some(Filter::delta(2)),
+                ..Default::default()
+            },
+        ));
+        let limit = 4 * data.len() as u64;
+        for stream in &streams {
+            let mut mutated = stream.clone();
+            for i in 0..stream.len() {
+                for delta in [1, 0x80, 0xff] {
+                    mutated[i] = stream[i].wrapping_add(delta);
+                    if let Ok(out) = decode_with_limit(&mutated, limit) {
+                        assert!(out.len() as u64 <= limit);
+                    }
+                    let _ = info::inspect(&mutated);
+                    if let Ok(encoded) = Encoded::from_bytes(&mutated) {
+                        let _ = encoded.decode_into(&mut vec![0; limit as usize]);
+                    }
+                }
+                mutated[i] = stream[i];
+                assert!(decode_with_limit(&stream[..i], limit).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn limit_applies_to_stage_output() {
+        let data = vec![0u8; 100_000];
+        for algorithm in [
+            Algorithm::LzHuffman,
+            Algorithm::RleHuffman,
+            Algorithm::Rle,
+            Algorithm::Bwt,
+        ] {
+            let encoded = compress_with(
+                &data,
+                &CompressionOptions {
+                    algorithm,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(
+                decode_with_limit(&encoded, 50_000),
+                Err(CompressionError::OutputLimitExceeded { limit: 50_000 })
+            );
+            assert_eq!(decode_with_limit(&encoded, 100_000).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn length_beyond_the_payload_bits_is_rejected() {
+        let mut encoded = encode_block(b"abcabc").to_bytes();
+        encoded[9..17].copy_from_slice(&1000u64.to_le_bytes());
+        assert_eq!(
+            Encoded::from_bytes(&encoded).err(),
+            Some(CompressionError::CorruptHeader)
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+
+/// Distance of the farthest match, in bytes.
+pub(crate) const WINDOW: usize = 4096;
+pub(crate) const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = MIN_MATCH + 15;
+/// Number of earlier positions tried per match search.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+const NONE: usize = usize::MAX;
+
+/// Replaces repeated substrings of `data` with references to an earlier
+/// copy within the last 4 KiB.
+///
+/// The output is a sequence of groups of up to eight tokens, each group
+/// preceded by a flag byte whose bits, least significant first, mark the
+/// tokens that are matches. A literal is the byte itself. A match is two
+/// bytes: the distance minus one in the upper 12 bits and the length minus
+/// three in the lower 4, so matches are 3 to 18 bytes long.
+pub fn lzss_encode(data: &[u8]) -> Vec<u8> {
+    encode_with(data, WINDOW, MAX_CHAIN)
+}
+
+/// Like `lzss_encode` but looks for matches at most `window` bytes back
+/// and tries `max_chain` earlier positions per match search. Longer chains
+/// find longer matches more slowly.
+pub(crate) fn encode_with(data: &[u8], window: usize, max_chain: usize) -> Vec<u8> {
+    let mut finder = MatchFinder::new(data, max_chain).with_window(window);
+    let mut out = Vec::new();
+    let mut flags_at = 0;
+    let mut tokens = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        if tokens % 8 == 0 {
+            flags_at = out.len();
+            out.push(0);
+        }
+        let (len, dist) = finder.longest_match(pos);
+        let next = if len >= MIN_MATCH {
+            out[flags_at] |= 1 << (tokens % 8);
+            let code = ((dist - 1) << 4) | (len - MIN_MATCH);
+            out.extend((code as u16).to_be_bytes());
+            pos + len
+        } else {
+            out.push(data[pos]);
+            pos + 1
+        };
+        for p in pos..next {
+            finder.insert(p);
+        }
+        pos = next;
+        tokens += 1;
+    }
+    out
+}
+
+/// Inverse of `lzss_encode`. Fails on a match reaching back before the
+/// start of the output or cut off by the end of `data`.
+pub fn lzss_decode(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    decode_limited(data, u64::MAX)
+}
+
+/// Like `lzss_decode` but fails once the output would exceed `limit` bytes.
+pub(crate) fn decode_limited(data: &[u8], limit: u64) -> Result<Vec<u8>, CompressionError> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let flags = data[i];
+        i += 1;
+        for bit in 0..8 {
+            if i == data.len() {
+                break;
+            }
+            if (flags >> bit) & 1 == 0 {
+                if out.len() as u64 == limit {
+                    return Err(CompressionError::OutputLimitExceeded { limit });
+                }
+                out.push(data[i]);
+                i += 1;
+                continue;
+            }
+            let &[hi, lo] = data.get(i..i + 2).ok_or(CompressionError::TruncatedInput)? else {
+                unreachable!("the slice has two bytes");
+            };
+            i += 2;
+            let code = u16::from_be_bytes([hi, lo]) as usize;
+            let dist = (code >> 4) + 1;
+            let len = (code & 0xf) + MIN_MATCH;
+            if dist > out.len() {
+                return Err(CompressionError::CorruptData);
+            }
+            if (out.len() + len) as u64 > limit {
+                return Err(CompressionError::OutputLimitExceeded { limit });
+            }
+            for _ in 0..len {
+                out.push(out[out.len() - dist]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Hash chains over the 3-byte prefixes of the positions inserted so far.
+pub(crate) struct MatchFinder<'a> {
+    data: &'a [u8],
+    /// Latest position with each prefix hash.
+    head: Vec<usize>,
+    /// Previous position with the same prefix hash, for each position.
+    prev: Vec<usize>,
+    max_chain: usize,
+    window: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    pub(crate) fn new(data: &'a [u8], max_chain: usize) -> MatchFinder<'a> {
+        MatchFinder {
+            data,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; data.len()],
+            max_chain,
+            window: WINDOW,
+        }
+    }
+
+    /// Looks for matches at most `window` bytes back instead of
+    /// [`WINDOW`]. Panics if `window` is larger.
+    pub(crate) fn with_window(mut self, window: usize) -> MatchFinder<'a> {
+        assert!(window <= WINDOW, "window exceeds the match distance limit");
+        self.window = window;
+        self
+    }
+
+    pub(crate) fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.data.len() {
+            let h = hash(&self.data[pos..pos + MIN_MATCH]);
+            self.prev[pos] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+    /// Longest earlier copy of the bytes at `pos` within the window, as its
+    /// length and distance. The length is 0 if there is none.
+    pub(crate) fn longest_match(&self, pos: usize) -> (usize, usize) {
+        let data = self.data;
+        if pos + MIN_MATCH > data.len() {
+            return (0, 0);
+        }
+        let max_len = MAX_MATCH.min(data.len() - pos);
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash(&data[pos..pos + MIN_MATCH])];
+        for _ in 0..self.max_chain {
+            if candidate == NONE || pos - candidate > self.window {
+                break;
+            }
+            let len = data[candidate..]
+                .iter()
+                .zip(&data[pos..pos + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best.0 {
+                best = (len, pos - candidate);
+                if len == max_len {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+        }
+        best
+    }
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let v = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_repeated_text() {
+        let data = b"to be or not to be, that is the question: to be or not to be";
+        let encoded = lzss_encode(data);
+        assert!(encoded.len() < data.len());
+        assert_eq!(lzss_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn overlapping_match_repeats_a_run() {
+        // A literal followed by one match of distance 1 and length 18.
+        let data = [b'x'; 19];
+        let encoded = lzss_encode(&data);
+        assert_eq!(encoded, vec![0b10, b'x', 0x00, 0x0f]);
+        assert_eq!(lzss_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_edge_lengths() {
+        for data in [&b""[..], b"a", b"ab", b"abcabc"] {
+            assert_eq!(lzss_decode(&lzss_encode(data)).unwrap(), data);
+        }
+        let long: Vec<u8> = (0..20_000u32).map(|i| (i % 5000 % 251) as u8).collect();
+        assert_eq!(lzss_decode(&lzss_encode(&long)).unwrap(), long);
+    }
+
+    #[test]
+    fn match_before_start_is_rejected() {
+        assert_eq!(
+            lzss_decode(&[0b1, 0x00, 0x00]),
+            Err(CompressionError::CorruptData)
+        );
+        assert_eq!(
+            lzss_decode(&[0b10, b'a', 0x00]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+}
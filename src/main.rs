@@ -1,205 +1,788 @@
-struct Encoded {
-    tree: Vec<u8>,
-    bytes: Vec<u8>,
-    padding: u8,
+use analysis::Stats;
+use archive::{EntryInfo, WalkOptions};
+use benchmark::BenchResult;
+use compression::block::{self, MAX_BLOCK_SIZE};
+use compression::filter::Filter;
+use compression::options::MAX_LEVEL;
+use compression::words::WordOrder;
+use compression::{
+    Algorithm, CompressionError, Encoder, analysis, archive, atomic, benchmark, decompress,
+    decompress_parallel, decompress_unverified, decompress_with_progress, dictionary, extension,
+    gzip, info, mmap, pack, resume, verify,
+};
+use info::{BlockTree, StreamInfo};
+use mmap::Mmap;
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const EXTENSION: &str = "hfz";
+const ARCHIVE_EXTENSION: &str = "hfa";
+const GZIP_EXTENSION: &str = "gz";
+/// Bytes in the MiB/s of `--progress`.
+const MIB: f64 = 1024.0 * 1024.0;
+/// Shortest time between two redraws of `--progress`.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+/// Output of `train` when `-o` is not given.
+const DICTIONARY_FILE: &str = "dictionary.hfd";
+/// Names of the algorithms for `--algo` and `info`.
+const ALGORITHMS: [(&str, Algorithm); 9] = [
+    ("huffman", Algorithm::Huffman),
+    ("lz", Algorithm::LzHuffman),
+    ("rle", Algorithm::Rle),
+    ("rle-huffman", Algorithm::RleHuffman),
+    ("adaptive", Algorithm::Adaptive),
+    ("bwt", Algorithm::Bwt),
+    ("range", Algorithm::Range),
+    ("order1", Algorithm::Order1),
+    ("words", Algorithm::Words),
+];
+/// Path that stands for standard input or output.
+const STDIO: &str = "-";
+
+fn read_input(path: &str) -> Result<Vec<u8>, CompressionError> {
+    if path == STDIO {
+        let mut data = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    Ok(std::fs::read(path)?)
 }
 
-impl Encoded {
-    fn from_bits(bits: &[bool], tree: Vec<u8>) -> Encoded {
-        let padding = if bits.len() % 8 == 0 {
-            0
-        } else {
-            8 - (bits.len() % 8) as u8
-        };
-        let mut bytes = Vec::new();
-        for chunk in bits.chunks(8) {
-            let mut byte = 0u8;
-            for (i, &bit) in chunk.iter().enumerate() {
-                if bit {
-                    byte |= 1 << (7 - i);
-                }
-            }
-            bytes.push(byte);
-        }
-        Encoded {
-            tree,
-            bytes,
-            padding,
-        }
-    }
-
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut out = vec![self.padding];
-        out.extend(&self.tree);
-        out.extend(&self.bytes);
-        out
-    }
-
-    fn from_bytes(data: &[u8]) -> Encoded {
-        let padding = data[0];
-        let tree_len = data[1] as usize;
-        let tree_end = 2 + tree_len;
-        let tree = data[1..tree_end].to_vec();
-        let bytes = data[tree_end..].to_vec();
-        Encoded {
-            tree,
-            bytes,
-            padding,
-        }
-    }
-
-    fn decode(&self) -> Vec<u8> {
-        let tree = HuffmanTree::from_sorted(&self.tree[1..]);
-        let total_bits = self.bytes.len() * 8 - self.padding as usize;
-        let mut out = Vec::new();
-        let mut current = &tree;
-        for i in 0..total_bits {
-            let byte_idx = i / 8;
-            let bit_idx = 7 - (i % 8);
-            let bit = (self.bytes[byte_idx] >> bit_idx) & 1 == 1;
-            if !bit {
-                out.push(current.left);
-                current = &tree;
-            } else {
-                match &current.right {
-                    Node::Leaf(b) => {
-                        out.push(*b);
-                        current = &tree;
-                    }
-                    Node::Tree(t) => current = t,
-                }
-            }
-        }
-        out
+/// Writes `data` to standard output, or else atomically to the file at
+/// `path`, which must not exist unless `force` is set.
+fn write_output(path: &str, data: &[u8], force: bool) -> Result<(), CompressionError> {
+    if path == STDIO {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
+        return Ok(());
     }
+    atomic::write_atomic(Path::new(path), data, force)
 }
 
-enum Node {
-    Leaf(u8),
-    Tree(Box<HuffmanTree>),
+/// Output path used when `-o` is not given: `compress` appends `.hfz`, or
+/// `.gz` with `--gzip`, and `decompress` strips either, or appends `.out`
+/// if the input has neither.
+fn default_output(mode: &str, input: &str, gzip: bool) -> String {
+    match mode {
+        "compress" if gzip => format!("{}.{}", input, GZIP_EXTENSION),
+        "compress" => format!("{}.{}", input, EXTENSION),
+        _ => [EXTENSION, GZIP_EXTENSION]
+            .iter()
+            .find_map(|extension| input.strip_suffix(&format!(".{}", extension)))
+            .filter(|stem| !stem.is_empty())
+            .map_or_else(|| format!("{}.out", input), str::to_string),
+    }
 }
 
-struct HuffmanTree {
-    left: u8,
-    right: Node,
+struct Options {
+    output: Option<String>,
+    stdout: bool,
+    verify: bool,
+    block_size: Option<usize>,
+    threads: Option<usize>,
+    algorithm: Algorithm,
+    level: Option<u8>,
+    gzip: bool,
+    round_trip: bool,
+    stats: bool,
+    mmap: bool,
+    append: Option<String>,
+    keep_going: bool,
+    progress: bool,
+    filter: Option<Filter>,
+    /// Byte order of the words of `--algo words`.
+    word_order: Option<WordOrder>,
+    /// Whether `dump-tree` prints Graphviz DOT.
+    dot: bool,
+    /// What `compress` takes from the directories of an archive.
+    walk: WalkOptions,
+    /// Whether `walk` differs from the default.
+    filtered: bool,
+    /// Whether outputs get the mode and modification time of their input.
+    preserve: bool,
+    /// Whether existing output files are replaced.
+    force: bool,
+    /// Whether `compress` keeps a state file to continue from if it is
+    /// interrupted.
+    resume: bool,
 }
 
-impl HuffmanTree {
-    fn serialize(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        self.collect_leaves(&mut bytes);
-        let mut result = vec![bytes.len() as u8];
-        result.extend(bytes);
-        result
+/// The algorithm named `name` on the command line.
+fn parse_algorithm(name: &str) -> Option<Algorithm> {
+    ALGORITHMS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|&(_, algorithm)| algorithm)
+}
+
+fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    ALGORITHMS
+        .iter()
+        .find(|(_, a)| *a == algorithm)
+        .map(|&(name, _)| name)
+        .unwrap()
+}
+
+/// A filter as `--filter` takes it: `delta:<stride>` or
+/// `interleave:<stride>`, with `delta` alone for a stride of 1.
+fn parse_filter(spec: &str) -> Option<Filter> {
+    let (kind, stride) = spec.split_once(':').unwrap_or((spec, "1"));
+    let stride = stride.parse().ok().filter(|&stride| stride > 0)?;
+    match kind {
+        "delta" => Some(Filter::delta(stride)),
+        "interleave" => Some(Filter::interleave(stride)),
+        _ => None,
     }
+}
 
-    fn collect_leaves(&self, out: &mut Vec<u8>) {
-        out.push(self.left);
-        match &self.right {
-            Node::Leaf(b) => out.push(*b),
-            Node::Tree(t) => t.collect_leaves(out),
+fn filter_name(filter: Filter) -> String {
+    let kind = if filter.delta { "delta" } else { "interleave" };
+    format!("{}:{}", kind, filter.stride)
+}
+
+/// Summary printed by `info`.
+fn describe(info: &StreamInfo) -> String {
+    let mut algorithm = algorithm_name(info.algorithm).to_string();
+    if info.stored && info.algorithm != Algorithm::Rle {
+        algorithm.push_str(" (stored)");
+    }
+    let tree_len: usize = info.blocks.iter().map(|block| block.tree_len).sum();
+    let mut out = format!("version:    {}\n", info.version);
+    out += &format!("algorithm:  {}\n", algorithm);
+    if let Some(filter) = info.filter {
+        out += &format!("filter:     {}\n", filter_name(filter));
+    }
+    out += &format!("blocks:     {}\n", info.blocks.len());
+    out += &format!("tree:       {} bytes\n", tree_len);
+    if let [block] = &info.blocks[..] {
+        out += &format!("padding:    {} bits\n", block.padding);
+    }
+    out += &format!("original:   {} bytes\n", info.original_len);
+    out += &format!("compressed: {} bytes\n", info.compressed_len);
+    match info.checksum {
+        Some(crc) => out += &format!("checksum:   {:08x}\n", crc),
+        None => out += "checksum:   none\n",
+    }
+    for extension in &info.extensions {
+        out += &format!(
+            "extension:  {:#04x}, {} bytes\n",
+            extension.kind,
+            extension.value.len()
+        );
+    }
+    out
+}
+
+/// Summary printed by `compress --stats`.
+fn describe_stats(stats: &Stats) -> String {
+    let symbols = stats.frequencies.iter().filter(|&&f| f > 0).count();
+    let mut out = format!("symbols:    {} distinct\n", symbols);
+    out += &format!("entropy:    {:.3} bits per byte\n", stats.entropy);
+    out += &format!(
+        "code:       {:.3} bits per byte\n",
+        stats.average_code_length
+    );
+    out += &format!("payload:    {} bytes\n", stats.payload_len);
+    out += &format!("header:     {} bytes\n", stats.header_len);
+    out += &format!("ratio:      {:.3}\n", stats.ratio());
+    out
+}
+
+/// The line `--progress` redraws: how much of `total` bytes of input is
+/// done, the throughput so far and the time left at that rate.
+fn progress_line(done: u64, total: u64, elapsed: Duration) -> String {
+    let percent = (done * 100).checked_div(total).unwrap_or(100);
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { done as f64 / secs } else { 0.0 };
+    let eta = match total.saturating_sub(done) as f64 / rate {
+        left if left.is_finite() => format!("{:.0}s", left),
+        _ => "?".to_string(),
+    };
+    format!("{:>3}% {:>8.1} MiB/s  ETA {}", percent, rate / MIB, eta)
+}
+
+/// A callback for `encode_with_progress` and `decompress_with_progress`
+/// that redraws the progress line on standard error at most every
+/// [`PROGRESS_INTERVAL`], and once more when all of `total` is done.
+fn progress_reporter(total: u64) -> impl FnMut(u64, u64) {
+    let start = Instant::now();
+    let mut last: Option<Instant> = None;
+    let mut finished = false;
+    move |done, _| {
+        let now = Instant::now();
+        let recent = last.is_some_and(|last| now - last < PROGRESS_INTERVAL);
+        if finished || (done < total && recent) {
+            return;
+        }
+        last = Some(now);
+        finished = done >= total;
+        eprint!("\r{}", progress_line(done, total, now - start));
+        if finished {
+            eprintln!();
         }
     }
+}
+
+/// Header of the table `compare` prints.
+const COMPARE_HEADER: &str = "algorithm   level        size   ratio    compress  decompress";
+
+/// One row of `compare`: the algorithm and level, the compressed size and
+/// ratio and both speeds.
+fn compare_line(result: &BenchResult) -> String {
+    format!(
+        "{:<11} {:>5} {:>11} {:>7.3} {:>6.1} MB/s {:>6.1} MB/s",
+        algorithm_name(result.options.algorithm),
+        result.options.level,
+        result.compressed_len,
+        result.ratio(),
+        result.compress_speed(),
+        result.decompress_speed()
+    )
+}
+
+/// Tries every setting of [`benchmark::sweep`] on the input and prints a
+/// row for each.
+fn run_compare(input: &str) -> Result<(), CompressionError> {
+    let data = read_input(input)?;
+    println!("{}", COMPARE_HEADER);
+    for result in benchmark::benchmark(&data, &benchmark::sweep()) {
+        println!("{}", compare_line(&result));
+    }
+    Ok(())
+}
 
-    fn build_map(&self) -> std::collections::HashMap<u8, Vec<bool>> {
-        let mut map = std::collections::HashMap::new();
-        let mut code = Vec::new();
-        let mut current = self;
+/// One line of `list`: mode, size, compressed size, mtime and path.
+fn list_line(entry: &EntryInfo) -> String {
+    format!(
+        "{:04o} {:>10} {:>10} {:>10} {}",
+        entry.mode, entry.size, entry.compressed_len, entry.mtime, entry.path
+    )
+}
 
-        loop {
-            code.push(false);
-            map.insert(current.left, code.clone());
+/// Splits the arguments after the mode into the input paths and the flags.
+/// The input is standard input if it is `-` or left out.
+fn split_inputs(args: &[String]) -> (Vec<&str>, &[String]) {
+    let count = args
+        .iter()
+        .take_while(|arg| *arg == STDIO || !arg.starts_with('-'))
+        .count();
+    let inputs: Vec<&str> = args[..count].iter().map(String::as_str).collect();
+    if inputs.is_empty() {
+        return (vec![STDIO], args);
+    }
+    (inputs, &args[count..])
+}
+
+/// Whether `compress` should build an archive: for several inputs or a
+/// directory.
+fn is_archive_input(inputs: &[&str]) -> bool {
+    inputs.len() > 1 || Path::new(inputs[0]).is_dir()
+}
 
-            code.pop();
-            code.push(true);
-            match &current.right {
-                Node::Leaf(b) => {
-                    map.insert(*b, code.clone());
-                    break;
+/// Output path of an archive: `-o`, standard output with `-c`, otherwise
+/// `<dir>.hfa` for a single directory and `archive.hfa` for several inputs.
+fn archive_output(inputs: &[&str], options: &Options) -> String {
+    match (&options.output, inputs) {
+        _ if options.stdout => STDIO.to_string(),
+        (Some(output), _) => output.clone(),
+        (None, [dir]) => format!("{}.{}", dir.trim_end_matches('/'), ARCHIVE_EXTENSION),
+        (None, _) => format!("archive.{}", ARCHIVE_EXTENSION),
+    }
+}
+
+/// Where the output goes: standard output with `-c` or when reading
+/// standard input without `-o`, otherwise `-o` or the default path.
+fn output_path(mode: &str, input: &str, options: &Options) -> String {
+    match &options.output {
+        _ if options.stdout => STDIO.to_string(),
+        Some(output) => output.clone(),
+        None if input == STDIO => STDIO.to_string(),
+        None => default_output(mode, input, options.gzip),
+    }
+}
+
+/// Parses the flags after the mode and input. `None` if any is unknown, if
+/// block options or `--gzip` are combined with an algorithm other than
+/// Huffman, if `--gzip` is combined with block options, `--level` or
+/// `--filter`, or if `--stats` is combined with anything that changes the
+/// format.
+fn parse_options(flags: &[String]) -> Option<Options> {
+    let mut options = Options {
+        output: None,
+        stdout: false,
+        verify: true,
+        block_size: None,
+        threads: None,
+        algorithm: Algorithm::Huffman,
+        level: None,
+        gzip: false,
+        round_trip: false,
+        stats: false,
+        mmap: false,
+        append: None,
+        keep_going: false,
+        progress: false,
+        filter: None,
+        word_order: None,
+        dot: false,
+        walk: WalkOptions::new(),
+        filtered: false,
+        preserve: true,
+        force: false,
+        resume: false,
+    };
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        options.filtered |=
+            ["--include", "--exclude", "--follow-symlinks"].contains(&flag.as_str());
+        match flag.as_str() {
+            "-o" => options.output = Some(flags.next()?.clone()),
+            "-c" => options.stdout = true,
+            "--no-verify" => options.verify = false,
+            "--block-size" => {
+                let size = flags.next()?.parse().ok()?;
+                if !(1..=MAX_BLOCK_SIZE).contains(&size) {
+                    return None;
+                }
+                options.block_size = Some(size);
+            }
+            "--threads" => {
+                let threads = flags.next()?.parse().ok()?;
+                if threads == 0 {
+                    return None;
                 }
-                Node::Tree(t) => current = t,
+                options.threads = Some(threads);
             }
+            "--algo" => options.algorithm = parse_algorithm(flags.next()?)?,
+            "--level" => {
+                let level = flags.next()?.parse().ok()?;
+                if !(1..=MAX_LEVEL).contains(&level) {
+                    return None;
+                }
+                options.level = Some(level);
+            }
+            "--gzip" => options.gzip = true,
+            "--verify" => options.round_trip = true,
+            "--stats" => options.stats = true,
+            "--mmap" => options.mmap = true,
+            "--append" => options.append = Some(flags.next()?.clone()),
+            "--keep-going" => options.keep_going = true,
+            "--progress" => options.progress = true,
+            "--dot" => options.dot = true,
+            "--filter" => options.filter = Some(parse_filter(flags.next()?)?),
+            "--word-order" => {
+                options.word_order = Some(match flags.next()?.as_str() {
+                    "le" => WordOrder::LittleEndian,
+                    "be" => WordOrder::BigEndian,
+                    _ => return None,
+                })
+            }
+            "--no-preserve" => options.preserve = false,
+            "--force" => options.force = true,
+            "--resume" => options.resume = true,
+            "--include" => options.walk = options.walk.with_include(flags.next()?),
+            "--exclude" => options.walk = options.walk.with_exclude(flags.next()?),
+            "--follow-symlinks" => options.walk = options.walk.with_follow_symlinks(true),
+            _ => return None,
         }
-        map
     }
-
-    fn encode(&self, data: &[u8]) -> Encoded {
-        let map = self.build_map();
-        let mut bits: Vec<bool> = Vec::new();
-        for &b in data {
-            let code = map.get(&b).expect("byte not in tree");
-            bits.extend(code);
-        }
-        Encoded::from_bits(&bits, self.serialize())
+    let blocks = options.block_size.is_some() || options.threads.is_some();
+    if (blocks || options.gzip) && options.algorithm != Algorithm::Huffman {
+        return None;
     }
+    if options.word_order.is_some() && options.algorithm != Algorithm::Words {
+        return None;
+    }
+    if (blocks || options.level.is_some() || options.filter.is_some()) && options.gzip {
+        return None;
+    }
+    let default_format =
+        options.algorithm == Algorithm::Huffman && !options.gzip && options.filter.is_none();
+    let default_blocks = options.block_size.is_none() && options.level.is_none();
+    if options.stats && !(default_format && default_blocks) {
+        return None;
+    }
+    if options.stdout && options.output.is_some() {
+        return None;
+    }
+    if options.keep_going && (!options.verify || options.threads.is_some()) {
+        return None;
+    }
+    if options.progress && (options.gzip || !options.verify || options.keep_going) {
+        return None;
+    }
+    Some(options)
+}
 
-    fn from_sorted(bytes: &[u8]) -> HuffmanTree {
-        if bytes.len() == 2 {
-            HuffmanTree {
-                left: bytes[0],
-                right: Node::Leaf(bytes[1]),
-            }
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "usage: {0} <compress|decompress|info> [<input>|-] [-o <output>|-c] [--no-verify] [--block-size <bytes>] [--threads <n>] [--algo <huffman|lz|rle|rle-huffman|adaptive|bwt|range|order1|words>] [--word-order <le|be>] [--level <1-9>] [--filter <delta|delta:<n>|interleave:<n>>] [--gzip] [--verify] [--stats] [--mmap] [--keep-going] [--progress] [--no-preserve] [--force]\n       {0} compress <input> --resume [-o <output>] [--block-size <bytes>] [--no-preserve] [--force]\n       {0} compress <path>... [-o <archive>|-c] [--force] [--include <glob>]... [--exclude <glob>]... [--follow-symlinks]\n       {0} compress --append <archive> <path>... [--include <glob>]... [--exclude <glob>]... [--follow-symlinks]\n       {0} extract <archive> [-o <dir>] [--no-preserve] [--force]\n       {0} dump-tree [<input>|-] [--dot]\n       {0} list <archive>\n       {0} compare [<input>|-]\n       {0} train <sample>... [-o <dictionary>] [--force]",
+        program
+    );
+    std::process::exit(1);
+}
+
+fn run(mode: &str, input: &str, output: &str, options: &Options) -> Result<(), CompressionError> {
+    let (mapped, owned);
+    let mut damaged = Vec::new();
+    let data: &[u8] = if options.mmap && input != STDIO {
+        mapped = Mmap::open(Path::new(input))?;
+        &mapped
+    } else {
+        owned = read_input(input)?;
+        &owned
+    };
+    if mode == "compress" {
+        let mut builder = Encoder::builder().with_algorithm(options.algorithm);
+        if let Some(size) = options.block_size {
+            builder = builder.with_block_size(size);
+        }
+        if let Some(threads) = options.threads {
+            builder = builder.with_threads(threads);
+        }
+        if let Some(level) = options.level {
+            builder = builder.with_level(level);
+        }
+        if let Some(filter) = options.filter {
+            builder = builder.with_filter(filter);
+        }
+        if let Some(order) = options.word_order {
+            builder = builder.with_word_order(order);
+        }
+        let mut compressed = if options.gzip {
+            gzip::gzip_encode(data)
+        } else if options.progress {
+            let reporter = progress_reporter(data.len() as u64);
+            builder.build().encode_with_progress(data, reporter)
         } else {
-            HuffmanTree {
-                left: bytes[0],
-                right: Node::Tree(Box::new(HuffmanTree::from_sorted(&bytes[1..]))),
-            }
+            builder.build().encode(data)
+        };
+        if options.preserve && input != STDIO && !options.gzip {
+            let metadata = archive::metadata_extension(Path::new(input))?;
+            compressed = extension::add_extensions(&compressed, &[metadata])?;
+        }
+        if options.round_trip {
+            verify(data, &compressed[..])?;
+        }
+        write_output(output, &compressed, options.force)?;
+        eprintln!("original:   {} bytes", data.len());
+        eprintln!("compressed: {} bytes", compressed.len());
+        if options.round_trip {
+            eprintln!("verified:   output decodes to the input");
+        }
+        if options.stats {
+            eprint!("{}", describe_stats(&analysis::analyze(data)));
+        }
+    } else {
+        let decoded = if pack::is_pack(data) {
+            pack::decode_pack(data)?
+        } else if gzip::is_gzip(data) {
+            gzip::gzip_decode(data)?
+        } else if options.keep_going {
+            let recovered = block::recover_blocks(data)?;
+            damaged = recovered.failed;
+            recovered.data
+        } else if options.progress {
+            decompress_with_progress(data, progress_reporter(data.len() as u64))?
+        } else if let (Some(threads), true) = (options.threads, options.verify) {
+            decompress_parallel(data, threads)?
+        } else if options.verify {
+            decompress(data)?
+        } else {
+            decompress_unverified(data)?
+        };
+        write_output(output, &decoded, options.force)?;
+        if options.preserve && output != STDIO {
+            // Packs and gzip members have no container header to read.
+            let extensions = extension::extensions(data).unwrap_or_default();
+            archive::restore_from_extensions(Path::new(output), &extensions);
+        }
+        eprintln!("compressed: {} bytes", data.len());
+        eprintln!("decoded:    {} bytes", decoded.len());
+        for (index, e) in &damaged {
+            eprintln!("damaged:    block {} zeroed: {}", index, e);
         }
     }
+    if output == STDIO {
+        eprintln!("written to: stdout");
+    } else {
+        eprintln!("written to: {}", output);
+    }
+    // The undamaged blocks are written, but the run still fails.
+    match damaged.into_iter().next() {
+        Some((_, e)) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Compresses `input` in blocks, continuing an interrupted run.
+fn run_resume(input: &str, output: &str, options: &Options) -> Result<(), CompressionError> {
+    let summary = resume::compress_resumable(
+        Path::new(input),
+        Path::new(output),
+        options.block_size.unwrap_or(block::DEFAULT_BLOCK_SIZE),
+        options.force,
+    )?;
+    if summary.resumed_blocks > 0 {
+        eprintln!(
+            "resumed:    after block {} of {}",
+            summary.resumed_blocks, summary.total_blocks
+        );
+    }
+    eprintln!("original:   {} bytes", summary.original_len);
+    eprintln!("compressed: {} bytes", summary.compressed_len);
+    if options.preserve {
+        let stream = std::fs::read(output)?;
+        let metadata = archive::metadata_extension(Path::new(input))?;
+        let tagged = extension::add_extensions(&stream, &[metadata])?;
+        atomic::write_atomic(Path::new(output), &tagged, true)?;
+    }
+    eprintln!("written to: {}", output);
+    Ok(())
+}
+
+fn run_archive(
+    inputs: &[&str],
+    output: &str,
+    walk: &WalkOptions,
+    force: bool,
+) -> Result<(), CompressionError> {
+    let paths: Vec<&Path> = inputs.iter().map(Path::new).collect();
+    let entries = archive::read_entries_with(&paths, walk)?;
+    let archive = archive::encode_archive(&entries)?;
+    write_output(output, &archive, force)?;
+    let original: usize = entries.iter().map(|entry| entry.data.len()).sum();
+    eprintln!("entries:    {}", entries.len());
+    eprintln!("original:   {} bytes", original);
+    eprintln!("compressed: {} bytes", archive.len());
+    if output == STDIO {
+        eprintln!("written to: stdout");
+    } else {
+        eprintln!("written to: {}", output);
+    }
+    Ok(())
 }
 
-fn encode(data: &[u8]) -> Vec<u8> {
-    let sorted = count_frequencies(data);
-    let tree = HuffmanTree::from_sorted(&sorted);
-    tree.encode(data).to_bytes()
+/// Adds the files at or below `inputs` that `walk` takes to the archive at
+/// `archive_path` in place.
+fn run_append(
+    inputs: &[&str],
+    archive_path: &str,
+    walk: &WalkOptions,
+) -> Result<(), CompressionError> {
+    let paths: Vec<&Path> = inputs.iter().map(Path::new).collect();
+    let entries = archive::read_entries_with(&paths, walk)?;
+    let mut file = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .open(archive_path)?;
+    archive::append_archive(&mut file, &entries)?;
+    eprintln!("appended:   {} entries", entries.len());
+    eprintln!("written to: {}", archive_path);
+    Ok(())
 }
 
-fn decode(data: &[u8]) -> Vec<u8> {
-    Encoded::from_bytes(data).decode()
+fn run_extract(
+    input: &str,
+    dir: &str,
+    preserve: bool,
+    force: bool,
+) -> Result<(), CompressionError> {
+    let entries = archive::decode_archive(&read_input(input)?)?;
+    archive::write_entries_with(&entries, Path::new(dir), preserve, force)?;
+    eprintln!("extracted:  {} entries to {}", entries.len(), dir);
+    Ok(())
 }
 
-fn read_input(path: &str) -> Vec<u8> {
-    std::fs::read(path).expect("failed to read file")
+/// Trains a dictionary on every file at or below `inputs`.
+fn run_train(inputs: &[&str], output: &str, force: bool) -> Result<(), CompressionError> {
+    let paths: Vec<&Path> = inputs.iter().map(Path::new).collect();
+    let entries = archive::read_entries(&paths)?;
+    let samples: Vec<&[u8]> = entries.iter().map(|entry| &entry.data[..]).collect();
+    let dict = dictionary::train(&samples);
+    write_output(output, &dict.to_bytes(), force)?;
+    eprintln!("samples:    {}", samples.len());
+    eprintln!("dictionary: {:08x}", dict.id());
+    eprintln!("written to: {}", output);
+    Ok(())
 }
 
-fn count_frequencies(bytes: &[u8]) -> Vec<u8> {
-    let mut freq = std::collections::HashMap::new();
-    for &b in bytes {
-        *freq.entry(b).or_insert(0) += 1;
+fn run_list(input: &str) -> Result<(), CompressionError> {
+    for entry in archive::list_archive(&read_input(input)?)? {
+        println!("{}", list_line(&entry));
     }
-    let mut sorted: Vec<u8> = freq.keys().copied().collect();
-    sorted.sort_by(|a, b| freq[b].cmp(&freq[a]));
-    sorted
+    Ok(())
+}
+
+/// What `dump-tree` prints for one block: the code table, or the tree as
+/// DOT with `dot`.
+fn describe_tree(index: usize, block: &BlockTree, dot: bool) -> String {
+    match (&block.tree, &block.leaves[..]) {
+        (Some(tree), _) if dot => tree.to_dot(),
+        (Some(tree), _) => format!("block {}:\n{}", index, tree.code_table(&block.frequencies)),
+        (None, [b]) if !dot => format!(
+            "block {}: only {:#04x}, {} times, needs no tree\n",
+            index, b, block.frequencies[*b as usize]
+        ),
+        (None, _) if !dot => format!("block {}: empty\n", index),
+        (None, _) => String::new(),
+    }
+}
+
+fn run_dump_tree(input: &str, dot: bool) -> Result<(), CompressionError> {
+    let blocks = info::block_trees(&read_input(input)?)?;
+    if blocks.is_empty() {
+        eprintln!("no Huffman trees: the stream is stored, adaptive or range-coded");
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        print!("{}", describe_tree(index, block, dot));
+    }
+    Ok(())
 }
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() != 4 {
-        eprintln!("usage: {} <encode|decode> <input> <output>", args[0]);
-        std::process::exit(1);
+    let (mode, inputs, options) = match &args[1..] {
+        [mode, rest @ ..] => {
+            // The archive may come first, as in `compress --append <archive> <path>...`.
+            let (rest, append) = match rest {
+                [flag, archive, rest @ ..] if flag == "--append" => (rest, Some(archive)),
+                _ => (rest, None),
+            };
+            let (inputs, flags) = split_inputs(rest);
+            match parse_options(flags) {
+                Some(mut options) if append.is_none() || options.append.is_none() => {
+                    options.append = options.append.or(append.cloned());
+                    (mode.as_str(), inputs, options)
+                }
+                _ => usage(&args[0]),
+            }
+        }
+        _ => usage(&args[0]),
+    };
+    let input = inputs[0];
+    let archiving = mode == "compress" && is_archive_input(&inputs);
+    if inputs.len() > 1 && !archiving && mode != "train" {
+        usage(&args[0]);
     }
-    let mode = &args[1];
-    let input = &args[2];
-    let output = &args[3];
-    let data = read_input(input);
-
-    match mode.as_str() {
-        "encode" => {
-            let compressed = encode(&data);
-            std::fs::write(output, &compressed).expect("failed to write output");
-            println!("original:   {} bytes", data.len());
-            println!("compressed: {} bytes", compressed.len());
+    if options.append.is_some() && (mode != "compress" || inputs.contains(&STDIO)) {
+        usage(&args[0]);
+    }
+    if options.keep_going && mode != "decompress" {
+        usage(&args[0]);
+    }
+    if options.dot && mode != "dump-tree" {
+        usage(&args[0]);
+    }
+    if options.filtered && !archiving && options.append.is_none() {
+        usage(&args[0]);
+    }
+    let single =
+        matches!(mode, "compress" | "decompress") && !archiving && options.append.is_none();
+    if !options.preserve && !single && mode != "extract" {
+        usage(&args[0]);
+    }
+    if options.progress && (mode == "decompress" && options.threads.is_some() || archiving) {
+        usage(&args[0]);
+    }
+    let writes_files = matches!(mode, "compress" | "decompress" | "extract" | "train");
+    if options.force && (!writes_files || options.append.is_some()) {
+        usage(&args[0]);
+    }
+    let resumable = single
+        && mode == "compress"
+        && input != STDIO
+        && !options.stdout
+        && options.threads.is_none()
+        && options.level.is_none()
+        && options.algorithm == Algorithm::Huffman
+        && options.filter.is_none()
+        && !options.gzip
+        && !options.round_trip
+        && !options.stats
+        && !options.mmap
+        && !options.progress;
+    if options.resume && !resumable {
+        usage(&args[0]);
+    }
+    atomic::remove_partial_output_on_interrupt();
+    let result = match mode {
+        "info" => read_input(input)
+            .and_then(|data| info::inspect(&data))
+            .map(|info| print!("{}", describe(&info))),
+        "list" => run_list(input),
+        "dump-tree" => run_dump_tree(input, options.dot),
+        "compare" => run_compare(input),
+        "train" => run_train(
+            &inputs,
+            options.output.as_deref().unwrap_or(DICTIONARY_FILE),
+            options.force,
+        ),
+        "extract" => run_extract(
+            input,
+            options.output.as_deref().unwrap_or("."),
+            options.preserve,
+            options.force,
+        ),
+        "compress" if options.append.is_some() => {
+            let blocks = options.block_size.is_some() || options.threads.is_some();
+            if blocks
+                || options.level.is_some()
+                || options.algorithm != Algorithm::Huffman
+                || options.filter.is_some()
+                || options.gzip
+                || options.round_trip
+                || options.stats
+                || options.mmap
+                || options.output.is_some()
+                || options.stdout
+            {
+                usage(&args[0]);
+            }
+            run_append(&inputs, options.append.as_deref().unwrap(), &options.walk)
         }
-        "decode" => {
-            let decoded = decode(&data);
-            std::fs::write(output, &decoded).expect("failed to write output");
-            println!("compressed: {} bytes", data.len());
-            println!("decoded:    {} bytes", decoded.len());
+        "compress" if archiving => {
+            let blocks = options.block_size.is_some() || options.threads.is_some();
+            if blocks
+                || options.level.is_some()
+                || options.algorithm != Algorithm::Huffman
+                || options.filter.is_some()
+                || options.round_trip
+                || options.stats
+            {
+                usage(&args[0]);
+            }
+            run_archive(
+                &inputs,
+                &archive_output(&inputs, &options),
+                &options.walk,
+                options.force,
+            )
+        }
+        "compress" | "decompress" => {
+            let output = output_path(mode, input, &options);
+            match options.resume {
+                true => run_resume(input, &output, &options),
+                false => run(mode, input, &output, &options),
+            }
         }
         _ => {
-            eprintln!("unknown mode '{}', expected 'encode' or 'decode'", mode);
+            eprintln!(
+                "unknown mode '{}', expected 'compress', 'decompress', 'info', 'dump-tree', 'extract', 'list', 'train' or 'compare'",
+                mode
+            );
             std::process::exit(1);
         }
+    };
+    if let Err(e) = result {
+        eprintln!("failed to {} {}: {}", mode, inputs.join(" "), e);
+        if e == CompressionError::Io(std::io::ErrorKind::AlreadyExists) {
+            eprintln!("use --force to overwrite existing files");
+        }
+        std::process::exit(1);
     }
 }
 
@@ -208,27 +791,279 @@ mod tests {
     use super::*;
 
     #[test]
-    fn round_trip_repetitive_data() {
-        let data = b"aaaaaaaaaaaaaaaaaaaab";
-        assert_eq!(decode(&encode(data)), data);
+    fn compress_appends_extension() {
+        assert_eq!(
+            default_output("compress", "notes.txt", false),
+            "notes.txt.hfz"
+        );
+    }
+
+    #[test]
+    fn decompress_strips_extension() {
+        let decompress = |input| default_output("decompress", input, false);
+        assert_eq!(decompress("notes.txt.hfz"), "notes.txt");
+        assert_eq!(decompress("notes.bin"), "notes.bin.out");
+        assert_eq!(decompress(".hfz"), ".hfz.out");
+        assert_eq!(decompress("notes.txt.gz"), "notes.txt");
+    }
+
+    #[test]
+    fn options_in_any_order() {
+        let flags: Vec<String> = ["--no-verify", "-o", "out.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let options = parse_options(&flags).unwrap();
+        assert_eq!(options.output.as_deref(), Some("out.txt"));
+        assert!(!options.verify);
+        assert!(parse_options(&flags[1..2]).is_none());
+    }
+
+    #[test]
+    fn existing_outputs_need_force() {
+        assert!(!parse_options(&[]).unwrap().force);
+        assert!(parse_options(&["--force".to_string()]).unwrap().force);
+    }
+
+    #[test]
+    fn resume_is_a_flag() {
+        assert!(!parse_options(&[]).unwrap().resume);
+        let flags = [
+            "--resume".to_string(),
+            "--block-size".to_string(),
+            "4096".to_string(),
+        ];
+        let options = parse_options(&flags).unwrap();
+        assert!(options.resume);
+        assert_eq!(options.block_size, Some(4096));
     }
 
     #[test]
-    fn round_trip_varied_data() {
-        let data = b"abcdefghijabcdefghij";
-        assert_eq!(decode(&encode(data)), data);
+    fn block_size_must_be_in_range() {
+        let parse = |size: &str| parse_options(&["--block-size".to_string(), size.to_string()]);
+        assert_eq!(parse("4096").unwrap().block_size, Some(4096));
+        assert!(parse("0").is_none());
+        assert!(parse("lots").is_none());
     }
 
     #[test]
-    fn repetitive_data_is_smaller_overall() {
-        let data = b"aaaaaaaaaaaaaaaaaaaab";
-        assert!(encode(data).len() < data.len());
+    fn threads_must_be_positive() {
+        let parse = |n: &str| parse_options(&["--threads".to_string(), n.to_string()]);
+        assert_eq!(parse("16").unwrap().threads, Some(16));
+        assert!(parse("0").is_none());
+    }
+
+    #[test]
+    fn append_takes_the_archive() {
+        let flags: Vec<String> = ["--append", "backup.hfa"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let options = parse_options(&flags).unwrap();
+        assert_eq!(options.append.as_deref(), Some("backup.hfa"));
+        assert!(parse_options(&flags[..1]).is_none());
+    }
+
+    #[test]
+    fn algorithm_by_name() {
+        let parse = |flags: &[&str]| {
+            parse_options(&flags.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            parse(&["--algo", "rle-huffman"]).unwrap().algorithm,
+            Algorithm::RleHuffman
+        );
+        assert!(parse(&["--algo", "zip"]).is_none());
+        assert!(parse(&["--algo", "lz", "--threads", "2"]).is_none());
     }
 
     #[test]
-    fn non_repetitive_data_has_smaller_data_part() {
-        let data = b"abcdefghijabcdefghij";
-        let encoded = Encoded::from_bytes(&encode(data));
-        assert!(encoded.bytes.len() < data.len());
+    fn level_must_be_in_range() {
+        let parse = |level: &str| parse_options(&["--level".to_string(), level.to_string()]);
+        assert_eq!(parse("9").unwrap().level, Some(9));
+        assert!(parse("0").is_none());
+        assert!(parse("10").is_none());
+    }
+
+    #[test]
+    fn dump_tree_prints_tables_or_dot() {
+        let data = b"abracadabra".repeat(5);
+        let trees = info::block_trees(&compression::compress(&data)).unwrap();
+        let table = describe_tree(0, &trees[0], false);
+        assert!(table.starts_with("block 0:\nsymbol  len   frequency  code\n'a'     1"));
+        assert!(describe_tree(0, &trees[0], true).starts_with("digraph huffman {"));
+        let single = info::block_trees(&compression::compress(&[7; 100])).unwrap();
+        assert_eq!(
+            describe_tree(0, &single[0], false),
+            "block 0: only 0x07, 100 times, needs no tree\n"
+        );
+        assert_eq!(describe_tree(0, &single[0], true), "");
+        assert!(parse_options(&["--dot".to_string()]).unwrap().dot);
+    }
+
+    #[test]
+    fn filters_by_name() {
+        let parse = |flags: &[&str]| {
+            parse_options(&flags.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            parse(&["--filter", "delta"]).unwrap().filter,
+            Some(Filter::delta(1))
+        );
+        let filter = parse(&["--filter", "interleave:4", "--algo", "lz"])
+            .unwrap()
+            .filter
+            .unwrap();
+        assert_eq!(filter, Filter::interleave(4));
+        assert_eq!(parse_filter(&filter_name(filter)), Some(filter));
+        assert!(parse(&["--filter", "delta:0"]).is_none());
+        assert!(parse(&["--filter", "delta:256"]).is_none());
+        assert!(parse(&["--filter", "xor"]).is_none());
+        assert!(parse(&["--filter", "delta", "--gzip"]).is_none());
+        assert!(parse(&["--filter", "delta", "--stats"]).is_none());
+    }
+
+    #[test]
+    fn stats_need_the_default_format() {
+        let parse = |flags: &[&str]| {
+            parse_options(&flags.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        assert!(parse(&["--stats", "--threads", "4"]).unwrap().stats);
+        assert!(parse(&["--stats", "--gzip"]).is_none());
+        assert!(parse(&["--stats", "--algo", "lz"]).is_none());
+        assert!(parse(&["--stats", "--block-size", "4096"]).is_none());
+    }
+
+    #[test]
+    fn keep_going_needs_checksums() {
+        let parse = |flags: &[&str]| {
+            parse_options(&flags.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        assert!(parse(&["--keep-going"]).unwrap().keep_going);
+        assert!(parse(&["--keep-going", "--no-verify"]).is_none());
+        assert!(parse(&["--keep-going", "--threads", "4"]).is_none());
+    }
+
+    #[test]
+    fn progress_line_shows_percent_rate_and_eta() {
+        let mib = 1024 * 1024;
+        assert_eq!(
+            progress_line(mib, 4 * mib, Duration::from_secs(2)),
+            " 25%      0.5 MiB/s  ETA 6s"
+        );
+        assert_eq!(
+            progress_line(0, 10, Duration::ZERO),
+            "  0%      0.0 MiB/s  ETA ?"
+        );
+        assert_eq!(
+            progress_line(0, 0, Duration::ZERO),
+            "100%      0.0 MiB/s  ETA ?"
+        );
+        let parse = |flags: &[&str]| {
+            parse_options(&flags.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        assert!(parse(&["--progress", "--threads", "4"]).unwrap().progress);
+        assert!(parse(&["--progress", "--gzip"]).is_none());
+        assert!(parse(&["--progress", "--no-verify"]).is_none());
+    }
+
+    #[test]
+    fn walk_flags_take_patterns() {
+        let parse = |flags: &[&str]| {
+            parse_options(&flags.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        assert!(!parse(&["-o", "out.hfa"]).unwrap().filtered);
+        assert!(
+            parse(&["--exclude", "target", "--include", "*.rs"])
+                .unwrap()
+                .filtered
+        );
+        assert!(parse(&["--follow-symlinks"]).unwrap().filtered);
+        assert!(parse(&["--exclude"]).is_none());
+    }
+
+    #[test]
+    fn compare_rows_line_up_with_the_header() {
+        let result = BenchResult {
+            options: compression::options::CompressionOptions {
+                algorithm: Algorithm::LzHuffman,
+                level: 9,
+                ..Default::default()
+            },
+            original_len: 2_000_000,
+            compressed_len: 500_000,
+            compress_time: Duration::from_millis(100),
+            decompress_time: Duration::from_millis(40),
+        };
+        let line = compare_line(&result);
+        assert_eq!(
+            line,
+            "lz              9      500000   0.250   20.0 MB/s   50.0 MB/s"
+        );
+        assert_eq!(line.len(), COMPARE_HEADER.len());
+    }
+
+    #[test]
+    fn missing_or_dash_input_reads_stdin() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let dash = args(&["-", "-c"]);
+        assert_eq!(split_inputs(&dash), (vec!["-"], &dash[1..]));
+        let flags_only = args(&["-o", "out.hfz"]);
+        assert_eq!(split_inputs(&flags_only), (vec!["-"], &flags_only[..]));
+        let file = args(&["notes.txt", "-c"]);
+        assert_eq!(split_inputs(&file), (vec!["notes.txt"], &file[1..]));
+    }
+
+    #[test]
+    fn several_inputs_make_an_archive() {
+        let args: Vec<String> = ["a.txt", "b.txt", "-o", "both.hfa"]
+            .map(String::from)
+            .into();
+        let (inputs, flags) = split_inputs(&args);
+        assert_eq!(inputs, ["a.txt", "b.txt"]);
+        assert!(is_archive_input(&inputs));
+        let options = parse_options(flags).unwrap();
+        assert_eq!(archive_output(&inputs, &options), "both.hfa");
+        let options = parse_options(&[]).unwrap();
+        assert_eq!(archive_output(&inputs, &options), "archive.hfa");
+        assert_eq!(archive_output(&["photos/"], &options), "photos.hfa");
+    }
+
+    #[test]
+    fn list_shows_entry_metadata() {
+        let entry = EntryInfo {
+            path: "docs/a.txt".to_string(),
+            mode: 0o644,
+            mtime: 1_700_000_000,
+            size: 1200,
+            compressed_len: 640,
+        };
+        assert_eq!(
+            list_line(&entry),
+            "0644       1200        640 1700000000 docs/a.txt"
+        );
+    }
+
+    #[test]
+    fn stdin_and_dash_c_write_stdout() {
+        let options = parse_options(&[]).unwrap();
+        assert_eq!(output_path("compress", "-", &options), "-");
+        assert_eq!(output_path("compress", "a.txt", &options), "a.txt.hfz");
+        let options = parse_options(&["-c".to_string()]).unwrap();
+        assert_eq!(output_path("decompress", "a.txt.hfz", &options), "-");
+        assert!(parse_options(&["-c", "-o", "x"].map(String::from)).is_none());
+    }
+
+    #[test]
+    fn info_describes_the_header() {
+        let data = b"describe me, describe me, describe me".repeat(3);
+        let info = info::inspect(&compression::compress(&data)).unwrap();
+        let text = describe(&info);
+        assert!(text.contains("algorithm:  huffman\n"));
+        assert!(text.contains("blocks:     1\n"));
+        assert!(text.contains(&format!("original:   {} bytes", data.len())));
+        for (name, algorithm) in ALGORITHMS {
+            assert_eq!(algorithm_name(algorithm), name);
+        }
     }
 }
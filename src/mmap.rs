@@ -0,0 +1,151 @@
+use std::ops::Deref;
+use std::path::Path;
+
+use crate::error::CompressionError;
+
+/// The bytes of a file, mapped read-only into memory on Unix so that large
+/// inputs can be passed to [`compress`](crate::compress) as a `&[u8]`
+/// without first being copied into a buffer. Elsewhere the file is read
+/// into memory instead.
+///
+/// The file must not be truncated while it is mapped; reading the missing
+/// pages would fault.
+pub struct Mmap {
+    #[cfg(unix)]
+    map: unix::Mapping,
+    #[cfg(not(unix))]
+    data: Vec<u8>,
+}
+
+impl Mmap {
+    pub fn open(path: &Path) -> Result<Mmap, CompressionError> {
+        let file = std::fs::File::open(path)?;
+        #[cfg(unix)]
+        return Ok(Mmap {
+            map: unix::Mapping::new(&file)?,
+        });
+        #[cfg(not(unix))]
+        {
+            use std::io::Read;
+            let mut data = Vec::new();
+            (&file).read_to_end(&mut data)?;
+            Ok(Mmap { data })
+        }
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        #[cfg(unix)]
+        return self.map.as_slice();
+        #[cfg(not(unix))]
+        &self.data
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::{c_int, c_void};
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+
+    use crate::error::CompressionError;
+
+    const PROT_READ: c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+
+    unsafe extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    /// A private read-only mapping of a whole file. Empty files are not
+    /// mapped, since a mapping cannot be empty.
+    pub(super) struct Mapping {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    // SAFETY: the mapping is read-only and owned by this value.
+    unsafe impl Send for Mapping {}
+    // SAFETY: as above; shared access only reads.
+    unsafe impl Sync for Mapping {}
+
+    impl Mapping {
+        pub(super) fn new(file: &File) -> Result<Mapping, CompressionError> {
+            let len = usize::try_from(file.metadata()?.len())
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::OutOfMemory))?;
+            if len == 0 {
+                return Ok(Mapping {
+                    ptr: std::ptr::null_mut(),
+                    len,
+                });
+            }
+            // SAFETY: a fresh private mapping of an open file descriptor
+            // does not alias any Rust memory.
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    PROT_READ,
+                    MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            // MAP_FAILED is (void *)-1.
+            if ptr as usize == usize::MAX {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok(Mapping { ptr, len })
+        }
+
+        pub(super) fn as_slice(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            // SAFETY: `ptr` maps `len` readable bytes until `drop`.
+            unsafe { std::slice::from_raw_parts(self.ptr.cast(), self.len) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            if self.len > 0 {
+                // SAFETY: unmaps the mapping made in `new`, once.
+                unsafe { munmap(self.ptr, self.len) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::scratch;
+    use crate::{compress, decompress};
+
+    #[test]
+    fn mapped_file_compresses_like_its_bytes() {
+        let dir = scratch("mmap");
+        let data = b"mapped bytes, mapped bytes, mapped bytes".repeat(50);
+        std::fs::write(dir.join("input"), &data).unwrap();
+        std::fs::write(dir.join("empty"), b"").unwrap();
+
+        let mapped = Mmap::open(&dir.join("input")).unwrap();
+        assert_eq!(&mapped[..], &data[..]);
+        assert_eq!(decompress(&compress(&mapped)).unwrap(), data);
+        assert!(Mmap::open(&dir.join("empty")).unwrap().is_empty());
+        assert!(Mmap::open(&dir.join("missing")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
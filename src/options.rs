@@ -0,0 +1,271 @@
+use alloc::vec::Vec;
+
+use crate::block::DEFAULT_BLOCK_SIZE;
+use crate::filter::Filter;
+use crate::words::WordOrder;
+
+/// The stages [`compress_with`](crate::compress_with) runs over the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Huffman coding of the bytes alone.
+    #[default]
+    Huffman,
+    /// LZSS match finding, then Huffman coding of the token stream.
+    LzHuffman,
+    /// Run-length encoding alone, stored without Huffman coding.
+    Rle,
+    /// Run-length encoding, then Huffman coding of the runs.
+    RleHuffman,
+    /// Adaptive Huffman coding in a single pass, with no stored tree.
+    Adaptive,
+    /// Burrows–Wheeler transform, move-to-front and run-length encoding,
+    /// then Huffman coding. Slower, but the best ratio on text.
+    Bwt,
+    /// Range coding with a static model of the byte frequencies. Within a
+    /// fraction of a bit per byte of the entropy, where Huffman coding can
+    /// lose up to a whole bit on skewed data.
+    Range,
+    /// Range coding with the previous byte as context, selecting one of 256
+    /// adaptive frequency tables. Slower than [`Algorithm::Range`], but
+    /// much better on text.
+    Order1,
+    /// Huffman coding of 16-bit words, paired from the bytes in the
+    /// [`word_order`](CompressionOptions::word_order) recorded in the
+    /// stream, so UTF-16 text and 16-bit samples are modelled on their
+    /// natural unit rather than as unrelated bytes.
+    Words,
+}
+
+impl Algorithm {
+    /// Every algorithm, in the order of their ids.
+    pub const ALL: [Algorithm; 9] = [
+        Algorithm::Huffman,
+        Algorithm::LzHuffman,
+        Algorithm::Rle,
+        Algorithm::RleHuffman,
+        Algorithm::Adaptive,
+        Algorithm::Bwt,
+        Algorithm::Range,
+        Algorithm::Order1,
+        Algorithm::Words,
+    ];
+
+    /// The id of the algorithm's [`Codec`](crate::codec::Codec), its
+    /// position in [`Algorithm::ALL`].
+    pub fn id(self) -> u8 {
+        Algorithm::ALL.iter().position(|&a| a == self).unwrap() as u8
+    }
+}
+
+/// Level used when none is chosen, the same effort as
+/// [`compress`](crate::compress) and [`lzss_encode`](crate::lzss::lzss_encode).
+pub const DEFAULT_LEVEL: u8 = 6;
+pub const MAX_LEVEL: u8 = 9;
+/// Smallest code length limit, enough for codes of all 256 byte values.
+pub const MIN_CODE_LENGTH_LIMIT: u8 = 8;
+
+/// What a level sets in the encoder. None of it is needed to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LevelSettings {
+    /// Block size of Huffman coding when none is chosen.
+    pub(crate) block_size: usize,
+    /// Farthest LZSS match distance.
+    pub(crate) window: usize,
+    /// Earlier positions tried per LZSS match search.
+    pub(crate) max_chain: usize,
+}
+
+/// Settings of levels 1 to 9. Low levels use small blocks and a short
+/// match search, middle levels the default blocks and the whole window, and
+/// high levels large blocks and a search of every earlier position in the
+/// window.
+const LEVELS: [LevelSettings; MAX_LEVEL as usize] = [
+    level(64 * 1024, 1024, 4),
+    level(64 * 1024, 2048, 8),
+    level(64 * 1024, 4096, 16),
+    level(DEFAULT_BLOCK_SIZE, 4096, 16),
+    level(DEFAULT_BLOCK_SIZE, 4096, 32),
+    level(DEFAULT_BLOCK_SIZE, 4096, 64),
+    level(1024 * 1024, 4096, 256),
+    level(1024 * 1024, 4096, 1024),
+    level(1024 * 1024, 4096, usize::MAX),
+];
+
+const fn level(block_size: usize, window: usize, max_chain: usize) -> LevelSettings {
+    LevelSettings {
+        block_size,
+        window,
+        max_chain,
+    }
+}
+
+/// Settings for [`compress_with`](crate::compress_with). The default is
+/// what [`compress`](crate::compress) does.
+///
+/// Everything a decoder needs is recorded in the header: the algorithm as
+/// stage flags, blocks as frames and the checksum as a flag, so
+/// [`decompress`](crate::decompress) takes no options. The level and the
+/// thread count only change how hard or how fast the encoder works.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionOptions {
+    pub algorithm: Algorithm,
+    /// Splits the input into blocks of this size before Huffman coding,
+    /// even if it is small. `None` splits only inputs over
+    /// [`DEFAULT_BLOCK_SIZE`](crate::block::DEFAULT_BLOCK_SIZE).
+    pub block_size: Option<usize>,
+    /// Number of threads coding blocks; more than one implies blocks.
+    pub threads: usize,
+    /// Effort from 1 to [`MAX_LEVEL`], trading speed for ratio. Higher
+    /// levels code larger blocks when `block_size` is `None` and search
+    /// farther and longer for LZSS matches.
+    pub level: u8,
+    /// Whether the stream ends with a CRC-32 of the data.
+    pub checksum: bool,
+    /// Longest Huffman code in bits. The default of 255 never limits, since
+    /// no code over 256 byte values is longer.
+    pub max_code_length: u8,
+    /// Pre-filter run over the data before the algorithm and recorded in
+    /// the header, for arrays of fixed-width numbers.
+    pub filter: Option<Filter>,
+    /// Byte order in which [`Algorithm::Words`] pairs bytes into words.
+    /// Other algorithms ignore it.
+    pub word_order: WordOrder,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> CompressionOptions {
+        CompressionOptions {
+            algorithm: Algorithm::Huffman,
+            block_size: None,
+            threads: 1,
+            level: DEFAULT_LEVEL,
+            checksum: true,
+            max_code_length: u8::MAX,
+            filter: None,
+            word_order: WordOrder::LittleEndian,
+        }
+    }
+}
+
+/// Compresses with settings fixed when it was built by
+/// [`Encoder::builder`].
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    options: CompressionOptions,
+}
+
+impl Encoder {
+    pub fn builder() -> EncoderBuilder {
+        EncoderBuilder {
+            options: CompressionOptions::default(),
+        }
+    }
+
+    pub fn options(&self) -> &CompressionOptions {
+        &self.options
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        crate::compress_with(data, &self.options)
+    }
+
+    /// Like `encode` but calls `on_progress` with the bytes of `data` coded
+    /// and the bytes of output so far, after every few blocks of plain
+    /// Huffman coding and once at the end, so a caller can show how far a
+    /// large input has come.
+    pub fn encode_with_progress(
+        &self,
+        data: &[u8],
+        mut on_progress: impl FnMut(u64, u64),
+    ) -> Vec<u8> {
+        crate::compress_with_progress(data, &self.options, &mut on_progress)
+    }
+}
+
+/// Collects the settings of an [`Encoder`], starting from the defaults of
+/// [`CompressionOptions`].
+#[derive(Debug, Clone)]
+pub struct EncoderBuilder {
+    options: CompressionOptions,
+}
+
+impl EncoderBuilder {
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> EncoderBuilder {
+        self.options.algorithm = algorithm;
+        self
+    }
+
+    /// Panics if `block_size` is 0 or exceeds
+    /// [`MAX_BLOCK_SIZE`](crate::block::MAX_BLOCK_SIZE).
+    pub fn with_block_size(mut self, block_size: usize) -> EncoderBuilder {
+        crate::block::check_block_size(block_size);
+        self.options.block_size = Some(block_size);
+        self
+    }
+
+    /// Panics if `threads` is 0.
+    pub fn with_threads(mut self, threads: usize) -> EncoderBuilder {
+        assert!(threads > 0, "at least one thread is needed");
+        self.options.threads = threads;
+        self
+    }
+
+    /// Panics if `level` is 0 or exceeds [`MAX_LEVEL`].
+    pub fn with_level(mut self, level: u8) -> EncoderBuilder {
+        check_level(level);
+        self.options.level = level;
+        self
+    }
+
+    /// Panics if `max_len` is below [`MIN_CODE_LENGTH_LIMIT`].
+    pub fn with_max_code_length(mut self, max_len: u8) -> EncoderBuilder {
+        check_code_length_limit(max_len);
+        self.options.max_code_length = max_len;
+        self
+    }
+
+    /// Panics if the filter's stride is 0.
+    pub fn with_filter(mut self, filter: Filter) -> EncoderBuilder {
+        crate::filter::check_stride(filter.stride);
+        self.options.filter = Some(filter);
+        self
+    }
+
+    pub fn with_word_order(mut self, order: WordOrder) -> EncoderBuilder {
+        self.options.word_order = order;
+        self
+    }
+
+    pub fn with_checksum(mut self, checksum: bool) -> EncoderBuilder {
+        self.options.checksum = checksum;
+        self
+    }
+
+    pub fn build(self) -> Encoder {
+        Encoder {
+            options: self.options,
+        }
+    }
+}
+
+/// Settings of `level`. Panics if it is out of range.
+pub(crate) fn level_settings(level: u8) -> LevelSettings {
+    check_level(level);
+    LEVELS[level as usize - 1]
+}
+
+pub(crate) fn check_code_length_limit(max_len: u8) {
+    assert!(
+        max_len >= MIN_CODE_LENGTH_LIMIT,
+        "code length limit must be at least {}",
+        MIN_CODE_LENGTH_LIMIT
+    );
+}
+
+pub(crate) fn check_level(level: u8) {
+    assert!(
+        (1..=MAX_LEVEL).contains(&level),
+        "level must be between 1 and {}",
+        MAX_LEVEL
+    );
+}
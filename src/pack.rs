@@ -0,0 +1,153 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+
+const PACK_MAGIC: [u8; 2] = [0x1f, 0x1e];
+const HEADER_LEN: usize = 7;
+const MAX_LEVELS: usize = 24;
+
+/// Returns whether `data` starts with the magic of a Unix pack(1) file.
+pub fn is_pack(data: &[u8]) -> bool {
+    data.starts_with(&PACK_MAGIC)
+}
+
+/// Decodes a file produced by the classic Unix pack(1) utility.
+///
+/// The header is the magic, the original length as a big-endian `u32`, the
+/// maximum code length, the number of leaves on each level (the deepest
+/// level offset by two) and the leaf bytes level by level. An implicit
+/// end-of-file symbol is the last leaf on the deepest level. On each level
+/// internal nodes take the lowest code values and leaves the rest.
+pub fn decode_pack(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    if data.len() < HEADER_LEN {
+        return Err(CompressionError::TruncatedInput);
+    }
+    if !is_pack(data) {
+        return Err(CompressionError::CorruptHeader);
+    }
+    let orig_len = u32::from_be_bytes([data[2], data[3], data[4], data[5]]) as usize;
+    let max_level = data[6] as usize;
+    if max_level == 0 || max_level > MAX_LEVELS {
+        return Err(CompressionError::CorruptHeader);
+    }
+
+    let mut pos = HEADER_LEN;
+    let counts = data
+        .get(pos..pos + max_level)
+        .ok_or(CompressionError::TruncatedInput)?;
+    pos += max_level;
+    // Index 0 is unused so that levels can be indexed directly.
+    let mut leaves = vec![0usize; max_level + 1];
+    for (level, &count) in counts.iter().enumerate() {
+        leaves[level + 1] = count as usize;
+    }
+    leaves[max_level] += 2;
+
+    let mut internal = vec![0usize; max_level + 1];
+    let mut nodes = 0usize;
+    for level in (1..=max_level).rev() {
+        if !nodes.is_multiple_of(2) {
+            return Err(CompressionError::CorruptHeader);
+        }
+        nodes /= 2;
+        internal[level] = nodes;
+        nodes += leaves[level];
+    }
+    if nodes != 2 {
+        return Err(CompressionError::CorruptHeader);
+    }
+
+    let total_leaves: usize = leaves.iter().sum();
+    if total_leaves > 257 {
+        return Err(CompressionError::CorruptHeader);
+    }
+    // The end-of-file leaf is not stored.
+    let symbols = data
+        .get(pos..pos + total_leaves - 1)
+        .ok_or(CompressionError::TruncatedInput)?;
+    pos += total_leaves - 1;
+    let mut level_start = vec![0usize; max_level + 1];
+    for level in 2..=max_level {
+        level_start[level] = level_start[level - 1] + leaves[level - 1];
+    }
+
+    // Every code is at least one bit long, so the payload caps the length
+    // even if the header is wrong.
+    let mut out = Vec::with_capacity(orig_len.min((data.len() - pos).saturating_mul(8)));
+    let mut code = 0;
+    let mut level = 1;
+    for &byte in &data[pos..] {
+        for bit_idx in (0..8).rev() {
+            code = code * 2 + ((byte >> bit_idx) & 1) as usize;
+            if code < internal[level] {
+                level += 1;
+                continue;
+            }
+            let leaf = code - internal[level];
+            if level == max_level && leaf == leaves[max_level] - 1 {
+                if out.len() != orig_len {
+                    return Err(CompressionError::CorruptData);
+                }
+                return Ok(out);
+            }
+            if out.len() == orig_len {
+                return Err(CompressionError::CorruptData);
+            }
+            out.push(symbols[level_start[level] + leaf]);
+            code = 0;
+            level = 1;
+        }
+    }
+    Err(CompressionError::TruncatedInput)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AAB: [u8; 12] = [
+        0x1f, 0x1e, 0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0x61, 0x62, 0xc4,
+    ];
+
+    const ABRACADABRA: [u8; 20] = [
+        0x1f, 0x1e, 0x00, 0x00, 0x00, 0x0b, 0x04, 0x01, 0x00, 0x03, 0x00, 0x61, 0x62, 0x64, 0x72,
+        0x63, 0x97, 0x0a, 0x97, 0x10,
+    ];
+
+    #[test]
+    fn decodes_two_level_file() {
+        assert_eq!(decode_pack(&AAB).unwrap(), b"aab");
+    }
+
+    #[test]
+    fn decodes_file_with_empty_level() {
+        assert_eq!(decode_pack(&ABRACADABRA).unwrap(), b"abracadabra");
+    }
+
+    #[test]
+    fn truncated_payload_is_rejected() {
+        assert_eq!(
+            decode_pack(&ABRACADABRA[..18]),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn inconsistent_level_counts_are_rejected() {
+        let mut data = AAB;
+        data[7] = 2;
+        assert_eq!(decode_pack(&data), Err(CompressionError::CorruptHeader));
+    }
+
+    #[test]
+    fn length_mismatch_is_rejected() {
+        let mut data = AAB;
+        data[5] = 4;
+        assert_eq!(decode_pack(&data), Err(CompressionError::CorruptData));
+
+        // A 12-byte file claiming 4 GiB fails without reserving it.
+        data[2..6].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(decode_pack(&data), Err(CompressionError::CorruptData));
+    }
+}
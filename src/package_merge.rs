@@ -0,0 +1,104 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Code lengths of at most `limit` bits that code `freq` in the fewest
+/// bits, found with the package-merge algorithm. Symbols with a frequency
+/// of 0 get length 0.
+///
+/// Every present symbol starts as a coin of its frequency at each of the
+/// `limit` depths. Going from the deepest depth up, the coins of a depth
+/// are paired into packages and merged with the fresh coins of the next.
+/// The lightest `2n - 2` items of the last list hold every symbol as many
+/// times as its code is long.
+///
+/// Needs at least two present symbols and at most `2^limit` of them.
+pub(crate) fn package_merge(freq: &[u64], limit: u8) -> Vec<u8> {
+    let mut leaves: Vec<(u64, usize)> = freq
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| **f > 0)
+        .map(|(symbol, &f)| (f, symbol))
+        .collect();
+    leaves.sort_unstable();
+    let n = leaves.len();
+    debug_assert!(n >= 2 && (limit >= 64 || n as u64 <= 1 << limit));
+
+    let coins: Vec<(u64, Vec<usize>)> = leaves.iter().map(|&(f, s)| (f, vec![s])).collect();
+    let mut items = coins.clone();
+    for _ in 1..limit {
+        let packages = items.chunks_exact(2).map(|pair| {
+            let symbols = [pair[0].1.as_slice(), &pair[1].1].concat();
+            (pair[0].0 + pair[1].0, symbols)
+        });
+        items = merge(&coins, packages.collect());
+    }
+
+    let mut lengths = vec![0u8; freq.len()];
+    for (_, symbols) in &items[..2 * n - 2] {
+        for &symbol in symbols {
+            lengths[symbol] += 1;
+        }
+    }
+    lengths
+}
+
+/// `coins` and `packages`, both sorted by weight, merged into one list
+/// sorted by weight, coins first among equal weights.
+fn merge(coins: &[(u64, Vec<usize>)], packages: Vec<(u64, Vec<usize>)>) -> Vec<(u64, Vec<usize>)> {
+    let mut out = Vec::with_capacity(coins.len() + packages.len());
+    let mut coins = coins.iter().peekable();
+    let mut packages = packages.into_iter().peekable();
+    loop {
+        let take_coin = match (coins.peek(), packages.peek()) {
+            (Some(coin), Some(package)) => coin.0 <= package.0,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return out,
+        };
+        match take_coin {
+            true => out.push(coins.next().unwrap().clone()),
+            false => out.push(packages.next().unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `lengths` describe a complete prefix code.
+    fn is_complete(lengths: &[u8]) -> bool {
+        let kraft: f64 = lengths
+            .iter()
+            .filter(|&&len| len > 0)
+            .map(|&len| 0.5f64.powi(len as i32))
+            .sum();
+        (kraft - 1.0).abs() < 1e-12
+    }
+
+    #[test]
+    fn fibonacci_frequencies_fit_the_limit() {
+        // Unlimited, these would give a chain 20 codes deep.
+        let mut freq = vec![0u64; 30];
+        let (mut a, mut b) = (1, 1);
+        for f in freq.iter_mut().take(21) {
+            *f = a;
+            (a, b) = (b, a + b);
+        }
+        for limit in [5, 8, 15] {
+            let lengths = package_merge(&freq, limit);
+            assert!(lengths.iter().all(|&len| len <= limit));
+            assert!(lengths[..21].iter().all(|&len| len > 0));
+            assert!(lengths[21..].iter().all(|&len| len == 0));
+            assert!(is_complete(&lengths));
+        }
+    }
+
+    #[test]
+    fn loose_limits_give_huffman_lengths() {
+        let freq = [45, 13, 12, 16, 9, 5];
+        assert_eq!(package_merge(&freq, 15), [1, 3, 3, 3, 4, 4]);
+        // Within three bits the two heaviest symbols share the short codes.
+        assert_eq!(package_merge(&freq, 3), [2, 3, 3, 2, 3, 3]);
+    }
+}
@@ -0,0 +1,442 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::histogram;
+
+/// The model total never exceeds this, so every frequency fits a `u16`
+/// and a range of at least `BOTTOM` leaves each symbol 256 values.
+const MAX_TOTAL: u64 = 1 << 16;
+/// Share of `MAX_TOTAL` scaled counts are fitted into, leaving room to
+/// round every present byte up to a count of one.
+const SCALED_TOTAL: u64 = MAX_TOTAL - 256;
+/// The range is widened a byte at a time whenever it drops below this.
+const BOTTOM: u32 = 1 << 24;
+/// Added to a byte's count in its context each time it is coded.
+const CONTEXT_STEP: u16 = 24;
+
+/// Codes `data` with a range coder over a static model of its byte
+/// frequencies. Unlike Huffman coding, a symbol can take a fraction of a
+/// bit, which pays off on skewed distributions.
+///
+/// The output is the number of distinct bytes as a little-endian `u16`,
+/// then each of those bytes with its scaled frequency as a little-endian
+/// `u16`, then the coded bytes. The model total is the sum of the stored
+/// frequencies, at most 65536.
+pub fn range_encode(data: &[u8]) -> Vec<u8> {
+    let model = scale(&histogram(data));
+    let mut out = Vec::new();
+    let present = model.iter().filter(|&&f| f > 0).count() as u16;
+    out.extend(present.to_le_bytes());
+    for (b, &f) in model.iter().enumerate().filter(|&(_, &f)| f > 0) {
+        out.push(b as u8);
+        out.extend((f as u16).to_le_bytes());
+    }
+    if data.is_empty() {
+        return out;
+    }
+
+    let (starts, total) = cumulative(&model);
+    let mut encoder = RangeEncoder::new(out);
+    for &b in data {
+        encoder.encode(starts[b as usize], model[b as usize], total);
+    }
+    encoder.finish()
+}
+
+/// Decodes `len` bytes written by `range_encode`. Fails if `data` goes on
+/// past the coded bytes.
+pub fn range_decode(data: &[u8], len: u64) -> Result<Vec<u8>, CompressionError> {
+    whole(range_decode_prefix(data, len)?, data)
+}
+
+/// Like `range_decode` but ignores whatever follows the coded bytes and
+/// returns the number of bytes read with the output.
+pub(crate) fn range_decode_prefix(
+    data: &[u8],
+    len: u64,
+) -> Result<(Vec<u8>, usize), CompressionError> {
+    let (count, mut rest) = data
+        .split_first_chunk::<2>()
+        .ok_or(CompressionError::TruncatedInput)?;
+    let mut model = [0u32; 256];
+    for _ in 0..u16::from_le_bytes(*count) {
+        let (&[b, lo, hi], next) = rest
+            .split_first_chunk::<3>()
+            .ok_or(CompressionError::TruncatedInput)?;
+        let f = u16::from_le_bytes([lo, hi]);
+        if f == 0 || model[b as usize] != 0 {
+            return Err(CompressionError::CorruptData);
+        }
+        model[b as usize] = f as u32;
+        rest = next;
+    }
+    if len == 0 {
+        return Ok((Vec::new(), data.len() - rest.len()));
+    }
+    let (starts, total) = cumulative(&model);
+    if total == 0 || total as u64 > MAX_TOTAL {
+        return Err(CompressionError::CorruptData);
+    }
+    let mut symbols = Vec::with_capacity(total as usize);
+    for (b, &f) in model.iter().enumerate() {
+        symbols.extend(core::iter::repeat_n(b as u8, f as usize));
+    }
+
+    let mut decoder = RangeDecoder::new(rest);
+    let mut out = Vec::with_capacity(capacity(len, rest));
+    for _ in 0..len {
+        let slot = decoder.target(total)?;
+        let b = symbols[slot as usize];
+        decoder.consume(starts[b as usize], model[b as usize]);
+        out.push(b);
+    }
+    Ok((out, data.len() - rest.len() + decoder.consumed))
+}
+
+/// Codes `data` with the range coder over an order-1 model: the byte before
+/// each byte selects one of 256 adaptive frequency tables. Every table
+/// starts with a count of one for each byte and the encoder and decoder
+/// update them the same way, so no model is stored and the output is only
+/// the coded bytes. On text, where the previous letter says much about the
+/// next, this codes well below the order-0 entropy.
+pub fn order1_encode(data: &[u8]) -> Vec<u8> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mut model = ContextModel::new();
+    let mut encoder = RangeEncoder::new(Vec::new());
+    let mut context = 0;
+    for &b in data {
+        let (start, freq, total) = model.interval(context, b);
+        encoder.encode(start, freq, total);
+        model.update(context, b);
+        context = b;
+    }
+    encoder.finish()
+}
+
+/// Decodes `len` bytes written by `order1_encode`. Fails if `data` goes on
+/// past the coded bytes.
+pub fn order1_decode(data: &[u8], len: u64) -> Result<Vec<u8>, CompressionError> {
+    whole(order1_decode_prefix(data, len)?, data)
+}
+
+/// Like `order1_decode` but ignores whatever follows the coded bytes and
+/// returns the number of bytes read with the output.
+pub(crate) fn order1_decode_prefix(
+    data: &[u8],
+    len: u64,
+) -> Result<(Vec<u8>, usize), CompressionError> {
+    if len == 0 {
+        return Ok((Vec::new(), 0));
+    }
+    let mut model = ContextModel::new();
+    let mut decoder = RangeDecoder::new(data);
+    let mut out = Vec::with_capacity(capacity(len, data));
+    let mut context = 0;
+    for _ in 0..len {
+        let slot = decoder.target(model.totals[context as usize])?;
+        let (b, start, freq) = model.find(context, slot);
+        decoder.consume(start, freq);
+        model.update(context, b);
+        out.push(b);
+        context = b;
+    }
+    Ok((out, decoder.consumed))
+}
+
+/// Room to reserve for `len` decoded bytes, capped by the size of the coded
+/// `input` so a corrupt length cannot reserve more than it plausibly holds.
+fn capacity(len: u64, input: &[u8]) -> usize {
+    len.min(input.len() as u64 * 8) as usize
+}
+
+/// The output of a prefix decoder if it read all of `data`.
+fn whole((out, used): (Vec<u8>, usize), data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    match used.cmp(&data.len()) {
+        core::cmp::Ordering::Less => Err(CompressionError::CorruptData),
+        core::cmp::Ordering::Equal => Ok(out),
+        core::cmp::Ordering::Greater => Err(CompressionError::TruncatedInput),
+    }
+}
+
+/// Adaptive byte counts for each possible previous byte.
+struct ContextModel {
+    counts: Vec<[u16; 256]>,
+    totals: Vec<u32>,
+}
+
+impl ContextModel {
+    fn new() -> ContextModel {
+        ContextModel {
+            counts: vec![[1; 256]; 256],
+            totals: vec![256; 256],
+        }
+    }
+
+    /// Start and count of `b` in the table of `context`, and the table total.
+    fn interval(&self, context: u8, b: u8) -> (u32, u32, u32) {
+        let counts = &self.counts[context as usize];
+        let start = counts[..b as usize].iter().map(|&c| c as u32).sum();
+        (
+            start,
+            counts[b as usize] as u32,
+            self.totals[context as usize],
+        )
+    }
+
+    /// The byte whose share of the table of `context` holds `slot`, with its
+    /// start and count.
+    fn find(&self, context: u8, slot: u32) -> (u8, u32, u32) {
+        let mut start = 0;
+        for (b, &c) in self.counts[context as usize].iter().enumerate() {
+            if slot < start + c as u32 {
+                return (b as u8, start, c as u32);
+            }
+            start += c as u32;
+        }
+        unreachable!("slot is below the table total")
+    }
+
+    /// Counts `b` in the table of `context`, halving the table once its
+    /// total would pass `MAX_TOTAL`.
+    fn update(&mut self, context: u8, b: u8) {
+        let counts = &mut self.counts[context as usize];
+        counts[b as usize] += CONTEXT_STEP;
+        let mut total = self.totals[context as usize] + CONTEXT_STEP as u32;
+        if total as u64 > MAX_TOTAL - CONTEXT_STEP as u64 {
+            for count in counts.iter_mut() {
+                *count = count.div_ceil(2);
+            }
+            total = counts.iter().map(|&c| c as u32).sum();
+        }
+        self.totals[context as usize] = total;
+    }
+}
+
+/// `freq` scaled to a total of at most `MAX_TOTAL`, keeping every present
+/// byte at a count of at least one. Counts that already fit are kept.
+fn scale(freq: &[u64; 256]) -> [u32; 256] {
+    let sum: u64 = freq.iter().sum();
+    core::array::from_fn(|b| match freq[b] {
+        0 => 0,
+        f if sum <= SCALED_TOTAL => f as u32,
+        f => (f * SCALED_TOTAL / sum).max(1) as u32,
+    })
+}
+
+/// Start of every byte's share of the model, and the model total.
+fn cumulative(model: &[u32; 256]) -> ([u32; 256], u32) {
+    let mut starts = [0; 256];
+    let mut total = 0;
+    for (start, &f) in starts.iter_mut().zip(model) {
+        *start = total;
+        total += f;
+    }
+    (starts, total)
+}
+
+/// Byte-oriented range coder with carry propagation. `low` keeps a ninth
+/// byte for the carry; bytes of `0xff` that a later carry could still
+/// change are counted in `pending` behind `cache` until they settle.
+struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    pending: u64,
+    out: Vec<u8>,
+}
+
+impl RangeEncoder {
+    fn new(out: Vec<u8>) -> RangeEncoder {
+        RangeEncoder {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            pending: 1,
+            out,
+        }
+    }
+
+    fn encode(&mut self, start: u32, freq: u32, total: u32) {
+        let step = self.range / total;
+        self.low += step as u64 * start as u64;
+        self.range = step * freq;
+        while self.range < BOTTOM {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low > u32::MAX as u64 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            while self.pending > 0 {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.pending -= 1;
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.pending += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+}
+
+struct RangeDecoder<'a> {
+    code: u32,
+    range: u32,
+    input: core::slice::Iter<'a, u8>,
+    /// Bytes read so far, including any read past the end of the input.
+    /// The encoder writes exactly as many as the decoder reads.
+    consumed: usize,
+    /// Length of the input, past which reads only give zeros.
+    len: usize,
+}
+
+impl RangeDecoder<'_> {
+    fn new(data: &[u8]) -> RangeDecoder<'_> {
+        let mut decoder = RangeDecoder {
+            code: 0,
+            range: u32::MAX,
+            input: data.iter(),
+            consumed: 0,
+            len: data.len(),
+        };
+        // The encoder's first byte is always zero and carries nothing.
+        decoder.next_byte();
+        for _ in 0..4 {
+            decoder.code = decoder.code << 8 | decoder.next_byte() as u32;
+        }
+        decoder
+    }
+
+    /// The next input byte, or zero past the end of a truncated input.
+    fn next_byte(&mut self) -> u8 {
+        self.consumed += 1;
+        self.input.next().copied().unwrap_or(0)
+    }
+
+    /// The model slot the code falls in. Fails once more than a code's
+    /// worth of bytes has been read past the end of the input, so a
+    /// truncated stream or an inflated length cannot keep decoding zeros.
+    fn target(&mut self, total: u32) -> Result<u32, CompressionError> {
+        if self.consumed > self.len + 4 {
+            return Err(CompressionError::TruncatedInput);
+        }
+        self.range /= total;
+        let slot = self.code / self.range;
+        if slot >= total {
+            return Err(CompressionError::CorruptData);
+        }
+        Ok(slot)
+    }
+
+    /// Narrows to the symbol `target` pointed at.
+    fn consume(&mut self, start: u32, freq: u32) {
+        self.code -= start * self.range;
+        self.range *= freq;
+        while self.range < BOTTOM {
+            self.code = self.code << 8 | self.next_byte() as u32;
+            self.range <<= 8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_text_and_edge_cases() {
+        let text = b"abracadabra, the quick brown fox jumps over the lazy dog".repeat(30);
+        let all: Vec<u8> = (0..=255).cycle().take(5000).collect();
+        for data in [&text[..], &all, b"", b"z", &[0xff; 3000]] {
+            let encoded = range_encode(data);
+            assert_eq!(range_decode(&encoded, data.len() as u64).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn skewed_data_takes_a_fraction_of_a_bit_per_byte() {
+        let data: Vec<u8> = (0..200_000u32)
+            .map(|i| if i % 50 == 0 { b'b' } else { b'a' })
+            .collect();
+        let encoded = range_encode(&data);
+        // Huffman coding needs one bit for each of the two symbols.
+        assert!(encoded.len() * 8 < data.len() / 5);
+        assert_eq!(range_decode(&encoded, data.len() as u64).unwrap(), data);
+    }
+
+    #[test]
+    fn order1_contexts_beat_order0_on_text() {
+        let text = b"It was the best of times, it was the worst of times, it was the age \
+            of wisdom, it was the age of foolishness, it was the epoch of belief. "
+            .repeat(20);
+        let encoded = order1_encode(&text);
+        assert!(encoded.len() * 3 < range_encode(&text).len() * 2);
+        assert_eq!(order1_decode(&encoded, text.len() as u64).unwrap(), text);
+
+        let all: Vec<u8> = (0..=255).cycle().take(70_000).collect();
+        assert_eq!(order1_decode(&order1_encode(&all), 70_000).unwrap(), all);
+        assert!(order1_encode(b"").is_empty());
+        assert!(order1_decode(b"", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn order1_stops_past_the_end_of_the_input() {
+        let text = b"truncated order-1 streams end early. ".repeat(50);
+        let encoded = order1_encode(&text);
+        assert_eq!(
+            order1_decode(&encoded[..encoded.len() / 2], text.len() as u64),
+            Err(CompressionError::TruncatedInput)
+        );
+        assert_eq!(
+            order1_decode_prefix(&encoded, 1 << 62).map(|(out, _)| out.len()),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn inflated_lengths_stop_past_the_end_of_the_input() {
+        let text = b"a range stream whose length was tampered with".repeat(20);
+        let encoded = range_encode(&text);
+        assert_eq!(
+            range_decode_prefix(&encoded, 1 << 62).map(|(out, _)| out.len()),
+            Err(CompressionError::TruncatedInput)
+        );
+        assert_eq!(
+            range_decode(&encoded[..encoded.len() - 8], text.len() as u64),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn malformed_models_are_rejected() {
+        assert_eq!(
+            range_decode(b"\x01", 1),
+            Err(CompressionError::TruncatedInput)
+        );
+        assert_eq!(
+            range_decode(b"\x02\x00a\x01\x00", 1),
+            Err(CompressionError::TruncatedInput)
+        );
+        assert_eq!(
+            range_decode(b"\x02\x00a\x01\x00a\x01\x00", 1),
+            Err(CompressionError::CorruptData)
+        );
+        assert_eq!(
+            range_decode(b"\x00\x00", 1),
+            Err(CompressionError::CorruptData)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::format::Cursor;
+use crate::options::CompressionOptions;
+use crate::{compress_with, decompress};
+
+const RECORDS_MAGIC: [u8; 4] = *b"WFHR";
+const RECORDS_VERSION: u8 = 1;
+
+/// Records are gathered into batches of about this many bytes, each
+/// compressed as one stream, so that short records such as log lines share
+/// a tree while decoding holds only one batch at a time.
+pub const RECORD_BATCH_LEN: usize = 1 << 20;
+
+/// Compresses `records` so that [`decompress_records`] gives them back one
+/// by one, with their boundaries intact.
+///
+/// The output is the magic `WFHR` and a version byte, followed by batches
+/// of consecutive records. A batch is the number of records in it as a
+/// little-endian `u32`, the length of its stream as a little-endian `u64`
+/// and the output of [`compress_with`] for the records, each preceded by
+/// its length as a little-endian `u32`. A batch is closed after the record
+/// that brings it to [`RECORD_BATCH_LEN`] bytes or more, so a long record
+/// ends the batch it joins.
+///
+/// Panics if a record is longer than `u32::MAX` bytes.
+pub fn compress_records<I>(records: I, options: &CompressionOptions) -> Vec<u8>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut out = RECORDS_MAGIC.to_vec();
+    out.push(RECORDS_VERSION);
+    let mut batch = Vec::new();
+    let mut count = 0u32;
+    for record in records {
+        let record = record.as_ref();
+        let len = u32::try_from(record.len()).expect("records are at most u32::MAX bytes");
+        batch.extend(len.to_le_bytes());
+        batch.extend(record);
+        count += 1;
+        if batch.len() >= RECORD_BATCH_LEN {
+            push_batch(&mut out, &batch, count, options);
+            batch.clear();
+            count = 0;
+        }
+    }
+    if count > 0 {
+        push_batch(&mut out, &batch, count, options);
+    }
+    out
+}
+
+fn push_batch(out: &mut Vec<u8>, batch: &[u8], count: u32, options: &CompressionOptions) {
+    let compressed = compress_with(batch, options);
+    out.extend(count.to_le_bytes());
+    out.extend((compressed.len() as u64).to_le_bytes());
+    out.extend(compressed);
+}
+
+/// The records of the output of [`compress_records`], in order. A batch is
+/// decompressed when its first record is reached.
+pub fn decompress_records(data: &[u8]) -> Records<'_> {
+    Records {
+        rest: data,
+        started: false,
+        batch: Vec::new(),
+        position: 0,
+        left: 0,
+        failed: false,
+    }
+}
+
+/// Iterator returned by [`decompress_records`]. It yields an error at most
+/// once, for a malformed header or batch, and ends after it.
+pub struct Records<'a> {
+    /// The batches not decompressed yet.
+    rest: &'a [u8],
+    /// Whether the magic and version have been checked.
+    started: bool,
+    /// The decompressed records of the current batch.
+    batch: Vec<u8>,
+    /// Start of the next record in `batch`.
+    position: usize,
+    /// Records of the current batch not yielded yet.
+    left: u32,
+    failed: bool,
+}
+
+impl Records<'_> {
+    fn next_record(&mut self) -> Result<Option<Vec<u8>>, CompressionError> {
+        if !self.started {
+            let mut cursor = Cursor::new(self.rest);
+            if cursor.array::<4>()? != RECORDS_MAGIC {
+                return Err(CompressionError::InvalidMagic);
+            }
+            let version = cursor.u8()?;
+            if version > RECORDS_VERSION {
+                return Err(CompressionError::UnsupportedVersion {
+                    found: version,
+                    supported: RECORDS_VERSION,
+                });
+            }
+            self.rest = cursor.rest();
+            self.started = true;
+        }
+        if self.left == 0 {
+            if self.position != self.batch.len() {
+                return Err(CompressionError::TrailingData);
+            }
+            if self.rest.is_empty() {
+                return Ok(None);
+            }
+            self.next_batch()?;
+        }
+        let mut cursor = Cursor::new(&self.batch[self.position..]);
+        let len = cursor.u32_le()? as usize;
+        let record = cursor.bytes(len)?.to_vec();
+        self.position += cursor.position();
+        self.left -= 1;
+        Ok(Some(record))
+    }
+
+    fn next_batch(&mut self) -> Result<(), CompressionError> {
+        let mut cursor = Cursor::new(self.rest);
+        let count = cursor.u32_le()?;
+        let len =
+            usize::try_from(cursor.u64_le()?).map_err(|_| CompressionError::TruncatedInput)?;
+        let compressed = cursor.bytes(len)?;
+        if count == 0 {
+            return Err(CompressionError::CorruptHeader);
+        }
+        self.batch = decompress(compressed)?;
+        self.position = 0;
+        self.left = count;
+        self.rest = cursor.rest();
+        Ok(())
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<Vec<u8>, CompressionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let record = self.next_record();
+        self.failed = record.is_err();
+        record.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Algorithm;
+
+    fn log_lines(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| format!("2026-10-16T12:00:{:02} INFO request {} served", i % 60, i))
+            .collect()
+    }
+
+    #[test]
+    fn records_round_trip_with_their_boundaries() {
+        let lines = log_lines(1000);
+        let options = CompressionOptions {
+            algorithm: Algorithm::LzHuffman,
+            ..Default::default()
+        };
+        let encoded = compress_records(&lines, &options);
+        let joined: usize = lines.iter().map(String::len).sum();
+        assert!(encoded.len() < joined / 2);
+        let decoded: Vec<Vec<u8>> = decompress_records(&encoded)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            decoded,
+            lines.iter().map(|l| l.as_bytes()).collect::<Vec<_>>()
+        );
+
+        let odd: [&[u8]; 3] = [b"", b"x", b""];
+        let encoded = compress_records(odd, &Default::default());
+        let decoded: Vec<Vec<u8>> = decompress_records(&encoded).map(Result::unwrap).collect();
+        assert_eq!(decoded, odd);
+        let empty = compress_records(Vec::<Vec<u8>>::new(), &Default::default());
+        assert_eq!(decompress_records(&empty).count(), 0);
+    }
+
+    #[test]
+    fn records_are_batched() {
+        let big = vec![7u8; RECORD_BATCH_LEN + 1];
+        let records = [&b"before"[..], &big, b"after"];
+        let encoded = compress_records(records, &Default::default());
+        // The big record closes the first batch, the last one is alone.
+        let first = u32::from_le_bytes(encoded[5..9].try_into().unwrap());
+        assert_eq!(first, 2);
+        let decoded: Vec<Vec<u8>> = decompress_records(&encoded).map(Result::unwrap).collect();
+        assert_eq!(decoded, records);
+    }
+
+    #[test]
+    fn malformed_input_ends_with_one_error() {
+        let encoded = compress_records(log_lines(10), &Default::default());
+        let mut records = decompress_records(&encoded[..encoded.len() - 1]);
+        assert_eq!(records.next(), Some(Err(CompressionError::TruncatedInput)));
+        assert_eq!(records.next(), None);
+
+        let mut records = decompress_records(b"WFHA\x01");
+        assert_eq!(records.next(), Some(Err(CompressionError::InvalidMagic)));
+        assert_eq!(records.next(), None);
+
+        // A batch that claims fewer records than it holds.
+        let mut short = encoded.clone();
+        short[5] -= 1;
+        let results: Vec<_> = decompress_records(&short).collect();
+        assert_eq!(results.len(), 10);
+        assert_eq!(results[9], Err(CompressionError::TrailingData));
+    }
+}
@@ -0,0 +1,335 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::CHECKSUM_LEN;
+use crate::atomic::write_atomic;
+use crate::block::{check_block_size, encode_frame, multi_block_prefix};
+use crate::checksum::{Crc32, crc32};
+use crate::error::CompressionError;
+use crate::format::Cursor;
+
+const STATE_MAGIC: [u8; 4] = *b"WFHS";
+const STATE_VERSION: u8 = 1;
+
+/// What [`compress_resumable`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeSummary {
+    /// Blocks taken over from an interrupted run.
+    pub resumed_blocks: u64,
+    pub total_blocks: u64,
+    pub original_len: u64,
+    pub compressed_len: u64,
+}
+
+/// Compresses the file at `input` into a multi-block stream at `output`,
+/// as [`compress_blocks`](crate::block::compress_blocks) with the same
+/// block size would, so that an interrupted run picks up where it left off.
+///
+/// The stream is written to `<output>.part`. Each block is appended and
+/// synced to disk, and then a sidecar state file, `<output>.resume`, is
+/// replaced with the blocks completed, the input and output offsets and the
+/// running checksum. A later call for the same input, output and block size
+/// cuts `<output>.part` back to the last recorded block and continues from
+/// there. Frames are coded the same way every time, so the result does not
+/// depend on where it was interrupted. Once the checksum is written,
+/// `<output>.part` is renamed to `output` and the state file removed.
+///
+/// A state file for a different block size, or for an input whose length
+/// or modification time changed since, is ignored and the compression
+/// starts over. Fails with an [`Io`](CompressionError::Io) error of kind
+/// [`AlreadyExists`](std::io::ErrorKind::AlreadyExists) if `output` exists,
+/// unless `overwrite` is set.
+///
+/// Panics if `block_size` is 0 or exceeds
+/// [`MAX_BLOCK_SIZE`](crate::block::MAX_BLOCK_SIZE).
+pub fn compress_resumable(
+    input: &Path,
+    output: &Path,
+    block_size: usize,
+    overwrite: bool,
+) -> Result<ResumeSummary, CompressionError> {
+    compress_resumable_with_progress(input, output, block_size, overwrite, |_, _| Ok(()))
+}
+
+/// Like [`compress_resumable`] but calls `on_block` with the blocks done
+/// and the total after every block is recorded. An error from `on_block`
+/// stops the compression, which a later call resumes.
+pub fn compress_resumable_with_progress(
+    input: &Path,
+    output: &Path,
+    block_size: usize,
+    overwrite: bool,
+    mut on_block: impl FnMut(u64, u64) -> Result<(), CompressionError>,
+) -> Result<ResumeSummary, CompressionError> {
+    check_block_size(block_size);
+    if !overwrite && output.symlink_metadata().is_ok() {
+        return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+    }
+    let mut source = File::open(input)?;
+    let metadata = source.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let fresh = State {
+        input_len: metadata.len(),
+        input_mtime: (modified.as_secs(), modified.subsec_nanos()),
+        block_size: block_size as u64,
+        blocks: 0,
+        input_offset: 0,
+        output_len: 0,
+        crc: 0,
+    };
+    let partial_path = sidecar(output, "part");
+    let state_path = state_path(output);
+    let mut partial = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&partial_path)?;
+    let written = partial.metadata()?.len();
+    let mut state = match State::read(&state_path)? {
+        Some(state) if state.continues(&fresh) && state.output_len <= written => state,
+        _ => {
+            let prefix = multi_block_prefix(fresh.input_len);
+            partial.set_len(0)?;
+            partial.write_all(&prefix)?;
+            partial.sync_data()?;
+            State {
+                output_len: prefix.len() as u64,
+                ..fresh
+            }
+        }
+    };
+    partial.set_len(state.output_len)?;
+    partial.seek(SeekFrom::End(0))?;
+    source.seek(SeekFrom::Start(state.input_offset))?;
+
+    let resumed_blocks = state.blocks;
+    let total_blocks = state.input_len.div_ceil(state.block_size);
+    let mut crc = Crc32::resume(state.crc);
+    let mut block = vec![0u8; block_size];
+    while state.input_offset < state.input_len {
+        let len = (state.input_len - state.input_offset).min(block_size as u64) as usize;
+        source
+            .read_exact(&mut block[..len])
+            .map_err(|e| match e.kind() {
+                // The input shrank after it was opened.
+                std::io::ErrorKind::UnexpectedEof => CompressionError::TruncatedInput,
+                _ => e.into(),
+            })?;
+        let frame = encode_frame(&block[..len]);
+        partial.write_all(&frame)?;
+        // The frame must be on disk before the state that counts it.
+        partial.sync_data()?;
+        crc.update(&block[..len]);
+        state.blocks += 1;
+        state.input_offset += len as u64;
+        state.output_len += frame.len() as u64;
+        state.crc = crc.finish();
+        write_atomic(&state_path, &state.to_bytes(), true)?;
+        on_block(state.blocks, total_blocks)?;
+    }
+    partial.write_all(&crc.finish().to_le_bytes())?;
+    partial.sync_all()?;
+    drop(partial);
+    if !overwrite && output.symlink_metadata().is_ok() {
+        return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+    }
+    std::fs::rename(&partial_path, output)?;
+    std::fs::remove_file(&state_path)?;
+    Ok(ResumeSummary {
+        resumed_blocks,
+        total_blocks,
+        original_len: state.input_len,
+        compressed_len: state.output_len + CHECKSUM_LEN as u64,
+    })
+}
+
+/// Path of the state file [`compress_resumable`] keeps for `output`.
+pub fn state_path(output: &Path) -> PathBuf {
+    sidecar(output, "resume")
+}
+
+/// `path` with `.{suffix}` appended to its file name.
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// The progress of a resumable compression, as kept in the state file.
+///
+/// The file is the magic `WFHS` and a version byte, followed by the fields
+/// below as little-endian numbers, the modification time as seconds and
+/// nanoseconds, and a CRC-32 of everything before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    input_len: u64,
+    input_mtime: (u64, u32),
+    block_size: u64,
+    /// Blocks completed.
+    blocks: u64,
+    /// Bytes of input in the completed blocks.
+    input_offset: u64,
+    /// Bytes of output up to the end of the last completed frame.
+    output_len: u64,
+    /// CRC-32 of the input in the completed blocks.
+    crc: u32,
+}
+
+impl State {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = STATE_MAGIC.to_vec();
+        out.push(STATE_VERSION);
+        out.extend(self.input_len.to_le_bytes());
+        out.extend(self.input_mtime.0.to_le_bytes());
+        out.extend(self.input_mtime.1.to_le_bytes());
+        for field in [
+            self.block_size,
+            self.blocks,
+            self.input_offset,
+            self.output_len,
+        ] {
+            out.extend(field.to_le_bytes());
+        }
+        out.extend(self.crc.to_le_bytes());
+        out.extend(crc32(&out).to_le_bytes());
+        out
+    }
+
+    /// The state in the file at `path`, or `None` if there is none or it is
+    /// not a state this build wrote.
+    fn read(path: &Path) -> Result<Option<State>, CompressionError> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(State::from_bytes(&bytes).ok()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<State, CompressionError> {
+        let (body, footer) = bytes
+            .split_last_chunk::<4>()
+            .ok_or(CompressionError::TruncatedInput)?;
+        let (expected, actual) = (u32::from_le_bytes(*footer), crc32(body));
+        if expected != actual {
+            return Err(CompressionError::ChecksumMismatch { expected, actual });
+        }
+        let mut cursor = Cursor::new(body);
+        if cursor.array::<4>()? != STATE_MAGIC || cursor.u8()? != STATE_VERSION {
+            return Err(CompressionError::CorruptHeader);
+        }
+        let state = State {
+            input_len: cursor.u64_le()?,
+            input_mtime: (cursor.u64_le()?, cursor.u32_le()?),
+            block_size: cursor.u64_le()?,
+            blocks: cursor.u64_le()?,
+            input_offset: cursor.u64_le()?,
+            output_len: cursor.u64_le()?,
+            crc: cursor.u32_le()?,
+        };
+        if !cursor.rest().is_empty() {
+            return Err(CompressionError::TrailingData);
+        }
+        Ok(state)
+    }
+
+    /// Whether this state belongs to a run over the same input with the
+    /// same block size as `fresh`, and is consistent with it.
+    fn continues(&self, fresh: &State) -> bool {
+        self.input_len == fresh.input_len
+            && self.input_mtime == fresh.input_mtime
+            && self.block_size == fresh.block_size
+            && self.input_offset <= self.input_len
+            && self.input_offset == (self.blocks * self.block_size).min(self.input_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::compress_blocks;
+    use crate::decompress;
+    use crate::test_util::scratch;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len as u32).map(|i| (i * i % 251 / 7) as u8).collect()
+    }
+
+    #[test]
+    fn interrupted_compression_resumes() {
+        let dir = scratch("resume-interrupted");
+        let (input, output) = (dir.join("big.bin"), dir.join("big.bin.hfz"));
+        let data = sample(10_000);
+        std::fs::write(&input, &data).unwrap();
+
+        let stop = |done: u64, _| match done {
+            4 => Err(CompressionError::Io(std::io::ErrorKind::Interrupted)),
+            _ => Ok(()),
+        };
+        assert_eq!(
+            compress_resumable_with_progress(&input, &output, 1024, false, stop),
+            Err(CompressionError::Io(std::io::ErrorKind::Interrupted))
+        );
+        assert!(!output.exists());
+        // A frame written after the last recorded block is dropped.
+        let partial = sidecar(&output, "part");
+        File::options()
+            .append(true)
+            .open(&partial)
+            .unwrap()
+            .write_all(b"half a frame")
+            .unwrap();
+
+        let summary = compress_resumable(&input, &output, 1024, false).unwrap();
+        assert_eq!((summary.resumed_blocks, summary.total_blocks), (4, 10));
+        let encoded = std::fs::read(&output).unwrap();
+        assert_eq!(encoded, compress_blocks(&data, 1024));
+        assert_eq!(summary.compressed_len, encoded.len() as u64);
+        assert!(!partial.exists() && !state_path(&output).exists());
+        assert_eq!(
+            compress_resumable(&input, &output, 1024, false),
+            Err(CompressionError::Io(std::io::ErrorKind::AlreadyExists))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stale_state_starts_over() {
+        let dir = scratch("resume-stale");
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        std::fs::write(&input, sample(5000)).unwrap();
+        let stop = |done: u64, _| match done {
+            2 => Err(CompressionError::Io(std::io::ErrorKind::Interrupted)),
+            _ => Ok(()),
+        };
+        let _ = compress_resumable_with_progress(&input, &output, 1000, false, stop);
+        // Another block size does not continue the run.
+        let summary = compress_resumable(&input, &output, 2000, false).unwrap();
+        assert_eq!(summary.resumed_blocks, 0);
+        assert_eq!(
+            decompress(&std::fs::read(&output).unwrap()).unwrap(),
+            sample(5000)
+        );
+
+        // Nor does a damaged state file, or one for a changed input.
+        let _ = compress_resumable_with_progress(&input, &output, 1000, true, stop);
+        let mut state = std::fs::read(state_path(&output)).unwrap();
+        state[10] ^= 1;
+        std::fs::write(state_path(&output), &state).unwrap();
+        let summary = compress_resumable(&input, &output, 1000, true).unwrap();
+        assert_eq!(summary.resumed_blocks, 0);
+        let _ = compress_resumable_with_progress(&input, &output, 1000, true, stop);
+        std::fs::write(&input, b"").unwrap();
+        let summary = compress_resumable(&input, &output, 1000, true).unwrap();
+        assert_eq!(summary.resumed_blocks, 0);
+        assert_eq!((summary.total_blocks, summary.original_len), (0, 0));
+        assert_eq!(decompress(&std::fs::read(&output).unwrap()).unwrap(), b"");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::block::{
+    INDEX_ENTRY_LEN, block_error, check_block_size, encode_frame, multi_block_prefix, read_frame,
+};
+use crate::checksum::crc32;
+use crate::error::CompressionError;
+use crate::source::{Source, read_container};
+use crate::version::SupportedFeature;
+use crate::{CHECKSUM_LEN, Container, add_flags};
+
+/// Like [`compress_blocks`](crate::block::compress_blocks) but with a block
+/// index before the checksum, so [`SeekableDecoder`] can decode any range
+/// without the blocks before it. The index has one entry per block, the
+/// offset of its frame in the stream and the offset of its data in the
+/// original, both as little-endian `u64`, followed by the number of
+/// entries as a little-endian `u32`.
+///
+/// Panics if `block_size` is 0 or exceeds
+/// [`MAX_BLOCK_SIZE`](crate::block::MAX_BLOCK_SIZE), or if `data` splits
+/// into more than `u32::MAX` blocks.
+pub fn compress_seekable(data: &[u8], block_size: usize) -> Vec<u8> {
+    check_block_size(block_size);
+    let mut out = multi_block_prefix(data.len() as u64);
+    add_flags(&mut out, SupportedFeature::BlockIndex.bit());
+    let mut index = Vec::new();
+    for (i, block) in data.chunks(block_size).enumerate() {
+        index.extend((out.len() as u64).to_le_bytes());
+        index.extend(((i * block_size) as u64).to_le_bytes());
+        out.extend(encode_frame(block));
+    }
+    let entries = u32::try_from(index.len() / INDEX_ENTRY_LEN).expect("at most u32::MAX blocks");
+    out.extend(index);
+    out.extend(entries.to_le_bytes());
+    out.extend(crc32(data).to_le_bytes());
+    out
+}
+
+/// Random access to a stream from [`compress_seekable`]. Reads decode only
+/// the block holding the current position, which is kept until the
+/// position leaves it. Blocks with a checksum of their own are checked as
+/// they are decoded; the checksum of all of the data is not.
+pub struct SeekableDecoder<R: Read + Seek> {
+    inner: R,
+    container: Container,
+    /// Frame offset and data offset of every block.
+    index: Vec<(u64, u64)>,
+    pos: u64,
+    /// The number and the data of the last decoded block.
+    block: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> SeekableDecoder<R> {
+    /// Reads the container fields and the block index. Fails with
+    /// [`MissingBlockIndex`](CompressionError::MissingBlockIndex) if the
+    /// stream has none.
+    pub fn new(mut inner: R) -> Result<SeekableDecoder<R>, CompressionError> {
+        inner.seek(SeekFrom::Start(0))?;
+        let (container, _) = read_container(&mut inner)?;
+        if !container.has_blocks() || !container.has_block_index() {
+            return Err(CompressionError::MissingBlockIndex);
+        }
+        let footer_len = if container.has_checksum() {
+            CHECKSUM_LEN as u64
+        } else {
+            0
+        };
+        let end = inner.seek(SeekFrom::End(0))?;
+        let count_at = end
+            .checked_sub(footer_len + 4)
+            .filter(|&at| at >= container.header_len as u64)
+            .ok_or(CompressionError::TruncatedInput)?;
+        inner.seek(SeekFrom::Start(count_at))?;
+        let mut count = [0u8; 4];
+        inner.fill(&mut count)?;
+        let index_len = u32::from_le_bytes(count) as u64 * INDEX_ENTRY_LEN as u64;
+        let index_at = count_at
+            .checked_sub(index_len)
+            .filter(|&at| at >= container.header_len as u64)
+            .ok_or(CompressionError::CorruptHeader)?;
+        inner.seek(SeekFrom::Start(index_at))?;
+        let mut raw = vec![0u8; index_len as usize];
+        inner.fill(&mut raw)?;
+        let index: Vec<(u64, u64)> = raw
+            .chunks(INDEX_ENTRY_LEN)
+            .map(|entry| {
+                (
+                    u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                    u64::from_le_bytes(entry[8..].try_into().unwrap()),
+                )
+            })
+            .collect();
+
+        // Frames start right after the container and end at the index;
+        // blocks start at 0 and stay within the original length.
+        let frames_in_order = index.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1);
+        let valid = match (index.first(), index.last()) {
+            (Some(first), Some(last)) => {
+                first == &(container.header_len as u64, 0)
+                    && frames_in_order
+                    && last.0 < index_at
+                    && last.1 < container.original_len
+            }
+            _ => container.original_len == 0,
+        };
+        if !valid {
+            return Err(CompressionError::CorruptHeader);
+        }
+        Ok(SeekableDecoder {
+            inner,
+            container,
+            index,
+            pos: 0,
+            block: None,
+        })
+    }
+
+    /// Length of the original data.
+    pub fn len(&self) -> u64 {
+        self.container.original_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Decodes `len` bytes starting at `offset`, decoding only the blocks
+    /// that overlap them. Fails if the range goes past the end.
+    pub fn decode_range(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, CompressionError> {
+        if offset.saturating_add(len as u64) > self.len() {
+            return Err(CompressionError::TruncatedInput);
+        }
+        self.pos = offset;
+        let mut out = vec![0u8; len];
+        let mut filled = 0;
+        while filled < len {
+            filled += self.read_block(&mut out[filled..])?;
+        }
+        Ok(out)
+    }
+
+    /// Copies bytes from the block holding `pos` into `out`.
+    fn read_block(&mut self, out: &mut [u8]) -> Result<usize, CompressionError> {
+        if self.pos >= self.len() || out.is_empty() {
+            return Ok(0);
+        }
+        let number = self.index.partition_point(|&(_, start)| start <= self.pos) - 1;
+        if self.block.as_ref().is_none_or(|(n, _)| *n != number) {
+            self.block = Some((number, self.load(number)?));
+        }
+        let (_, block) = self.block.as_ref().unwrap();
+        let start = (self.pos - self.index[number].1) as usize;
+        let n = out.len().min(block.len() - start);
+        out[..n].copy_from_slice(&block[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn load(&mut self, number: usize) -> Result<Vec<u8>, CompressionError> {
+        let (frame_at, start) = self.index[number];
+        let end = self
+            .index
+            .get(number + 1)
+            .map_or(self.container.original_len, |&(_, next)| next);
+        self.inner.seek(SeekFrom::Start(frame_at))?;
+        let frame = read_frame(&mut self.inner, &self.container)?;
+        if frame.original_len != end - start {
+            return Err(CompressionError::CorruptHeader);
+        }
+        frame.decode().map_err(block_error(number as u64))
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableDecoder<R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        self.read_block(out)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableDecoder<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek to a negative position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompress;
+    use std::io::Cursor;
+
+    fn numbered_lines() -> Vec<u8> {
+        (0..2000)
+            .flat_map(|i| format!("line {:04}\n", i).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn seekable_stream_decompresses_whole() {
+        let data = numbered_lines();
+        let encoded = compress_seekable(&data, 1000);
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        let mut streamed = Vec::new();
+        crate::stream::decode_streaming(&encoded[..], &mut streamed).unwrap();
+        assert_eq!(streamed, data);
+    }
+
+    #[test]
+    fn ranges_across_blocks() {
+        let data = numbered_lines();
+        let mut decoder =
+            SeekableDecoder::new(Cursor::new(compress_seekable(&data, 1000))).unwrap();
+        assert_eq!(decoder.len(), data.len() as u64);
+        for (offset, len) in [(0, 10), (995, 10), (12_345, 3000), (19_990, 10)] {
+            assert_eq!(
+                decoder.decode_range(offset as u64, len).unwrap(),
+                &data[offset..offset + len]
+            );
+        }
+        assert_eq!(
+            decoder.decode_range(19_995, 10),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn read_and_seek() {
+        let data = numbered_lines();
+        let mut decoder = SeekableDecoder::new(Cursor::new(compress_seekable(&data, 512))).unwrap();
+        decoder.seek(SeekFrom::End(-10)).unwrap();
+        let mut tail = Vec::new();
+        decoder.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &data[data.len() - 10..]);
+        decoder.seek(SeekFrom::Start(100)).unwrap();
+        decoder.seek(SeekFrom::Current(-50)).unwrap();
+        let mut buf = [0u8; 20];
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[50..70]);
+        assert!(decoder.seek(SeekFrom::Current(-1000)).is_err());
+    }
+
+    #[test]
+    fn stream_without_index_is_rejected() {
+        let plain = crate::block::compress_blocks(b"no index here", 4);
+        assert_eq!(
+            SeekableDecoder::new(Cursor::new(plain)).err(),
+            Some(CompressionError::MissingBlockIndex)
+        );
+        let empty = compress_seekable(b"", 4);
+        assert!(SeekableDecoder::new(Cursor::new(empty)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::{compress, decode_with_limit};
+
+const MAX_SCHEMA_LEN: usize = 255;
+
+/// Prefixes the compressed `data` with `schema`, a short description (for
+/// example a JSON schema) of its content. The schema is stored as a length
+/// byte followed by its UTF-8 bytes, so it may be at most 255 bytes long.
+pub fn encode_self_describing(data: &[u8], schema: &str) -> Result<Vec<u8>, CompressionError> {
+    if schema.len() > MAX_SCHEMA_LEN {
+        return Err(CompressionError::SchemaTooLong { len: schema.len() });
+    }
+    let mut out = vec![schema.len() as u8];
+    out.extend(schema.as_bytes());
+    out.extend(compress(data));
+    Ok(out)
+}
+
+/// Returns the schema stored by `encode_self_describing` with the data.
+pub fn decode_self_describing(data: &[u8]) -> Result<(String, Vec<u8>), CompressionError> {
+    decode_self_describing_with_limit(data, u64::MAX)
+}
+
+/// Like [`decode_self_describing`] but fails with
+/// [`OutputLimitExceeded`](CompressionError::OutputLimitExceeded) instead of
+/// decoding more than `max_output_bytes`, as [`decode_with_limit`] does.
+pub fn decode_self_describing_with_limit(
+    data: &[u8],
+    max_output_bytes: u64,
+) -> Result<(String, Vec<u8>), CompressionError> {
+    let (&len, rest) = data.split_first().ok_or(CompressionError::TruncatedInput)?;
+    if rest.len() < len as usize {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let (schema, payload) = rest.split_at(len as usize);
+    let schema = String::from_utf8(schema.to_vec()).map_err(|_| CompressionError::CorruptHeader)?;
+    Ok((schema, decode_with_limit(payload, max_output_bytes)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_with_schema() {
+        let schema = r#"{"type":"string","contentMediaType":"text/plain"}"#;
+        let encoded = encode_self_describing(b"hello schema", schema).unwrap();
+        let (decoded_schema, data) = decode_self_describing(&encoded).unwrap();
+        assert_eq!(decoded_schema, schema);
+        assert_eq!(data, b"hello schema");
+    }
+
+    #[test]
+    fn limit_applies_to_the_data() {
+        let encoded = encode_self_describing(b"hello schema", "text").unwrap();
+        assert_eq!(
+            decode_self_describing_with_limit(&encoded, 12).unwrap().1,
+            b"hello schema"
+        );
+        assert_eq!(
+            decode_self_describing_with_limit(&encoded, 11),
+            Err(CompressionError::OutputLimitExceeded { limit: 11 })
+        );
+    }
+
+    #[test]
+    fn long_schema_is_rejected() {
+        let schema = "x".repeat(256);
+        assert_eq!(
+            encode_self_describing(b"data", &schema),
+            Err(CompressionError::SchemaTooLong { len: 256 })
+        );
+    }
+
+    #[test]
+    fn truncated_schema_is_rejected() {
+        assert_eq!(
+            decode_self_describing(&[10, b'{']),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+}
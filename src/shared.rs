@@ -0,0 +1,91 @@
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::{HuffmanTree, compress, decompress};
+
+/// Encodes related streams (for example the columns of a table) with one
+/// tree built from their combined byte frequencies. Every output carries
+/// the shared tree, so each decodes on its own.
+pub fn encode_interleaved(streams: &[&[u8]]) -> Result<Vec<Vec<u8>>, CompressionError> {
+    encode_shared(streams)
+}
+
+pub fn decode_interleaved(streams: &[&[u8]]) -> Result<Vec<Vec<u8>>, CompressionError> {
+    streams.iter().map(|s| decompress(s)).collect()
+}
+
+/// Encodes many small messages with one tree built from all of them, which
+/// suits short inputs that share a vocabulary better than a tree each.
+pub fn encode_batch(items: &[&[u8]]) -> Result<Vec<Vec<u8>>, CompressionError> {
+    encode_shared(items)
+}
+
+pub fn decode_batch(items: &[&[u8]]) -> Result<Vec<Vec<u8>>, CompressionError> {
+    items.iter().map(|item| decompress(item)).collect()
+}
+
+fn encode_shared(items: &[&[u8]]) -> Result<Vec<Vec<u8>>, CompressionError> {
+    let mut freq = [0u64; 256];
+    for item in items {
+        for &b in *item {
+            freq[b as usize] += 1;
+        }
+    }
+    let Some(tree) = HuffmanTree::from_frequencies(&freq) else {
+        return Ok(items.iter().map(|item| compress(item)).collect());
+    };
+    items
+        .iter()
+        .map(|item| Ok(tree.encode(item)?.to_bytes()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encoded;
+
+    const COLUMNS: [&[u8]; 3] = [b"2024-01-01", b"2024-01-02", b"2025-12-31"];
+
+    #[test]
+    fn round_trip_interleaved() {
+        let encoded = encode_interleaved(&COLUMNS).unwrap();
+        let refs: Vec<&[u8]> = encoded.iter().map(|e| e.as_slice()).collect();
+        assert_eq!(decode_interleaved(&refs).unwrap(), COLUMNS);
+    }
+
+    #[test]
+    fn streams_share_one_tree() {
+        let encoded = encode_interleaved(&COLUMNS).unwrap();
+        let first = Encoded::from_bytes(&encoded[0]).unwrap().tree;
+        assert!(
+            encoded
+                .iter()
+                .all(|e| Encoded::from_bytes(e).unwrap().tree == first)
+        );
+    }
+
+    #[test]
+    fn round_trip_stream_missing_shared_symbols() {
+        let streams: [&[u8]; 2] = [b"aaaa", b"abcabc"];
+        let encoded = encode_interleaved(&streams).unwrap();
+        let refs: Vec<&[u8]> = encoded.iter().map(|e| e.as_slice()).collect();
+        assert_eq!(decode_interleaved(&refs).unwrap(), streams);
+    }
+
+    #[test]
+    fn round_trip_batch() {
+        let items: [&[u8]; 4] = [br#"{"id":1}"#, br#"{"id":22}"#, b"", br#"{"id":333}"#];
+        let encoded = encode_batch(&items).unwrap();
+        let refs: Vec<&[u8]> = encoded.iter().map(|e| e.as_slice()).collect();
+        assert_eq!(decode_batch(&refs).unwrap(), items);
+    }
+
+    #[test]
+    fn round_trip_batch_of_one_symbol() {
+        let items: [&[u8]; 2] = [b"zz", b"zzzz"];
+        let encoded = encode_batch(&items).unwrap();
+        let refs: Vec<&[u8]> = encoded.iter().map(|e| e.as_slice()).collect();
+        assert_eq!(decode_batch(&refs).unwrap(), items);
+    }
+}
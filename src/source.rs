@@ -0,0 +1,127 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+use crate::error::CompressionError;
+#[cfg(feature = "std")]
+use crate::{CONTAINER_LEN, Container, parse_container};
+
+/// Where the block decoder reads frames from: any reader with the `std`
+/// feature, and byte slices without it.
+pub(crate) trait Source {
+    /// Fills `buf`, failing with
+    /// [`TruncatedInput`](CompressionError::TruncatedInput) if the input
+    /// ends first.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), CompressionError>;
+
+    /// Reads `len` bytes, or fewer if the input ends first.
+    fn take_up_to(&mut self, len: usize) -> Result<Vec<u8>, CompressionError>;
+
+    /// Whether the input has no bytes left.
+    fn is_exhausted(&mut self) -> Result<bool, CompressionError>;
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Source for R {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), CompressionError> {
+        self.read_exact(buf).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => CompressionError::TruncatedInput,
+            _ => e.into(),
+        })
+    }
+
+    fn take_up_to(&mut self, len: usize) -> Result<Vec<u8>, CompressionError> {
+        let mut bytes = Vec::new();
+        self.take(len as u64).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn is_exhausted(&mut self) -> Result<bool, CompressionError> {
+        Ok(self.read(&mut [0u8])? == 0)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Source for &[u8] {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), CompressionError> {
+        let (head, rest) = self
+            .split_at_checked(buf.len())
+            .ok_or(CompressionError::TruncatedInput)?;
+        buf.copy_from_slice(head);
+        *self = rest;
+        Ok(())
+    }
+
+    fn take_up_to(&mut self, len: usize) -> Result<Vec<u8>, CompressionError> {
+        let (head, rest) = self.split_at(len.min(self.len()));
+        *self = rest;
+        Ok(head.to_vec())
+    }
+
+    fn is_exhausted(&mut self) -> Result<bool, CompressionError> {
+        Ok(self.is_empty())
+    }
+}
+
+/// Reads the container fields and any extension area and returns the
+/// container and the bytes read.
+#[cfg(feature = "std")]
+pub(crate) fn read_container<S: Source>(
+    source: &mut S,
+) -> Result<(Container, Vec<u8>), CompressionError> {
+    let mut header = alloc::vec![0u8; CONTAINER_LEN];
+    source.fill(&mut header)?;
+    // With all of the container fields read, only an extension area can
+    // be missing.
+    if parse_container(&header).err() == Some(CompressionError::TruncatedInput) {
+        loop {
+            let mut kind = [0u8];
+            source.fill(&mut kind)?;
+            header.push(kind[0]);
+            if kind[0] == 0 {
+                break;
+            }
+            let mut len = [0u8; 2];
+            source.fill(&mut len)?;
+            header.extend(len);
+            let start = header.len();
+            header.resize(start + u16::from_le_bytes(len) as usize, 0);
+            source.fill(&mut header[start..])?;
+        }
+    }
+    let (container, _) = parse_container(&header)?;
+    Ok((container, header))
+}
+
+/// Reads a tree section in the canonical or the older layout and returns
+/// its bytes unparsed.
+pub(crate) fn read_tree_section<S: Source>(
+    source: &mut S,
+    canonical: bool,
+) -> Result<Vec<u8>, CompressionError> {
+    let mut count = [0u8; 2];
+    source.fill(&mut count)?;
+    let leaf_count = u16::from_le_bytes(count) as usize;
+    if leaf_count > 256 {
+        return Err(CompressionError::CorruptTree);
+    }
+    let mut section = count.to_vec();
+    section.resize(2 + leaf_count, 0);
+    source.fill(&mut section[2..])?;
+    if leaf_count >= 2 {
+        let tail_len = if canonical {
+            let mut longest = [0u8];
+            source.fill(&mut longest)?;
+            section.push(longest[0]);
+            (longest[0] as usize)
+                .checked_sub(1)
+                .ok_or(CompressionError::CorruptTree)?
+        } else {
+            leaf_count
+        };
+        let start = section.len();
+        section.resize(start + tail_len, 0);
+        source.fill(&mut section[start..])?;
+    }
+    Ok(section)
+}
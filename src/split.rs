@@ -0,0 +1,134 @@
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::{compress, decompress};
+
+const PART_HEADER_LEN: usize = 4;
+/// The part count is stored as a `u16`.
+const MAX_PARTS: usize = u16::MAX as usize;
+
+/// Splits compressed output into parts of at most `part_size` bytes.
+///
+/// Each part starts with its index and the total part count, both as
+/// little-endian `u16`, so `join` can reorder parts and detect missing ones.
+pub struct SplitArchive {
+    part_size: usize,
+}
+
+impl SplitArchive {
+    /// Panics if `part_size` leaves no room for data after the part header.
+    pub fn new(part_size: usize) -> SplitArchive {
+        assert!(
+            part_size > PART_HEADER_LEN,
+            "part size must exceed the {} byte part header",
+            PART_HEADER_LEN
+        );
+        SplitArchive { part_size }
+    }
+
+    /// Panics if the compressed data needs more than 65535 parts, which
+    /// the part header cannot count; a larger `part_size` avoids that.
+    pub fn split(&self, data: &[u8]) -> Vec<Vec<u8>> {
+        let compressed = compress(data);
+        let chunks: Vec<&[u8]> = compressed
+            .chunks(self.part_size - PART_HEADER_LEN)
+            .collect();
+        assert!(
+            chunks.len() <= MAX_PARTS,
+            "{} parts are needed, at most {} are allowed",
+            chunks.len(),
+            MAX_PARTS
+        );
+        let total = chunks.len() as u16;
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut part = Vec::with_capacity(PART_HEADER_LEN + chunk.len());
+                part.extend((i as u16).to_le_bytes());
+                part.extend(total.to_le_bytes());
+                part.extend(*chunk);
+                part
+            })
+            .collect()
+    }
+
+    /// Reassembles parts given in any order and decompresses the result.
+    pub fn join(parts: &[&[u8]]) -> Result<Vec<u8>, CompressionError> {
+        let mut ordered: Vec<Option<&[u8]>> = Vec::new();
+        for part in parts {
+            if part.len() < PART_HEADER_LEN {
+                return Err(CompressionError::TruncatedInput);
+            }
+            let index = u16::from_le_bytes([part[0], part[1]]) as usize;
+            let total = u16::from_le_bytes([part[2], part[3]]) as usize;
+            if ordered.is_empty() {
+                ordered.resize(total, None);
+            }
+            if total != ordered.len() || index >= total || ordered[index].is_some() {
+                return Err(CompressionError::CorruptHeader);
+            }
+            ordered[index] = Some(&part[PART_HEADER_LEN..]);
+        }
+        if ordered.is_empty() {
+            return Err(CompressionError::TruncatedInput);
+        }
+
+        let mut compressed = Vec::new();
+        for (index, part) in ordered.iter().enumerate() {
+            let part = part.ok_or(CompressionError::MissingPart {
+                index: index as u16,
+            })?;
+            compressed.extend(part);
+        }
+        decompress(&compressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"the quick brown fox jumps over the lazy dog, again and again";
+
+    #[test]
+    fn parts_respect_part_size() {
+        let parts = SplitArchive::new(16).split(DATA);
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.len() <= 16));
+    }
+
+    #[test]
+    fn join_accepts_parts_out_of_order() {
+        let parts = SplitArchive::new(16).split(DATA);
+        let mut refs: Vec<&[u8]> = parts.iter().map(|p| p.as_slice()).collect();
+        refs.reverse();
+        assert_eq!(SplitArchive::join(&refs).unwrap(), DATA);
+    }
+
+    #[test]
+    fn join_reports_missing_part() {
+        let parts = SplitArchive::new(16).split(DATA);
+        let refs: Vec<&[u8]> = parts.iter().skip(1).map(|p| p.as_slice()).collect();
+        assert_eq!(
+            SplitArchive::join(&refs),
+            Err(CompressionError::MissingPart { index: 0 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 65535 are allowed")]
+    fn part_count_must_fit_the_header() {
+        // Random bytes are stored, one byte to a part.
+        let mut state = 0x2545_f491u32;
+        let data: Vec<u8> = (0..MAX_PARTS)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        SplitArchive::new(PART_HEADER_LEN + 1).split(&data);
+    }
+}
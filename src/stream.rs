@@ -0,0 +1,714 @@
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+use crate::block::decode_frames;
+use crate::checksum::Crc32;
+use crate::error::CompressionError;
+use crate::flat::{FlatNode, FlatTree};
+use crate::source::{Source, read_container, read_tree_section};
+use crate::version::SupportedFeature;
+use crate::{
+    CHECKSUM_LEN, Container, HuffmanTree, MAX_LONE_SYMBOL_LEN, STORED_OVERHEAD, TreeSection,
+    bound_lone_symbol, container_prefix, parse_tree_section, serialize_tree_section,
+};
+
+const CHUNK_SIZE: usize = 4096;
+
+/// A writer that compresses everything written to it into the format of
+/// `compress`. The tree depends on the whole input, so the input is
+/// buffered and nothing reaches the inner writer before `finish`. Use
+/// `encode_streaming` instead when the source can be read twice.
+pub struct HuffmanWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> HuffmanWriter<W> {
+    pub fn new(inner: W) -> HuffmanWriter<W> {
+        HuffmanWriter {
+            inner,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Writes the header and the payload, padding its last byte, and
+    /// returns the inner writer.
+    pub fn finish(mut self) -> Result<W, CompressionError> {
+        let mut freq = [0u64; 256];
+        for &b in &self.buf {
+            freq[b as usize] += 1;
+        }
+        let mut encoder = StreamEncoder::new(&freq, &mut self.inner)?;
+        encoder.encode(&self.buf)?;
+        encoder.finish()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for HuffmanWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// Does nothing: no output exists before `finish`.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Encodes `reader` into `writer` in two passes, one counting byte
+/// frequencies and, after seeking back, one encoding, so neither the input
+/// nor the output is held in memory. Returns the number of bytes written.
+pub fn encode_streaming<R: Read + Seek, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+) -> Result<u64, CompressionError> {
+    let start = reader.stream_position()?;
+    let mut freq = [0u64; 256];
+    for_each_chunk(&mut reader, |chunk| {
+        for &b in chunk {
+            freq[b as usize] += 1;
+        }
+        Ok(())
+    })?;
+    reader.seek(SeekFrom::Start(start))?;
+    let mut encoder = StreamEncoder::new(&freq, writer)?;
+    for_each_chunk(&mut reader, |chunk| encoder.encode(chunk))?;
+    encoder.finish()
+}
+
+/// Decodes a stream produced by `compress` from `reader` into `writer`
+/// without holding the whole input or output in memory. Decoded bytes are
+/// written in chunks of 4 KiB, or a block at a time for multi-block
+/// streams. Returns the number of bytes written. Streams with an LZSS or
+/// RLE stage are rejected as unsupported; use `decompress` for those.
+pub fn decode_streaming<R: Read, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+) -> Result<u64, CompressionError> {
+    let (container, head) = read_container(&mut reader)?;
+    let stages = container.stage_flags();
+    if stages != 0 {
+        return Err(CompressionError::UnsupportedFeatures { flags: stages });
+    }
+    if container.has_blocks() {
+        return decode_frames(&container, &mut reader, true, |block, _| {
+            Ok(writer.write_all(block)?)
+        });
+    }
+    let mut reader = HuffmanReader::new(head.as_slice().chain(reader));
+    let mut written = 0;
+    for_each_chunk(&mut reader, |chunk| {
+        writer.write_all(chunk)?;
+        written += chunk.len() as u64;
+        Ok(())
+    })?;
+    Ok(written)
+}
+
+/// A reader that decompresses a stream produced by `compress` from the
+/// inner reader on demand. Streams written one after another are read as
+/// one, so the output of `cat a.hfz b.hfz` reads back as both inputs in
+/// order. Each header is read on the first call to `read` that needs it.
+/// Multi-block streams are not supported; use `decode_streaming` for those.
+pub struct HuffmanReader<R: Read> {
+    inner: BufReader<R>,
+    /// The stream being decoded, or `None` before its header is read.
+    header: Option<ReaderHeader>,
+    /// Whether a whole stream has been read, so the input may end.
+    finished_one: bool,
+    byte: u8,
+    bits_left: u8,
+    /// Whether the current stream has a payload byte in `byte`.
+    in_payload: bool,
+    /// Index of the current node in the flat tree.
+    node: usize,
+    produced: u64,
+    crc: Crc32,
+}
+
+struct ReaderHeader {
+    original_len: u64,
+    padding: u8,
+    has_checksum: bool,
+    tree: Option<FlatTree>,
+    leaves: Vec<u8>,
+    /// Whether the payload holds the bytes as is.
+    stored: bool,
+}
+
+impl<R: Read> HuffmanReader<R> {
+    pub fn new(inner: R) -> HuffmanReader<R> {
+        HuffmanReader {
+            inner: BufReader::with_capacity(CHUNK_SIZE, inner),
+            header: None,
+            finished_one: false,
+            byte: 0,
+            bits_left: 0,
+            in_payload: false,
+            node: 0,
+            produced: 0,
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Reads the header of the next stream. Returns `false` if the input
+    /// ends instead, which it may only do after a stream.
+    fn next_header(&mut self) -> Result<bool, CompressionError> {
+        if self.finished_one && self.at_end()? {
+            return Ok(false);
+        }
+        let (container, coded) = read_header(&mut self.inner)?;
+        let stored = coded.is_none();
+        let (padding, section) = coded.unwrap_or((
+            0,
+            TreeSection {
+                leaves: Vec::new(),
+                tree: None,
+                len: 0,
+            },
+        ));
+        self.header = Some(ReaderHeader {
+            original_len: container.original_len,
+            padding,
+            has_checksum: container.has_checksum(),
+            tree: section.tree.as_ref().map(FlatTree::from_huffman_tree),
+            leaves: section.leaves,
+            stored,
+        });
+        self.bits_left = 0;
+        self.in_payload = false;
+        self.node = 0;
+        self.produced = 0;
+        self.crc = Crc32::new();
+        Ok(true)
+    }
+
+    /// Whether the input has no bytes left, without consuming any.
+    fn at_end(&mut self) -> Result<bool, CompressionError> {
+        loop {
+            match self.inner.fill_buf() {
+                Ok(buf) => return Ok(buf.is_empty()),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Decodes up to `out.len()` bytes of the current stream, and at least
+    /// one unless it has produced all of its bytes.
+    fn decode(&mut self, out: &mut [u8]) -> Result<usize, CompressionError> {
+        let header = self.header.as_ref().unwrap();
+        let remaining = header.original_len - self.produced;
+        let n = out
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        if header.stored {
+            self.inner.fill(&mut out[..n])?;
+            return Ok(n);
+        }
+        let Some(tree) = &header.tree else {
+            // A lone symbol has an empty code and repeats up to the original
+            // length without any payload.
+            return match header.leaves[..] {
+                [symbol] => {
+                    out[..n].fill(symbol);
+                    Ok(n)
+                }
+                // Without a tree there is no payload to read.
+                _ => Err(CompressionError::CorruptData),
+            };
+        };
+        let mut decoded = 0;
+        while decoded < n {
+            if self.bits_left == 0 {
+                let mut byte = [0u8];
+                self.inner.fill(&mut byte)?;
+                self.byte = byte[0];
+                self.bits_left = 8;
+                self.in_payload = true;
+            }
+            let bit = self.byte & 0x80 != 0;
+            self.byte <<= 1;
+            self.bits_left -= 1;
+
+            let FlatNode::Internal { left, right } = tree.nodes[self.node] else {
+                unreachable!("the current node is always internal");
+            };
+            self.node = if bit { right } else { left };
+            if let FlatNode::Leaf(b) = tree.nodes[self.node] {
+                out[decoded] = b;
+                decoded += 1;
+                self.node = 0;
+            }
+        }
+        Ok(decoded)
+    }
+
+    /// Checks that the payload of the current stream ends where its padding
+    /// starts, and its checksum if it has one, once all of its bytes are
+    /// out.
+    fn finish_stream(&mut self) -> Result<(), CompressionError> {
+        let header = self.header.take().unwrap();
+        if !self.in_payload && header.padding != 0 {
+            return Err(CompressionError::InvalidPadding);
+        }
+        if self.bits_left < header.padding {
+            // The last code ran into the padding.
+            return Err(CompressionError::DecodingFailed);
+        }
+        if self.bits_left > header.padding {
+            return Err(CompressionError::CorruptData);
+        }
+        if header.has_checksum {
+            let mut footer = [0u8; CHECKSUM_LEN];
+            self.inner.fill(&mut footer)?;
+            let expected = u32::from_le_bytes(footer);
+            let actual = self.crc.finish();
+            if actual != expected {
+                return Err(CompressionError::ChecksumMismatch { expected, actual });
+            }
+        }
+        self.finished_one = true;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for HuffmanReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if self.header.is_none() && !self.next_header().map_err(to_io_error)? {
+                return Ok(0);
+            }
+            if out.is_empty() {
+                return Ok(0);
+            }
+            let header = self.header.as_ref().unwrap();
+            if self.produced == header.original_len {
+                self.finish_stream().map_err(to_io_error)?;
+                continue;
+            }
+            let has_checksum = header.has_checksum;
+            let n = self.decode(out).map_err(to_io_error)?;
+            if has_checksum {
+                self.crc.update(&out[..n]);
+            }
+            self.produced += n as u64;
+            return Ok(n);
+        }
+    }
+}
+
+fn to_io_error(e: CompressionError) -> std::io::Error {
+    match e {
+        CompressionError::Io(kind) => kind.into(),
+        e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// Reads the container fields, the padding byte and the tree section and
+/// returns the container and the padding and tree. A stored stream has
+/// neither padding byte nor tree.
+fn read_header<R: Read>(
+    reader: &mut R,
+) -> Result<(Container, Option<(u8, TreeSection)>), CompressionError> {
+    let (container, _) = read_container(reader)?;
+    container.check_whole_stream()?;
+    if container.has_stored() {
+        return Ok((container, None));
+    }
+    let mut padding = [0u8];
+    reader.fill(&mut padding)?;
+    if padding[0] >= 8 {
+        return Err(CompressionError::InvalidPadding);
+    }
+    let canonical = container.has_canonical_tree();
+    let section = read_tree_section(reader, canonical)?;
+    let section = parse_tree_section(&section, canonical)?;
+    // Nothing but the header backs the length of a lone symbol.
+    if section.tree.is_none() && container.original_len > MAX_LONE_SYMBOL_LEN {
+        return Err(CompressionError::CorruptData);
+    }
+    Ok((container, Some((padding[0], section))))
+}
+
+/// Calls `f` with successive chunks of `reader` until it is exhausted.
+fn for_each_chunk<R: Read>(
+    reader: &mut R,
+    mut f: impl FnMut(&[u8]) -> Result<(), CompressionError>,
+) -> Result<(), CompressionError> {
+    let mut buf = [0u8; CHUNK_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(from_io_error(e)),
+        };
+        f(&buf[..n])?;
+    }
+}
+
+/// Undoes `to_io_error`, so errors raised by `HuffmanReader` come back out
+/// as the `CompressionError` they started as.
+fn from_io_error(e: std::io::Error) -> CompressionError {
+    if !e
+        .get_ref()
+        .is_some_and(|inner| inner.is::<CompressionError>())
+    {
+        return e.into();
+    }
+    *e.into_inner().unwrap().downcast().unwrap()
+}
+
+/// The code and code length of every byte, as from `to_lookup_array`.
+type CodeTable = Box<[(Option<Vec<bool>>, u8); 256]>;
+
+/// Writes the payload bit by bit once the header is out.
+struct StreamEncoder<'w, W: Write> {
+    /// The code of every byte, or `None` when the data is stored as is.
+    codes: Option<CodeTable>,
+    sink: ChunkedSink<'w, W>,
+    byte: u8,
+    filled: u32,
+    crc: Crc32,
+}
+
+impl<'w, W: Write> StreamEncoder<'w, W> {
+    /// Writes the header for data with the byte frequencies `freq`, or the
+    /// container of a stored stream if coding would expand the data, as
+    /// `compress` does.
+    fn new(freq: &[u64; 256], writer: &'w mut W) -> Result<Self, CompressionError> {
+        let (section, codes) = match HuffmanTree::from_frequencies(&bound_lone_symbol(freq)) {
+            Some(tree) => (tree.serialize(), tree.to_lookup_array()),
+            None => {
+                // A lone symbol gets an empty code.
+                let leaves: Vec<u8> = (0..=255u8).filter(|&b| freq[b as usize] > 0).collect();
+                let mut codes: CodeTable = Box::new(std::array::from_fn(|_| (None, 0)));
+                for &b in &leaves {
+                    codes[b as usize] = (Some(Vec::new()), 0);
+                }
+                (serialize_tree_section(&leaves, &[]), codes)
+            }
+        };
+        let bits: u64 = freq
+            .iter()
+            .zip(codes.iter())
+            .map(|(&f, (_, len))| f * *len as u64)
+            .sum();
+        let mut sink = ChunkedSink {
+            writer,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            written: 0,
+        };
+        let original_len: u64 = freq.iter().sum();
+        let flags = SupportedFeature::Checksum.bit() | SupportedFeature::CanonicalTree.bit();
+        let mut header = container_prefix(original_len, flags);
+        header.push(((8 - bits % 8) % 8) as u8);
+        header.extend(section);
+        let coded_len = (header.len() + CHECKSUM_LEN) as u64 + bits.div_ceil(8);
+        let codes = if coded_len > original_len + STORED_OVERHEAD as u64 {
+            let flags = SupportedFeature::Stored.bit() | SupportedFeature::Checksum.bit();
+            header = container_prefix(original_len, flags);
+            None
+        } else {
+            Some(codes)
+        };
+        for b in header {
+            sink.push(b)?;
+        }
+        Ok(StreamEncoder {
+            codes,
+            sink,
+            byte: 0,
+            filled: 0,
+            crc: Crc32::new(),
+        })
+    }
+
+    fn encode(&mut self, data: &[u8]) -> Result<(), CompressionError> {
+        self.crc.update(data);
+        let Some(codes) = &self.codes else {
+            for &b in data {
+                self.sink.push(b)?;
+            }
+            return Ok(());
+        };
+        for &b in data {
+            let code = codes[b as usize]
+                .0
+                .as_ref()
+                .ok_or(CompressionError::SymbolNotInTree { symbol: b })?;
+            for &bit in code {
+                self.byte = (self.byte << 1) | bit as u8;
+                self.filled += 1;
+                if self.filled == 8 {
+                    self.sink.push(self.byte)?;
+                    self.byte = 0;
+                    self.filled = 0;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pads and writes the last byte, then the checksum. Returns the number
+    /// of bytes written.
+    fn finish(mut self) -> Result<u64, CompressionError> {
+        if self.filled > 0 {
+            self.sink.push(self.byte << (8 - self.filled))?;
+        }
+        for b in self.crc.finish().to_le_bytes() {
+            self.sink.push(b)?;
+        }
+        self.sink.flush()?;
+        Ok(self.sink.written)
+    }
+}
+
+struct ChunkedSink<'w, W: Write> {
+    writer: &'w mut W,
+    buf: Vec<u8>,
+    written: u64,
+}
+
+impl<W: Write> ChunkedSink<'_, W> {
+    fn push(&mut self, b: u8) -> Result<(), CompressionError> {
+        self.buf.push(b);
+        if self.buf.len() == CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), CompressionError> {
+        self.writer.write_all(&self.buf)?;
+        self.written += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::compress_blocks;
+    use crate::{CONTAINER_LEN, compress, container_prefix};
+    use std::io::Cursor;
+
+    #[test]
+    fn streaming_decode_through_cursor() {
+        let data: Vec<u8> = (0..20_000u32)
+            .map(|i| b"streaming"[i as usize % 9])
+            .collect();
+        let encoded = compress(&data);
+        let mut out = Cursor::new(Vec::new());
+        let written = decode_streaming(Cursor::new(&encoded), &mut out).unwrap();
+        assert_eq!(written, data.len() as u64);
+        assert_eq!(out.into_inner(), data);
+    }
+
+    #[test]
+    fn streaming_decode_skips_extensions() {
+        use crate::extension::{Extension, add_extensions};
+        let data = b"tagged for later readers".repeat(500);
+        let tag = [Extension::new(0x42, "optional")];
+        for encoded in [compress(&data), compress_blocks(&data, 4096)] {
+            let tagged = add_extensions(&encoded, &tag).unwrap();
+            let mut out = Vec::new();
+            decode_streaming(Cursor::new(&tagged), &mut out).unwrap();
+            assert_eq!(out, data);
+        }
+        let tagged = add_extensions(&compress(&data), &tag).unwrap();
+        let mut out = Vec::new();
+        HuffmanReader::new(Cursor::new(tagged))
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn streams_beyond_4_gib_decode_in_constant_memory() {
+        // A synthetic stream of 5 GiB and 3 bytes, whose length does not fit
+        // in a `u32`: stored bytes read from a generated source, without a
+        // checksum, which would dominate the run time.
+        let len = (5 << 30) + 3;
+        let stored = Cursor::new(container_prefix(len, SupportedFeature::Stored.bit()))
+            .chain(std::io::repeat(b'z').take(len));
+        assert_eq!(decode_streaming(stored, &mut std::io::sink()).unwrap(), len);
+
+        // A lone symbol claiming as much has nothing to back it.
+        let lone = [
+            container_prefix(len, SupportedFeature::CanonicalTree.bit()),
+            vec![0, 1, 0, b'x'],
+        ]
+        .concat();
+        assert_eq!(
+            decode_streaming(Cursor::new(&lone), &mut std::io::sink()),
+            Err(CompressionError::CorruptData)
+        );
+        let mut out = Vec::new();
+        let err = HuffmanReader::new(Cursor::new(&lone)).read_to_end(&mut out);
+        assert_eq!(
+            from_io_error(err.unwrap_err()),
+            CompressionError::CorruptData
+        );
+    }
+
+    #[test]
+    fn writer_output_matches_compress() {
+        let data = b"written in pieces, encoded at the end";
+        let mut writer = HuffmanWriter::new(Vec::new());
+        for piece in data.chunks(5) {
+            writer.write_all(piece).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), compress(data));
+    }
+
+    #[test]
+    fn two_pass_encode_round_trips() {
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i * i % 251) as u8).collect();
+        let mut encoded = Vec::new();
+        let written = encode_streaming(Cursor::new(&data), &mut encoded).unwrap();
+        assert_eq!(written, encoded.len() as u64);
+        assert_eq!(encoded, compress(&data));
+        let mut out = Vec::new();
+        decode_streaming(Cursor::new(&encoded), &mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn streaming_encode_degenerate_inputs() {
+        for data in [&b""[..], b"zzzz"] {
+            let mut encoded = Vec::new();
+            encode_streaming(Cursor::new(data), &mut encoded).unwrap();
+            assert_eq!(encoded, compress(data));
+        }
+    }
+
+    #[test]
+    fn reader_works_with_io_copy() {
+        let data: Vec<u8> = (0..30_000u32).map(|i| (i % 13 * 7) as u8).collect();
+        let mut reader = HuffmanReader::new(Cursor::new(compress(&data)));
+        let mut out = Vec::new();
+        std::io::copy(&mut reader, &mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn reader_fills_small_buffers() {
+        for data in [&b""[..], b"q", b"qqqq", b"small reads, one byte at a time"] {
+            let mut reader = HuffmanReader::new(Cursor::new(compress(data)));
+            let mut out = Vec::new();
+            let mut buf = [0u8; 1];
+            while reader.read(&mut buf).unwrap() == 1 {
+                out.push(buf[0]);
+            }
+            assert_eq!(out, data);
+        }
+    }
+
+    #[test]
+    fn reader_reads_concatenated_streams() {
+        let stored: Vec<u8> = (0..=255).collect();
+        let inputs = [
+            &b"first stream, "[..],
+            b"",
+            b"qqqq",
+            &stored,
+            b"last stream",
+        ];
+        let joined: Vec<u8> = inputs.iter().flat_map(|data| compress(data)).collect();
+        let expected = inputs.concat();
+        let mut out = Vec::new();
+        HuffmanReader::new(Cursor::new(&joined))
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, expected);
+
+        let mut reader = HuffmanReader::new(Cursor::new(&joined));
+        let mut out = Vec::new();
+        let mut buf = [0u8; 1];
+        while reader.read(&mut buf).unwrap() == 1 {
+            out.push(buf[0]);
+        }
+        assert_eq!(out, expected);
+
+        let mut streamed = Vec::new();
+        decode_streaming(Cursor::new(&joined), &mut streamed).unwrap();
+        assert_eq!(streamed, expected);
+
+        let trailing = [&joined[..], b"tail"].concat();
+        let mut reader = HuffmanReader::new(Cursor::new(trailing));
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reader_rejects_partial_code() {
+        let encoded = [
+            container_prefix(1, 0),
+            vec![7, 3, 0, b'a', b'b', b'c', 1, 2, 2, 0x80],
+        ]
+        .concat();
+        let mut reader = HuffmanReader::new(Cursor::new(encoded));
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn streaming_decode_degenerate_inputs() {
+        for data in [&b""[..], b"aaaaaaaaa"] {
+            let mut out = Vec::new();
+            decode_streaming(Cursor::new(compress(data)), &mut out).unwrap();
+            assert_eq!(out, data);
+        }
+    }
+
+    #[test]
+    fn streaming_decode_rejects_truncated_tree() {
+        let mut out = Vec::new();
+        assert_eq!(
+            decode_streaming(
+                Cursor::new([container_prefix(4, 0), vec![0, 4, 0, b'a']].concat()),
+                &mut out
+            ),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+
+    #[test]
+    fn streaming_decode_rejects_partial_code() {
+        // Tree a = 0, b = 10, c = 11 with a payload of "1" and seven padding bits.
+        let mut out = Vec::new();
+        assert_eq!(
+            decode_streaming(
+                Cursor::new(
+                    [
+                        container_prefix(1, 0),
+                        vec![7, 3, 0, b'a', b'b', b'c', 1, 2, 2, 0x80]
+                    ]
+                    .concat()
+                ),
+                &mut out
+            ),
+            Err(CompressionError::DecodingFailed)
+        );
+    }
+
+    #[test]
+    fn stored_streams_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut encoded = Vec::new();
+        encode_streaming(Cursor::new(&data), &mut encoded).unwrap();
+        assert_eq!(encoded, compress(&data));
+        let mut out = Vec::new();
+        decode_streaming(Cursor::new(&encoded), &mut out).unwrap();
+        assert_eq!(out, data);
+        encoded[CONTAINER_LEN] ^= 1;
+        assert!(matches!(
+            decode_streaming(Cursor::new(&encoded), &mut Vec::new()),
+            Err(CompressionError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::bits::{BitReader, BitWriter};
+use crate::error::CompressionError;
+use crate::gzip::huffman_lengths;
+
+/// Longest code a [`SymbolCode`] assigns.
+pub const MAX_SYMBOL_CODE_LEN: u8 = 32;
+
+/// The unit a [`SymbolCode`] codes: a byte, or a 16-bit word for UTF-16
+/// text and 16-bit samples.
+pub trait Symbol: Copy {
+    /// Number of distinct values.
+    const COUNT: usize;
+    /// Bytes a value takes up in a code table.
+    const BYTES: usize;
+
+    fn index(self) -> usize;
+    fn from_index(index: usize) -> Self;
+}
+
+impl Symbol for u8 {
+    const COUNT: usize = 1 << 8;
+    const BYTES: usize = 1;
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn from_index(index: usize) -> u8 {
+        index as u8
+    }
+}
+
+impl Symbol for u16 {
+    const COUNT: usize = 1 << 16;
+    const BYTES: usize = 2;
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn from_index(index: usize) -> u16 {
+        index as u16
+    }
+}
+
+/// A canonical Huffman code over the values of `S`, for alphabets too
+/// large for the 256-leaf [`HuffmanTree`](crate::HuffmanTree).
+///
+/// Codes are assigned in order of length and then of value, so the code
+/// lengths alone describe the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolCode<S: Symbol> {
+    /// Code length of every value, 0 for values without a code.
+    lengths: Vec<u8>,
+    /// Code of every value, in its lowest `lengths` bits.
+    codes: Vec<u32>,
+    /// Values with a code, shortest code first.
+    sorted: Vec<u32>,
+    /// Number of codes of every length.
+    counts: [u32; MAX_SYMBOL_CODE_LEN as usize + 1],
+    symbol: PhantomData<S>,
+}
+
+impl<S: Symbol> SymbolCode<S> {
+    /// The code for `symbols`, with frequencies halved until no code is
+    /// longer than [`MAX_SYMBOL_CODE_LEN`]. `None` if `symbols` is empty.
+    pub fn from_symbols(symbols: &[S]) -> Option<SymbolCode<S>> {
+        let mut freq = vec![0u64; S::COUNT];
+        for &symbol in symbols {
+            freq[symbol.index()] += 1;
+        }
+        let mut lengths = huffman_lengths(&freq);
+        while lengths.iter().any(|&len| len > MAX_SYMBOL_CODE_LEN) {
+            for f in freq.iter_mut().filter(|f| **f > 0) {
+                *f = (*f / 2).max(1);
+            }
+            lengths = huffman_lengths(&freq);
+        }
+        // A lone value still needs a one-bit code.
+        let present: Vec<usize> = (0..S::COUNT).filter(|&index| freq[index] > 0).collect();
+        if let [index] = present[..] {
+            lengths[index] = 1;
+        }
+        SymbolCode::from_lengths(lengths).ok()
+    }
+
+    /// The code with the given length for every value. Fails if no value
+    /// has a code, a code is too long or the lengths overfill the code
+    /// space.
+    pub fn from_lengths(lengths: Vec<u8>) -> Result<SymbolCode<S>, CompressionError> {
+        if lengths.len() != S::COUNT {
+            return Err(CompressionError::CorruptTree);
+        }
+        let mut counts = [0u32; MAX_SYMBOL_CODE_LEN as usize + 1];
+        for &len in &lengths {
+            if len > MAX_SYMBOL_CODE_LEN {
+                return Err(CompressionError::CorruptTree);
+            }
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let kraft: u64 = (1..=MAX_SYMBOL_CODE_LEN)
+            .map(|len| (counts[len as usize] as u64) << (MAX_SYMBOL_CODE_LEN - len))
+            .sum();
+        if kraft == 0 || kraft > 1 << MAX_SYMBOL_CODE_LEN {
+            return Err(CompressionError::CorruptTree);
+        }
+
+        let mut sorted: Vec<u32> = (0..S::COUNT as u32)
+            .filter(|&index| lengths[index as usize] > 0)
+            .collect();
+        sorted.sort_by_key(|&index| lengths[index as usize]);
+        let mut codes = vec![0u32; S::COUNT];
+        let mut code = 0u64;
+        let mut len = 0;
+        for &index in &sorted {
+            let symbol_len = lengths[index as usize];
+            code <<= symbol_len - len;
+            len = symbol_len;
+            codes[index as usize] = code as u32;
+            code += 1;
+        }
+        Ok(SymbolCode {
+            lengths,
+            codes,
+            sorted,
+            counts,
+            symbol: PhantomData,
+        })
+    }
+
+    /// Code length of every value, 0 for values without a code.
+    pub fn lengths(&self) -> &[u8] {
+        &self.lengths
+    }
+
+    /// Appends the codes of `symbols` to `bits`. Fails with
+    /// [`CompressionError::CorruptData`] on a value without a code.
+    pub fn encode(&self, symbols: &[S], bits: &mut BitWriter) -> Result<(), CompressionError> {
+        for &symbol in symbols {
+            let len = self.lengths[symbol.index()];
+            if len == 0 {
+                return Err(CompressionError::CorruptData);
+            }
+            bits.push_value(self.codes[symbol.index()] as u64, len as u32);
+        }
+        Ok(())
+    }
+
+    /// Reads `count` values from `bits`.
+    pub fn decode(&self, bits: &mut BitReader, count: usize) -> Result<Vec<S>, CompressionError> {
+        // Every code is at least one bit long.
+        if count as u64 > bits.remaining() {
+            return Err(CompressionError::TruncatedInput);
+        }
+        (0..count).map(|_| self.decode_symbol(bits)).collect()
+    }
+
+    fn decode_symbol(&self, bits: &mut BitReader) -> Result<S, CompressionError> {
+        // `first` is the first code of the current length and `offset` the
+        // position of its value in `sorted`.
+        let (mut code, mut first, mut offset) = (0u64, 0u64, 0usize);
+        for &count in &self.counts[1..] {
+            code |= bits.read_bit().ok_or(CompressionError::TruncatedInput)? as u64;
+            let count = count as u64;
+            if code < first + count {
+                let index = self.sorted[offset + (code - first) as usize];
+                return Ok(S::from_index(index as usize));
+            }
+            offset += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(CompressionError::CorruptData)
+    }
+
+    /// The lengths as a table: the number of values with a code as a
+    /// little-endian `u32`, then every such value, little-endian in
+    /// [`Symbol::BYTES`] bytes, followed by its code length.
+    pub fn table_bytes(&self) -> Vec<u8> {
+        let mut present: Vec<usize> = self.sorted.iter().map(|&index| index as usize).collect();
+        present.sort_unstable();
+        let mut out = (present.len() as u32).to_le_bytes().to_vec();
+        for index in present {
+            out.extend(&index.to_le_bytes()[..S::BYTES]);
+            out.push(self.lengths[index]);
+        }
+        out
+    }
+
+    /// The code of a table written by `table_bytes` at the start of
+    /// `data`, and the length of the table.
+    pub fn read_table(data: &[u8]) -> Result<(SymbolCode<S>, usize), CompressionError> {
+        let count = data.get(..4).ok_or(CompressionError::TruncatedInput)?;
+        let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+        if count > S::COUNT {
+            return Err(CompressionError::CorruptTree);
+        }
+        let entry_len = S::BYTES + 1;
+        let table_len = 4 + count * entry_len;
+        let entries = data
+            .get(4..table_len)
+            .ok_or(CompressionError::TruncatedInput)?;
+        let mut lengths = vec![0u8; S::COUNT];
+        for entry in entries.chunks(entry_len) {
+            let mut index = [0u8; 8];
+            index[..S::BYTES].copy_from_slice(&entry[..S::BYTES]);
+            let index = u64::from_le_bytes(index) as usize;
+            if index >= S::COUNT || lengths[index] != 0 || entry[S::BYTES] == 0 {
+                return Err(CompressionError::CorruptTree);
+            }
+            lengths[index] = entry[S::BYTES];
+        }
+        Ok((SymbolCode::from_lengths(lengths)?, table_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<S: Symbol + PartialEq + core::fmt::Debug>(symbols: &[S]) -> u64 {
+        let code = SymbolCode::from_symbols(symbols).unwrap();
+        let mut bits = BitWriter::new();
+        code.encode(symbols, &mut bits).unwrap();
+        let len = bits.bit_len();
+        let mut table = code.table_bytes();
+        let table_len = table.len();
+        table.extend(bits.into_bytes());
+        let (read, used) = SymbolCode::<S>::read_table(&table).unwrap();
+        assert_eq!((&read, used), (&code, table_len));
+        let mut reader = BitReader::with_len(&table[used..], len);
+        assert_eq!(read.decode(&mut reader, symbols.len()).unwrap(), symbols);
+        len
+    }
+
+    #[test]
+    fn bytes_and_words_round_trip() {
+        assert_eq!(round_trip(&b"abracadabra"[..]), 23);
+        let words: Vec<u16> = (0..3000u32).map(|i| (i * i % 997 * 61) as u16).collect();
+        round_trip(&words);
+        assert_eq!(round_trip(&[0xfeffu16; 10]), 10);
+        assert!(SymbolCode::<u16>::from_symbols(&[]).is_none());
+    }
+
+    #[test]
+    fn overfull_lengths_are_rejected() {
+        let mut lengths = vec![0u8; 256];
+        lengths[..3].fill(1);
+        assert_eq!(
+            SymbolCode::<u8>::from_lengths(lengths).err(),
+            Some(CompressionError::CorruptTree)
+        );
+        // The table names value 7 twice.
+        let table = [2, 0, 0, 0, 7, 1, 7, 1];
+        assert_eq!(
+            SymbolCode::<u8>::read_table(&table).err(),
+            Some(CompressionError::CorruptTree)
+        );
+    }
+}
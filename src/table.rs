@@ -0,0 +1,178 @@
+use alloc::vec::Vec;
+
+use crate::HuffmanTree;
+use crate::error::CompressionError;
+use crate::flat::{FlatNode, FlatTree};
+
+/// Number of payload bits a table lookup resolves at once.
+pub const TABLE_BITS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    /// A code of at most `TABLE_BITS` bits and its length.
+    Symbol { symbol: u8, len: u8 },
+    /// A longer code, continued from this node of the flat tree after
+    /// `TABLE_BITS` bits.
+    Long { node: usize },
+}
+
+/// Decodes a payload with a lookup table indexed by the next `TABLE_BITS`
+/// bits, which resolves every code of up to that length in one step. Only
+/// longer codes walk the rest of the tree bit by bit.
+pub struct DecodeTable {
+    entries: Vec<Entry>,
+    tree: FlatTree,
+}
+
+impl DecodeTable {
+    pub fn new(tree: &HuffmanTree) -> DecodeTable {
+        let tree = FlatTree::from_huffman_tree(tree);
+        let entries = (0..1usize << TABLE_BITS)
+            .map(|index| {
+                let mut node = 0;
+                for len in 1..=TABLE_BITS {
+                    let bit = (index >> (TABLE_BITS - len)) & 1 == 1;
+                    node = tree.child(node, bit);
+                    if let FlatNode::Leaf(symbol) = tree.nodes[node] {
+                        return Entry::Symbol {
+                            symbol,
+                            len: len as u8,
+                        };
+                    }
+                }
+                Entry::Long { node }
+            })
+            .collect();
+        DecodeTable { entries, tree }
+    }
+
+    /// Decodes the first `total_bits` bits of `bytes`, most significant bit
+    /// first. Fails if they end in the middle of a code.
+    pub fn decode(&self, bytes: &[u8], total_bits: u64) -> Result<Vec<u8>, CompressionError> {
+        let mut out = Vec::new();
+        self.decode_each(bytes, total_bits, |symbol| {
+            out.push(symbol);
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    /// Passes every decoded symbol to `emit`, stopping at its first error.
+    pub(crate) fn decode_each(
+        &self,
+        bytes: &[u8],
+        total_bits: u64,
+        emit: impl FnMut(u8) -> Result<(), CompressionError>,
+    ) -> Result<(), CompressionError> {
+        self.decode_up_to(bytes, total_bits, u64::MAX, emit)
+            .map(|_| ())
+    }
+
+    /// Like `decode_each` but stops after `count` symbols and returns the
+    /// number of bits they took up.
+    pub(crate) fn decode_up_to(
+        &self,
+        bytes: &[u8],
+        total_bits: u64,
+        mut count: u64,
+        mut emit: impl FnMut(u8) -> Result<(), CompressionError>,
+    ) -> Result<u64, CompressionError> {
+        let mut pos = 0;
+        while pos < total_bits && count > 0 {
+            count -= 1;
+            let remaining = total_bits - pos;
+            match self.entries[peek_bits(bytes, pos)] {
+                Entry::Symbol { symbol, len } if len as u64 <= remaining => {
+                    emit(symbol)?;
+                    pos += len as u64;
+                }
+                Entry::Long { mut node } if remaining > TABLE_BITS as u64 => {
+                    pos += TABLE_BITS as u64;
+                    loop {
+                        if pos == total_bits {
+                            return Err(CompressionError::DecodingFailed);
+                        }
+                        let bit = (bytes[(pos / 8) as usize] >> (7 - pos % 8)) & 1 == 1;
+                        pos += 1;
+                        node = self.tree.child(node, bit);
+                        if let FlatNode::Leaf(symbol) = self.tree.nodes[node] {
+                            emit(symbol)?;
+                            break;
+                        }
+                    }
+                }
+                // The code continues past the end of the payload.
+                _ => return Err(CompressionError::DecodingFailed),
+            }
+        }
+        Ok(pos)
+    }
+}
+
+/// The `TABLE_BITS` bits starting at bit `pos`, reading zeros past the end
+/// of `bytes`.
+fn peek_bits(bytes: &[u8], pos: u64) -> usize {
+    let start = (pos / 8) as usize;
+    let window = match bytes.get(start..start + 3) {
+        Some(&[a, b, c]) => (a as usize) << 16 | (b as usize) << 8 | c as usize,
+        _ => (0..3).fold(0, |window, i| {
+            (window << 8) | *bytes.get(start + i).unwrap_or(&0) as usize
+        }),
+    };
+    (window >> (24 - TABLE_BITS - (pos % 8) as usize)) & ((1 << TABLE_BITS) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bits::bit_count;
+    use crate::histogram;
+
+    fn bits_of(bytes: &[u8], total_bits: u64) -> Vec<bool> {
+        (0..total_bits)
+            .map(|i| (bytes[(i / 8) as usize] >> (7 - i % 8)) & 1 == 1)
+            .collect()
+    }
+
+    #[test]
+    fn table_matches_tree_walk() {
+        let data = b"a table lookup decodes several bits at a time";
+        let tree = HuffmanTree::from_frequencies(&histogram(data)).unwrap();
+        let encoded = tree.encode(data).unwrap();
+        let total = bit_count(&encoded.bytes) - encoded.padding as u64;
+        let table = DecodeTable::new(&tree);
+        assert_eq!(table.decode(&encoded.bytes, total).unwrap(), data);
+    }
+
+    #[test]
+    fn codes_longer_than_the_table_fall_back_to_the_tree() {
+        // Fibonacci frequencies give codes up to 15 bits long.
+        let mut data = Vec::new();
+        let (mut a, mut b) = (1, 1);
+        for symbol in 0..16u8 {
+            data.extend(std::iter::repeat_n(symbol, a));
+            (a, b) = (b, a + b);
+        }
+        let tree = HuffmanTree::from_frequencies(&histogram(&data)).unwrap();
+        assert!(tree.longest_code_length() as usize > TABLE_BITS);
+        let encoded = tree.encode(&data).unwrap();
+        let total = bit_count(&encoded.bytes) - encoded.padding as u64;
+        let table = DecodeTable::new(&tree);
+        let decoded = table.decode(&encoded.bytes, total).unwrap();
+        assert_eq!(decoded, data);
+        let flat = FlatTree::from_huffman_tree(&tree);
+        assert_eq!(flat.decode(&bits_of(&encoded.bytes, total)).unwrap(), data);
+    }
+
+    #[test]
+    fn payload_ending_mid_code_is_rejected() {
+        // a = 0, b = 10, c = 11 and a payload of a lone 1 bit.
+        let tree = HuffmanTree::from_frequencies(&histogram(b"aabc")).unwrap();
+        let table = DecodeTable::new(&tree);
+        assert_eq!(
+            table.decode(&[0b1000_0000], 1),
+            Err(CompressionError::DecodingFailed)
+        );
+        assert_eq!(table.decode(&[0b1000_0000], 3).unwrap(), b"ba");
+    }
+}
@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+/// An empty directory for test `name` under the system temporary
+/// directory, emptied first if a previous run left it behind.
+pub(crate) fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wfh-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,297 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+use crate::{compress, decompress};
+
+/// A reversible stage of a compression pipeline.
+pub trait Transform: Send + Sync {
+    fn forward(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError>;
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError>;
+}
+
+/// Replaces every byte with its difference from the previous byte.
+pub struct DeltaTransform;
+
+impl Transform for DeltaTransform {
+    fn forward(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let mut prev = 0u8;
+        Ok(data
+            .iter()
+            .map(|&b| {
+                let delta = b.wrapping_sub(prev);
+                prev = b;
+                delta
+            })
+            .collect())
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let mut prev = 0u8;
+        Ok(data
+            .iter()
+            .map(|&delta| {
+                prev = prev.wrapping_add(delta);
+                prev
+            })
+            .collect())
+    }
+}
+
+/// Stores runs of equal bytes as (run length, byte) pairs, with runs capped
+/// at 255 bytes.
+pub struct RleTransform;
+
+impl Transform for RleTransform {
+    fn forward(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Ok(rle_encode(data))
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        rle_decode(data)
+    }
+}
+
+/// The (run length, byte) pairs of `RleTransform`.
+pub fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let b = data[i];
+        let run = data[i..]
+            .iter()
+            .take(u8::MAX as usize)
+            .take_while(|&&x| x == b)
+            .count();
+        out.push(run as u8);
+        out.push(b);
+        i += run;
+    }
+    out
+}
+
+pub fn rle_decode(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    rle_decode_limited(data, u64::MAX)
+}
+
+/// Like `rle_decode` but fails once the output would exceed `limit` bytes.
+pub(crate) fn rle_decode_limited(data: &[u8], limit: u64) -> Result<Vec<u8>, CompressionError> {
+    if !data.len().is_multiple_of(2) {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let mut out = Vec::new();
+    for pair in data.chunks(2) {
+        if pair[0] == 0 {
+            return Err(CompressionError::CorruptData);
+        }
+        if (out.len() + pair[0] as usize) as u64 > limit {
+            return Err(CompressionError::OutputLimitExceeded { limit });
+        }
+        out.extend(core::iter::repeat_n(pair[1], pair[0] as usize));
+    }
+    Ok(out)
+}
+
+/// Huffman coding as a pipeline stage.
+pub struct HuffmanTransform;
+
+impl Transform for HuffmanTransform {
+    fn forward(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Ok(compress(data))
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        decompress(data)
+    }
+}
+
+/// Transforms applied in order on encode and in reverse order on decode.
+#[derive(Default)]
+pub struct TransformChain {
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl TransformChain {
+    pub fn new() -> TransformChain {
+        TransformChain::default()
+    }
+
+    pub fn push(&mut self, t: impl Transform + 'static) -> &mut TransformChain {
+        self.transforms.push(Box::new(t));
+        self
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        self.transforms
+            .iter()
+            .try_fold(data.to_vec(), |acc, t| t.forward(&acc))
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        self.transforms
+            .iter()
+            .rev()
+            .try_fold(data.to_vec(), |acc, t| t.inverse(&acc))
+    }
+}
+
+#[cfg(feature = "std")]
+/// Splits `data` into blocks of `block_size` bytes and runs every block
+/// through all `stages` on a pool of scoped threads.
+///
+/// Layout: the block count as a little-endian `u32`, then each block's
+/// length as a little-endian `u32` followed by its bytes.
+pub fn encode_pipeline_parallel(
+    data: &[u8],
+    block_size: usize,
+    stages: &[Box<dyn Transform>],
+) -> Result<Vec<u8>, CompressionError> {
+    let blocks: Vec<&[u8]> = data.chunks(block_size.max(1)).collect();
+    let encoded = run_parallel(&blocks, |block| {
+        stages
+            .iter()
+            .try_fold(block.to_vec(), |acc, t| t.forward(&acc))
+    })?;
+
+    let mut out = (encoded.len() as u32).to_le_bytes().to_vec();
+    for block in encoded {
+        out.extend((block.len() as u32).to_le_bytes());
+        out.extend(block);
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "std")]
+/// Inverse of `encode_pipeline_parallel` for the same `stages`.
+pub fn decode_pipeline_parallel(
+    data: &[u8],
+    stages: &[Box<dyn Transform>],
+) -> Result<Vec<u8>, CompressionError> {
+    let (count, mut rest) = read_u32(data)?;
+    let mut blocks = Vec::new();
+    for _ in 0..count {
+        let (len, tail) = read_u32(rest)?;
+        if tail.len() < len as usize {
+            return Err(CompressionError::TruncatedInput);
+        }
+        let (block, tail) = tail.split_at(len as usize);
+        blocks.push(block);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        return Err(CompressionError::CorruptData);
+    }
+    let decoded = run_parallel(&blocks, |block| {
+        stages
+            .iter()
+            .rev()
+            .try_fold(block.to_vec(), |acc, t| t.inverse(&acc))
+    })?;
+    Ok(decoded.concat())
+}
+
+#[cfg(feature = "std")]
+fn read_u32(data: &[u8]) -> Result<(u32, &[u8]), CompressionError> {
+    if data.len() < 4 {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let (n, rest) = data.split_at(4);
+    Ok((u32::from_le_bytes(n.try_into().unwrap()), rest))
+}
+
+#[cfg(feature = "std")]
+/// Applies `f` to every block, spreading contiguous runs of blocks over
+/// the available cores, and returns the results in block order.
+fn run_parallel<F>(blocks: &[&[u8]], f: F) -> Result<Vec<Vec<u8>>, CompressionError>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>, CompressionError> + Sync,
+{
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let per_worker = blocks.len().div_ceil(workers).max(1);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = blocks
+            .chunks(per_worker)
+            .map(|group| {
+                scope.spawn(move || group.iter().map(|block| f(block)).collect::<Vec<_>>())
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("pipeline worker panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn three_stage_chain_round_trips() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i / 10) as u8).collect();
+        let mut chain = TransformChain::new();
+        chain
+            .push(DeltaTransform)
+            .push(RleTransform)
+            .push(HuffmanTransform);
+        let encoded = chain.encode(&data).unwrap();
+        assert!(encoded.len() < data.len() / 4);
+        assert_eq!(chain.decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn empty_chain_is_identity() {
+        let chain = TransformChain::new();
+        assert_eq!(chain.encode(b"abc").unwrap(), b"abc");
+        assert_eq!(chain.decode(b"abc").unwrap(), b"abc");
+    }
+
+    #[test]
+    fn rle_splits_long_runs() {
+        let data = vec![7u8; 300];
+        let encoded = RleTransform.forward(&data).unwrap();
+        assert_eq!(encoded, vec![255, 7, 45, 7]);
+        assert_eq!(RleTransform.inverse(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn rle_rejects_zero_length_run() {
+        assert_eq!(
+            RleTransform.inverse(&[0, 7]),
+            Err(CompressionError::CorruptData)
+        );
+    }
+
+    fn stages() -> Vec<Box<dyn Transform>> {
+        vec![
+            Box::new(DeltaTransform),
+            Box::new(RleTransform),
+            Box::new(HuffmanTransform),
+        ]
+    }
+
+    #[test]
+    fn parallel_pipeline_round_trips() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i / 100) as u8).collect();
+        let encoded = encode_pipeline_parallel(&data, 4096, &stages()).unwrap();
+        assert!(encoded.len() < data.len() / 4);
+        assert_eq!(decode_pipeline_parallel(&encoded, &stages()).unwrap(), data);
+    }
+
+    #[test]
+    fn parallel_pipeline_handles_empty_input() {
+        let encoded = encode_pipeline_parallel(b"", 4096, &stages()).unwrap();
+        assert_eq!(encoded, 0u32.to_le_bytes());
+        assert_eq!(decode_pipeline_parallel(&encoded, &stages()).unwrap(), b"");
+    }
+
+    #[test]
+    fn parallel_pipeline_rejects_truncated_block() {
+        let encoded = encode_pipeline_parallel(b"abcabcabc", 4, &stages()).unwrap();
+        assert_eq!(
+            decode_pipeline_parallel(&encoded[..encoded.len() - 1], &stages()),
+            Err(CompressionError::TruncatedInput)
+        );
+    }
+}
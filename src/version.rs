@@ -0,0 +1,158 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CompressionError;
+
+/// Format version written by this library.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Flag bits a decoder may ignore when it does not know them. Any other
+/// unknown bit makes the stream unreadable.
+pub const OPTIONAL_FLAGS: u32 = 0xff00_0000;
+
+/// Optional format features, each assigned a bit in the header flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupportedFeature {
+    Checksum,
+    CanonicalTree,
+    MultiBlock,
+    Lzss,
+    Rle,
+    Stored,
+    Adaptive,
+    BlockIndex,
+    Bwt,
+    Range,
+    Order1,
+    BlockChecksum,
+    Filter,
+    Codec,
+    Extensions,
+    Words,
+}
+
+impl SupportedFeature {
+    pub fn bit(self) -> u32 {
+        match self {
+            SupportedFeature::Checksum => 1 << 0,
+            SupportedFeature::CanonicalTree => 1 << 1,
+            SupportedFeature::MultiBlock => 1 << 2,
+            SupportedFeature::Lzss => 1 << 3,
+            SupportedFeature::Rle => 1 << 4,
+            SupportedFeature::Stored => 1 << 5,
+            SupportedFeature::Adaptive => 1 << 6,
+            SupportedFeature::BlockIndex => 1 << 7,
+            SupportedFeature::Bwt => 1 << 8,
+            SupportedFeature::Range => 1 << 9,
+            SupportedFeature::Order1 => 1 << 10,
+            SupportedFeature::BlockChecksum => 1 << 11,
+            SupportedFeature::Filter => 1 << 12,
+            SupportedFeature::Codec => 1 << 13,
+            SupportedFeature::Extensions => 1 << 14,
+            SupportedFeature::Words => 1 << 15,
+        }
+    }
+}
+
+/// Decides whether a header's version and feature flags can be decoded.
+pub struct VersionNegotiator {
+    pub current_version: u8,
+    pub features: Vec<SupportedFeature>,
+}
+
+impl VersionNegotiator {
+    /// The capabilities of this build of the library, which implements
+    /// all of the optional features.
+    pub fn current() -> VersionNegotiator {
+        VersionNegotiator {
+            current_version: FORMAT_VERSION,
+            features: vec![
+                SupportedFeature::Checksum,
+                SupportedFeature::CanonicalTree,
+                SupportedFeature::MultiBlock,
+                SupportedFeature::Lzss,
+                SupportedFeature::Rle,
+                SupportedFeature::Stored,
+                SupportedFeature::Adaptive,
+                SupportedFeature::BlockIndex,
+                SupportedFeature::Bwt,
+                SupportedFeature::Range,
+                SupportedFeature::Order1,
+                SupportedFeature::BlockChecksum,
+                SupportedFeature::Filter,
+                SupportedFeature::Codec,
+                SupportedFeature::Extensions,
+                SupportedFeature::Words,
+            ],
+        }
+    }
+
+    /// The flag bits of the supported features.
+    pub fn known_flags(&self) -> u32 {
+        self.features.iter().fold(0, |acc, f| acc | f.bit())
+    }
+
+    /// Fails on a newer version or on unknown flags outside
+    /// [`OPTIONAL_FLAGS`].
+    pub fn check(&self, header_version: u8, header_flags: u32) -> Result<(), CompressionError> {
+        if header_version > self.current_version {
+            return Err(CompressionError::UnsupportedVersion {
+                found: header_version,
+                supported: self.current_version,
+            });
+        }
+        let unknown = header_flags & !self.known_flags() & !OPTIONAL_FLAGS;
+        if unknown != 0 {
+            return Err(CompressionError::UnsupportedFeatures { flags: unknown });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_current_and_older_versions() {
+        let negotiator = VersionNegotiator::current();
+        assert_eq!(negotiator.check(FORMAT_VERSION, 0), Ok(()));
+        assert_eq!(negotiator.check(0, 0), Ok(()));
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        assert_eq!(
+            VersionNegotiator::current().check(FORMAT_VERSION + 1, 0),
+            Err(CompressionError::UnsupportedVersion {
+                found: FORMAT_VERSION + 1,
+                supported: FORMAT_VERSION,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_only_unknown_flags() {
+        let negotiator = VersionNegotiator {
+            current_version: FORMAT_VERSION,
+            features: vec![SupportedFeature::Checksum],
+        };
+        let flags = SupportedFeature::Checksum.bit() | SupportedFeature::MultiBlock.bit();
+        assert_eq!(
+            negotiator.check(FORMAT_VERSION, flags),
+            Err(CompressionError::UnsupportedFeatures {
+                flags: SupportedFeature::MultiBlock.bit(),
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_optional_flags() {
+        let negotiator = VersionNegotiator::current();
+        assert_eq!(negotiator.check(FORMAT_VERSION, 1 << 31), Ok(()));
+        assert_eq!(
+            negotiator.check(FORMAT_VERSION, 1 << 31 | 1 << 23),
+            Err(CompressionError::UnsupportedFeatures { flags: 1 << 23 })
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bits::{BitReader, BitWriter};
+use crate::error::CompressionError;
+use crate::symbol::SymbolCode;
+use crate::{compress, decode_with_limit};
+
+/// Byte order used to split each `u16` before byte-level encoding, or to
+/// pair bytes into words for [`Algorithm::Words`](crate::Algorithm::Words).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WordOrder {
+    #[default]
+    LittleEndian,
+    BigEndian,
+}
+
+impl WordOrder {
+    fn tag(self) -> u8 {
+        match self {
+            WordOrder::LittleEndian => 0,
+            WordOrder::BigEndian => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<WordOrder, CompressionError> {
+        match tag {
+            0 => Ok(WordOrder::LittleEndian),
+            1 => Ok(WordOrder::BigEndian),
+            _ => Err(CompressionError::CorruptHeader),
+        }
+    }
+
+    fn split(self, word: u16) -> [u8; 2] {
+        match self {
+            WordOrder::LittleEndian => word.to_le_bytes(),
+            WordOrder::BigEndian => word.to_be_bytes(),
+        }
+    }
+
+    fn join(self, pair: [u8; 2]) -> u16 {
+        match self {
+            WordOrder::LittleEndian => u16::from_le_bytes(pair),
+            WordOrder::BigEndian => u16::from_be_bytes(pair),
+        }
+    }
+}
+
+/// First byte of a stream of [`encode_word_symbols`]; the byte streams of
+/// [`encode_words_with_order`] start with the tag of their order.
+const SYMBOLS_TAG: u8 = 2;
+const COUNT_LEN: usize = 8;
+
+/// Encodes 16-bit data using little-endian byte order.
+pub fn encode_words(data: &[u16]) -> Result<Vec<u8>, CompressionError> {
+    encode_words_with_order(data, WordOrder::LittleEndian)
+}
+
+/// Encodes 16-bit data by Huffman coding its bytes in the given order.
+/// The order is stored in the first output byte.
+pub fn encode_words_with_order(
+    data: &[u16],
+    order: WordOrder,
+) -> Result<Vec<u8>, CompressionError> {
+    let mut out = vec![order.tag()];
+    out.extend(compress(&words_to_bytes(data, order)));
+    Ok(out)
+}
+
+/// Encodes 16-bit data by Huffman coding the words themselves, so that
+/// UTF-16 text and 16-bit samples are modelled on their natural unit
+/// rather than as unrelated bytes.
+///
+/// Layout: the tag byte 2, the word count as a little-endian `u64`, the
+/// table of [`SymbolCode::table_bytes`] and the packed codes.
+pub fn encode_word_symbols(data: &[u16]) -> Vec<u8> {
+    let mut out = vec![SYMBOLS_TAG];
+    out.extend((data.len() as u64).to_le_bytes());
+    if let Some(code) = SymbolCode::from_symbols(data) {
+        let mut bits = BitWriter::new();
+        code.encode(data, &mut bits).unwrap();
+        out.extend(code.table_bytes());
+        out.extend(bits.into_bytes());
+    }
+    out
+}
+
+/// Decodes the output of [`encode_words_with_order`] or
+/// [`encode_word_symbols`].
+pub fn decode_words(data: &[u8]) -> Result<Vec<u16>, CompressionError> {
+    decode_words_with_limit(data, u64::MAX)
+}
+
+/// Like [`decode_words`] but fails with
+/// [`OutputLimitExceeded`](CompressionError::OutputLimitExceeded) instead of
+/// producing more than `max_output_bytes` bytes of words, as
+/// [`decode_with_limit`] does.
+pub fn decode_words_with_limit(
+    data: &[u8],
+    max_output_bytes: u64,
+) -> Result<Vec<u16>, CompressionError> {
+    let (&tag, rest) = data.split_first().ok_or(CompressionError::TruncatedInput)?;
+    if tag == SYMBOLS_TAG {
+        return decode_word_symbols(rest, max_output_bytes);
+    }
+    let order = WordOrder::from_tag(tag)?;
+    bytes_to_words(&decode_with_limit(rest, max_output_bytes)?, order)
+}
+
+fn decode_word_symbols(data: &[u8], max_output_bytes: u64) -> Result<Vec<u16>, CompressionError> {
+    if data.len() < COUNT_LEN {
+        return Err(CompressionError::TruncatedInput);
+    }
+    let (count, rest) = data.split_at(COUNT_LEN);
+    let count = u64::from_le_bytes(count.try_into().unwrap());
+    if count.saturating_mul(2) > max_output_bytes {
+        return Err(CompressionError::OutputLimitExceeded {
+            limit: max_output_bytes,
+        });
+    }
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let count = usize::try_from(count).map_err(|_| CompressionError::TruncatedInput)?;
+    let (code, table_len) = SymbolCode::<u16>::read_table(rest)?;
+    code.decode(&mut BitReader::new(&rest[table_len..]), count)
+}
+
+/// The payload of an [`Algorithm::Words`](crate::Algorithm::Words) stream
+/// holding `data`: the tag of `order`, then, if `data` has a whole word, the
+/// table of [`SymbolCode::table_bytes`] and the codes of its words, padded
+/// to a byte, and then the last byte of an odd-length `data` as is.
+pub(crate) fn encode_payload(data: &[u8], order: WordOrder) -> Vec<u8> {
+    let (pairs, odd) = data.split_at(data.len() & !1);
+    let words = bytes_to_words(pairs, order).unwrap();
+    let mut out = vec![order.tag()];
+    if let Some(code) = SymbolCode::from_symbols(&words) {
+        let mut bits = BitWriter::new();
+        code.encode(&words, &mut bits).unwrap();
+        out.extend(code.table_bytes());
+        out.extend(bits.into_bytes());
+    }
+    out.extend(odd);
+    out
+}
+
+/// The `len` bytes held by the payload of [`encode_payload`] at the start
+/// of `data`, ignoring whatever follows, and the length of the payload.
+pub(crate) fn decode_payload_prefix(
+    data: &[u8],
+    len: u64,
+) -> Result<(Vec<u8>, usize), CompressionError> {
+    let (&tag, rest) = data.split_first().ok_or(CompressionError::TruncatedInput)?;
+    let order = WordOrder::from_tag(tag)?;
+    let count = usize::try_from(len / 2).map_err(|_| CompressionError::TruncatedInput)?;
+    let mut used = 1;
+    let mut out = Vec::new();
+    if count > 0 {
+        let (code, table_len) = SymbolCode::<u16>::read_table(rest)?;
+        let mut bits = BitReader::new(&rest[table_len..]);
+        let words = code.decode(&mut bits, count)?;
+        out = words_to_bytes(&words, order);
+        used += table_len + bits.position().div_ceil(8) as usize;
+    }
+    if len % 2 == 1 {
+        out.push(*data.get(used).ok_or(CompressionError::TruncatedInput)?);
+        used += 1;
+    }
+    Ok((out, used))
+}
+
+/// `data` as bytes, each word split in `order`.
+pub fn words_to_bytes(data: &[u16], order: WordOrder) -> Vec<u8> {
+    data.iter().flat_map(|&word| order.split(word)).collect()
+}
+
+/// Pairs of `bytes` read as words in `order`, for coding UTF-16 text or
+/// 16-bit samples with [`encode_word_symbols`]. Fails on an odd length.
+pub fn bytes_to_words(bytes: &[u8], order: WordOrder) -> Result<Vec<u16>, CompressionError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(CompressionError::TruncatedInput);
+    }
+    Ok(bytes
+        .chunks(2)
+        .map(|pair| order.join([pair[0], pair[1]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_words(n: usize) -> Vec<u16> {
+        let mut state = 0x2545_f491u32;
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_random_words() {
+        let data = random_words(500);
+        assert_eq!(decode_words(&encode_words(&data).unwrap()).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_sorted_words() {
+        let mut data = random_words(500);
+        data.sort();
+        assert_eq!(decode_words(&encode_words(&data).unwrap()).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_delta_words_big_endian() {
+        let data: Vec<u16> = (0..500u16).map(|i| 1 + i % 3).collect();
+        let encoded = encode_words_with_order(&data, WordOrder::BigEndian).unwrap();
+        assert_eq!(decode_words(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn symbols_beat_bytes_on_scattered_words() {
+        // Sixteen code points whose high and low bytes alone say little.
+        let data: Vec<u16> = random_words(4000)
+            .iter()
+            .map(|w| (w % 16) * 0x1111)
+            .collect();
+        let symbols = encode_word_symbols(&data);
+        assert!(symbols.len() < encode_words(&data).unwrap().len() * 3 / 4);
+        assert_eq!(decode_words(&symbols).unwrap(), data);
+        assert_eq!(decode_words(&encode_word_symbols(&[])).unwrap(), []);
+        assert_eq!(
+            decode_words(&symbols[..symbols.len() - 1]),
+            Err(CompressionError::TruncatedInput)
+        );
+
+        let text: Vec<u8> = "Grüße, 世界"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let words = bytes_to_words(&text, WordOrder::BigEndian).unwrap();
+        assert_eq!(String::from_utf16(&words).unwrap(), "Grüße, 世界");
+        assert_eq!(words_to_bytes(&words, WordOrder::BigEndian), text);
+        assert!(bytes_to_words(&text[1..], WordOrder::BigEndian).is_err());
+    }
+
+    #[test]
+    fn limit_applies_to_both_layouts() {
+        let data = random_words(500);
+        for encoded in [encode_words(&data).unwrap(), encode_word_symbols(&data)] {
+            assert_eq!(decode_words_with_limit(&encoded, 1000).unwrap(), data);
+            assert_eq!(
+                decode_words_with_limit(&encoded, 999),
+                Err(CompressionError::OutputLimitExceeded { limit: 999 })
+            );
+        }
+    }
+
+    #[test]
+    fn empty_input_is_rejected() {
+        assert_eq!(decode_words(&[]), Err(CompressionError::TruncatedInput));
+    }
+}